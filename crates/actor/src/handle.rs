@@ -1,11 +1,23 @@
 use std::sync::Arc;
 
+use tokio::sync::oneshot;
 use tracing::Instrument;
 
 use crate::mailbox::{Mailbox, MailboxParts};
 use crate::scheduler::run_actor;
 use crate::{ActorDeadError, Message};
 
+/// What an actor does with the queued messages when it's killed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ShutdownMode {
+    /// Stops immediately, the queued messages are discarded.
+    #[default]
+    Immediate,
+    /// Handles the queued messages (up to the given number) before
+    /// stopping, e.g. to flush the pending results.
+    Drain(usize),
+}
+
 /// Handle to an actor.
 pub struct Actor<S> {
     mailbox: Arc<Mailbox<S>>,
@@ -18,6 +30,16 @@ impl<S: Send + Sync + 'static> Actor<S> {
     /// use [`crate::define_actor`] macro to define your actor type and
     /// then call `spawn` method on that type.
     pub fn spawn(state: S, label: Option<&str>) -> Self {
+        Self::spawn_with_shutdown_mode(state, label, ShutdownMode::default())
+    }
+
+    /// Spawn a new actor like [`Actor::spawn`], with a specified behavior
+    /// when it's killed.
+    pub fn spawn_with_shutdown_mode(
+        state: S,
+        label: Option<&str>,
+        shutdown_mode: ShutdownMode,
+    ) -> Self {
         let MailboxParts {
             mailbox,
             msg_rx,
@@ -25,8 +47,14 @@ impl<S: Send + Sync + 'static> Actor<S> {
         } = Mailbox::new_parts();
         let mailbox = Arc::new(mailbox);
         tokio::spawn(
-            run_actor(Arc::downgrade(&mailbox), state, msg_rx, kill_rx)
-                .instrument(trace_span!("actor", label = label)),
+            run_actor(
+                Arc::downgrade(&mailbox),
+                state,
+                msg_rx,
+                kill_rx,
+                shutdown_mode,
+            )
+            .instrument(trace_span!("actor", label = label)),
         );
         Self { mailbox }
     }
@@ -45,10 +73,27 @@ impl<S: Send + Sync + 'static> Actor<S> {
         self.mailbox.send(Box::new(msg))
     }
 
+    /// Sends a message carrying a reply channel to the actor, and waits for
+    /// the reply.
+    ///
+    /// `make_msg` is called with the sender of the reply. Returns an error if
+    /// the actor is dead, or the message is dropped without replying.
+    pub async fn ask<M, R, F>(&self, make_msg: F) -> Result<R, ActorDeadError>
+    where
+        M: Message<S> + 'static,
+        F: FnOnce(oneshot::Sender<R>) -> M,
+    {
+        let (tx, rx) = oneshot::channel();
+        self.send(make_msg(tx))?;
+        rx.await.map_err(|_| ActorDeadError)
+    }
+
     /// Attempts to kill the actor.
     ///
     /// The actor is not guaranteed to be killed immediately, but it
-    /// will stop handling further messages and quit soon.
+    /// will stop handling further messages and quit soon. The queued
+    /// messages are discarded unless the actor is spawned with
+    /// [`ShutdownMode::Drain`].
     #[inline]
     pub fn try_kill(&self) {
         self.mailbox.try_kill();
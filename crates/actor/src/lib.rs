@@ -12,9 +12,14 @@ mod mailbox;
 mod scheduler;
 
 pub use error::ActorDeadError;
-pub use handle::Actor;
+pub use handle::{Actor, ShutdownMode};
 pub use mailbox::Message;
 
+#[doc(hidden)]
+pub mod __private {
+    pub use tokio::sync::oneshot;
+}
+
 #[cfg(test)]
 mod tests {
     use tokio::sync::oneshot;
@@ -56,6 +61,62 @@ mod tests {
         }
     }
 
+    define_actor! {
+        #[wrapper_type(Counter)]
+        #[derive(Default)]
+        struct CounterState {
+            value: u32,
+        }
+
+        #[query(GetCount)]
+        /// Returns the current value.
+        pub fn value(state) -> u32 {
+            state.value
+        }
+
+        #[query(Increment)]
+        fn increment(state) -> u32 {
+            state.value += 1;
+            state.value
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        let counter = Counter::spawn(CounterState::default(), None);
+        assert_eq!(counter.increment().await.unwrap(), 1);
+        assert_eq!(counter.increment().await.unwrap(), 2);
+        assert_eq!(counter.value().await.unwrap(), 2);
+
+        counter.handle().try_kill();
+        tokio::task::yield_now().await;
+        assert!(counter.value().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_drain_on_shutdown() {
+        async fn value_after_kill(shutdown_mode: ShutdownMode) -> u32 {
+            let (tx, rx) = oneshot::channel();
+            let actor = Actor::spawn_with_shutdown_mode(
+                TestActorState::default(),
+                None,
+                shutdown_mode,
+            );
+            // The actor task hasn't run yet, so the messages are queued.
+            for _ in 0..3 {
+                actor.send(AddMessage(1)).unwrap();
+            }
+            actor.send(GetMessage(tx)).unwrap();
+            actor.try_kill();
+            rx.await.unwrap_or(0)
+        }
+
+        assert_eq!(value_after_kill(ShutdownMode::Immediate).await, 0);
+        assert_eq!(value_after_kill(ShutdownMode::Drain(10)).await, 3);
+        // The messages over the bound are discarded.
+        assert_eq!(value_after_kill(ShutdownMode::Drain(3)).await, 0);
+    }
+
     #[tokio::test]
     async fn test_send_message() {
         let actor = TestActor::spawn(TestActorState::default(), None);
@@ -3,6 +3,28 @@
 /// Use this macro to both define an actor's state type and wrapper type.
 /// The wrapper type can later have `impl` blocks to add some convenient
 /// methods to interact with the actor.
+///
+/// Queries (request-response messages) can be declared after the state
+/// type, each of them generates a message type and an async method on the
+/// wrapper type, which returns the reply or [`ActorDeadError`]:
+///
+/// ```
+/// # use little_agent_actor::define_actor;
+/// define_actor! {
+///     #[wrapper_type(Counter)]
+///     struct CounterState {
+///         value: u32,
+///     }
+///
+///     #[query(GetValue)]
+///     /// Returns the current value.
+///     pub fn value(state) -> u32 {
+///         state.value
+///     }
+/// }
+/// ```
+///
+/// [`ActorDeadError`]: crate::ActorDeadError
 #[macro_export]
 macro_rules! define_actor {
     {
@@ -12,12 +34,16 @@ macro_rules! define_actor {
         $v:vis struct $state_type:ident {
             $($state_items:tt)*
         }
+
+        $($queries:tt)*
     } => {
         $crate::__define_actor! {
             $(#[$($attrs)*])*
             $v struct $state_type {
                 $($state_items)*
             }
+
+            $($queries)*
         }
     }
 }
@@ -31,6 +57,8 @@ macro_rules! __define_actor {
         $v:vis struct $state_type:ident {
             $($state_items:tt)*
         }
+
+        $($queries:tt)*
     } => {
         $crate::__define_actor! {
             (
@@ -45,6 +73,9 @@ macro_rules! __define_actor {
             {
                 $($state_items)*
             }
+            [
+                $($queries)*
+            ]
         }
     };
 
@@ -63,6 +94,9 @@ macro_rules! __define_actor {
         {
             $($state_items:tt)*
         }
+        [
+            $($queries:tt)*
+        ]
     } => {
         $crate::__define_actor! {
             (
@@ -77,6 +111,9 @@ macro_rules! __define_actor {
             {
                 $($state_items)*
             }
+            [
+                $($queries)*
+            ]
         }
     };
 
@@ -95,6 +132,9 @@ macro_rules! __define_actor {
         {
             $($state_items:tt)*
         }
+        [
+            $($queries:tt)*
+        ]
     } => {
         $crate::__define_actor! {
             (
@@ -109,6 +149,9 @@ macro_rules! __define_actor {
             {
                 $($state_items)*
             }
+            [
+                $($queries)*
+            ]
         }
     };
 
@@ -126,6 +169,9 @@ macro_rules! __define_actor {
         {
             $($state_items:tt)*
         }
+        [
+            $($queries:tt)*
+        ]
     } => {
         $crate::__define_actor! {
             @
@@ -141,6 +187,9 @@ macro_rules! __define_actor {
             {
                 $($state_items)*
             }
+            [
+                $($queries)*
+            ]
         }
     };
     {
@@ -156,6 +205,9 @@ macro_rules! __define_actor {
         {
             $($state_items:tt)*
         }
+        [
+            $($queries:tt)*
+        ]
     } => {
         compile_error!("Wrapper type must be specified")
     };
@@ -175,6 +227,9 @@ macro_rules! __define_actor {
         {
             $($state_items:tt)*
         }
+        [
+            $($queries:tt)*
+        ]
     } => {
         $(#[$($rest_attrs)*])*
         struct $state_type {
@@ -211,5 +266,63 @@ macro_rules! __define_actor {
                 }
             }
         }
+
+        $crate::__define_actor_queries! {
+            ($state_type)
+            ($wrapper_type)
+            $($queries)*
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __define_actor_queries {
+    {
+        ($state_type:ident)
+        ($wrapper_type:ident)
+    } => {};
+
+    {
+        ($state_type:ident)
+        ($wrapper_type:ident)
+        #[query($msg_type:ident)]
+        $(#[$($attrs:tt)*])*
+        $v:vis fn $name:ident($state:ident) -> $reply_type:ty $body:block
+
+        $($rest:tt)*
+    } => {
+        struct $msg_type($crate::__private::oneshot::Sender<$reply_type>);
+
+        impl ::std::fmt::Debug for $msg_type {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                f.write_str(stringify!($msg_type))
+            }
+        }
+
+        impl $crate::Message<$state_type> for $msg_type {
+            fn handle(
+                self,
+                $state: &mut $state_type,
+                _handle: &$crate::Actor<$state_type>,
+            ) {
+                let reply: $reply_type = $body;
+                // The asker may have given up waiting.
+                self.0.send(reply).ok();
+            }
+        }
+
+        impl $wrapper_type {
+            $(#[$($attrs)*])*
+            $v async fn $name(&self) -> Result<$reply_type, $crate::ActorDeadError> {
+                self.handle.ask($msg_type).await
+            }
+        }
+
+        $crate::__define_actor_queries! {
+            ($state_type)
+            ($wrapper_type)
+            $($rest)*
+        }
     };
 }
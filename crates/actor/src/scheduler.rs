@@ -4,7 +4,7 @@ use tokio::select;
 use tokio::sync::{mpsc, watch};
 
 use crate::mailbox::Mailbox;
-use crate::{Actor, Message};
+use crate::{Actor, Message, ShutdownMode};
 
 #[inline]
 pub async fn run_actor<S: Send + Sync + 'static>(
@@ -12,6 +12,7 @@ pub async fn run_actor<S: Send + Sync + 'static>(
     mut state: S,
     mut msg_rx: mpsc::UnboundedReceiver<Box<dyn Message<S>>>,
     mut kill_rx: watch::Receiver<bool>,
+    shutdown_mode: ShutdownMode,
 ) {
     debug!("started");
     loop {
@@ -19,6 +20,9 @@ pub async fn run_actor<S: Send + Sync + 'static>(
             biased;
 
             _ = kill_rx.changed() => {
+                if let ShutdownMode::Drain(max_messages) = shutdown_mode {
+                    drain(&mailbox, &mut state, &mut msg_rx, max_messages);
+                }
                 break;
             }
             msg = msg_rx.recv() => {
@@ -28,20 +32,51 @@ pub async fn run_actor<S: Send + Sync + 'static>(
                 msg
             }
         };
-        trace!("received message: {msg:?}");
-
-        {
-            let Some(mailbox) = mailbox.upgrade() else {
-                warn!("last mailbox has been dropped, discard the message");
-                break;
-            };
-
-            let proc_span = trace_span!("proc msg");
-            proc_span.in_scope(|| {
-                msg.handle(&mut state, &Actor::from_mailbox(mailbox));
-                trace!("finished");
-            });
+        if !handle_msg(&mailbox, &mut state, msg) {
+            break;
         }
     }
     debug!("will terminate");
 }
+
+/// Handles the queued messages, up to `max_messages`.
+fn drain<S: Send + Sync + 'static>(
+    mailbox: &Weak<Mailbox<S>>,
+    state: &mut S,
+    msg_rx: &mut mpsc::UnboundedReceiver<Box<dyn Message<S>>>,
+    max_messages: usize,
+) {
+    let mut drained = 0;
+    while drained < max_messages {
+        let Ok(msg) = msg_rx.try_recv() else {
+            break;
+        };
+        if !handle_msg(mailbox, state, msg) {
+            break;
+        }
+        drained += 1;
+    }
+    let discarded = msg_rx.len();
+    debug!("drained {drained} messages, discarded {discarded}");
+}
+
+/// Handles a message, returns `false` if the actor should stop.
+fn handle_msg<S: Send + Sync + 'static>(
+    mailbox: &Weak<Mailbox<S>>,
+    state: &mut S,
+    msg: Box<dyn Message<S>>,
+) -> bool {
+    trace!("received message: {msg:?}");
+
+    let Some(mailbox) = mailbox.upgrade() else {
+        warn!("last mailbox has been dropped, discard the message");
+        return false;
+    };
+
+    let proc_span = trace_span!("proc msg");
+    proc_span.in_scope(|| {
+        msg.handle(state, &Actor::from_mailbox(mailbox));
+        trace!("finished");
+    });
+    true
+}
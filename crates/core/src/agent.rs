@@ -1,31 +1,50 @@
+mod backoff;
 mod builder;
 mod state;
 #[cfg(test)]
 mod tests;
 
 use std::collections::{HashMap, VecDeque};
-use std::time::Duration;
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use backoff::ExponentialBackoffBuilder;
-use backoff::backoff::Backoff;
+use ::backoff::backoff::Backoff;
 use little_agent_actor::define_actor;
-use little_agent_model::{ModelMessage, ModelProviderError};
+use little_agent_model::{
+    ModelFinishReason, ModelMessage, ModelProviderError, Usage,
+};
+use serde::Serialize;
 use tokio::task::JoinHandle;
 
-use crate::agent::state::EnqueueUserInput;
+use crate::Tool;
+use crate::agent::state::{
+    Checkpoint, EnqueueUserInput, EnqueueUserInputs, HealthCheck, Interrupt,
+    Rollback, SetPaused, UpdateToolset,
+};
+use crate::clock::Clock;
 use crate::conversation::{Conversation, Item as ConversationItem};
 use crate::model_client::ModelClient;
-use crate::tool::{Manager as ToolManager, ToolResult};
+use crate::pricing::PricingTable;
+use crate::tool::{
+    Manager as ToolManager, ProvisionalApproval, ToolResult, ToolsetChange,
+};
+pub use backoff::Jitter;
+use backoff::JitteredBackoff;
 pub use builder::AgentBuilder;
 use state::AgentStage;
 
 /// Where the transcript comes from.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum TranscriptSource {
     /// User input message.
     User,
     /// Assistant message.
     Assistant,
+    /// Tool result, reported once for each finished tool call.
+    Tool,
 }
 
 impl TranscriptSource {
@@ -36,6 +55,69 @@ impl TranscriptSource {
     }
 }
 
+/// The error returned when the agent task has stopped unexpectedly, e.g. it
+/// panicked.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AgentDeadError;
+
+impl Display for AgentDeadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the agent task has stopped")
+    }
+}
+
+impl StdError for AgentDeadError {}
+
+/// Identifies a snapshot of the conversation, see [`Agent::checkpoint`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CheckpointId(u64);
+
+/// The error returned by [`Agent::checkpoint`] and [`Agent::rollback`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CheckpointError {
+    /// The agent is running a turn.
+    Busy,
+    /// The checkpoint doesn't exist.
+    NotFound,
+    /// The agent task has stopped.
+    AgentDead,
+}
+
+impl Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Busy => f.write_str("the agent is running a turn"),
+            CheckpointError::NotFound => f.write_str("checkpoint not found"),
+            CheckpointError::AgentDead => Display::fmt(&AgentDeadError, f),
+        }
+    }
+}
+
+impl StdError for CheckpointError {}
+
+/// Summary of a finished turn.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TurnSummary {
+    /// Whether any tools ran during the turn.
+    pub tools_ran: bool,
+    /// The finish reason of the last model response in the turn, or `None`
+    /// if the turn was aborted before the model finished.
+    pub finish_reason: Option<ModelFinishReason>,
+}
+
+/// The token usage of a model response, with the estimated cost.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UsageReport {
+    /// The usage of the response.
+    pub usage: Usage,
+    /// The estimated cost of the response, or `None` if the model is not
+    /// in the pricing table.
+    pub cost: Option<f64>,
+    /// The running spend of the agent, summing up the responses with a
+    /// known cost.
+    pub total_cost: f64,
+}
+
 define_actor! {
     /// An agent instance, which maintains a session, a model provider, and
     /// internal state.
@@ -48,29 +130,195 @@ define_actor! {
     #[wrapper_type(Agent)]
     #[allow(clippy::type_complexity)]
     pub struct AgentState {
-        model_client: Option<ModelClient>,
+        model_client: ModelClient,
         tool_manager: ToolManager,
         conversation: Conversation,
         retry_backoff: Box<dyn Backoff + Send + Sync>,
+        clock: Arc<dyn Clock>,
         current_stage: AgentStage,
         pending_inputs: VecDeque<String>,
+        pending_toolset_changes: Vec<ToolsetChange>,
         pending_tool_results: HashMap<String, Option<ToolResult>>,
         running_tasks: HashMap<u64, JoinHandle<()>>,
         next_task_id: u64,
+        generation: u64,
+        paused: bool,
+        checkpoints: HashMap<CheckpointId, Vec<ConversationItem>>,
+        next_checkpoint_id: u64,
+        max_turns: Option<usize>,
+        input_dedup_window: Option<Duration>,
+        last_input: Option<(String, Instant)>,
+        max_rate_limit_retries: usize,
+        max_retry_after: Duration,
+        rate_limit_retries: usize,
+        completed_turns: usize,
+        current_turn: Option<TurnSummary>,
+        history_transform:
+            Option<Box<dyn Fn(&Conversation) -> Vec<ModelMessage> + Send + Sync>>,
+        turn_context: Option<Box<dyn Fn() -> Vec<ModelMessage> + Send + Sync>>,
+        compactor: Option<Box<dyn Fn(&mut Conversation) + Send + Sync>>,
+        compacted: bool,
+        pricing: Option<PricingTable>,
+        total_cost: f64,
 
         on_idle: Option<Box<dyn Fn() + Send + Sync>>,
         on_error:
             Option<Box<dyn Fn(Box<dyn ModelProviderError>) + Send + Sync>>,
         on_transcript: Option<Box<dyn Fn(&str, TranscriptSource) + Send + Sync>>,
+        on_limit_reached: Option<Box<dyn Fn() + Send + Sync>>,
+        on_turn_start: Option<Box<dyn Fn() + Send + Sync>>,
+        on_turn_end: Option<Box<dyn Fn(&TurnSummary) + Send + Sync>>,
+        on_waiting: Option<Box<dyn Fn() + Send + Sync>>,
+        on_pause_changed: Option<Box<dyn Fn(bool) + Send + Sync>>,
+        on_usage: Option<Box<dyn Fn(&UsageReport) + Send + Sync>>,
+        on_provisional_approval:
+            Option<Box<dyn Fn(&ProvisionalApproval) + Send + Sync>>,
+        on_conversation_item:
+            Option<Box<dyn Fn(&ConversationItem) + Send + Sync>>,
+        on_conversation_reset: Option<Box<dyn Fn(&Conversation) + Send + Sync>>,
     }
 }
 
 impl Agent {
     /// Enqueues a user input for processing.
-    pub fn enqueue_user_input<S: Into<String>>(&self, input: S) {
+    ///
+    /// Returns an error if the agent task has stopped, in which case the
+    /// agent can't be used anymore.
+    pub fn enqueue_user_input<S: Into<String>>(
+        &self,
+        input: S,
+    ) -> Result<(), AgentDeadError> {
         self.handle()
             .send(EnqueueUserInput(input.into()))
-            .expect("agent task has been dropped too early");
+            .map_err(|_| AgentDeadError)
+    }
+
+    /// Enqueues multiple user inputs for processing.
+    ///
+    /// The inputs are enqueued at once, in order, so no other input can
+    /// be interleaved with them.
+    ///
+    /// Returns an error if the agent task has stopped, in which case the
+    /// agent can't be used anymore.
+    pub fn enqueue_user_inputs<I, S>(
+        &self,
+        inputs: I,
+    ) -> Result<(), AgentDeadError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let inputs = inputs.into_iter().map(Into::into).collect();
+        self.handle()
+            .send(EnqueueUserInputs(inputs))
+            .map_err(|_| AgentDeadError)
+    }
+
+    /// Interrupts the current turn.
+    ///
+    /// The in-flight model request and the running tools are aborted, and
+    /// their results are discarded. Tools are notified via
+    /// [`Tool::on_cancel`]. The queued inputs are kept, and the agent will
+    /// continue with the next one. Does nothing if the agent is idle.
+    ///
+    /// Returns an error if the agent task has stopped.
+    pub fn interrupt(&self) -> Result<(), AgentDeadError> {
+        self.handle().send(Interrupt).map_err(|_| AgentDeadError)
+    }
+
+    /// Pauses the agent.
+    ///
+    /// The current turn is allowed to finish, but no new inputs are
+    /// processed until [`Agent::resume`] is called. The inputs received in
+    /// the meantime are queued.
+    ///
+    /// Returns an error if the agent task has stopped.
+    pub fn pause(&self) -> Result<(), AgentDeadError> {
+        self.handle()
+            .send(SetPaused(true))
+            .map_err(|_| AgentDeadError)
+    }
+
+    /// Resumes the agent, and processes the queued inputs.
+    ///
+    /// Returns an error if the agent task has stopped.
+    pub fn resume(&self) -> Result<(), AgentDeadError> {
+        self.handle()
+            .send(SetPaused(false))
+            .map_err(|_| AgentDeadError)
+    }
+
+    /// Checks whether the model provider is usable, e.g. the credentials are
+    /// valid and the endpoint is reachable.
+    ///
+    /// This can be called before sending any inputs, to fail early. The
+    /// outer error is returned if the agent task has stopped, and the inner
+    /// one carries the result of the check.
+    pub async fn health_check(
+        &self,
+    ) -> Result<Result<(), Box<dyn ModelProviderError>>, AgentDeadError> {
+        self.handle()
+            .ask(HealthCheck)
+            .await
+            .map_err(|_| AgentDeadError)
+    }
+
+    /// Takes a snapshot of the conversation, which can be restored later
+    /// with [`Agent::rollback`].
+    ///
+    /// Returns [`CheckpointError::Busy`] if the agent is not idle.
+    pub async fn checkpoint(&self) -> Result<CheckpointId, CheckpointError> {
+        self.handle()
+            .ask(Checkpoint)
+            .await
+            .map_err(|_| CheckpointError::AgentDead)?
+    }
+
+    /// Restores the conversation to a checkpoint, discarding everything
+    /// after it. The following turns continue from the restored state.
+    ///
+    /// The checkpoint is kept, so it can be restored again, while the
+    /// checkpoints taken after it are discarded. The restored conversation
+    /// is reported to [`AgentBuilder::on_conversation_reset`]. Returns
+    /// [`CheckpointError::Busy`] if the agent is not idle.
+    pub async fn rollback(
+        &self,
+        checkpoint: CheckpointId,
+    ) -> Result<(), CheckpointError> {
+        self.handle()
+            .ask(|tx| Rollback(checkpoint, tx))
+            .await
+            .map_err(|_| CheckpointError::AgentDead)?
+    }
+
+    /// Registers a tool, replacing the existing one with the same name.
+    ///
+    /// The tool becomes available since the next turn. If the agent is
+    /// running a turn, the change is deferred until the next turn starts.
+    ///
+    /// Returns an error if the agent task has stopped.
+    pub fn register_tool<T: Tool>(
+        &self,
+        tool: T,
+    ) -> Result<(), AgentDeadError> {
+        self.handle()
+            .send(UpdateToolset(ToolsetChange::add(tool)))
+            .map_err(|_| AgentDeadError)
+    }
+
+    /// Unregisters a tool by its name.
+    ///
+    /// Like [`Agent::register_tool`], the change is deferred until the next
+    /// turn starts if the agent is running a turn.
+    ///
+    /// Returns an error if the agent task has stopped.
+    pub fn unregister_tool<S: Into<String>>(
+        &self,
+        name: S,
+    ) -> Result<(), AgentDeadError> {
+        self.handle()
+            .send(UpdateToolset(ToolsetChange::Remove(name.into())))
+            .map_err(|_| AgentDeadError)
     }
 }
 
@@ -80,39 +328,113 @@ impl Agent {
             model_client,
             tool_manager,
             system_prompt,
+            mut conversation,
+            max_turns,
+            input_dedup_window,
+            max_rate_limit_retries,
+            max_retry_after,
+            clock,
+            retry_jitter,
+            retry_seed,
+            history_transform,
+            turn_context,
+            compactor,
+            pricing,
+            transcript_writer,
             on_idle,
             on_error,
             on_transcript,
+            on_limit_reached,
+            on_turn_start,
+            mut on_turn_end,
+            on_waiting,
+            on_pause_changed,
+            on_usage,
+            on_provisional_approval,
+            mut on_conversation_item,
+            on_conversation_reset,
         } = builder;
 
-        let mut conversation = Conversation::default();
+        if let Some(writer) = transcript_writer {
+            // One record per conversation item, rather than per delta.
+            on_conversation_item = Some(Box::new({
+                let writer = writer.clone();
+                move |item| {
+                    writer.write_item(item);
+                    if let Some(on_conversation_item) = &on_conversation_item {
+                        on_conversation_item(item);
+                    }
+                }
+            }));
+            on_turn_end = Some(Box::new(move |summary| {
+                writer.flush();
+                if let Some(on_turn_end) = &on_turn_end {
+                    on_turn_end(summary);
+                }
+            }));
+        }
+
         if let Some(system_prompt) = system_prompt {
-            conversation.items.push(ConversationItem {
-                msg: ModelMessage::System(system_prompt.clone()),
-                transcript: system_prompt,
-            });
+            conversation
+                .items
+                .retain(|item| !matches!(item.msg, ModelMessage::System(_)));
+            conversation.items.insert(
+                0,
+                ConversationItem::new(
+                    ModelMessage::System(system_prompt.clone()),
+                    system_prompt,
+                ),
+            );
         }
 
-        let retry_backoff = Box::new(
-            ExponentialBackoffBuilder::default()
-                .with_max_interval(Duration::from_secs(5 * 60))
-                .with_max_elapsed_time(Some(Duration::from_secs(30 * 60)))
-                .build(),
-        );
+        let retry_backoff = Box::new(JitteredBackoff::new(
+            retry_jitter,
+            retry_seed,
+            Arc::clone(&clock),
+        ));
 
         let state = AgentState {
-            model_client: Some(model_client),
+            model_client,
             tool_manager,
             conversation,
             retry_backoff,
+            clock,
             current_stage: Default::default(),
             pending_inputs: Default::default(),
+            pending_toolset_changes: Default::default(),
             pending_tool_results: Default::default(),
             running_tasks: Default::default(),
             next_task_id: 1,
+            generation: 0,
+            paused: false,
+            checkpoints: Default::default(),
+            next_checkpoint_id: 1,
+            max_turns,
+            input_dedup_window,
+            last_input: None,
+            max_rate_limit_retries,
+            max_retry_after,
+            rate_limit_retries: 0,
+            completed_turns: 0,
+            current_turn: None,
+            history_transform,
+            turn_context,
+            compactor,
+            compacted: false,
+            pricing,
+            total_cost: 0.0,
             on_idle,
             on_error,
             on_transcript,
+            on_limit_reached,
+            on_turn_start,
+            on_turn_end,
+            on_waiting,
+            on_pause_changed,
+            on_usage,
+            on_provisional_approval,
+            on_conversation_item,
+            on_conversation_reset,
         };
         Self::spawn(state, Some("agent"))
     }
@@ -0,0 +1,169 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use backoff::backoff::Backoff;
+
+use crate::clock::Clock;
+
+const INITIAL_INTERVAL: Duration = Duration::from_millis(500);
+const MULTIPLIER: f64 = 1.5;
+const MAX_INTERVAL: Duration = Duration::from_secs(5 * 60);
+const MAX_ELAPSED_TIME: Duration = Duration::from_secs(30 * 60);
+
+/// The strategy to randomize the retry delays, which avoids many agents
+/// retrying at the same time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Jitter {
+    /// Uses the exponential delay as is.
+    None,
+    /// Picks a random delay between zero and the exponential delay.
+    #[default]
+    Full,
+    /// Keeps half of the exponential delay, and randomizes the other half.
+    Equal,
+}
+
+/// An exponential backoff with jitter.
+pub(crate) struct JitteredBackoff {
+    jitter: Jitter,
+    rng: SplitMix64,
+    current_interval: Duration,
+    clock: Arc<dyn Clock>,
+    start_time: Instant,
+}
+
+impl JitteredBackoff {
+    /// Creates a backoff whose randomness is determined by `seed`. A random
+    /// seed is used if it's `None`. The elapsed time is measured by
+    /// `clock`.
+    pub fn new(
+        jitter: Jitter,
+        seed: Option<u64>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        let seed = seed.unwrap_or_else(|| RandomState::new().hash_one(0u64));
+        let start_time = clock.now();
+        Self {
+            jitter,
+            rng: SplitMix64(seed),
+            current_interval: INITIAL_INTERVAL,
+            clock,
+            start_time,
+        }
+    }
+}
+
+impl Backoff for JitteredBackoff {
+    fn reset(&mut self) {
+        self.current_interval = INITIAL_INTERVAL;
+        self.start_time = self.clock.now();
+    }
+
+    fn next_backoff(&mut self) -> Option<Duration> {
+        if self.clock.now() - self.start_time > MAX_ELAPSED_TIME {
+            return None;
+        }
+
+        let interval = self.current_interval;
+        self.current_interval = interval.mul_f64(MULTIPLIER).min(MAX_INTERVAL);
+        let delay = match self.jitter {
+            Jitter::None => interval,
+            Jitter::Full => interval.mul_f64(self.rng.next_f64()),
+            Jitter::Equal => {
+                let half = interval / 2;
+                half + half.mul_f64(self.rng.next_f64())
+            }
+        };
+        Some(delay)
+    }
+}
+
+/// A tiny PRNG, which is good enough for jitters.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random number in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{MockClock, TokioClock};
+
+    fn backoff(jitter: Jitter, seed: u64) -> JitteredBackoff {
+        JitteredBackoff::new(jitter, Some(seed), Arc::new(TokioClock))
+    }
+
+    fn exponential_intervals() -> impl Iterator<Item = Duration> {
+        std::iter::successors(Some(INITIAL_INTERVAL), |interval| {
+            Some(interval.mul_f64(MULTIPLIER).min(MAX_INTERVAL))
+        })
+    }
+
+    #[test]
+    fn test_no_jitter() {
+        let mut backoff = backoff(Jitter::None, 42);
+        for interval in exponential_intervals().take(20) {
+            assert_eq!(backoff.next_backoff(), Some(interval));
+        }
+    }
+
+    #[test]
+    fn test_full_jitter() {
+        let mut backoff = backoff(Jitter::Full, 42);
+        for interval in exponential_intervals().take(20) {
+            let delay = backoff.next_backoff().unwrap();
+            assert!(delay <= interval);
+        }
+    }
+
+    #[test]
+    fn test_equal_jitter() {
+        let mut backoff = backoff(Jitter::Equal, 42);
+        for interval in exponential_intervals().take(20) {
+            let delay = backoff.next_backoff().unwrap();
+            assert!(delay >= interval / 2 && delay <= interval);
+        }
+    }
+
+    #[test]
+    fn test_deterministic_with_seed() {
+        let mut a = backoff(Jitter::Full, 7);
+        let mut b = backoff(Jitter::Full, 7);
+        for _ in 0..10 {
+            assert_eq!(a.next_backoff(), b.next_backoff());
+        }
+
+        a.reset();
+        let delay = a.next_backoff().unwrap();
+        assert!(delay <= INITIAL_INTERVAL);
+    }
+
+    #[test]
+    fn test_max_elapsed_time() {
+        let clock = MockClock::new();
+        let mut backoff =
+            JitteredBackoff::new(Jitter::None, None, Arc::new(clock.clone()));
+        clock.advance(MAX_ELAPSED_TIME);
+        assert!(backoff.next_backoff().is_some());
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(backoff.next_backoff(), None);
+
+        backoff.reset();
+        assert_eq!(backoff.next_backoff(), Some(INITIAL_INTERVAL));
+    }
+}
@@ -1,9 +1,23 @@
-use little_agent_model::{ModelProvider, ModelProviderError};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
 
-use super::{Agent, TranscriptSource};
+use little_agent_model::{
+    ModelMessage, ModelProvider, ModelProviderError, ModelTool,
+};
+
+use super::{Agent, Jitter, TranscriptSource, TurnSummary, UsageReport};
 use crate::Tool;
-use crate::model_client::ModelClient;
-use crate::tool::{Approval, Manager as ToolManager};
+use crate::clock::{Clock, TokioClock};
+use crate::conversation::{Conversation, Item as ConversationItem};
+use crate::model_client::{ModelClient, RequestMiddleware};
+use crate::pricing::PricingTable;
+use crate::tool::{
+    Approval, ApprovalDecision, Manager as ToolManager, Metrics as ToolMetrics,
+    ProvisionalApproval, UnknownToolPolicy,
+};
+use crate::transcript::TranscriptWriter;
 
 /// [`Agent`] builder.
 #[allow(clippy::type_complexity)]
@@ -11,11 +25,38 @@ pub struct AgentBuilder {
     pub(crate) model_client: ModelClient,
     pub(crate) tool_manager: ToolManager,
     pub(crate) system_prompt: Option<String>,
+    pub(crate) conversation: Conversation,
+    pub(crate) max_turns: Option<usize>,
+    pub(crate) input_dedup_window: Option<Duration>,
+    pub(crate) max_rate_limit_retries: usize,
+    pub(crate) max_retry_after: Duration,
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) retry_jitter: Jitter,
+    pub(crate) retry_seed: Option<u64>,
+    pub(crate) history_transform:
+        Option<Box<dyn Fn(&Conversation) -> Vec<ModelMessage> + Send + Sync>>,
+    pub(crate) turn_context:
+        Option<Box<dyn Fn() -> Vec<ModelMessage> + Send + Sync>>,
+    pub(crate) compactor: Option<Box<dyn Fn(&mut Conversation) + Send + Sync>>,
+    pub(crate) pricing: Option<PricingTable>,
+    pub(crate) transcript_writer: Option<TranscriptWriter>,
     pub(crate) on_idle: Option<Box<dyn Fn() + Send + Sync>>,
     pub(crate) on_error:
         Option<Box<dyn Fn(Box<dyn ModelProviderError>) + Send + Sync>>,
     pub(crate) on_transcript:
         Option<Box<dyn Fn(&str, TranscriptSource) + Send + Sync>>,
+    pub(crate) on_limit_reached: Option<Box<dyn Fn() + Send + Sync>>,
+    pub(crate) on_turn_start: Option<Box<dyn Fn() + Send + Sync>>,
+    pub(crate) on_turn_end: Option<Box<dyn Fn(&TurnSummary) + Send + Sync>>,
+    pub(crate) on_waiting: Option<Box<dyn Fn() + Send + Sync>>,
+    pub(crate) on_pause_changed: Option<Box<dyn Fn(bool) + Send + Sync>>,
+    pub(crate) on_usage: Option<Box<dyn Fn(&UsageReport) + Send + Sync>>,
+    pub(crate) on_provisional_approval:
+        Option<Box<dyn Fn(&ProvisionalApproval) + Send + Sync>>,
+    pub(crate) on_conversation_item:
+        Option<Box<dyn Fn(&ConversationItem) + Send + Sync>>,
+    pub(crate) on_conversation_reset:
+        Option<Box<dyn Fn(&Conversation) + Send + Sync>>,
 }
 
 impl AgentBuilder {
@@ -28,9 +69,31 @@ impl AgentBuilder {
             model_client: ModelClient::new(provider),
             tool_manager: Default::default(),
             system_prompt: None,
+            conversation: Conversation::default(),
+            max_turns: None,
+            input_dedup_window: None,
+            max_rate_limit_retries: 5,
+            max_retry_after: Duration::from_secs(60),
+            clock: Arc::new(TokioClock),
+            retry_jitter: Jitter::default(),
+            retry_seed: None,
+            history_transform: None,
+            turn_context: None,
+            compactor: None,
+            pricing: None,
+            transcript_writer: None,
             on_idle: None,
             on_error: None,
             on_transcript: None,
+            on_limit_reached: None,
+            on_turn_start: None,
+            on_turn_end: None,
+            on_waiting: None,
+            on_pause_changed: None,
+            on_usage: None,
+            on_provisional_approval: None,
+            on_conversation_item: None,
+            on_conversation_reset: None,
         }
     }
 
@@ -41,6 +104,182 @@ impl AgentBuilder {
         self
     }
 
+    /// Starts the agent with an existing conversation, e.g. one restored by
+    /// [`Conversation::from_json`].
+    ///
+    /// If the system prompt is set, it replaces the system messages in the
+    /// conversation.
+    #[inline]
+    pub fn with_conversation(mut self, conversation: Conversation) -> Self {
+        self.conversation = conversation;
+        self
+    }
+
+    /// Limits the number of turns the agent can complete.
+    ///
+    /// A turn is completed when the model finishes responding to a user
+    /// input (including all the tool calls in between). Once the limit is
+    /// reached, the queued inputs are dropped, and further inputs will be
+    /// refused, each reported to [`AgentBuilder::on_limit_reached`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_turns` is zero.
+    #[inline]
+    pub fn with_max_turns(mut self, max_turns: usize) -> Self {
+        assert!(max_turns > 0, "max_turns must be greater than zero");
+        self.max_turns = Some(max_turns);
+        self
+    }
+
+    /// Drops a user input if it's identical to the previous one, and arrives
+    /// within `window` while a turn is active.
+    ///
+    /// This guards against accidental double-sends, e.g. from double-clicking
+    /// a send button. Disabled by default.
+    #[inline]
+    pub fn with_input_dedup_window(mut self, window: Duration) -> Self {
+        self.input_dedup_window = Some(window);
+        self
+    }
+
+    /// Configures how rate limit errors are retried.
+    ///
+    /// The agent gives up after `max_retries` rate limit errors in a turn,
+    /// the count is reset when the next turn begins. The delay suggested by
+    /// the provider is honored, but capped at `max_retry_after`, so a huge
+    /// one won't wedge the agent. Defaults to 5 retries and 60 seconds.
+    #[inline]
+    pub fn with_rate_limit_retries(
+        mut self,
+        max_retries: usize,
+        max_retry_after: Duration,
+    ) -> Self {
+        self.max_rate_limit_retries = max_retries;
+        self.max_retry_after = max_retry_after;
+        self
+    }
+
+    /// Sets the jitter strategy of the retry delays. Defaults to
+    /// [`Jitter::Full`].
+    ///
+    /// Delays suggested by the provider (like rate limit errors with a
+    /// `Retry-After` header) are not affected.
+    #[inline]
+    pub fn with_retry_jitter(mut self, jitter: Jitter) -> Self {
+        self.retry_jitter = jitter;
+        self
+    }
+
+    /// Sets the seed of the random delays, which makes them deterministic.
+    /// This is mostly useful for tests.
+    #[inline]
+    pub fn with_retry_seed(mut self, seed: u64) -> Self {
+        self.retry_seed = Some(seed);
+        self
+    }
+
+    /// Sets the clock used for time-based behaviors, like retry backoff.
+    ///
+    /// Defaults to [`TokioClock`]. This is mostly useful for tests.
+    #[inline]
+    pub fn with_clock(mut self, clock: impl Clock) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Sets a function that converts the conversation into the messages
+    /// sent to the model.
+    ///
+    /// This can be used to filter or merge messages for models that are
+    /// picky about the message sequence. Defaults to
+    /// [`Conversation::to_model_messages`].
+    #[inline]
+    pub fn with_history_transform(
+        mut self,
+        transform: impl Fn(&Conversation) -> Vec<ModelMessage>
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        self.history_transform = Some(Box::new(transform));
+        self
+    }
+
+    /// Sets a function that provides ephemeral context messages, like the
+    /// current time.
+    ///
+    /// It's called for every model request, and the messages are inserted
+    /// after the system prompt. They are never stored in the conversation.
+    #[inline]
+    pub fn with_turn_context(
+        mut self,
+        turn_context: impl Fn() -> Vec<ModelMessage> + Send + Sync + 'static,
+    ) -> Self {
+        self.turn_context = Some(Box::new(turn_context));
+        self
+    }
+
+    /// Sets a function that compacts the conversation when it outgrows the
+    /// context window of the model.
+    ///
+    /// When a request fails with [`ErrorKind::ContextLengthExceeded`], the
+    /// agent compacts the conversation and retries once, before reporting
+    /// the error to [`AgentBuilder::on_error`]. The compaction is kept in
+    /// the conversation, and reported to
+    /// [`AgentBuilder::on_conversation_reset`].
+    ///
+    /// [`ErrorKind::ContextLengthExceeded`]: little_agent_model::ErrorKind::ContextLengthExceeded
+    #[inline]
+    pub fn with_compactor(
+        mut self,
+        compactor: impl Fn(&mut Conversation) + Send + Sync + 'static,
+    ) -> Self {
+        self.compactor = Some(Box::new(compactor));
+        self
+    }
+
+    /// Sets the prices of the models, which are used to estimate the cost
+    /// reported to [`AgentBuilder::on_usage`].
+    #[inline]
+    pub fn with_pricing(mut self, pricing: PricingTable) -> Self {
+        self.pricing = Some(pricing);
+        self
+    }
+
+    /// Adds a middleware for the model requests, see [`RequestMiddleware`].
+    #[inline]
+    pub fn with_request_middleware<M: RequestMiddleware>(
+        mut self,
+        middleware: M,
+    ) -> Self {
+        self.model_client = self.model_client.with_middleware(middleware);
+        self
+    }
+
+    /// Logs the transcripts to a [`TranscriptWriter`], one record for each
+    /// conversation item, which is flushed at the end of each turn.
+    ///
+    /// This works alongside [`AgentBuilder::on_conversation_item`].
+    #[inline]
+    pub fn with_transcript_writer(mut self, writer: TranscriptWriter) -> Self {
+        self.transcript_writer = Some(writer);
+        self
+    }
+
+    /// Logs the transcripts to the file at `path` as JSON lines, see
+    /// [`TranscriptWriter`].
+    ///
+    /// Returns an error if the file can't be opened.
+    #[inline]
+    pub fn with_transcript_log<P: AsRef<Path>>(
+        self,
+        path: P,
+    ) -> io::Result<Self> {
+        let writer = TranscriptWriter::append_to_file(path)?;
+        Ok(self.with_transcript_writer(writer))
+    }
+
     /// Attaches a callback to be invoked when the agent is idle.
     #[inline]
     pub fn on_idle(
@@ -71,6 +310,112 @@ impl AgentBuilder {
         self
     }
 
+    /// Attaches a callback to be invoked when the turn limit is reached,
+    /// and again for every input refused after that.
+    ///
+    /// See [`AgentBuilder::with_max_turns`].
+    #[inline]
+    pub fn on_limit_reached(
+        mut self,
+        on_limit_reached: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        self.on_limit_reached = Some(Box::new(on_limit_reached));
+        self
+    }
+
+    /// Attaches a callback to be invoked when a turn starts.
+    ///
+    /// A turn starts when the agent begins to process a user input, and
+    /// this callback is invoked before the first model request is sent.
+    #[inline]
+    pub fn on_turn_start(
+        mut self,
+        on_turn_start: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        self.on_turn_start = Some(Box::new(on_turn_start));
+        self
+    }
+
+    /// Attaches a callback to be invoked when a turn ends.
+    ///
+    /// A turn ends when the model finishes responding to the user input,
+    /// including all the tool calls in between. It's also invoked when the
+    /// turn is aborted due to errors.
+    #[inline]
+    pub fn on_turn_end(
+        mut self,
+        on_turn_end: impl Fn(&TurnSummary) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_turn_end = Some(Box::new(on_turn_end));
+        self
+    }
+
+    /// Attaches a callback to be invoked when a model request is sent.
+    ///
+    /// It's invoked before any events of the response are received, so
+    /// it can be used to show a progress indicator or to measure the time
+    /// to the first token. Every request fires it once, including the
+    /// retries and the follow-ups after tool calls.
+    #[inline]
+    pub fn on_waiting(
+        mut self,
+        on_waiting: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        self.on_waiting = Some(Box::new(on_waiting));
+        self
+    }
+
+    /// Attaches a callback to be invoked when a model response reports its
+    /// token usage, with the cost estimated by the table set with
+    /// [`AgentBuilder::with_pricing`].
+    #[inline]
+    pub fn on_usage(
+        mut self,
+        on_usage: impl Fn(&UsageReport) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_usage = Some(Box::new(on_usage));
+        self
+    }
+
+    /// Attaches a callback to be invoked when the agent is paused or
+    /// resumed, with whether it's paused now.
+    ///
+    /// See [`Agent::pause`] for details.
+    #[inline]
+    pub fn on_pause_changed(
+        mut self,
+        on_pause_changed: impl Fn(bool) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_pause_changed = Some(Box::new(on_pause_changed));
+        self
+    }
+
+    /// Attaches a callback to be invoked when an item is added to the
+    /// conversation, i.e. a user input, a model response or a tool result.
+    #[inline]
+    pub fn on_conversation_item(
+        mut self,
+        on_conversation_item: impl Fn(&ConversationItem) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_conversation_item = Some(Box::new(on_conversation_item));
+        self
+    }
+
+    /// Attaches a callback to be invoked when the conversation is replaced
+    /// as a whole, e.g. by [`Agent::rollback`] or the compactor (see
+    /// [`AgentBuilder::with_compactor`]), with the new conversation.
+    ///
+    /// Anything built from [`AgentBuilder::on_conversation_item`] should be
+    /// rebuilt from it.
+    #[inline]
+    pub fn on_conversation_reset(
+        mut self,
+        on_conversation_reset: impl Fn(&Conversation) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_conversation_reset = Some(Box::new(on_conversation_reset));
+        self
+    }
+
     /// Attaches a callback to be invoked when a tool call request is received.
     ///
     /// The receiver can either approve or reject the request. If this callback
@@ -84,6 +429,107 @@ impl AgentBuilder {
         self
     }
 
+    /// Attaches a callback to be invoked when a tool call starts streaming
+    /// in, and each time more of its arguments arrive, so the UI can start
+    /// rendering the approval early.
+    ///
+    /// The approval is finalized with [`AgentBuilder::on_tool_call_request`]
+    /// once the arguments are complete. Only the providers that report the
+    /// progress of tool calls support this.
+    #[inline]
+    pub fn on_provisional_approval(
+        mut self,
+        on_provisional_approval: impl Fn(&ProvisionalApproval)
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        self.on_provisional_approval = Some(Box::new(on_provisional_approval));
+        self
+    }
+
+    /// Attaches a callback to be invoked once with all the tool call
+    /// requests of a model response, so they can be approved or rejected
+    /// at once.
+    ///
+    /// When provided, it takes over [`AgentBuilder::on_tool_call_request`],
+    /// which is no longer invoked.
+    #[inline]
+    pub fn on_tool_call_batch(
+        mut self,
+        on_tool_call_batch: impl Fn(Vec<Approval>) + Send + Sync + 'static,
+    ) -> Self {
+        self.tool_manager.on_batch(on_tool_call_batch);
+        self
+    }
+
+    /// Attaches a callback to be invoked when a tool call request is
+    /// approved or rejected, which is useful for auditing.
+    ///
+    /// Automatic approvals (when no [`AgentBuilder::on_tool_call_request`]
+    /// callback is provided) are reported as well.
+    #[inline]
+    pub fn on_approval_decision(
+        mut self,
+        on_approval_decision: impl Fn(&ApprovalDecision) + Send + Sync + 'static,
+    ) -> Self {
+        self.tool_manager.on_decision(on_approval_decision);
+        self
+    }
+
+    /// Attaches a callback to be invoked with the tool call id and the tool
+    /// name, when an approved tool call starts executing.
+    ///
+    /// Together with [`AgentBuilder::on_tool_call_request`], it lets the UI
+    /// tell the calls awaiting approval from the running ones.
+    #[inline]
+    pub fn on_tool_execution_start(
+        mut self,
+        on_tool_execution_start: impl Fn(&str, &str) + Send + Sync + 'static,
+    ) -> Self {
+        self.tool_manager
+            .on_execution_start(on_tool_execution_start);
+        self
+    }
+
+    /// Sets how to handle the calls to tools that are not registered.
+    ///
+    /// Defaults to [`UnknownToolPolicy::Report`], which responds with an
+    /// error result, so the model can recover.
+    #[inline]
+    pub fn with_unknown_tool_policy(
+        mut self,
+        policy: UnknownToolPolicy,
+    ) -> Self {
+        self.tool_manager.set_unknown_tool_policy(policy);
+        self
+    }
+
+    /// Sets a filter to select the tools exposed to the model, e.g. only the
+    /// read-only tools.
+    ///
+    /// The tools that are filtered out are not sent in the requests, and
+    /// the calls to them are rejected with an error result.
+    #[inline]
+    pub fn with_tool_filter(
+        mut self,
+        filter: impl Fn(&ModelTool) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.tool_manager.set_filter(filter);
+        self
+    }
+
+    /// Enables or disables the dry-run mode.
+    ///
+    /// In dry-run mode, tool call requests still go through the approval
+    /// flow, but approved tools are not actually executed. Instead, a
+    /// synthetic result describing the call is returned to the model.
+    #[inline]
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.tool_manager.set_dry_run(dry_run);
+        self
+    }
+
     /// Registers a tool.
     #[inline]
     pub fn with_tool<T: Tool>(mut self, tool: T) -> Self {
@@ -91,6 +537,31 @@ impl AgentBuilder {
         self
     }
 
+    /// Registers a tool with a different description, which is sent to the
+    /// model instead of [`Tool::description`].
+    ///
+    /// This is useful when the same tool needs a description that depends
+    /// on the context, e.g. mentioning the project name.
+    #[inline]
+    pub fn with_tool_described<T: Tool, S: Into<String>>(
+        mut self,
+        tool: T,
+        description: S,
+    ) -> Self {
+        self.tool_manager
+            .add_tool_described(tool, description.into());
+        self
+    }
+
+    /// Returns a handle to the execution metrics of the registered tools.
+    ///
+    /// The handle stays valid after the agent is built, and the metrics are
+    /// updated whenever a tool finishes executing.
+    #[inline]
+    pub fn tool_metrics(&self) -> ToolMetrics {
+        self.tool_manager.metrics().clone()
+    }
+
     /// Builds the agent.
     #[inline]
     pub fn build(self) -> Agent {
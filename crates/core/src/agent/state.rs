@@ -1,17 +1,25 @@
 use std::fmt::{self, Debug};
 use std::future::Future;
+use std::time::Duration;
 
 use little_agent_actor::{Actor, Message};
 use little_agent_model::{
-    ModelFinishReason, ModelMessage, ModelProviderError, ModelRequest,
-    ToolCallRequest, ToolCallResult,
+    ErrorKind, ModelFinishReason, ModelMessage, ModelProviderError,
+    ModelRequest, OpaqueMessage, ToolCallProgress, ToolCallRequest,
+    ToolCallResult, Usage,
 };
-use tokio::time::sleep;
+use tokio::sync::oneshot;
 
-use super::{AgentState, TranscriptSource};
-use crate::conversation::Item as ConversationItem;
-use crate::model_client::{ModelClient, ModelClientResponse};
-use crate::tool::ToolResult;
+use super::{
+    AgentState, CheckpointError, CheckpointId, TranscriptSource, TurnSummary,
+    UsageReport,
+};
+use crate::conversation::{Conversation, Item as ConversationItem};
+use crate::model_client::{ModelClientError, ModelClientResponse};
+use crate::tool::{
+    Error as ToolError, ProvisionalApproval, ToolOutput, ToolResult,
+    ToolsetChange,
+};
 
 #[derive(Clone, Copy, Default, PartialEq, Eq)]
 pub enum AgentStage {
@@ -24,19 +32,155 @@ pub enum AgentStage {
 impl AgentState {
     #[inline]
     fn enqueue_user_input(&mut self, input: String, handle: &Actor<Self>) {
-        if self.current_stage != AgentStage::Idle {
-            // If we are not in idle stage, just enqueue the input and
-            // do nothing else.
+        if self.is_turn_limit_reached() {
+            warn!("turn limit reached, refusing the input");
+            if let Some(on_limit_reached) = &self.on_limit_reached {
+                on_limit_reached();
+            }
+            return;
+        }
+        if self.is_duplicate_input(&input) {
+            debug!("dropping a duplicate input: {input}");
+            return;
+        }
+        self.last_input = Some((input.clone(), self.clock.now()));
+        if self.current_stage != AgentStage::Idle || self.paused {
+            // If we are not in idle stage (or paused), just enqueue the input
+            // and do nothing else.
             self.pending_inputs.push_back(input);
             return;
         }
         self.process_input_checked(input, handle);
     }
 
+    fn set_paused(&mut self, paused: bool, handle: &Actor<Self>) {
+        if self.paused == paused {
+            return;
+        }
+        debug!("agent paused: {paused}");
+        self.paused = paused;
+        if let Some(on_pause_changed) = &self.on_pause_changed {
+            on_pause_changed(paused);
+        }
+        if paused || self.current_stage != AgentStage::Idle {
+            return;
+        }
+        if let Some(input) = self.pending_inputs.pop_front() {
+            self.process_input_checked(input, handle);
+        }
+    }
+
+    fn checkpoint(&mut self) -> Result<CheckpointId, CheckpointError> {
+        if self.current_stage != AgentStage::Idle {
+            return Err(CheckpointError::Busy);
+        }
+        let id = CheckpointId(self.next_checkpoint_id);
+        self.next_checkpoint_id += 1;
+        self.checkpoints.insert(id, self.conversation.items.clone());
+        Ok(id)
+    }
+
+    fn rollback(&mut self, id: CheckpointId) -> Result<(), CheckpointError> {
+        if self.current_stage != AgentStage::Idle {
+            return Err(CheckpointError::Busy);
+        }
+        let items =
+            self.checkpoints.get(&id).ok_or(CheckpointError::NotFound)?;
+        debug!("rolling back to checkpoint {id:?}");
+        self.conversation.items = items.clone();
+        // The later checkpoints belong to the discarded history.
+        self.checkpoints.retain(|other, _| other.0 <= id.0);
+        if let Some(on_conversation_reset) = &self.on_conversation_reset {
+            on_conversation_reset(&self.conversation);
+        }
+        Ok(())
+    }
+
+    fn record_usage(&mut self, usage: Usage) {
+        let cost = self
+            .pricing
+            .as_ref()
+            .and_then(|pricing| pricing.cost_of(&usage));
+        self.total_cost += cost.unwrap_or_default();
+        if let Some(on_usage) = &self.on_usage {
+            on_usage(&UsageReport {
+                usage,
+                cost,
+                total_cost: self.total_cost,
+            });
+        }
+    }
+
+    #[inline]
+    fn update_toolset(&mut self, change: ToolsetChange) {
+        if self.current_stage != AgentStage::Idle {
+            // Changing the toolset in the middle of a turn may confuse the
+            // model, defer it to the next turn.
+            self.pending_toolset_changes.push(change);
+            return;
+        }
+        self.tool_manager.apply_change(change);
+    }
+
+    fn apply_pending_toolset_changes(&mut self) {
+        for change in self.pending_toolset_changes.drain(..) {
+            self.tool_manager.apply_change(change);
+        }
+    }
+
+    /// Returns `true` if the input is identical to the last one, and arrives
+    /// within the dedup window while a turn is active.
+    fn is_duplicate_input(&self, input: &str) -> bool {
+        let Some(window) = self.input_dedup_window else {
+            return false;
+        };
+        if self.current_stage == AgentStage::Idle {
+            return false;
+        }
+        let Some((last_input, received_at)) = &self.last_input else {
+            return false;
+        };
+        last_input == input
+            && self.clock.now().duration_since(*received_at) < window
+    }
+
+    #[inline]
+    fn is_turn_limit_reached(&self) -> bool {
+        self.max_turns
+            .is_some_and(|max_turns| self.completed_turns >= max_turns)
+    }
+
+    fn complete_turn(&mut self, handle: &Actor<Self>) {
+        self.completed_turns += 1;
+        if self.is_turn_limit_reached() {
+            let dropped = self.pending_inputs.len();
+            self.pending_inputs.clear();
+            debug!("turn limit reached, dropped {dropped} pending inputs");
+            if let Some(on_limit_reached) = &self.on_limit_reached {
+                on_limit_reached();
+            }
+        }
+        self.complete_agent_loop(handle);
+    }
+
     fn complete_agent_loop(&mut self, handle: &Actor<Self>) {
-        let Some(input) = self.pending_inputs.pop_front() else {
-            // Nothing to process, so we can become idle.
+        if let Some(turn) = self.current_turn.take() {
+            if let Some(on_turn_end) = &self.on_turn_end {
+                on_turn_end(&turn);
+            }
+        }
+
+        let input = if self.paused {
+            None
+        } else {
+            self.pending_inputs.pop_front()
+        };
+        let Some(input) = input else {
+            // Nothing to process (or paused), so we can become idle.
             self.current_stage = AgentStage::Idle;
+            // Apply the deferred toolset changes now, the changes made while
+            // idle are applied right away and must not be overridden by them.
+            self.apply_pending_toolset_changes();
             if let Some(on_idle) = &self.on_idle {
                 on_idle();
             }
@@ -48,6 +192,10 @@ impl AgentState {
     /// Process the input string, assuming the stage is checked.
     fn process_input_checked(&mut self, input: String, handle: &Actor<Self>) {
         self.retry_backoff.reset();
+        self.rate_limit_retries = 0;
+
+        // A new turn begins, apply the deferred toolset changes.
+        self.apply_pending_toolset_changes();
 
         // Also invoke the transcript callback for user input, which can make
         // the messages in the conversation ordered correctly.
@@ -56,14 +204,68 @@ impl AgentState {
         }
 
         // Insert the message to the conversation.
-        self.conversation.items.push(ConversationItem {
-            msg: ModelMessage::User(input.clone()),
-            transcript: input,
-        });
+        self.push_conversation_item(ConversationItem::new(
+            ModelMessage::User(input.clone()),
+            input,
+        ));
 
         self.request_model_checked(handle);
     }
 
+    fn interrupt(&mut self, handle: &Actor<Self>) {
+        if self.current_stage == AgentStage::Idle {
+            return;
+        }
+        debug!("interrupting the current turn");
+
+        // Messages sent by the tasks of the interrupted turn are ignored
+        // from now on.
+        self.generation += 1;
+        self.model_client.abort_current();
+        for (_, task) in self.running_tasks.drain() {
+            task.abort();
+        }
+
+        // Every tool call needs a result, otherwise the model may reject
+        // the conversation later.
+        for result in self.pending_tool_results.values_mut() {
+            result.get_or_insert_with(|| Err(ToolError::interrupted()));
+        }
+        self.flush_tool_results();
+        self.complete_agent_loop(handle);
+    }
+
+    /// Adds the tool results to the conversation, assuming all of them are
+    /// finished.
+    fn flush_tool_results(&mut self) {
+        let results: Vec<_> = self.pending_tool_results.drain().collect();
+        for (id, result) in results {
+            let result = result.expect("tool call should be finished");
+            let (content, is_err) = match result {
+                Ok(res) => (res, false),
+                Err(err) => (error_output(&err), true),
+            };
+            let transcript = if is_err {
+                format!("Failed to run a tool, error: {content}")
+            } else {
+                format!("Ran a tool, result:\n{content}")
+            };
+            if let Some(on_transcript) = &self.on_transcript {
+                on_transcript(&transcript, TranscriptSource::Tool);
+            }
+            let msg = ModelMessage::Tool(ToolCallResult { id, content });
+            let conversation_item = ConversationItem::new(msg, transcript);
+            self.push_conversation_item(conversation_item);
+        }
+    }
+
+    fn push_conversation_item(&mut self, item: ConversationItem) {
+        if let Some(on_conversation_item) = &self.on_conversation_item {
+            on_conversation_item(&item);
+        }
+        self.conversation.items.push(item);
+    }
+
     fn handle_tool_call_requests(
         &mut self,
         requests: Vec<ToolCallRequest>,
@@ -73,15 +275,24 @@ impl AgentState {
         self.tool_manager.handle_requests(requests, |id, fut| {
             tool_calls.push((id, fut));
         });
+        if tool_calls.is_empty() {
+            // All the calls are dropped, there is nothing to wait for.
+            self.complete_turn(handle);
+            return;
+        }
         for (id, fut) in tool_calls {
             self.pending_tool_results.insert(id.clone(), None);
             let handle_clone = handle.clone();
+            let generation = self.generation;
             self.spawn_task(
                 |_| async move {
                     let result = fut.await;
-                    handle_clone
-                        .send(ToolCallFinishedMessage { id, result })
-                        .ok();
+                    let msg = ToolCallFinishedMessage {
+                        generation,
+                        id,
+                        result,
+                    };
+                    handle_clone.send(msg).ok();
                 },
                 handle,
             );
@@ -93,11 +304,22 @@ impl AgentState {
         err: Box<dyn ModelProviderError>,
         handle: &Actor<Self>,
     ) {
+        let kind = err.kind();
+        let retry_after = err.retry_after();
+        if kind == ErrorKind::ContextLengthExceeded && self.compact() {
+            self.request_model_checked(handle);
+            return;
+        }
         if let Some(on_error) = &self.on_error {
             on_error(err);
         }
 
-        let Some(timeout) = self.retry_backoff.next_backoff() else {
+        if !kind.is_retryable() {
+            debug!("model request failed with a non-retryable error");
+            self.complete_agent_loop(handle);
+            return;
+        }
+        let Some(timeout) = self.next_retry_delay(kind, retry_after) else {
             // Maximum retries reached, abort.
             self.complete_agent_loop(handle);
             return;
@@ -105,42 +327,117 @@ impl AgentState {
 
         // We leave the agent stage unchanged, so the further operations won't
         // jump in while we're waiting for the retry.
+        let sleep = self.clock.sleep(timeout);
+        let generation = self.generation;
         self.spawn_task(
             {
                 let handle = handle.clone();
                 |_| async move {
-                    sleep(timeout).await;
-                    handle.send(RetryMessage).ok();
+                    sleep.await;
+                    handle.send(RetryMessage(generation)).ok();
                 }
             },
             handle,
         );
     }
 
+    /// Compacts the conversation, returns `false` if there is no compactor
+    /// or it has been tried since the last successful request.
+    fn compact(&mut self) -> bool {
+        let Some(compactor) = &self.compactor else {
+            return false;
+        };
+        if self.compacted {
+            debug!("context length exceeded again after compacting");
+            return false;
+        }
+        self.compacted = true;
+        let len = self.conversation.len();
+        compactor(&mut self.conversation);
+        debug!(
+            "context length exceeded, compacted the conversation from {len} \
+             to {} items",
+            self.conversation.len()
+        );
+        if let Some(on_conversation_reset) = &self.on_conversation_reset {
+            on_conversation_reset(&self.conversation);
+        }
+        true
+    }
+
+    fn next_retry_delay(
+        &mut self,
+        kind: ErrorKind,
+        retry_after: Option<Duration>,
+    ) -> Option<Duration> {
+        if kind != ErrorKind::RateLimitExceeded {
+            return self.retry_backoff.next_backoff();
+        }
+
+        self.rate_limit_retries += 1;
+        if self.rate_limit_retries > self.max_rate_limit_retries {
+            debug!("too many rate limit errors, giving up");
+            return None;
+        }
+        match retry_after {
+            Some(retry_after) => Some(retry_after.min(self.max_retry_after)),
+            None => self.retry_backoff.next_backoff(),
+        }
+    }
+
     /// Request the model with the current conversation, assuming the
     /// stage is checked.
     fn request_model_checked(&mut self, handle: &Actor<Self>) {
         self.current_stage = AgentStage::ModelThinking;
+        if self.current_turn.is_none() {
+            self.current_turn = Some(TurnSummary::default());
+            if let Some(on_turn_start) = &self.on_turn_start {
+                on_turn_start();
+            }
+        }
+
+        if let Some(on_waiting) = &self.on_waiting {
+            on_waiting();
+        }
 
         let request = self.build_model_request();
-        let model_client = self
-            .model_client
-            .take()
-            .expect("model client is already in use");
+        let model_client = self.model_client.clone();
+        let generation = self.generation;
         let on_transcript = {
             let handle = handle.clone();
             move |transcript| {
-                let msg = TranscriptGeneratedMessage(transcript);
+                let msg = TranscriptGeneratedMessage(generation, transcript);
                 handle.send(msg).ok();
             }
         };
+        // Only ask for the progress when someone is listening.
+        let on_tool_call_progress =
+            self.on_provisional_approval.as_ref().map(|_| {
+                let handle = handle.clone();
+                move |progress| {
+                    let msg = ToolCallProgressMessage(generation, progress);
+                    handle.send(msg).ok();
+                }
+            });
         let task = {
             let handle = handle.clone();
             |_| async move {
-                let resp_res =
-                    model_client.send_request(request, on_transcript).await;
+                let resp_res = match on_tool_call_progress {
+                    Some(on_progress) => {
+                        model_client
+                            .send_request_with_progress(
+                                request,
+                                on_transcript,
+                                on_progress,
+                            )
+                            .await
+                    }
+                    None => {
+                        model_client.send_request(request, on_transcript).await
+                    }
+                };
                 let msg = ModelClientRequestFinishedMessage {
-                    model_client,
+                    generation,
                     response: resp_res,
                 };
                 handle.send(msg).ok();
@@ -150,14 +447,24 @@ impl AgentState {
     }
 
     fn build_model_request(&self) -> ModelRequest {
-        let messages = self
-            .conversation
-            .items
-            .iter()
-            .map(|item| item.msg.clone())
-            .collect();
+        let mut messages = match &self.history_transform {
+            Some(transform) => transform(&self.conversation),
+            None => self.conversation.to_model_messages(),
+        };
+        if let Some(turn_context) = &self.turn_context {
+            let insert_at = messages
+                .iter()
+                .position(|msg| !matches!(msg, ModelMessage::System(_)))
+                .unwrap_or(messages.len());
+            messages.splice(insert_at..insert_at, turn_context());
+        }
         let tools = self.tool_manager.definitions();
-        ModelRequest { messages, tools }
+        ModelRequest {
+            messages,
+            tools,
+            model: None,
+            extra: Default::default(),
+        }
     }
 
     fn spawn_task<F, Fut>(&mut self, f: F, handle: &Actor<Self>)
@@ -188,19 +495,124 @@ impl Message<AgentState> for EnqueueUserInput {
 }
 
 #[derive(Debug)]
-struct TranscriptGeneratedMessage(pub String);
+pub struct EnqueueUserInputs(pub Vec<String>);
+
+impl Message<AgentState> for EnqueueUserInputs {
+    fn handle(self, state: &mut AgentState, handle: &Actor<AgentState>) {
+        for input in self.0 {
+            state.enqueue_user_input(input, handle);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct UpdateToolset(pub ToolsetChange);
+
+impl Message<AgentState> for UpdateToolset {
+    fn handle(self, state: &mut AgentState, _handle: &Actor<AgentState>) {
+        state.update_toolset(self.0);
+    }
+}
+
+#[derive(Debug)]
+pub struct Interrupt;
+
+impl Message<AgentState> for Interrupt {
+    fn handle(self, state: &mut AgentState, handle: &Actor<AgentState>) {
+        state.interrupt(handle);
+    }
+}
+
+#[derive(Debug)]
+pub struct SetPaused(pub bool);
+
+impl Message<AgentState> for SetPaused {
+    fn handle(self, state: &mut AgentState, handle: &Actor<AgentState>) {
+        state.set_paused(self.0, handle);
+    }
+}
+
+#[derive(Debug)]
+pub struct HealthCheck(
+    pub oneshot::Sender<Result<(), Box<dyn ModelProviderError>>>,
+);
+
+impl Message<AgentState> for HealthCheck {
+    fn handle(self, state: &mut AgentState, _handle: &Actor<AgentState>) {
+        // The check is independent of the turns, so it's not tracked as a
+        // running task.
+        let model_client = state.model_client.clone();
+        tokio::spawn(async move {
+            self.0.send(model_client.health_check().await).ok();
+        });
+    }
+}
+
+#[derive(Debug)]
+pub struct Checkpoint(
+    pub oneshot::Sender<Result<CheckpointId, CheckpointError>>,
+);
+
+impl Message<AgentState> for Checkpoint {
+    fn handle(self, state: &mut AgentState, _handle: &Actor<AgentState>) {
+        self.0.send(state.checkpoint()).ok();
+    }
+}
+
+#[derive(Debug)]
+pub struct Rollback(
+    pub CheckpointId,
+    pub oneshot::Sender<Result<(), CheckpointError>>,
+);
+
+impl Message<AgentState> for Rollback {
+    fn handle(self, state: &mut AgentState, _handle: &Actor<AgentState>) {
+        self.1.send(state.rollback(self.0)).ok();
+    }
+}
+
+#[derive(Debug)]
+struct TranscriptGeneratedMessage(u64, String);
 
 impl Message<AgentState> for TranscriptGeneratedMessage {
     fn handle(self, state: &mut AgentState, _handle: &Actor<AgentState>) {
+        if self.0 != state.generation {
+            return;
+        }
         if let Some(on_transcript) = &state.on_transcript {
-            on_transcript(&self.0, TranscriptSource::Assistant);
+            on_transcript(&self.1, TranscriptSource::Assistant);
         }
     }
 }
 
+#[derive(Debug)]
+struct ToolCallProgressMessage(u64, ToolCallProgress);
+
+impl Message<AgentState> for ToolCallProgressMessage {
+    fn handle(self, state: &mut AgentState, _handle: &Actor<AgentState>) {
+        if self.0 != state.generation {
+            return;
+        }
+        let Some(on_provisional_approval) = &state.on_provisional_approval
+        else {
+            return;
+        };
+        let ToolCallProgress {
+            id,
+            name,
+            partial_arguments,
+        } = self.1;
+        on_provisional_approval(&ProvisionalApproval {
+            tool_call_id: id,
+            tool_name: name,
+            partial_arguments,
+        });
+    }
+}
+
 struct ModelClientRequestFinishedMessage {
-    model_client: ModelClient,
-    response: Result<ModelClientResponse, Box<dyn ModelProviderError>>,
+    generation: u64,
+    response: Result<ModelClientResponse, ModelClientError>,
 }
 
 impl Debug for ModelClientRequestFinishedMessage {
@@ -213,39 +625,115 @@ impl Debug for ModelClientRequestFinishedMessage {
 
 impl Message<AgentState> for ModelClientRequestFinishedMessage {
     fn handle(self, state: &mut AgentState, handle: &Actor<AgentState>) {
-        // Release the model client.
-        state.model_client = Some(self.model_client);
+        if self.generation != state.generation {
+            // The turn has been interrupted.
+            return;
+        }
 
-        let resp = match self.response {
+        let mut resp = match self.response {
             Ok(resp) => resp,
             Err(err) => {
-                state.handle_model_request_error(err, handle);
+                // The partial response is discarded, since the request will
+                // be retried from scratch.
+                state.handle_model_request_error(err.error, handle);
                 return;
             }
         };
+        state.compacted = false;
+        if let Some(usage) = resp.usage.take() {
+            state.record_usage(usage);
+        }
 
         // Insert the message to the conversation.
         let transcript = resp.transcript;
-        let msg = if let Some(opaque_msg) = resp.opaque_msg {
-            ModelMessage::Opaque(opaque_msg)
+        if transcript.trim().is_empty() && resp.tool_calls.is_empty() {
+            // Nothing worth replaying, and some providers reject the empty
+            // assistant messages.
+            debug!("skipping an empty assistant message");
         } else {
-            // Downgrade to a text-only message.
-            ModelMessage::Assistant(transcript.clone())
-        };
-        let conversation_item = ConversationItem { msg, transcript };
-        state.conversation.items.push(conversation_item);
+            let msg = response_message(
+                &state.conversation,
+                resp.opaque_msg,
+                &transcript,
+                &resp.tool_calls,
+            );
+            let conversation_item = ConversationItem::new(msg, transcript);
+            state.push_conversation_item(conversation_item);
+        }
+
+        if resp.finish_reason == Some(ModelFinishReason::Length) {
+            warn!("model response was truncated due to the token limit");
+        }
 
         // Check if we need to execute tools.
         let should_run_tools = resp.finish_reason
             == Some(ModelFinishReason::ToolCalls)
             && !resp.tool_calls.is_empty();
+        if let Some(turn) = &mut state.current_turn {
+            turn.finish_reason = resp.finish_reason;
+            turn.tools_ran |= should_run_tools;
+        }
         if should_run_tools {
             state.current_stage = AgentStage::RunningTools;
             state.handle_tool_call_requests(resp.tool_calls, handle);
         } else {
-            // No tools to execute, complete the loop;
-            state.complete_agent_loop(handle);
+            // No tools to execute, complete the turn.
+            state.complete_turn(handle);
+        }
+    }
+}
+
+/// Makes the message of a response to insert into the conversation.
+///
+/// Exactly one of the opaque message and the transcript is sent to the model
+/// later, never both.
+fn response_message(
+    conversation: &Conversation,
+    opaque_msg: Option<OpaqueMessage>,
+    transcript: &str,
+    tool_calls: &[ToolCallRequest],
+) -> ModelMessage {
+    match opaque_msg {
+        Some(opaque_msg) if conversation.contains_opaque(opaque_msg.id()) => {
+            // Opaque messages are identified by their ids, a duplicate one
+            // indicates a buggy provider.
+            warn!(
+                "duplicate opaque message id {:?}, \
+                 falling back to the transcript",
+                opaque_msg.id()
+            );
+            fallback_message(transcript, tool_calls)
         }
+        Some(opaque_msg) => ModelMessage::Opaque(opaque_msg),
+        None => fallback_message(transcript, tool_calls),
+    }
+}
+
+/// Makes a message from the response parts, used when the response has no
+/// usable opaque message.
+fn fallback_message(
+    transcript: &str,
+    tool_calls: &[ToolCallRequest],
+) -> ModelMessage {
+    if tool_calls.is_empty() {
+        // Downgrade to a text-only message.
+        return ModelMessage::Assistant(transcript.to_owned());
+    }
+    // Keep the tool calls, so the following tool results can be matched.
+    ModelMessage::AssistantToolCalls {
+        content: transcript.to_owned(),
+        tool_calls: tool_calls.to_vec(),
+    }
+}
+
+/// Makes the output of a failed tool call, annotated when it's worth
+/// retrying.
+fn error_output(err: &ToolError) -> ToolOutput {
+    let reason = err.reason();
+    if err.is_retryable() {
+        ToolOutput::Text(format!("[retryable]\n{reason}"))
+    } else {
+        ToolOutput::Text(reason.into_owned())
     }
 }
 
@@ -255,21 +743,27 @@ struct TaskEndedMessage(u64);
 impl Message<AgentState> for TaskEndedMessage {
     #[inline]
     fn handle(self, state: &mut AgentState, _handle: &Actor<AgentState>) {
-        state
-            .running_tasks
-            .remove(&self.0)
-            .expect("internal state is inconsistent");
+        // The task may have been removed if the turn was interrupted right
+        // after it ended.
+        if state.running_tasks.remove(&self.0).is_none() {
+            trace!("task {} ended after being interrupted", self.0);
+        }
     }
 }
 
 #[derive(Debug)]
 struct ToolCallFinishedMessage {
+    generation: u64,
     id: String,
     result: ToolResult,
 }
 
 impl Message<AgentState> for ToolCallFinishedMessage {
     fn handle(self, state: &mut AgentState, handle: &Actor<AgentState>) {
+        if self.generation != state.generation {
+            // The turn has been interrupted.
+            return;
+        }
         let Some(result) = state.pending_tool_results.get_mut(&self.id) else {
             debug_assert!(false, "internal state is inconsistent");
             return;
@@ -281,22 +775,7 @@ impl Message<AgentState> for ToolCallFinishedMessage {
             return;
         }
 
-        // Add the tool results to the conversation.
-        for (id, result) in state.pending_tool_results.drain() {
-            let result = result.unwrap();
-            let (content, is_err) = match result {
-                Ok(res) => (res, false),
-                Err(err) => (err.reason().into_owned(), true),
-            };
-            let transcript = if is_err {
-                format!("Failed to run a tool, error: {content}")
-            } else {
-                format!("Ran a tool, result:\n{content}")
-            };
-            let msg = ModelMessage::Tool(ToolCallResult { id, content });
-            let conversation_item = ConversationItem { msg, transcript };
-            state.conversation.items.push(conversation_item);
-        }
+        state.flush_tool_results();
 
         // Now, proceed to the next turn directly.
         state.request_model_checked(handle);
@@ -304,10 +783,14 @@ impl Message<AgentState> for ToolCallFinishedMessage {
 }
 
 #[derive(Debug)]
-struct RetryMessage;
+struct RetryMessage(u64);
 
 impl Message<AgentState> for RetryMessage {
     fn handle(self, state: &mut AgentState, handle: &Actor<AgentState>) {
+        if self.0 != state.generation {
+            // The turn has been interrupted.
+            return;
+        }
         state.request_model_checked(handle);
     }
 }
@@ -1,16 +1,26 @@
 use std::future::ready;
+use std::pin::Pin;
 use std::sync::atomic::{self, AtomicBool};
 use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
 use std::time::Duration;
 
-use little_agent_model::ToolCallRequest;
+use little_agent_model::{
+    ErrorKind, ModelMessage, ModelProvider, ModelRequest, ModelResponse,
+    ModelResponseEvent, OpaqueMessage, ToolCallProgress, ToolCallRequest,
+    Usage,
+};
 use little_agent_test_model::{PresetEvent, PresetResponse, TestModelProvider};
 use serde_json::{Value, json};
 use tokio::sync::watch;
 use tokio::time::timeout;
 
-use crate::AgentBuilder;
-use crate::tool::{Approval, Error as ToolError, Tool, ToolResult};
+use super::{CheckpointError, CheckpointId};
+use crate::clock::MockClock;
+use crate::conversation::{Conversation, Item as ConversationItem};
+use crate::pricing::{ModelPrice, PricingTable};
+use crate::tool::{Approval, Error as ToolError, Tool, ToolOutput, ToolResult};
+use crate::{AgentBuilder, AgentDeadError, TranscriptSource};
 
 #[tokio::test]
 async fn test_simple_message() {
@@ -35,7 +45,7 @@ async fn test_simple_message() {
             idle_tx.send(true).unwrap();
         })
         .build();
-    agent.enqueue_user_input("Hello");
+    agent.enqueue_user_input("Hello").unwrap();
 
     timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
         .await
@@ -76,7 +86,7 @@ impl Tool for ListTodosTool {
         &self,
         _input: Self::Input,
     ) -> impl Future<Output = ToolResult> + Send + 'static {
-        ready(Ok("Found 0 todos".to_owned()))
+        ready(Ok("Found 0 todos".into()))
     }
 }
 
@@ -134,6 +144,7 @@ async fn test_tool_call() {
     ]));
 
     let tool_call_requests = Arc::new(Mutex::new(vec![]));
+    let tool_transcripts = Arc::new(Mutex::new(vec![]));
     let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
 
     let agent = AgentBuilder::with_model_provider(model_provider)
@@ -149,11 +160,22 @@ async fn test_tool_call() {
                 request.approve();
             }
         })
+        .on_transcript({
+            let tool_transcripts = Arc::clone(&tool_transcripts);
+            move |transcript, source| {
+                if source == TranscriptSource::Tool {
+                    tool_transcripts
+                        .lock()
+                        .unwrap()
+                        .push(transcript.to_owned());
+                }
+            }
+        })
         .on_idle(move || {
             idle_tx.send(true).unwrap();
         })
         .build();
-    agent.enqueue_user_input("Hello");
+    agent.enqueue_user_input("Hello").unwrap();
 
     timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
         .await
@@ -164,9 +186,212 @@ async fn test_tool_call() {
     assert_eq!(tool_call_requests.len(), 2);
     assert_eq!(tool_call_requests[0], "Lists all todos");
     assert_eq!(tool_call_requests[1], "Lists all calendar events");
+
+    let mut tool_transcripts = tool_transcripts.lock().unwrap();
+    tool_transcripts.sort();
+    assert_eq!(tool_transcripts.len(), 2);
+    assert!(tool_transcripts[0].starts_with("Failed to run a tool"));
+    assert_eq!(tool_transcripts[1], "Ran a tool, result:\nFound 0 todos");
+}
+
+#[tokio::test]
+async fn test_approval_tool_name() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "list_todos".to_owned(),
+            arguments: json!({}),
+        }),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Done".to_owned()),
+    ]));
+
+    let requests = Arc::new(Mutex::new(vec![]));
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider)
+        .with_tool(ListTodosTool)
+        .on_tool_call_request({
+            let requests = Arc::clone(&requests);
+            move |request| {
+                requests.lock().unwrap().push((
+                    request.tool_name().to_owned(),
+                    request.tool_call_id().to_owned(),
+                ));
+                request.approve();
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        *requests.lock().unwrap(),
+        [("list_todos".to_owned(), "tool:1".to_owned())]
+    );
+}
+
+/// A tool that doesn't need approvals.
+struct CountTodosTool;
+
+impl Tool for CountTodosTool {
+    type Input = Value;
+
+    fn name(&self) -> &str {
+        "count_todos"
+    }
+
+    fn description(&self) -> &str {
+        "Counts the todos"
+    }
+
+    fn parameter_schema(&self) -> &Value {
+        EMPTY_SCHEMA
+    }
+
+    fn make_approval(&self, _input: &Self::Input) -> Approval {
+        Approval::new(self.description(), "")
+    }
+
+    fn requires_approval(&self, _input: &Self::Input) -> bool {
+        false
+    }
+
+    fn execute(
+        &self,
+        _input: Self::Input,
+    ) -> impl Future<Output = ToolResult> + Send + 'static {
+        ready(Ok("0".into()))
+    }
+}
+
+#[tokio::test]
+async fn test_tool_without_approval() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "count_todos".to_owned(),
+            arguments: json!({}),
+        }),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("You have no todos.".to_owned()),
+    ]));
+
+    let decisions = Arc::new(Mutex::new(vec![]));
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_tool(CountTodosTool)
+        .on_tool_call_request(|_| panic!("no approval should be requested"))
+        .on_approval_decision({
+            let decisions = Arc::clone(&decisions);
+            move |decision| {
+                decisions.lock().unwrap().push(decision.clone());
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("How many todos?").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let decisions = decisions.lock().unwrap();
+    assert_eq!(decisions.len(), 1);
+    assert!(decisions[0].approved);
+    assert!(decisions[0].automatic);
+    let last_request = model_provider.requests().pop().unwrap();
+    assert!(matches!(
+        last_request.messages.last(),
+        Some(ModelMessage::Tool(result)) if result.content.to_text() == "0"
+    ));
+}
+
+#[tokio::test]
+async fn test_provisional_approval() {
+    let progress = |partial_arguments: &str| {
+        PresetEvent::ToolCallProgress(ToolCallProgress {
+            id: Some("tool:1".to_owned()),
+            name: "list_todos".to_owned(),
+            partial_arguments: partial_arguments.to_owned(),
+        })
+    };
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        progress("{"),
+        progress("{}"),
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "list_todos".to_owned(),
+            arguments: json!({}),
+        }),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Your todo is clean.".to_owned()),
+    ]));
+
+    let events = Arc::new(Mutex::new(vec![]));
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider)
+        .with_tool(ListTodosTool)
+        .on_provisional_approval({
+            let events = Arc::clone(&events);
+            move |approval| {
+                assert_eq!(approval.tool_call_id.as_deref(), Some("tool:1"));
+                assert_eq!(approval.tool_name, "list_todos");
+                let event =
+                    format!("provisional {}", approval.partial_arguments);
+                events.lock().unwrap().push(event);
+            }
+        })
+        .on_tool_call_request({
+            let events = Arc::clone(&events);
+            move |request| {
+                let event = format!("final {}", request.tool_call_id());
+                events.lock().unwrap().push(event);
+                request.approve();
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        ["provisional {", "provisional {}", "final tool:1"]
+    );
 }
 
 #[tokio::test(start_paused = true)]
+#[allow(clippy::bool_assert_comparison)]
 async fn test_retry() {
     let mut model_provider = TestModelProvider::default();
     model_provider.add_user_input_step();
@@ -200,7 +425,7 @@ async fn test_retry() {
             idle_tx.send(true).unwrap();
         })
         .build();
-    agent.enqueue_user_input("Hello");
+    agent.enqueue_user_input("Hello").unwrap();
 
     idle_rx.wait_for(|v| *v).await.unwrap();
 
@@ -208,3 +433,1442 @@ async fn test_retry() {
     assert_eq!(*transcripts, "Hi");
     assert_eq!(on_error_triggered.load(atomic::Ordering::Relaxed), true);
 }
+
+#[tokio::test]
+async fn test_compact_on_context_length_exceeded() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi".to_owned()),
+    ]));
+    model_provider.set_context_limit(2);
+
+    let on_error_triggered = Arc::new(AtomicBool::new(false));
+    let resets = Arc::new(Mutex::new(vec![]));
+    let (idle_tx, mut idle_rx) = watch::channel(0);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .on_conversation_reset({
+            let resets = Arc::clone(&resets);
+            move |conversation| {
+                resets.lock().unwrap().push(conversation.len());
+            }
+        })
+        .with_compactor(|conversation| {
+            // Keep the latest user input only.
+            let len = conversation.len();
+            let mut idx = 0;
+            conversation.retain(|_| {
+                idx += 1;
+                idx == len
+            });
+        })
+        .on_error({
+            let on_error_triggered = Arc::clone(&on_error_triggered);
+            move |_| {
+                on_error_triggered.store(true, atomic::Ordering::Relaxed);
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send_modify(|count| *count += 1);
+        })
+        .build();
+    for (idx, input) in ["Hello", "Hello again"].into_iter().enumerate() {
+        agent.enqueue_user_input(input).unwrap();
+        timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v > idx))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    let requests = model_provider.requests();
+    assert_eq!(requests.len(), 3);
+    // The second input overflowed the context, and was retried alone.
+    assert_eq!(requests[1].messages.len(), 3);
+    assert!(matches!(
+        requests[2].messages.as_slice(),
+        [ModelMessage::User(text)] if text == "Hello again"
+    ));
+    assert!(!on_error_triggered.load(atomic::Ordering::Relaxed));
+    assert_eq!(*resets.lock().unwrap(), [1]);
+}
+
+#[tokio::test]
+async fn test_retry_with_mock_clock() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(
+        PresetResponse::with_events([PresetEvent::MessageDelta(
+            "Hi".to_owned(),
+        )])
+        .with_failures(3),
+    );
+
+    let clock = MockClock::new();
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_clock(clock.clone())
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+
+    for attempt in 1..=3 {
+        // Wait for the agent to back off, then step over it.
+        timeout(Duration::from_millis(500), async {
+            while clock.pending_sleeps() == 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(model_provider.requests().len(), attempt);
+        clock.advance(Duration::from_secs(60));
+    }
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(model_provider.requests().len(), 4);
+    assert_eq!(clock.elapsed(), Duration::from_secs(180));
+}
+
+#[tokio::test(start_paused = true)]
+async fn test_non_retryable_error() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    // Requesting a user input step fails with an invalid request error.
+    model_provider.add_user_input_step();
+
+    let error_count = Arc::new(Mutex::new(0));
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .on_error({
+            let error_count = Arc::clone(&error_count);
+            move |_| {
+                *error_count.lock().unwrap() += 1;
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+
+    idle_rx.wait_for(|v| *v).await.unwrap();
+
+    assert_eq!(*error_count.lock().unwrap(), 1);
+    assert_eq!(model_provider.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_register_tool() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "list_todos".to_owned(),
+            arguments: json!({}),
+        }),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Your todo is clean.".to_owned()),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Your calendar is clean.".to_owned()),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi!".to_owned()),
+    ]));
+
+    let (idle_tx, mut idle_rx) = watch::channel::<usize>(0);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_tool(ListTodosTool)
+        .on_idle(move || {
+            idle_tx.send_modify(|count| *count += 1);
+        })
+        .build();
+    agent.enqueue_user_input("Check my todo").unwrap();
+    // The agent is running a turn, so the change should be deferred.
+    agent.register_tool(ListCalendarEventsTool).unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v == 1))
+        .await
+        .unwrap()
+        .unwrap();
+
+    agent.enqueue_user_input("Check my calendar").unwrap();
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v == 2))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let tool_names = model_provider
+        .requests()
+        .into_iter()
+        .map(|req| {
+            let mut names: Vec<_> =
+                req.tools.into_iter().map(|tool| tool.name).collect();
+            names.sort();
+            names
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        tool_names,
+        [
+            vec!["list_todos"],
+            vec!["list_todos"],
+            vec!["list_calendar_events", "list_todos"],
+        ]
+    );
+
+    agent.unregister_tool("list_todos").unwrap();
+    agent.enqueue_user_input("Hello").unwrap();
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v == 3))
+        .await
+        .unwrap()
+        .unwrap();
+    let last_request = model_provider.requests().pop().unwrap();
+    assert_eq!(last_request.tools.len(), 1);
+    assert_eq!(last_request.tools[0].name, "list_calendar_events");
+}
+
+#[tokio::test]
+async fn test_deferred_toolset_change_then_idle_change() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi!".to_owned()),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi again!".to_owned()),
+    ]));
+
+    let (idle_tx, mut idle_rx) = watch::channel::<usize>(0);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_tool(ListTodosTool)
+        .on_idle(move || {
+            idle_tx.send_modify(|count| *count += 1);
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+    // Deferred, since the agent is running a turn.
+    agent.register_tool(ListCalendarEventsTool).unwrap();
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v == 1))
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Applied right away, and must win over the deferred registration.
+    agent.unregister_tool("list_calendar_events").unwrap();
+    agent.enqueue_user_input("Hello again").unwrap();
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v == 2))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let last_request = model_provider.requests().pop().unwrap();
+    let tool_names: Vec<_> =
+        last_request.tools.iter().map(|tool| &tool.name).collect();
+    assert_eq!(tool_names, ["list_todos"]);
+}
+
+#[tokio::test]
+async fn test_max_turns() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi!".to_owned()),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi again!".to_owned()),
+    ]));
+
+    let (limit_tx, mut limit_rx) = watch::channel::<usize>(0);
+    let (idle_tx, mut idle_rx) = watch::channel::<usize>(0);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_max_turns(1)
+        .on_limit_reached(move || {
+            limit_tx.send_modify(|count| *count += 1);
+        })
+        .on_idle(move || {
+            idle_tx.send_modify(|count| *count += 1);
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+    // This input is queued, and should be dropped when the limit is reached.
+    agent.enqueue_user_input("Hello again").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v == 1))
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(*limit_rx.borrow(), 1);
+
+    // Further inputs should be refused, and reported.
+    agent.enqueue_user_input("Are you there?").unwrap();
+    timeout(Duration::from_millis(500), limit_rx.wait_for(|v| *v == 2))
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(model_provider.requests().len(), 1);
+    assert_eq!(*idle_rx.borrow(), 1);
+}
+
+#[test]
+#[should_panic(expected = "max_turns must be greater than zero")]
+fn test_zero_max_turns() {
+    let _ = AgentBuilder::with_model_provider(TestModelProvider::default())
+        .with_max_turns(0);
+}
+
+#[tokio::test]
+async fn test_turn_events() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi!".to_owned()),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "list_todos".to_owned(),
+            arguments: json!({}),
+        }),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Your todo is clean.".to_owned()),
+    ]));
+
+    let events = Arc::new(Mutex::new(vec![]));
+    let (idle_tx, mut idle_rx) = watch::channel::<usize>(0);
+
+    let agent = AgentBuilder::with_model_provider(model_provider)
+        .with_tool(ListTodosTool)
+        .on_turn_start({
+            let events = Arc::clone(&events);
+            move || {
+                events.lock().unwrap().push("start".to_owned());
+            }
+        })
+        .on_turn_end({
+            let events = Arc::clone(&events);
+            move |summary| {
+                events.lock().unwrap().push(format!(
+                    "end (tools_ran: {}, finish_reason: {:?})",
+                    summary.tools_ran, summary.finish_reason
+                ));
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send_modify(|count| *count += 1);
+        })
+        .build();
+
+    agent.enqueue_user_input("Hello").unwrap();
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v == 1))
+        .await
+        .unwrap()
+        .unwrap();
+    agent.enqueue_user_input("Check my todo").unwrap();
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v == 2))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(
+        *events,
+        [
+            "start",
+            "end (tools_ran: false, finish_reason: Some(Stop))",
+            "start",
+            "end (tools_ran: true, finish_reason: Some(Stop))",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_history_transform() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi!".to_owned()),
+    ]));
+
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    // Drop the system prompt, so the conversation matches the script.
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_system_prompt("You are a helpful assistant.")
+        .with_history_transform(|conversation| {
+            conversation
+                .to_model_messages()
+                .into_iter()
+                .filter(|msg| !matches!(msg, ModelMessage::System(_)))
+                .collect()
+        })
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let requests = model_provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert!(matches!(
+        requests[0].messages.as_slice(),
+        [ModelMessage::User(text)] if text == "Hello"
+    ));
+}
+
+/// A provider that overrides the opaque messages of the responses. It makes
+/// messages with the given id, or no messages if the id is `None`.
+struct OpaqueOverrideProvider(TestModelProvider, Option<&'static str>);
+
+struct OpaqueOverrideResponse<R>(Pin<Box<R>>, Option<&'static str>);
+
+impl ModelProvider for OpaqueOverrideProvider {
+    type Error = <TestModelProvider as ModelProvider>::Error;
+    type Response =
+        OpaqueOverrideResponse<<TestModelProvider as ModelProvider>::Response>;
+
+    fn send_request(
+        &self,
+        req: &ModelRequest,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
+    {
+        let fut = self.0.send_request(req);
+        let id = self.1;
+        async move { Ok(OpaqueOverrideResponse(Box::pin(fut.await?), id)) }
+    }
+}
+
+impl<R: ModelResponse> ModelResponse for OpaqueOverrideResponse<R> {
+    type Error = R::Error;
+
+    fn poll_next_event(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<ModelResponseEvent>, Self::Error>> {
+        self.get_mut().0.as_mut().poll_next_event(cx)
+    }
+
+    fn make_opaque_message(&self) -> Option<OpaqueMessage> {
+        self.1.map(|id| OpaqueMessage::new(id, ()))
+    }
+}
+
+#[tokio::test]
+async fn test_duplicate_opaque_id() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi!".to_owned()),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Bye!".to_owned()),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("...".to_owned()),
+    ]));
+
+    let (idle_tx, mut idle_rx) = watch::channel(0);
+
+    let agent = AgentBuilder::with_model_provider(OpaqueOverrideProvider(
+        model_provider.clone(),
+        Some("dup"),
+    ))
+    .on_idle(move || {
+        idle_tx.send_modify(|count| *count += 1);
+    })
+    .build();
+    for (idx, input) in ["Hello", "Goodbye", "Hey?"].into_iter().enumerate() {
+        agent.enqueue_user_input(input).unwrap();
+        timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v > idx))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    // The second response reuses the id, so it's downgraded to the
+    // transcript instead of being sent as a duplicate.
+    let last_request = model_provider.requests().pop().unwrap();
+    assert!(matches!(
+        last_request.messages.as_slice(),
+        [
+            ModelMessage::User(_),
+            ModelMessage::Opaque(msg),
+            ModelMessage::User(_),
+            ModelMessage::Assistant(text),
+            ModelMessage::User(_),
+        ] if msg.id() == "dup" && text == "Bye!"
+    ));
+}
+
+#[tokio::test]
+async fn test_tool_calls_without_opaque_message() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "list_todos".to_owned(),
+            arguments: Value::Null,
+        }),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("You have no todos.".to_owned()),
+    ]));
+
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(OpaqueOverrideProvider(
+        model_provider.clone(),
+        None,
+    ))
+    .with_tool(ListTodosTool)
+    .on_idle(move || {
+        idle_tx.send(true).unwrap();
+    })
+    .build();
+    agent.enqueue_user_input("Check my todo").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+
+    // The tool calls are kept, so the tool result has a matching call.
+    let last_request = model_provider.requests().pop().unwrap();
+    assert!(matches!(
+        last_request.messages.as_slice(),
+        [
+            ModelMessage::User(_),
+            ModelMessage::AssistantToolCalls { tool_calls, .. },
+            ModelMessage::Tool(result),
+        ] if tool_calls[0].id == "tool:1" && result.id == "tool:1"
+    ));
+}
+
+#[tokio::test]
+async fn test_empty_response_not_stored() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "list_todos".to_owned(),
+            arguments: Value::Null,
+        }),
+    ]));
+    model_provider.add_user_input_step();
+    // Neither text nor tool calls.
+    model_provider.add_assistant_response_step(PresetResponse::with_events([]));
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("You're welcome.".to_owned()),
+    ]));
+
+    let (idle_tx, mut idle_rx) = watch::channel(0);
+
+    // Replay the conversation as is, without the default filtering.
+    let agent = AgentBuilder::with_model_provider(OpaqueOverrideProvider(
+        model_provider.clone(),
+        None,
+    ))
+    .with_tool(ListTodosTool)
+    .with_history_transform(|conversation| {
+        conversation
+            .into_iter()
+            .map(|item| item.message().clone())
+            .collect()
+    })
+    .on_idle(move || {
+        idle_tx.send_modify(|count| *count += 1);
+    })
+    .build();
+    for (idx, input) in ["Check my todo", "Thanks"].into_iter().enumerate() {
+        agent.enqueue_user_input(input).unwrap();
+        timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v > idx))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    let last_request = model_provider.requests().pop().unwrap();
+    assert!(matches!(
+        last_request.messages.as_slice(),
+        [
+            ModelMessage::User(_),
+            ModelMessage::AssistantToolCalls { content, .. },
+            ModelMessage::Tool(_),
+            ModelMessage::User(text),
+        ] if content.is_empty() && text == "Thanks"
+    ));
+}
+
+struct ReadLogTool;
+
+impl Tool for ReadLogTool {
+    type Input = Value;
+
+    fn name(&self) -> &str {
+        "read_log"
+    }
+
+    fn description(&self) -> &str {
+        "Reads the log"
+    }
+
+    fn parameter_schema(&self) -> &Value {
+        EMPTY_SCHEMA
+    }
+
+    fn make_approval(&self, _input: &Self::Input) -> Approval {
+        Approval::new(self.description(), "")
+    }
+
+    fn execute(
+        &self,
+        input: Self::Input,
+    ) -> impl Future<Output = ToolResult> + Send + 'static {
+        let result = if input["busy"].as_bool().unwrap_or_default() {
+            Err(ToolError::execution_error()
+                .with_reason("Log is locked")
+                .with_retryable(true))
+        } else {
+            Ok(ToolOutput::from("line 1\nline 2").truncated())
+        };
+        ready(result)
+    }
+}
+
+#[tokio::test]
+async fn test_annotated_tool_results() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "read_log".to_owned(),
+            arguments: json!({}),
+        }),
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:2".to_owned(),
+            name: "read_log".to_owned(),
+            arguments: json!({ "busy": true }),
+        }),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Done".to_owned()),
+    ]));
+
+    let tool_results = Arc::new(Mutex::new(vec![]));
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider)
+        .with_tool(ReadLogTool)
+        .on_conversation_item({
+            let tool_results = Arc::clone(&tool_results);
+            move |item| {
+                if let ModelMessage::Tool(result) = item.message() {
+                    tool_results.lock().unwrap().push(result.clone());
+                }
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("Read the log").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let mut tool_results = tool_results.lock().unwrap().clone();
+    tool_results.sort_by(|a, b| a.id.cmp(&b.id));
+    assert_eq!(tool_results.len(), 2);
+    assert!(tool_results[0].content.is_truncated());
+    assert_eq!(
+        tool_results[0].content.to_text(),
+        "[truncated]\nline 1\nline 2"
+    );
+    assert_eq!(
+        tool_results[1].content.to_text(),
+        "[retryable]\nLog is locked"
+    );
+}
+
+#[tokio::test]
+async fn test_unknown_tool() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "delete_todos".to_owned(),
+            arguments: Value::Null,
+        }),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("I can't delete todos.".to_owned()),
+    ]));
+
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_tool(ListTodosTool)
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("Delete my todos").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let last_request = model_provider.requests().pop().unwrap();
+    let Some(ModelMessage::Tool(result)) = last_request.messages.last() else {
+        panic!("expected a tool result: {last_request:?}");
+    };
+    assert_eq!(result.id, "tool:1");
+    assert_eq!(result.content.to_text(), "unknown tool: delete_todos");
+}
+
+#[tokio::test]
+async fn test_tool_described() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi".to_owned()),
+    ]));
+
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_tool_described(ListTodosTool, "Lists the todos of little-agent")
+        .with_tool(ListCalendarEventsTool)
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let requests = model_provider.requests();
+    let mut descriptions: Vec<_> = requests[0]
+        .tools
+        .iter()
+        .map(|tool| (tool.name.as_str(), tool.description.as_str()))
+        .collect();
+    descriptions.sort();
+    assert_eq!(
+        descriptions,
+        [
+            ("list_calendar_events", "Lists all calendar events"),
+            ("list_todos", "Lists the todos of little-agent"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_tool_filter() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "list_calendar_events".to_owned(),
+            arguments: Value::Null,
+        }),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("I can't see your calendar.".to_owned()),
+    ]));
+
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_tool(ListTodosTool)
+        .with_tool(ListCalendarEventsTool)
+        .with_tool_filter(|tool| tool.name == "list_todos")
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("What's on my calendar?").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let requests = model_provider.requests();
+    let tool_names: Vec<_> =
+        requests[0].tools.iter().map(|tool| &tool.name).collect();
+    assert_eq!(tool_names, ["list_todos"]);
+
+    let last_request = requests.last().unwrap();
+    let Some(ModelMessage::Tool(result)) = last_request.messages.last() else {
+        panic!("expected a tool result: {last_request:?}");
+    };
+    assert_eq!(result.id, "tool:1");
+    assert_eq!(
+        result.content.to_text(),
+        "tool is not available: list_calendar_events"
+    );
+}
+
+#[tokio::test]
+async fn test_rate_limit_retries() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(
+        PresetResponse::with_events([PresetEvent::MessageDelta(
+            "Hi".to_owned(),
+        )])
+        .with_failures(0)
+        .with_retry_after(Duration::from_secs(3600)),
+    );
+
+    let clock = MockClock::new();
+    let error_count = Arc::new(Mutex::new(0));
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_clock(clock.clone())
+        .with_rate_limit_retries(2, Duration::from_secs(10))
+        .on_error({
+            let error_count = Arc::clone(&error_count);
+            move |_| {
+                *error_count.lock().unwrap() += 1;
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+
+    for attempt in 1..=2 {
+        timeout(Duration::from_millis(500), async {
+            while clock.pending_sleeps() == 0 {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(model_provider.requests().len(), attempt);
+        // The capped delay is enough to trigger the retry.
+        clock.advance(Duration::from_secs(10));
+    }
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(model_provider.requests().len(), 3);
+    assert_eq!(*error_count.lock().unwrap(), 3);
+    assert_eq!(clock.elapsed(), Duration::from_secs(20));
+}
+
+#[tokio::test]
+async fn test_rate_limit_retry_after() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(
+        PresetResponse::with_events([PresetEvent::MessageDelta(
+            "Hi".to_owned(),
+        )])
+        .with_failures(1)
+        .with_retry_after(Duration::from_secs(5)),
+    );
+
+    let clock = MockClock::new();
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_clock(clock.clone())
+        .with_rate_limit_retries(2, Duration::from_secs(60))
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+
+    timeout(Duration::from_millis(500), async {
+        while clock.pending_sleeps() == 0 {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .unwrap();
+
+    // The delay suggested by the provider is honored.
+    clock.advance(Duration::from_secs(4));
+    assert_eq!(clock.pending_sleeps(), 1);
+    assert_eq!(model_provider.requests().len(), 1);
+    clock.advance(Duration::from_secs(1));
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(model_provider.requests().len(), 2);
+    assert_eq!(clock.elapsed(), Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn test_input_dedup() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi".to_owned()),
+    ]));
+    model_provider.set_delay(Duration::from_millis(20));
+
+    let (turns_tx, mut turns_rx) = watch::channel(0);
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_input_dedup_window(Duration::from_secs(1))
+        .on_turn_end(move |_| {
+            turns_tx.send_modify(|turns| *turns += 1);
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+    agent.enqueue_user_input("Hello").unwrap();
+
+    timeout(
+        Duration::from_millis(500),
+        turns_rx.wait_for(|turns| *turns > 0),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    // Give the duplicate a chance to run, if it was not dropped.
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(*turns_rx.borrow(), 1);
+    assert_eq!(model_provider.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn test_enqueue_user_inputs() {
+    let mut model_provider = TestModelProvider::default();
+    for reply in ["Hi", "Bye"] {
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                reply.to_owned(),
+            )]),
+        );
+    }
+    model_provider.set_delay(Duration::from_millis(20));
+
+    let (turns_tx, mut turns_rx) = watch::channel(0);
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .on_turn_end(move |_| {
+            turns_tx.send_modify(|turns| *turns += 1);
+        })
+        .build();
+    agent.enqueue_user_inputs(["Hello", "Goodbye"]).unwrap();
+
+    timeout(
+        Duration::from_millis(500),
+        turns_rx.wait_for(|turns| *turns == 2),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    let requests = model_provider.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[1].messages.len(), 3);
+}
+
+#[tokio::test]
+async fn test_health_check() {
+    let model_provider = TestModelProvider::default();
+    let agent =
+        AgentBuilder::with_model_provider(model_provider.clone()).build();
+    assert!(agent.health_check().await.unwrap().is_ok());
+    assert!(model_provider.requests().is_empty());
+
+    let mut model_provider = TestModelProvider::default();
+    model_provider.set_unhealthy();
+    let agent = AgentBuilder::with_model_provider(model_provider).build();
+    let err = agent.health_check().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::Authentication);
+}
+
+#[tokio::test]
+async fn test_on_waiting() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi, ".to_owned()),
+        PresetEvent::MessageDelta("there.".to_owned()),
+    ]));
+
+    let events = Arc::new(Mutex::new(vec![]));
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider)
+        .on_waiting({
+            let events = Arc::clone(&events);
+            move || {
+                events.lock().unwrap().push("waiting".to_owned());
+            }
+        })
+        .on_transcript({
+            let events = Arc::clone(&events);
+            move |transcript, source| {
+                if source.is_assistant() {
+                    events.lock().unwrap().push(transcript.to_owned());
+                }
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let events = events.lock().unwrap();
+    assert_eq!(*events, ["waiting", "Hi, ", "there."]);
+}
+
+/// A tool that never finishes, and records whether it's cancelled.
+struct BlockingTool {
+    started: watch::Sender<bool>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Tool for BlockingTool {
+    type Input = Value;
+
+    fn name(&self) -> &str {
+        "blocking"
+    }
+
+    fn description(&self) -> &str {
+        "Blocks forever"
+    }
+
+    fn parameter_schema(&self) -> &Value {
+        EMPTY_SCHEMA
+    }
+
+    fn make_approval(&self, _input: &Self::Input) -> Approval {
+        Approval::new(self.description(), "")
+    }
+
+    fn execute(
+        &self,
+        _input: Self::Input,
+    ) -> impl Future<Output = ToolResult> + Send + 'static {
+        self.started.send_replace(true);
+        std::future::pending()
+    }
+
+    fn on_cancel(&self) {
+        self.cancelled.store(true, atomic::Ordering::SeqCst);
+    }
+}
+
+#[tokio::test]
+async fn test_enqueue_to_dead_agent() {
+    let model_provider = TestModelProvider::default();
+    let agent = AgentBuilder::with_model_provider(model_provider).build();
+
+    agent.handle().try_kill();
+    // Let the agent task observe the kill and stop.
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert_eq!(agent.enqueue_user_input("Hello"), Err(AgentDeadError));
+    assert_eq!(agent.interrupt(), Err(AgentDeadError));
+    assert_eq!(agent.pause(), Err(AgentDeadError));
+    assert_eq!(agent.resume(), Err(AgentDeadError));
+    assert_eq!(agent.register_tool(ListTodosTool), Err(AgentDeadError));
+    assert_eq!(agent.unregister_tool("list_todos"), Err(AgentDeadError));
+    assert!(matches!(agent.health_check().await, Err(AgentDeadError)));
+}
+
+#[tokio::test]
+async fn test_pause_and_resume() {
+    let mut model_provider = TestModelProvider::default();
+    for reply in ["Hi", "Bye"] {
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                reply.to_owned(),
+            )]),
+        );
+    }
+
+    let pause_changes = Arc::new(Mutex::new(vec![]));
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .on_pause_changed({
+            let pause_changes = Arc::clone(&pause_changes);
+            move |paused| pause_changes.lock().unwrap().push(paused)
+        })
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.pause().unwrap();
+    agent.enqueue_user_input("Hello").unwrap();
+    agent.enqueue_user_input("Goodbye").unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(model_provider.requests().is_empty());
+    assert_eq!(*pause_changes.lock().unwrap(), [true]);
+
+    agent.resume().unwrap();
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(model_provider.requests().len(), 2);
+    assert_eq!(*pause_changes.lock().unwrap(), [true, false]);
+}
+
+#[tokio::test]
+async fn test_interrupt() {
+    let mut model_provider = TestModelProvider::default();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::ToolCall(ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "blocking".to_owned(),
+            arguments: Value::Null,
+        }),
+    ]));
+    // The interrupted tool call gets a result.
+    model_provider.add_user_input_step();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("OK".to_owned()),
+    ]));
+
+    let (started_tx, mut started_rx) = watch::channel(false);
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let summaries = Arc::new(Mutex::new(vec![]));
+    let (idle_tx, mut idle_rx) = watch::channel(0);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_tool(BlockingTool {
+            started: started_tx,
+            cancelled: Arc::clone(&cancelled),
+        })
+        .on_turn_end({
+            let summaries = Arc::clone(&summaries);
+            move |summary| {
+                summaries.lock().unwrap().push(summary.clone());
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send_modify(|count| *count += 1);
+        })
+        .build();
+    agent.enqueue_user_input("Block").unwrap();
+
+    timeout(Duration::from_millis(500), started_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+    agent.interrupt().unwrap();
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v == 1))
+        .await
+        .unwrap()
+        .unwrap();
+    timeout(Duration::from_millis(500), async {
+        while !cancelled.load(atomic::Ordering::SeqCst) {
+            tokio::task::yield_now().await;
+        }
+    })
+    .await
+    .unwrap();
+    assert_eq!(summaries.lock().unwrap().len(), 1);
+
+    // The agent works as usual after being interrupted.
+    agent.enqueue_user_input("Hello").unwrap();
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v == 2))
+        .await
+        .unwrap()
+        .unwrap();
+    let last_request = model_provider.requests().pop().unwrap();
+    assert!(matches!(
+        last_request.messages.as_slice(),
+        [
+            ModelMessage::User(_),
+            ModelMessage::Opaque(_),
+            ModelMessage::Tool(result),
+            ModelMessage::User(_),
+        ] if result.id == "tool:1" && result.content.to_text() == "Interrupted"
+    ));
+}
+
+#[tokio::test]
+async fn test_turn_context() {
+    let mut model_provider = TestModelProvider::default();
+    // The system prompt and the context message take steps in the script.
+    model_provider.add_user_input_step();
+    model_provider.add_user_input_step();
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Hi!".to_owned()),
+    ]));
+    model_provider.add_user_input_step();
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("It's noon.".to_owned()),
+    ]));
+
+    let error_count = Arc::new(Mutex::new(0));
+    let (idle_tx, mut idle_rx) = watch::channel(0);
+
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_system_prompt("You are a helpful assistant.")
+        .with_turn_context(|| {
+            vec![ModelMessage::User("Current time: 12:00".to_owned())]
+        })
+        .on_error({
+            let error_count = Arc::clone(&error_count);
+            move |_| {
+                *error_count.lock().unwrap() += 1;
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send_modify(|count| *count += 1);
+        })
+        .build();
+    for (idx, input) in ["Hello", "What time is it?"].into_iter().enumerate() {
+        agent.enqueue_user_input(input).unwrap();
+        timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v > idx))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+
+    let is_context = |msg: &ModelMessage| matches!(msg, ModelMessage::User(text) if text.starts_with("Current"));
+    assert_eq!(*error_count.lock().unwrap(), 0);
+    let requests = model_provider.requests();
+    assert_eq!(requests.len(), 2);
+    for request in &requests {
+        assert!(matches!(request.messages[0], ModelMessage::System(_)));
+        assert!(is_context(&request.messages[1]));
+        // The context from the previous turns is not kept.
+        let count = request.messages.iter().filter(|m| is_context(m)).count();
+        assert_eq!(count, 1);
+    }
+}
+
+#[tokio::test]
+async fn test_resume_conversation() {
+    let mut conversation = Conversation::default();
+    conversation.extend([
+        ConversationItem::new(
+            ModelMessage::System("Old prompt".to_owned()),
+            "Old prompt".to_owned(),
+        ),
+        ConversationItem::new(
+            ModelMessage::User("Hi".to_owned()),
+            "Hi".to_owned(),
+        ),
+        ConversationItem::new(
+            ModelMessage::Assistant("Hello!".to_owned()),
+            "Hello!".to_owned(),
+        ),
+    ]);
+
+    let mut model_provider = TestModelProvider::default();
+    for _ in 0..4 {
+        model_provider.add_user_input_step();
+    }
+    model_provider.add_assistant_response_step(PresetResponse::with_events([
+        PresetEvent::MessageDelta("Welcome back!".to_owned()),
+    ]));
+
+    let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .with_system_prompt("New prompt")
+        .with_conversation(conversation)
+        .on_idle(move || {
+            idle_tx.send(true).unwrap();
+        })
+        .build();
+    agent.enqueue_user_input("I'm back").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let requests = model_provider.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(
+        requests[0].messages,
+        [
+            ModelMessage::System("New prompt".to_owned()),
+            ModelMessage::User("Hi".to_owned()),
+            ModelMessage::Assistant("Hello!".to_owned()),
+            ModelMessage::User("I'm back".to_owned()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_checkpoint_and_rollback() {
+    let mut model_provider = TestModelProvider::default();
+    for reply in ["Hi", "Bye"] {
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                reply.to_owned(),
+            )]),
+        );
+    }
+
+    let resets = Arc::new(Mutex::new(vec![]));
+    let (idle_tx, mut idle_rx) = watch::channel::<usize>(0);
+    let agent = AgentBuilder::with_model_provider(model_provider.clone())
+        .on_conversation_reset({
+            let resets = Arc::clone(&resets);
+            move |conversation| {
+                resets.lock().unwrap().push(conversation.len());
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send_modify(|count| *count += 1);
+        })
+        .build();
+    let wait_for_idle = async |rx: &mut watch::Receiver<usize>, count| {
+        timeout(Duration::from_millis(500), rx.wait_for(|v| *v == count))
+            .await
+            .unwrap()
+            .unwrap();
+    };
+
+    agent.enqueue_user_input("Hello").unwrap();
+    wait_for_idle(&mut idle_rx, 1).await;
+    let checkpoint = agent.checkpoint().await.unwrap();
+
+    agent.enqueue_user_input("Discard me").unwrap();
+    wait_for_idle(&mut idle_rx, 2).await;
+    let discarded = agent.checkpoint().await.unwrap();
+    agent.rollback(checkpoint).await.unwrap();
+    // The restored conversation has the first input and its reply.
+    assert_eq!(*resets.lock().unwrap(), [2]);
+    let err = agent.rollback(discarded).await.unwrap_err();
+    assert_eq!(err, CheckpointError::NotFound);
+
+    agent.enqueue_user_input("Keep me").unwrap();
+    wait_for_idle(&mut idle_rx, 3).await;
+
+    let requests = model_provider.requests();
+    assert_eq!(requests.len(), 3);
+    let user_inputs: Vec<_> = requests[2]
+        .messages
+        .iter()
+        .filter_map(|msg| match msg {
+            ModelMessage::User(text) => Some(text.as_str()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(user_inputs, ["Hello", "Keep me"]);
+
+    let err = agent.rollback(CheckpointId(42)).await.unwrap_err();
+    assert_eq!(err, CheckpointError::NotFound);
+}
+
+#[tokio::test]
+async fn test_usage_report() {
+    let mut model_provider = TestModelProvider::default();
+    for model in ["cheap", "unknown"] {
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Hi".to_owned(),
+            )])
+            .with_usage(Usage {
+                model: Some(model.to_owned()),
+                prompt_tokens: 100,
+                completion_tokens: 10,
+            }),
+        );
+    }
+
+    let reports = Arc::new(Mutex::new(vec![]));
+    let (idle_tx, mut idle_rx) = watch::channel::<usize>(0);
+    let agent = AgentBuilder::with_model_provider(model_provider)
+        .with_pricing(PricingTable::new().with_price(
+            "cheap",
+            ModelPrice {
+                prompt_token: 0.5,
+                completion_token: 1.0,
+            },
+        ))
+        .on_usage({
+            let reports = Arc::clone(&reports);
+            move |report| reports.lock().unwrap().push(report.clone())
+        })
+        .on_idle(move || {
+            idle_tx.send_modify(|count| *count += 1);
+        })
+        .build();
+    agent.enqueue_user_input("Hello").unwrap();
+    agent.enqueue_user_input("Hello again").unwrap();
+
+    timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v == 1))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 2);
+    assert_eq!(reports[0].cost, Some(60.0));
+    assert_eq!(reports[0].total_cost, 60.0);
+    assert_eq!(reports[1].usage.model.as_deref(), Some("unknown"));
+    assert_eq!(reports[1].cost, None);
+    assert_eq!(reports[1].total_cost, 60.0);
+}
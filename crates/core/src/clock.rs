@@ -0,0 +1,168 @@
+//! Time sources for the agent.
+//!
+//! Time-based behaviors (like retry backoff) go through a [`Clock`], so they
+//! can be fully controlled in tests by using a [`MockClock`].
+
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+
+/// A source of time.
+pub trait Clock: Send + Sync + 'static {
+    /// Returns a future that completes after `duration` has elapsed.
+    fn sleep(
+        &self,
+        duration: Duration,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>>;
+
+    /// Returns the current instant.
+    ///
+    /// The default implementation returns [`Instant::now`], a monotonic
+    /// instant rather than the wall-clock time.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock backed by the Tokio timer. This is the default clock.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    #[inline]
+    fn sleep(
+        &self,
+        duration: Duration,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        Box::pin(tokio::time::sleep(duration))
+    }
+
+    #[inline]
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+}
+
+/// A clock that only moves forward when it's advanced manually.
+///
+/// Clones of the clock share the same time.
+#[derive(Clone, Debug)]
+pub struct MockClock {
+    inner: Arc<Mutex<MockClockInner>>,
+}
+
+#[derive(Debug)]
+struct MockClockInner {
+    start: Instant,
+    elapsed: Duration,
+    sleepers: Vec<(Duration, oneshot::Sender<()>)>,
+}
+
+impl MockClock {
+    /// Creates a new mock clock.
+    #[inline]
+    pub fn new() -> Self {
+        let inner = MockClockInner {
+            start: Instant::now(),
+            elapsed: Duration::ZERO,
+            sleepers: vec![],
+        };
+        Self {
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Advances the clock, waking up the sleeps that are due.
+    pub fn advance(&self, duration: Duration) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.elapsed += duration;
+        let elapsed = inner.elapsed;
+        let (due, pending) = inner
+            .sleepers
+            .drain(..)
+            .partition(|(deadline, _)| *deadline <= elapsed);
+        inner.sleepers = pending;
+        for (_, tx) in due {
+            tx.send(()).ok();
+        }
+    }
+
+    /// Returns the total duration the clock has been advanced.
+    #[inline]
+    pub fn elapsed(&self) -> Duration {
+        self.inner.lock().unwrap().elapsed
+    }
+
+    /// Returns the number of sleeps that are waiting for the clock.
+    #[inline]
+    pub fn pending_sleeps(&self) -> usize {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .sleepers
+            .iter()
+            .filter(|(_, tx)| !tx.is_closed())
+            .count()
+    }
+}
+
+impl Clock for MockClock {
+    fn sleep(
+        &self,
+        duration: Duration,
+    ) -> Pin<Box<dyn Future<Output = ()> + Send>> {
+        if duration.is_zero() {
+            return Box::pin(std::future::ready(()));
+        }
+        let (tx, rx) = oneshot::channel();
+        let mut inner = self.inner.lock().unwrap();
+        let deadline = inner.elapsed + duration;
+        inner.sleepers.push((deadline, tx));
+        Box::pin(async move {
+            rx.await.ok();
+        })
+    }
+
+    #[inline]
+    fn now(&self) -> Instant {
+        let inner = self.inner.lock().unwrap();
+        inner.start + inner.elapsed
+    }
+}
+
+impl Default for MockClock {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::timeout;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_mock_clock() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        let short = clock.sleep(Duration::from_secs(1));
+        let long = clock.sleep(Duration::from_secs(10));
+        assert_eq!(clock.pending_sleeps(), 2);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(clock.pending_sleeps(), 1);
+        timeout(Duration::from_millis(100), short).await.unwrap();
+
+        let mut long = long;
+        let result = timeout(Duration::from_millis(10), &mut long).await;
+        assert!(result.is_err());
+
+        clock.advance(Duration::from_secs(5));
+        timeout(Duration::from_millis(100), long).await.unwrap();
+        assert_eq!(clock.elapsed(), Duration::from_secs(10));
+        assert_eq!(clock.now() - start, Duration::from_secs(10));
+    }
+}
@@ -1,21 +1,157 @@
 //! Conversation-related types.
 
+use std::time::SystemTime;
+
 use little_agent_model::ModelMessage;
+use serde::{Deserialize, Serialize};
 
 /// Represents a conversation.
-#[derive(Clone, Default, Debug)]
+///
+/// A conversation can be saved with [`Conversation::to_json`], and restored
+/// later to resume the session (see [`AgentBuilder::with_conversation`]).
+///
+/// [`AgentBuilder::with_conversation`]: crate::AgentBuilder::with_conversation
+#[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
 pub struct Conversation {
     pub(crate) items: Vec<Item>,
 }
 
+impl Conversation {
+    /// Returns an iterator over the items in this conversation, from the
+    /// oldest to the newest.
+    #[inline]
+    pub fn iter(&self) -> std::slice::Iter<'_, Item> {
+        self.items.iter()
+    }
+
+    /// Returns the number of items in this conversation.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    /// Returns `true` if this conversation has no items.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Keeps only the items for which `f` returns `true`, in their order.
+    ///
+    /// This is mostly useful for compacting the history, see
+    /// [`AgentBuilder::with_compactor`].
+    ///
+    /// [`AgentBuilder::with_compactor`]: crate::AgentBuilder::with_compactor
+    #[inline]
+    pub fn retain<F: FnMut(&Item) -> bool>(&mut self, f: F) {
+        self.items.retain(f);
+    }
+
+    /// Serializes this conversation into JSON.
+    ///
+    /// Returns an error if it contains an opaque message whose payload type
+    /// is not registered, see [`OpaqueMessage::register`].
+    ///
+    /// [`OpaqueMessage::register`]: little_agent_model::OpaqueMessage::register
+    #[inline]
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// Restores a conversation from the JSON made by
+    /// [`Conversation::to_json`].
+    ///
+    /// The payload types of the opaque messages must be registered before
+    /// calling this, which is usually done by creating the model provider.
+    #[inline]
+    pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    /// Returns `true` if this conversation contains an opaque message with
+    /// the given id.
+    pub(crate) fn contains_opaque(&self, id: &str) -> bool {
+        self.items.iter().any(|item| {
+            matches!(&item.msg, ModelMessage::Opaque(msg) if msg.id() == id)
+        })
+    }
+
+    /// Converts this conversation into the messages to send to the model.
+    ///
+    /// This is the default history transform of the agent, which drops
+    /// the assistant messages with empty text, since some models reject
+    /// them. The opaque messages are sent with their transcripts attached,
+    /// for the providers to fall back to. Custom transforms can build on
+    /// top of it.
+    pub fn to_model_messages(&self) -> Vec<ModelMessage> {
+        self.items
+            .iter()
+            .filter(|item| {
+                !matches!(
+                    &item.msg,
+                    ModelMessage::Assistant(text) if text.trim().is_empty()
+                )
+            })
+            .map(Item::to_model_message)
+            .collect()
+    }
+}
+
+impl Extend<Item> for Conversation {
+    #[inline]
+    fn extend<T: IntoIterator<Item = Item>>(&mut self, iter: T) {
+        self.items.extend(iter);
+    }
+}
+
+impl<'a> IntoIterator for &'a Conversation {
+    type Item = &'a Item;
+    type IntoIter = std::slice::Iter<'a, Item>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
 /// An item in the conversation.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Item {
+    #[serde(rename = "message")]
     pub(crate) msg: ModelMessage,
     pub(crate) transcript: String,
+    pub(crate) created_at: SystemTime,
 }
 
 impl Item {
+    /// Creates an item that is timestamped with the current time.
+    #[inline]
+    pub(crate) fn new(msg: ModelMessage, transcript: String) -> Self {
+        Self {
+            msg,
+            transcript,
+            created_at: SystemTime::now(),
+        }
+    }
+
+    /// Returns the message to send to the model, with the transcript
+    /// attached if it's opaque.
+    fn to_model_message(&self) -> ModelMessage {
+        match &self.msg {
+            ModelMessage::Opaque(msg) => ModelMessage::Opaque(
+                msg.clone().with_transcript(self.transcript.as_str()),
+            ),
+            msg => msg.clone(),
+        }
+    }
+
+    /// Returns the message of this item, as it's sent to the model.
+    #[inline]
+    pub fn message(&self) -> &ModelMessage {
+        &self.msg
+    }
+
     /// Returns the transcript of this item.
     ///
     /// The transcript is a string representation of the message item,
@@ -25,4 +161,119 @@ impl Item {
     pub fn transcript(&self) -> &str {
         &self.transcript
     }
+
+    /// Returns the time when this item was added to the conversation.
+    #[inline]
+    pub fn timestamp(&self) -> SystemTime {
+        self.created_at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use little_agent_model::OpaqueMessage;
+
+    use super::*;
+
+    #[test]
+    fn test_iter() {
+        let mut conversation = Conversation::default();
+        assert!(conversation.is_empty());
+
+        conversation.items.push(Item::new(
+            ModelMessage::System("Be helpful.".to_owned()),
+            "Be helpful.".to_owned(),
+        ));
+        conversation.items.push(Item::new(
+            ModelMessage::User("Hi".to_owned()),
+            "Hi".to_owned(),
+        ));
+        assert_eq!(conversation.len(), 2);
+
+        let transcripts: Vec<_> =
+            conversation.iter().map(Item::transcript).collect();
+        assert_eq!(transcripts, ["Be helpful.", "Hi"]);
+        let last = (&conversation).into_iter().last().unwrap();
+        assert!(
+            matches!(last.message(), ModelMessage::User(text) if text == "Hi")
+        );
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut conversation = Conversation::default();
+        conversation.extend([
+            Item::new(ModelMessage::User("Hi".to_owned()), "Hi".to_owned()),
+            Item::new(
+                ModelMessage::Assistant("Hello!".to_owned()),
+                "Hello!".to_owned(),
+            ),
+        ]);
+
+        let json = conversation.to_json().unwrap();
+        let restored = Conversation::from_json(&json).unwrap();
+        assert_eq!(restored.len(), 2);
+        for (item, restored_item) in conversation.iter().zip(&restored) {
+            assert_eq!(restored_item.message(), item.message());
+            assert_eq!(restored_item.transcript(), item.transcript());
+            assert_eq!(restored_item.timestamp(), item.timestamp());
+        }
+
+        assert!(Conversation::from_json("{}").is_err());
+    }
+
+    #[test]
+    fn test_to_model_messages() {
+        let mut conversation = Conversation::default();
+        for msg in [
+            ModelMessage::User("Hi".to_owned()),
+            ModelMessage::Assistant("".to_owned()),
+            ModelMessage::Assistant(" \n".to_owned()),
+            ModelMessage::User("Are you there?".to_owned()),
+            ModelMessage::Assistant("Yes.".to_owned()),
+        ] {
+            conversation.items.push(Item::new(msg, String::new()));
+        }
+
+        let messages = conversation.to_model_messages();
+        assert_eq!(messages.len(), 3);
+        assert!(
+            matches!(&messages[0], ModelMessage::User(text) if text == "Hi")
+        );
+        assert!(matches!(&messages[1], ModelMessage::User(_)));
+        assert!(matches!(&messages[2], ModelMessage::Assistant(_)));
+    }
+
+    #[test]
+    fn test_opaque_transcript_attached() {
+        let mut conversation = Conversation::default();
+        let opaque = OpaqueMessage::new("msg:0", ());
+        conversation
+            .items
+            .push(Item::new(ModelMessage::Opaque(opaque), "Hi".to_owned()));
+        let ModelMessage::Opaque(stored) = conversation.items[0].message()
+        else {
+            panic!("expected an opaque message");
+        };
+        assert_eq!(stored.transcript(), None);
+
+        let messages = conversation.to_model_messages();
+        let ModelMessage::Opaque(sent) = &messages[0] else {
+            panic!("expected an opaque message");
+        };
+        assert_eq!(sent.transcript(), Some("Hi"));
+    }
+
+    #[test]
+    fn test_timestamps_non_decreasing() {
+        let items: Vec<_> = (0..16)
+            .map(|i| {
+                let text = format!("message {i}");
+                Item::new(ModelMessage::User(text.clone()), text)
+            })
+            .collect();
+        for pair in items.windows(2) {
+            assert!(pair[0].timestamp() <= pair[1].timestamp());
+        }
+    }
 }
@@ -7,9 +7,20 @@
 extern crate tracing;
 
 mod agent;
+pub mod clock;
 pub mod conversation;
 mod model_client;
+pub mod pricing;
+pub mod prompt;
 pub mod tool;
+pub mod transcript;
 
-pub use agent::{Agent, AgentBuilder, TranscriptSource};
+pub use agent::{
+    Agent, AgentBuilder, AgentDeadError, CheckpointError, CheckpointId, Jitter,
+    TranscriptSource, TurnSummary, UsageReport,
+};
+pub use model_client::{
+    BoxedSendRequestFuture, MiddlewareNext, ModelClient, ModelClientError,
+    ModelClientResponse, RequestMiddleware,
+};
 pub use tool::Tool;
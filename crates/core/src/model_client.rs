@@ -1,48 +1,181 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
 use std::future::poll_fn;
 use std::pin::{Pin, pin};
 use std::sync::Arc;
 
 use little_agent_model::{
-    ModelFinishReason, ModelProvider, ModelProviderError, ModelRequest,
-    ModelResponse, ModelResponseEvent, OpaqueMessage, ToolCallRequest,
+    ErrorKind, MessageJoinPolicy, ModelFinishReason, ModelProvider,
+    ModelProviderError, ModelRequest, ModelResponse, ModelResponseEvent,
+    OpaqueMessage, ToolCallProgress, ToolCallRequest, Usage,
 };
+use tokio::sync::{Semaphore, broadcast, watch};
 use tracing::Instrument;
 
-type SendRequestResult =
-    Result<ModelClientResponse, Box<dyn ModelProviderError>>;
-type BoxedSendRequestFuture =
+/// How many events a subscriber can lag behind before missing some.
+const EVENT_CHANNEL_CAPACITY: usize = 256;
+
+type SendRequestResult = Result<ModelClientResponse, ModelClientError>;
+/// The future returned by [`RequestMiddleware::around`] and
+/// [`MiddlewareNext::run`].
+pub type BoxedSendRequestFuture =
     Pin<Box<dyn Future<Output = SendRequestResult> + Send>>;
-#[rustfmt::skip]
+type HealthCheckFn = Arc<
+    dyn Fn() -> Pin<
+            Box<
+                dyn Future<Output = Result<(), Box<dyn ModelProviderError>>>
+                    + Send,
+            >,
+        > + Send
+        + Sync,
+>;
 type HandlerFn = Arc<
-    dyn Fn(ModelRequest, Box<dyn Fn(String) + Send + 'static>)
-        -> BoxedSendRequestFuture + Send + Sync
+    dyn Fn(ModelRequest, StreamCallbacks) -> BoxedSendRequestFuture
+        + Send
+        + Sync,
 >;
 
+/// Callbacks for the events streamed before the response completes.
+struct StreamCallbacks {
+    on_transcript: Box<dyn Fn(String) + Send + 'static>,
+    on_tool_call_progress:
+        Option<Box<dyn Fn(ToolCallProgress) + Send + 'static>>,
+    /// Set when anyone subscribes to the events of the client.
+    events_tx: Option<broadcast::Sender<ModelResponseEvent>>,
+}
+
+/// A middleware that wraps the requests sent by a [`ModelClient`], which
+/// is useful for cross-cutting concerns like logging, caching or injecting
+/// parameters.
+///
+/// Unlike wrapping the provider, a middleware sees the requests of any
+/// provider (including the wrapper providers), and can be added to an
+/// existing client with [`ModelClient::with_middleware`].
+pub trait RequestMiddleware: Send + Sync + 'static {
+    /// Handles a request.
+    ///
+    /// The implementation can modify the request before passing it to
+    /// `next`, inspect the response returned by `next`, or respond without
+    /// calling `next` at all.
+    fn around(
+        &self,
+        req: ModelRequest,
+        next: MiddlewareNext,
+    ) -> BoxedSendRequestFuture;
+}
+
+/// The rest of the middleware chain, ending with the provider.
+pub struct MiddlewareNext {
+    middlewares: Arc<[Arc<dyn RequestMiddleware>]>,
+    index: usize,
+    handler_fn: HandlerFn,
+    callbacks: StreamCallbacks,
+}
+
+impl MiddlewareNext {
+    /// Passes the request to the next middleware, or to the provider if
+    /// this is the end of the chain.
+    pub fn run(self, req: ModelRequest) -> BoxedSendRequestFuture {
+        let Some(middleware) = self.middlewares.get(self.index).cloned() else {
+            return (self.handler_fn)(req, self.callbacks);
+        };
+        let next = MiddlewareNext {
+            index: self.index + 1,
+            ..self
+        };
+        middleware.around(req, next)
+    }
+}
+
 /// A wrapper around a model provider that maintains an execution
 /// environment for the provider and provides a type-erased interface
 /// for the other modules.
+///
+/// The client can be cloned and shared, requests sent from all the clones
+/// are subject to the same concurrency limit, and can be aborted together
+/// with [`ModelClient::abort_current`].
 #[derive(Clone)]
 pub struct ModelClient {
     handler_fn: HandlerFn,
+    health_check_fn: HealthCheckFn,
+    middlewares: Arc<[Arc<dyn RequestMiddleware>]>,
+    semaphore: Arc<Semaphore>,
+    abort_tx: Arc<watch::Sender<()>>,
+    events_tx: broadcast::Sender<ModelResponseEvent>,
 }
 
 impl ModelClient {
+    /// Creates a model client from the provider.
+    ///
+    /// The message deltas of the responses are joined by the provider's
+    /// [`ModelProvider::message_join_policy`]. By default, only one request
+    /// can be in flight at a time, and the others will wait in a queue.
     #[inline]
     pub fn new<P: ModelProvider + 'static>(provider: P) -> Self {
+        let provider = Arc::new(provider);
+        let health_check_fn: HealthCheckFn = {
+            let provider = Arc::clone(&provider);
+            Arc::new(move || {
+                let fut = provider.health_check();
+                Box::pin(async move {
+                    fut.await.map_err(|err| {
+                        Box::new(err) as Box<dyn ModelProviderError>
+                    })
+                })
+            })
+        };
+
         // We have to erase the type `P`, since `ModelClient` doesn't have a
         // generic parameter and we don't want it either.
-        let handler_fn: HandlerFn = Arc::new(move |req, on_transcript| {
+        let join_policy = provider.message_join_policy();
+        let handler_fn: HandlerFn = Arc::new(move |req, callbacks| {
             let fut = provider.send_request(&req);
             Box::pin(
                 async move {
                     trace!("got a request: {:?}", req);
                     let resp_or_err = fut.await;
-                    handle_response::<P>(resp_or_err, on_transcript).await
+                    handle_response::<P>(resp_or_err, callbacks, join_policy)
+                        .await
                 }
                 .instrument(trace_span!("model client req")),
             )
         });
-        Self { handler_fn }
+        Self {
+            handler_fn,
+            health_check_fn,
+            middlewares: Arc::new([]),
+            semaphore: Arc::new(Semaphore::new(1)),
+            abort_tx: Arc::new(watch::Sender::new(())),
+            events_tx: broadcast::Sender::new(EVENT_CHANNEL_CAPACITY),
+        }
+    }
+
+    /// Sets the maximum number of requests that can be in flight at the
+    /// same time.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `concurrency` is zero.
+    #[inline]
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        assert!(concurrency > 0, "concurrency must be greater than zero");
+        self.semaphore = Arc::new(Semaphore::new(concurrency));
+        self
+    }
+
+    /// Adds a middleware to the end of the chain.
+    ///
+    /// The middlewares are applied in the order they are added, so the
+    /// first one sees the request first. The clones created before this
+    /// call are not affected.
+    pub fn with_middleware<M: RequestMiddleware>(
+        mut self,
+        middleware: M,
+    ) -> Self {
+        let mut middlewares = self.middlewares.to_vec();
+        middlewares.push(Arc::new(middleware));
+        self.middlewares = middlewares.into();
+        self
     }
 
     /// Sends a request and returns the response.
@@ -50,45 +183,190 @@ impl ModelClient {
     /// # Cancel safety
     ///
     /// This method is cancel safe. The response stops streaming further
-    /// events when this operation is cancelled.
+    /// events when this operation is cancelled, and it's dropped right away
+    /// instead of being drained, so the provider can close the underlying
+    /// connection.
     #[inline]
     pub async fn send_request(
         &self,
         req: ModelRequest,
         on_transcript: impl Fn(String) + Send + 'static,
-    ) -> Result<ModelClientResponse, Box<dyn ModelProviderError>> {
-        (self.handler_fn)(req, Box::new(on_transcript)).await
+    ) -> Result<ModelClientResponse, ModelClientError> {
+        let callbacks = StreamCallbacks {
+            on_transcript: Box::new(on_transcript),
+            on_tool_call_progress: None,
+            events_tx: None,
+        };
+        self.send_request_with_callbacks(req, callbacks).await
+    }
+
+    /// Sends a request like [`ModelClient::send_request`], and also reports
+    /// the progress of the tool calls that are still streaming in.
+    ///
+    /// The progress is only reported by the providers that support it.
+    #[inline]
+    pub async fn send_request_with_progress(
+        &self,
+        req: ModelRequest,
+        on_transcript: impl Fn(String) + Send + 'static,
+        on_tool_call_progress: impl Fn(ToolCallProgress) + Send + 'static,
+    ) -> Result<ModelClientResponse, ModelClientError> {
+        let callbacks = StreamCallbacks {
+            on_transcript: Box::new(on_transcript),
+            on_tool_call_progress: Some(Box::new(on_tool_call_progress)),
+            events_tx: None,
+        };
+        self.send_request_with_callbacks(req, callbacks).await
+    }
+
+    async fn send_request_with_callbacks(
+        &self,
+        req: ModelRequest,
+        mut callbacks: StreamCallbacks,
+    ) -> Result<ModelClientResponse, ModelClientError> {
+        let _permit = self
+            .semaphore
+            .acquire()
+            .await
+            .expect("semaphore should never be closed");
+        // Subscribe after the request becomes active, so that it's not
+        // affected by the aborts happened while it was queued.
+        let mut abort_rx = self.abort_tx.subscribe();
+        if self.events_tx.receiver_count() > 0 {
+            callbacks.events_tx = Some(self.events_tx.clone());
+        }
+        let next = MiddlewareNext {
+            middlewares: Arc::clone(&self.middlewares),
+            index: 0,
+            handler_fn: Arc::clone(&self.handler_fn),
+            callbacks,
+        };
+        let fut = next.run(req);
+        tokio::select! {
+            resp_or_err = fut => resp_or_err,
+            _ = abort_rx.changed() => {
+                debug!("the request is aborted");
+                Err(ModelClientError::new(Aborted, Default::default()))
+            }
+        }
+    }
+
+    /// Subscribes to the events of the responses, which are delivered as
+    /// they stream in, before the aggregated [`ModelClientResponse`] is
+    /// returned.
+    ///
+    /// The events of the requests sent from this client and its clones
+    /// after this call are delivered. A receiver that lags too far behind
+    /// misses the oldest events, see [`broadcast::Receiver::recv`].
+    #[inline]
+    pub fn subscribe(&self) -> broadcast::Receiver<ModelResponseEvent> {
+        self.events_tx.subscribe()
+    }
+
+    /// Aborts the active requests sent from this client and its clones.
+    ///
+    /// The aborted requests fail with an error that
+    /// [`ModelClientError::is_aborted`] returns `true` for. The queued
+    /// requests are not affected, and the client remains usable.
+    #[inline]
+    pub fn abort_current(&self) {
+        self.abort_tx.send_replace(());
+    }
+
+    /// Checks whether the underlying provider is usable.
+    ///
+    /// The check is not subject to the concurrency limit.
+    #[inline]
+    pub async fn health_check(
+        &self,
+    ) -> Result<(), Box<dyn ModelProviderError>> {
+        (self.health_check_fn)().await
     }
 }
 
 /// A completely received response from the model client.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub struct ModelClientResponse {
+    /// The full text generated by the model.
     pub transcript: String,
+    /// The opaque message made by the model response, if any.
     pub opaque_msg: Option<OpaqueMessage>,
     /// Tool calls requested by the model.
     pub tool_calls: Vec<ToolCallRequest>,
     /// The reason the model finished generating.
     pub finish_reason: Option<ModelFinishReason>,
+    /// The token usage, if reported by the provider.
+    pub usage: Option<Usage>,
+}
+
+/// An error from the model client, with the partial response received
+/// before the error occurred.
+#[derive(Debug)]
+pub struct ModelClientError {
+    /// The underlying error from the model provider.
+    pub error: Box<dyn ModelProviderError>,
+    /// The partial response received so far. It's empty if the request
+    /// failed before any events were received.
+    ///
+    /// Callers can decide whether to keep the partial transcript and tool
+    /// calls, or just discard them.
+    pub partial: ModelClientResponse,
+}
+
+impl ModelClientError {
+    /// Returns `true` if the request is aborted by
+    /// [`ModelClient::abort_current`].
+    #[inline]
+    pub fn is_aborted(&self) -> bool {
+        let error: &dyn StdError = &*self.error;
+        error.is::<Aborted>()
+    }
+
+    #[inline]
+    fn new(
+        error: impl ModelProviderError,
+        partial: ModelClientResponse,
+    ) -> Self {
+        Self {
+            error: Box::new(error),
+            partial,
+        }
+    }
+}
+
+/// The error for requests aborted by [`ModelClient::abort_current`].
+#[derive(Debug)]
+struct Aborted;
+
+impl Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the request is aborted")
+    }
+}
+
+impl StdError for Aborted {}
+
+impl ModelProviderError for Aborted {
+    #[inline]
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
 }
 
 async fn handle_response<P: ModelProvider + 'static>(
     resp_or_err: Result<P::Response, P::Error>,
-    on_transcript: Box<dyn Fn(String) + Send + 'static>,
+    callbacks: StreamCallbacks,
+    join_policy: MessageJoinPolicy,
 ) -> SendRequestResult {
     let resp = match resp_or_err {
         Ok(resp) => resp,
         Err(err) => {
             error!("got an error: {err:?}");
-            // req.error_tx.send(Box::new(err)).ok();
-            return Err(Box::new(err));
+            return Err(ModelClientError::new(err, Default::default()));
         }
     };
 
-    let mut transcript = String::new();
-    let opaque_msg;
-    let mut tool_calls = Vec::new();
-    let mut finish_reason = None;
+    let mut partial = ModelClientResponse::default();
 
     trace!("start receiving events");
 
@@ -100,53 +378,123 @@ async fn handle_response<P: ModelProvider + 'static>(
             Ok(event) => event,
             Err(err) => {
                 error!("got an error: {err:?}");
-                return Err(Box::new(err));
+                return Err(ModelClientError::new(err, partial));
             }
         };
 
         let Some(event) = event else {
             // The request has been handled gracefully without errors,
             // now try getting the opaque message for this response.
-            opaque_msg = pinned_resp.make_opaque_message();
+            partial.opaque_msg = pinned_resp.make_opaque_message();
+            partial.usage = pinned_resp.usage();
             break;
         };
         trace!("got an event: {event:?}");
+        if let Some(events_tx) = &callbacks.events_tx {
+            // It's fine if all the subscribers are gone.
+            events_tx.send(event.clone()).ok();
+        }
 
         match event {
             ModelResponseEvent::MessageDelta(msg) => {
-                transcript.push_str(&msg);
-                on_transcript(msg);
+                let msg = join_delta(&partial.transcript, msg, join_policy);
+                partial.transcript.push_str(&msg);
+                (callbacks.on_transcript)(msg);
+            }
+            ModelResponseEvent::ToolCallProgress(progress) => {
+                if let Some(on_progress) = &callbacks.on_tool_call_progress {
+                    on_progress(progress);
+                }
             }
             ModelResponseEvent::ToolCall(req) => {
-                tool_calls.push(req);
+                partial.tool_calls.push(req);
             }
             ModelResponseEvent::Completed(reason) => {
-                finish_reason = Some(reason);
+                partial.finish_reason = Some(reason);
             }
+            // Already traced above, nothing else to do.
+            ModelResponseEvent::Heartbeat => {}
         }
     }
 
     trace!("finished a request");
 
-    Ok(ModelClientResponse {
-        transcript,
-        opaque_msg,
-        tool_calls,
-        finish_reason,
-    })
+    Ok(partial)
+}
+
+/// Returns the delta to append to the transcript, with a leading space if
+/// the policy needs one.
+fn join_delta(
+    transcript: &str,
+    delta: String,
+    policy: MessageJoinPolicy,
+) -> String {
+    let needs_space = policy == MessageJoinPolicy::SpaceJoin
+        && !transcript.is_empty()
+        && !delta.is_empty()
+        && !transcript.ends_with(char::is_whitespace)
+        && !delta.starts_with(char::is_whitespace);
+    if needs_space {
+        format!(" {delta}")
+    } else {
+        delta
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Mutex;
     use std::sync::atomic::{AtomicBool, Ordering};
+    use std::task::{Context, Poll};
+    use std::time::Duration;
 
     use little_agent_model::ModelMessage;
     use little_agent_test_model::{
         PresetEvent, PresetResponse, TestModelProvider,
     };
+    use tokio::time::{Instant, sleep};
 
     use super::*;
 
+    struct OverrideModel(&'static str);
+
+    impl RequestMiddleware for OverrideModel {
+        fn around(
+            &self,
+            mut req: ModelRequest,
+            next: MiddlewareNext,
+        ) -> BoxedSendRequestFuture {
+            req.model = Some(self.0.to_owned());
+            next.run(req)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_middleware() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Hi".to_owned(),
+            )]),
+        );
+
+        // The last middleware is the closest to the provider.
+        let model_client = ModelClient::new(model_provider.clone())
+            .with_middleware(OverrideModel("outer"))
+            .with_middleware(OverrideModel("inner"));
+        let req = ModelRequest {
+            messages: vec![ModelMessage::User("Hi".to_owned())],
+            ..Default::default()
+        };
+        let resp = model_client.send_request(req, |_| {}).await.unwrap();
+        assert_eq!(resp.transcript, "Hi");
+
+        let requests = model_provider.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].model.as_deref(), Some("inner"));
+    }
+
     #[tokio::test]
     async fn test_send_request() {
         let mut model_provider = TestModelProvider::default();
@@ -168,6 +516,8 @@ mod tests {
                     ModelRequest {
                         messages: vec![ModelMessage::User("Hi".to_owned())],
                         tools: vec![],
+                        model: None,
+                        extra: Default::default(),
                     },
                     {
                         let on_transcript_called =
@@ -186,6 +536,46 @@ mod tests {
     }
 
     #[tokio::test]
+    async fn test_message_join_policy() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([
+                PresetEvent::MessageDelta("Done.".to_owned()),
+                PresetEvent::MessageDelta("Anything else?".to_owned()),
+                PresetEvent::MessageDelta(" Bye.".to_owned()),
+            ]),
+        );
+        let req = ModelRequest {
+            messages: vec![ModelMessage::User("Hi".to_owned())],
+            ..Default::default()
+        };
+
+        let resp = ModelClient::new(model_provider.clone())
+            .send_request(req.clone(), |_| {})
+            .await
+            .unwrap();
+        assert_eq!(resp.transcript, "Done.Anything else? Bye.");
+
+        model_provider.set_message_join_policy(MessageJoinPolicy::SpaceJoin);
+        let deltas = Arc::new(Mutex::new(vec![]));
+        let resp = ModelClient::new(model_provider)
+            .send_request(req, {
+                let deltas = Arc::clone(&deltas);
+                move |delta| deltas.lock().unwrap().push(delta)
+            })
+            .await
+            .unwrap();
+        assert_eq!(resp.transcript, "Done. Anything else? Bye.");
+        // The callback sees the deltas as joined.
+        assert_eq!(
+            *deltas.lock().unwrap(),
+            ["Done.", " Anything else?", " Bye."]
+        );
+    }
+
+    #[tokio::test]
+    #[allow(clippy::redundant_pattern_matching)]
     async fn test_error_handling() {
         let model_provider = TestModelProvider::default();
         let model_client = ModelClient::new(model_provider);
@@ -194,10 +584,274 @@ mod tests {
                 ModelRequest {
                     messages: vec![ModelMessage::User("Hi".to_owned())],
                     tools: vec![],
+                    model: None,
+                    extra: Default::default(),
                 },
                 |_| {},
             )
             .await;
         assert!(matches!(resp_or_err, Err(_)));
     }
+
+    #[tokio::test]
+    async fn test_partial_response() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([
+                PresetEvent::MessageDelta("Let me ".to_owned()),
+                PresetEvent::MessageDelta("check.".to_owned()),
+                PresetEvent::Failure,
+            ]),
+        );
+
+        let model_client = ModelClient::new(model_provider);
+        let err = model_client
+            .send_request(
+                ModelRequest {
+                    messages: vec![ModelMessage::User("Hi".to_owned())],
+                    tools: vec![],
+                    model: None,
+                    extra: Default::default(),
+                },
+                |_| {},
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(err.partial.transcript, "Let me check.");
+        assert!(err.partial.finish_reason.is_none());
+        assert!(err.partial.opaque_msg.is_none());
+    }
+
+    /// Records whether the responses have been dropped.
+    struct DropTrackingProvider(TestModelProvider, Arc<AtomicBool>);
+
+    struct DropTrackingResponse<R>(Pin<Box<R>>, Arc<AtomicBool>);
+
+    impl ModelProvider for DropTrackingProvider {
+        type Error = <TestModelProvider as ModelProvider>::Error;
+        type Response = DropTrackingResponse<
+            <TestModelProvider as ModelProvider>::Response,
+        >;
+
+        fn send_request(
+            &self,
+            req: &ModelRequest,
+        ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
+        {
+            let fut = self.0.send_request(req);
+            let dropped = Arc::clone(&self.1);
+            async move { Ok(DropTrackingResponse(Box::pin(fut.await?), dropped)) }
+        }
+    }
+
+    impl<R: ModelResponse> ModelResponse for DropTrackingResponse<R> {
+        type Error = R::Error;
+
+        fn poll_next_event(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Result<Option<ModelResponseEvent>, Self::Error>> {
+            self.get_mut().0.as_mut().poll_next_event(cx)
+        }
+
+        fn make_opaque_message(&self) -> Option<OpaqueMessage> {
+            self.0.make_opaque_message()
+        }
+    }
+
+    impl<R> Drop for DropTrackingResponse<R> {
+        fn drop(&mut self) {
+            self.1.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_drop_mid_stream() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([
+                PresetEvent::MessageDelta("Hi".to_owned()),
+                PresetEvent::MessageDelta(", there".to_owned()),
+            ]),
+        );
+        model_provider.set_delay(Duration::from_secs(10));
+
+        let dropped = Arc::new(AtomicBool::new(false));
+        let model_client = ModelClient::new(DropTrackingProvider(
+            model_provider,
+            Arc::clone(&dropped),
+        ));
+        let (delta_tx, mut delta_rx) = watch::channel(false);
+        let task = tokio::spawn({
+            let model_client = model_client.clone();
+            async move {
+                let req = ModelRequest {
+                    messages: vec![ModelMessage::User("Hi".to_owned())],
+                    tools: vec![],
+                    model: None,
+                    extra: Default::default(),
+                };
+                model_client
+                    .send_request(req, move |_| {
+                        delta_tx.send_replace(true);
+                    })
+                    .await
+            }
+        });
+
+        // Wait for the first delta, so the response is in the middle of
+        // streaming, then drop the request.
+        delta_rx.wait_for(|v| *v).await.unwrap();
+        assert!(!dropped.load(Ordering::SeqCst));
+        let start = Instant::now();
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+        assert!(dropped.load(Ordering::SeqCst));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // The permit is released, so the client is still usable.
+        let req = ModelRequest {
+            messages: vec![ModelMessage::User("Hi".to_owned())],
+            tools: vec![],
+            model: None,
+            extra: Default::default(),
+        };
+        let resp = model_client.send_request(req, |_| {}).await.unwrap();
+        assert_eq!(resp.transcript, "Hi, there");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_subscribe() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([
+                PresetEvent::MessageDelta("Hi".to_owned()),
+                PresetEvent::MessageDelta(", there".to_owned()),
+            ]),
+        );
+        model_provider.set_delay(Duration::from_secs(1));
+
+        let model_client = ModelClient::new(model_provider);
+        let mut events_rx = model_client.subscribe();
+        let req = ModelRequest {
+            messages: vec![ModelMessage::User("Hi".to_owned())],
+            tools: vec![],
+            model: None,
+            extra: Default::default(),
+        };
+        let mut send_request = pin!(model_client.send_request(req, |_| {}));
+
+        // The first event arrives while the request is still streaming.
+        let first_event = tokio::select! {
+            event = events_rx.recv() => event.unwrap(),
+            _ = &mut send_request => panic!("the response completed first"),
+        };
+        assert_eq!(
+            first_event,
+            ModelResponseEvent::MessageDelta("Hi".to_owned())
+        );
+
+        let resp = send_request.await.unwrap();
+        assert_eq!(resp.transcript, "Hi, there");
+        let mut events = vec![first_event];
+        while let Ok(event) = events_rx.try_recv() {
+            events.push(event);
+        }
+        assert_eq!(
+            events,
+            [
+                ModelResponseEvent::MessageDelta("Hi".to_owned()),
+                ModelResponseEvent::MessageDelta(", there".to_owned()),
+                ModelResponseEvent::Completed(ModelFinishReason::Stop),
+            ]
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_abort_current() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Hi".to_owned(),
+            )]),
+        );
+        model_provider.set_delay(Duration::from_secs(10));
+
+        let model_client = ModelClient::new(model_provider);
+        let send_request = || {
+            model_client.send_request(
+                ModelRequest {
+                    messages: vec![ModelMessage::User("Hi".to_owned())],
+                    tools: vec![],
+                    model: None,
+                    extra: Default::default(),
+                },
+                |_| {},
+            )
+        };
+
+        // The second request is queued when the first one is aborted, so it
+        // should not be affected.
+        let start = Instant::now();
+        let (err, resp, _) =
+            tokio::join!(send_request(), send_request(), async {
+                sleep(Duration::from_secs(1)).await;
+                model_client.abort_current();
+            });
+        assert!(err.unwrap_err().is_aborted());
+        assert_eq!(resp.unwrap().transcript, "Hi");
+        assert!(start.elapsed() < Duration::from_secs(40));
+
+        // The client is still usable.
+        assert_eq!(send_request().await.unwrap().transcript, "Hi");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_concurrency() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Hi".to_owned(),
+            )]),
+        );
+        model_provider.set_delay(Duration::from_millis(100));
+
+        async fn send_requests(model_client: &ModelClient) -> Duration {
+            let start = Instant::now();
+            let send_request = || {
+                model_client.send_request(
+                    ModelRequest {
+                        messages: vec![ModelMessage::User("Hi".to_owned())],
+                        tools: vec![],
+                        model: None,
+                        extra: Default::default(),
+                    },
+                    |_| {},
+                )
+            };
+            let (a, b, c) =
+                tokio::join!(send_request(), send_request(), send_request());
+            for resp in [a, b, c] {
+                assert_eq!(resp.unwrap().transcript, "Hi");
+            }
+            start.elapsed()
+        }
+
+        // Each response takes 200ms to complete (a delta event and a
+        // completion event).
+        let model_client = ModelClient::new(model_provider.clone());
+        assert!(
+            send_requests(&model_client).await >= Duration::from_millis(600)
+        );
+
+        let model_client = ModelClient::new(model_provider).with_concurrency(3);
+        assert!(
+            send_requests(&model_client).await < Duration::from_millis(400)
+        );
+    }
 }
@@ -0,0 +1,106 @@
+//! Cost estimation of the model usage.
+//!
+//! A [`PricingTable`] maps the model ids to their prices, and converts the
+//! [`Usage`] of a response into a monetary estimate. The currency is
+//! whatever the prices are in.
+
+use std::collections::HashMap;
+
+use little_agent_model::Usage;
+
+/// The price of a model.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ModelPrice {
+    /// The price of a prompt token.
+    pub prompt_token: f64,
+    /// The price of a completion token.
+    pub completion_token: f64,
+}
+
+impl ModelPrice {
+    /// Creates a price from the per-million-tokens prices, which is how
+    /// most providers list them.
+    #[inline]
+    pub fn per_million_tokens(prompt: f64, completion: f64) -> Self {
+        Self {
+            prompt_token: prompt / 1_000_000.0,
+            completion_token: completion / 1_000_000.0,
+        }
+    }
+
+    /// Returns the cost of the usage.
+    #[inline]
+    pub fn cost_of(&self, usage: &Usage) -> f64 {
+        usage.prompt_tokens as f64 * self.prompt_token
+            + usage.completion_tokens as f64 * self.completion_token
+    }
+}
+
+/// Prices of the models, keyed by the model ids.
+#[derive(Clone, Debug, Default)]
+pub struct PricingTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PricingTable {
+    /// Creates an empty table.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the price of a model.
+    #[inline]
+    pub fn with_price<S: Into<String>>(
+        mut self,
+        model: S,
+        price: ModelPrice,
+    ) -> Self {
+        self.prices.insert(model.into(), price);
+        self
+    }
+
+    /// Returns the price of a model.
+    #[inline]
+    pub fn price(&self, model: &str) -> Option<&ModelPrice> {
+        self.prices.get(model)
+    }
+
+    /// Estimates the cost of the usage.
+    ///
+    /// Returns `None` if the usage doesn't tell the model, or the model is
+    /// not in the table.
+    pub fn cost_of(&self, usage: &Usage) -> Option<f64> {
+        let model = usage.model.as_deref()?;
+        Some(self.price(model)?.cost_of(usage))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_of() {
+        let pricing = PricingTable::new()
+            .with_price("gpt-4o", ModelPrice::per_million_tokens(2.5, 10.0));
+        let usage = Usage {
+            model: Some("gpt-4o".to_owned()),
+            prompt_tokens: 1_000,
+            completion_tokens: 200,
+        };
+        let cost = pricing.cost_of(&usage).unwrap();
+        assert!((cost - 0.0045).abs() < 1e-12);
+
+        let unknown = Usage {
+            model: Some("gpt-5".to_owned()),
+            ..usage.clone()
+        };
+        assert_eq!(pricing.cost_of(&unknown), None);
+        let anonymous = Usage {
+            model: None,
+            ..usage
+        };
+        assert_eq!(pricing.cost_of(&anonymous), None);
+    }
+}
@@ -0,0 +1,131 @@
+//! Prompt templates.
+//!
+//! A template is a plain text with `{{KEY}}` placeholders, which are
+//! substituted by [`render_template`]. It's mainly used for the system
+//! prompts, the rendered text can be set with
+//! [`AgentBuilder::with_system_prompt`].
+//!
+//! [`AgentBuilder::with_system_prompt`]: crate::AgentBuilder::with_system_prompt
+
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+
+/// The error returned when rendering a template.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TemplateError {
+    /// A placeholder has no value in the variables.
+    UnresolvedPlaceholder(String),
+    /// A placeholder is opened with `{{` but never closed.
+    UnclosedPlaceholder,
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnresolvedPlaceholder(key) => {
+                write!(f, "unresolved placeholder: {{{{{key}}}}}")
+            }
+            TemplateError::UnclosedPlaceholder => {
+                f.write_str("unclosed placeholder")
+            }
+        }
+    }
+}
+
+impl StdError for TemplateError {}
+
+/// Substitutes the `{{KEY}}` placeholders in `template` with the values of
+/// `vars`.
+///
+/// The whitespaces around the keys are ignored, so `{{ KEY }}` works too.
+/// A literal `{{` can be written as `\{{`. Returns an error if any
+/// placeholder has no value.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::HashMap;
+///
+/// use little_agent_core::prompt::render_template;
+///
+/// let vars = HashMap::from([("HOST_OS".to_owned(), "Linux".to_owned())]);
+/// let prompt = render_template("Running in {{HOST_OS}}.", &vars).unwrap();
+/// assert_eq!(prompt, "Running in Linux.");
+/// ```
+pub fn render_template(
+    template: &str,
+    vars: &HashMap<String, String>,
+) -> Result<String, TemplateError> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let after_open = &rest[start + 2..];
+        if let Some(text) = rest[..start].strip_suffix('\\') {
+            rendered.push_str(text);
+            rendered.push_str("{{");
+            rest = after_open;
+            continue;
+        }
+        rendered.push_str(&rest[..start]);
+        let Some(end) = after_open.find("}}") else {
+            return Err(TemplateError::UnclosedPlaceholder);
+        };
+        let key = after_open[..end].trim();
+        let Some(value) = vars.get(key) else {
+            return Err(TemplateError::UnresolvedPlaceholder(key.to_owned()));
+        };
+        rendered.push_str(value);
+        rest = &after_open[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> HashMap<String, String> {
+        HashMap::from([
+            ("HOST_OS".to_owned(), "macOS".to_owned()),
+            ("NAME".to_owned(), "little-agent".to_owned()),
+        ])
+    }
+
+    #[test]
+    fn test_substitution() {
+        let rendered = render_template(
+            "I'm {{NAME}}, running in {{ HOST_OS }}. Bye from {{NAME}}!",
+            &vars(),
+        );
+        assert_eq!(
+            rendered.unwrap(),
+            "I'm little-agent, running in macOS. Bye from little-agent!"
+        );
+        let rendered = render_template("No placeholders.", &vars());
+        assert_eq!(rendered.unwrap(), "No placeholders.");
+    }
+
+    #[test]
+    fn test_escaped_braces() {
+        let rendered =
+            render_template(r"Write \{{NAME}} for {{NAME}}.", &vars());
+        assert_eq!(rendered.unwrap(), "Write {{NAME}} for little-agent.");
+        let rendered = render_template(r"Unpaired \{{ is fine.", &vars());
+        assert_eq!(rendered.unwrap(), "Unpaired {{ is fine.");
+    }
+
+    #[test]
+    fn test_missing_variable() {
+        let err = render_template("Today is {{DATE}}.", &vars()).unwrap_err();
+        assert_eq!(
+            err,
+            TemplateError::UnresolvedPlaceholder("DATE".to_owned())
+        );
+        assert_eq!(err.to_string(), "unresolved placeholder: {{DATE}}");
+
+        let err = render_template("Hi {{NAME", &vars()).unwrap_err();
+        assert_eq!(err, TemplateError::UnclosedPlaceholder);
+    }
+}
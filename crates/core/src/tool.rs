@@ -3,17 +3,23 @@
 mod approval;
 mod error;
 mod manager;
+mod metrics;
 mod object;
+mod paginated;
 
 use serde::de::DeserializeOwned;
 use serde_json::Value;
 
-pub use approval::Approval;
+pub use approval::{Approval, ApprovalDecision, ProvisionalApproval};
 pub use error::{Error, ErrorKind};
-pub(crate) use manager::Manager;
+pub use little_agent_model::ToolOutput;
+pub use manager::UnknownToolPolicy;
+pub(crate) use manager::{Change as ToolsetChange, Manager};
+pub use metrics::{Metrics, ToolStats};
+pub use paginated::Paginated;
 
 /// The result of a tool call.
-pub type ToolResult = Result<String, Error>;
+pub type ToolResult = Result<ToolOutput, Error>;
 
 /// A tool that can be called by the model.
 ///
@@ -40,12 +46,38 @@ pub trait Tool: Send + Sync + 'static {
     /// Makes an approval for calling this tool with the given input.
     fn make_approval(&self, input: &Self::Input) -> Approval;
 
+    /// Returns whether calling this tool with the given input needs to be
+    /// approved.
+    ///
+    /// Tools without side effects (like reading the conversation) can
+    /// return `false`, then the call is approved automatically without
+    /// asking. The default implementation returns `true`.
+    fn requires_approval(&self, _input: &Self::Input) -> bool {
+        true
+    }
+
     /// Executes the tool with the given input.
     ///
     /// This method must return a future that is fully independent of `self`,
     /// and the future should be cancellation safe.
+    ///
+    /// # Cancel safety
+    ///
+    /// The future may be dropped at any await point, e.g. when the agent is
+    /// interrupted. Tools with side effects should avoid leaving partial
+    /// results behind, by either making the changes atomic (like writing to
+    /// a temporary file and then renaming it), or cleaning them up in
+    /// [`Tool::on_cancel`].
     fn execute(
         &self,
         input: Self::Input,
     ) -> impl Future<Output = ToolResult> + Send + 'static;
+
+    /// Called when an execution of this tool is cancelled before it
+    /// completes.
+    ///
+    /// This is invoked synchronously when the future returned by
+    /// [`Tool::execute`] is dropped, so it should not block. The default
+    /// implementation does nothing.
+    fn on_cancel(&self) {}
 }
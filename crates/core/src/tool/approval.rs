@@ -6,8 +6,39 @@ pub struct ApprovalResult {
     pub why: Option<String>,
 }
 
+/// A decision made on an [`Approval`], for auditing purposes.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ApprovalDecision {
+    /// The name of the tool.
+    pub tool_name: String,
+    /// What the approval was for.
+    pub what: String,
+    /// Whether the tool call was approved.
+    pub approved: bool,
+    /// Whether the decision was made automatically, since no approval
+    /// handler was provided.
+    pub automatic: bool,
+    /// The reason of the rejection, if any.
+    pub reason: Option<String>,
+}
+
+/// A tool call whose arguments are still streaming in, reported before its
+/// [`Approval`] so the UI can start rendering.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ProvisionalApproval {
+    /// The id of the tool call, if already known. It's the same as
+    /// [`Approval::tool_call_id`] of the final approval.
+    pub tool_call_id: Option<String>,
+    /// The name of the tool.
+    pub tool_name: String,
+    /// The arguments received so far, which are usually incomplete JSON.
+    pub partial_arguments: String,
+}
+
 /// Approval for a tool call request.
 pub struct Approval {
+    pub(crate) tool_name: String,
+    pub(crate) tool_call_id: String,
     what: String,
     justification: String,
     pub(crate) on_result: Option<Box<dyn FnOnce(ApprovalResult) + Send>>,
@@ -21,12 +52,30 @@ impl Approval {
         justification: S2,
     ) -> Self {
         Self {
+            tool_name: String::new(),
+            tool_call_id: String::new(),
             what: what.into(),
             justification: justification.into(),
             on_result: None,
         }
     }
 
+    /// Returns the name of the tool that requests the approval.
+    ///
+    /// It's filled in by the agent, and empty for a newly created approval.
+    #[inline]
+    pub fn tool_name(&self) -> &str {
+        &self.tool_name
+    }
+
+    /// Returns the id of the tool call that requests the approval.
+    ///
+    /// Like [`Approval::tool_name`], it's filled in by the agent.
+    #[inline]
+    pub fn tool_call_id(&self) -> &str {
+        &self.tool_call_id
+    }
+
     /// Returns what the approval is for.
     #[inline]
     pub fn what(&self) -> &str {
@@ -67,6 +116,8 @@ impl Approval {
 impl Debug for Approval {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Approval")
+            .field("tool_name", &self.tool_name)
+            .field("tool_call_id", &self.tool_call_id)
             .field("what", &self.what)
             .field("justification", &self.justification)
             .finish_non_exhaustive()
@@ -10,6 +10,10 @@ pub enum ErrorKind {
     ExecutionError,
     /// The tool was not allowed to execute by user.
     UserRejected,
+    /// The tool was not allowed to access the resource by its policy.
+    PermissionDenied,
+    /// The tool call was cut short because the turn was interrupted.
+    Interrupted,
 }
 
 impl Display for ErrorKind {
@@ -18,6 +22,8 @@ impl Display for ErrorKind {
             ErrorKind::InvalidInput => write!(f, "Invalid input"),
             ErrorKind::ExecutionError => write!(f, "Execution error"),
             ErrorKind::UserRejected => write!(f, "User rejected"),
+            ErrorKind::PermissionDenied => write!(f, "Permission denied"),
+            ErrorKind::Interrupted => write!(f, "Interrupted"),
         }
     }
 }
@@ -27,6 +33,7 @@ impl Display for ErrorKind {
 pub struct Error {
     kind: ErrorKind,
     reason: Option<String>,
+    retryable: bool,
 }
 
 impl Error {
@@ -36,6 +43,7 @@ impl Error {
         Self {
             kind: ErrorKind::InvalidInput,
             reason: None,
+            retryable: false,
         }
     }
 
@@ -45,6 +53,7 @@ impl Error {
         Self {
             kind: ErrorKind::ExecutionError,
             reason: None,
+            retryable: false,
         }
     }
 
@@ -54,6 +63,27 @@ impl Error {
         Self {
             kind: ErrorKind::UserRejected,
             reason: None,
+            retryable: false,
+        }
+    }
+
+    /// Creates a new error with the `PermissionDenied` kind.
+    #[inline]
+    pub fn permission_denied() -> Self {
+        Self {
+            kind: ErrorKind::PermissionDenied,
+            reason: None,
+            retryable: false,
+        }
+    }
+
+    /// Creates a new error with the `Interrupted` kind.
+    #[inline]
+    pub fn interrupted() -> Self {
+        Self {
+            kind: ErrorKind::Interrupted,
+            reason: None,
+            retryable: false,
         }
     }
 
@@ -61,11 +91,31 @@ impl Error {
     #[inline]
     pub fn with_reason<S: Into<String>>(self, reason: S) -> Self {
         Self {
-            kind: self.kind,
             reason: Some(reason.into()),
+            ..self
         }
     }
 
+    /// Marks the error as transient, so the model is told that calling the
+    /// tool again may succeed.
+    #[inline]
+    pub fn with_retryable(self, retryable: bool) -> Self {
+        Self { retryable, ..self }
+    }
+
+    /// Returns the kind of the error.
+    #[inline]
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// Returns whether the error is transient, see
+    /// [`Error::with_retryable`].
+    #[inline]
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+
     /// Returns the reason for the error.
     #[inline]
     pub fn reason(&self) -> Cow<'_, str> {
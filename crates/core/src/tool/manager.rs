@@ -1,24 +1,100 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::future::ready;
 use std::pin::Pin;
 use std::sync::Arc;
 
 use little_agent_model::{ModelTool, ToolCallRequest};
 
 use crate::Tool;
-use crate::tool::object::{ToolObject, ToolObjectImpl};
-use crate::tool::{Approval, ToolResult};
+use crate::tool::object::{
+    DecisionHandler, ExecutionContext, ExecutionStartHandler, ToolObject,
+    ToolObjectImpl,
+};
+use crate::tool::{Approval, ApprovalDecision, Error, Metrics, ToolResult};
+
+type ToolFilter = Box<dyn Fn(&ModelTool) -> bool + Send + Sync>;
+type BatchHandler = Box<dyn Fn(Vec<Approval>) + Send + Sync>;
 
 /// An object that manages toolset and handles requests from the model.
 #[derive(Default)]
 pub struct Manager {
     tools: HashMap<String, Arc<dyn ToolObject>>,
     on_request: Option<Box<dyn Fn(Approval) + Send + Sync>>,
+    on_batch: Option<BatchHandler>,
+    on_decision: Option<DecisionHandler>,
+    on_execution_start: Option<ExecutionStartHandler>,
+    metrics: Metrics,
+    dry_run: bool,
+    unknown_tool_policy: UnknownToolPolicy,
+    filter: Option<ToolFilter>,
+}
+
+/// How to handle the calls to tools that are not registered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum UnknownToolPolicy {
+    /// Responds with an error result, so the model can recover.
+    #[default]
+    Report,
+    /// Drops the call silently. The model is left with a tool call that has
+    /// no result, which may be rejected by some providers.
+    Ignore,
+}
+
+/// A pending change to the toolset of a [`Manager`].
+pub enum Change {
+    Add(Arc<dyn ToolObject>),
+    Remove(String),
+}
+
+impl Change {
+    #[inline]
+    pub fn add<T: Tool + 'static>(tool: T) -> Self {
+        Change::Add(Arc::new(ToolObjectImpl::new(tool)))
+    }
+}
+
+impl Debug for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Add(tool) => {
+                f.debug_tuple("Add").field(&tool.name()).finish()
+            }
+            Change::Remove(name) => {
+                f.debug_tuple("Remove").field(name).finish()
+            }
+        }
+    }
 }
 
 impl Manager {
+    #[inline]
     pub fn add_tool<T: Tool + 'static>(&mut self, tool: T) {
-        let name = tool.name().to_owned();
-        self.tools.insert(name, Arc::new(ToolObjectImpl(tool)));
+        self.apply_change(Change::add(tool));
+    }
+
+    #[inline]
+    pub fn add_tool_described<T: Tool + 'static>(
+        &mut self,
+        tool: T,
+        description: String,
+    ) {
+        let tool = ToolObjectImpl::new(tool).with_description(description);
+        self.apply_change(Change::Add(Arc::new(tool)));
+    }
+
+    pub fn apply_change(&mut self, change: Change) {
+        match change {
+            Change::Add(tool) => {
+                self.tools.insert(tool.name().to_owned(), tool);
+            }
+            Change::Remove(name) => {
+                if self.tools.remove(&name).is_none() {
+                    warn!("removing a tool that doesn't exist: {name}");
+                }
+            }
+        }
     }
 
     #[inline]
@@ -29,18 +105,68 @@ impl Manager {
         self.on_request = Some(Box::new(on_request));
     }
 
+    #[inline]
+    pub fn on_batch<F: Fn(Vec<Approval>) + Send + Sync + 'static>(
+        &mut self,
+        on_batch: F,
+    ) {
+        self.on_batch = Some(Box::new(on_batch));
+    }
+
+    #[inline]
+    pub fn on_decision<F>(&mut self, on_decision: F)
+    where
+        F: Fn(&ApprovalDecision) + Send + Sync + 'static,
+    {
+        self.on_decision = Some(Arc::new(on_decision));
+    }
+
+    #[inline]
+    pub fn on_execution_start<F>(&mut self, on_execution_start: F)
+    where
+        F: Fn(&str, &str) + Send + Sync + 'static,
+    {
+        self.on_execution_start = Some(Arc::new(on_execution_start));
+    }
+
+    #[inline]
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    #[inline]
+    pub fn set_unknown_tool_policy(&mut self, policy: UnknownToolPolicy) {
+        self.unknown_tool_policy = policy;
+    }
+
+    #[inline]
+    pub fn set_filter<F>(&mut self, filter: F)
+    where
+        F: Fn(&ModelTool) -> bool + Send + Sync + 'static,
+    {
+        self.filter = Some(Box::new(filter));
+    }
+
+    #[inline]
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Returns the definitions of the tools exposed to the model.
     #[inline]
     pub fn definitions(&self) -> Vec<ModelTool> {
         self.tools
             .values()
-            .map(|tool| ModelTool {
-                name: tool.name().to_owned(),
-                description: tool.description().to_owned(),
-                parameters: tool.parameter_schema().clone(),
-            })
+            .map(|tool| definition_of(tool.as_ref()))
+            .filter(|definition| self.is_exposed(definition))
             .collect()
     }
 
+    #[inline]
+    fn is_exposed(&self, definition: &ModelTool) -> bool {
+        self.filter.as_ref().is_none_or(|filter| filter(definition))
+    }
+
     pub fn handle_requests<S>(&self, requests: Vec<ToolCallRequest>, spawner: S)
     where
         S: FnMut(String, Pin<Box<dyn Future<Output = ToolResult> + Send>>),
@@ -50,25 +176,69 @@ impl Manager {
         let span = debug_span!("tool manager");
         let _enter = span.enter();
 
+        let batch = self.on_batch.as_ref().map(|_| RefCell::new(vec![]));
+        let ctx = ExecutionContext {
+            on_request: &self.on_request,
+            batch: batch.as_ref(),
+            on_decision: &self.on_decision,
+            on_execution_start: &self.on_execution_start,
+            metrics: &self.metrics,
+            dry_run: self.dry_run,
+        };
         for req in requests {
             let Some(tool) = self.tools.get(&req.name) else {
                 warn!("tool not found: {}", req.name);
+                if self.unknown_tool_policy == UnknownToolPolicy::Report {
+                    let reason = format!("unknown tool: {}", req.name);
+                    let err = Error::invalid_input().with_reason(reason);
+                    spawner(req.id, Box::pin(ready(Err(err))));
+                }
                 continue;
             };
+            // The model may still call a hidden tool, e.g. from the history.
+            if !self.is_exposed(&definition_of(tool.as_ref())) {
+                warn!("tool not exposed: {}", req.name);
+                let reason = format!("tool is not available: {}", req.name);
+                let err = Error::invalid_input().with_reason(reason);
+                spawner(req.id, Box::pin(ready(Err(err))));
+                continue;
+            }
 
             let id = req.id;
             let arguments = req.arguments;
             trace!("spawning a tool ({id}) with args: {arguments:?}");
-            spawner(id, Arc::clone(tool).execute(arguments, &self.on_request));
+            let fut = Arc::clone(tool).execute(&id, arguments, &ctx);
+            spawner(id, fut);
+        }
+
+        let Some(on_batch) = &self.on_batch else {
+            return;
+        };
+        let approvals = batch.map(RefCell::into_inner).unwrap_or_default();
+        if !approvals.is_empty() {
+            on_batch(approvals);
         }
     }
 }
 
+#[inline]
+fn definition_of(tool: &dyn ToolObject) -> ModelTool {
+    ModelTool {
+        name: tool.name().to_owned(),
+        description: tool.description().to_owned(),
+        parameters: tool.parameter_schema().clone(),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::future::ready;
+    use std::sync::Mutex;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
 
     use serde_json::{Value, json};
+    use tokio::time::sleep;
 
     use super::*;
 
@@ -91,15 +261,48 @@ mod tests {
             EMPTY_SCHEMA
         }
 
+        fn make_approval(&self, _input: &Self::Input) -> Approval {
+            Approval::new("test", "")
+        }
+
+        fn execute(
+            &self,
+            _input: Self::Input,
+        ) -> impl Future<Output = ToolResult> + Send + 'static {
+            ready(Ok("success".into()))
+        }
+    }
+
+    struct SlowTool;
+
+    impl Tool for SlowTool {
+        type Input = serde_json::Value;
+
+        fn name(&self) -> &str {
+            "slow_tool"
+        }
+
+        fn description(&self) -> &str {
+            "A slow tool"
+        }
+
+        fn parameter_schema(&self) -> &serde_json::Value {
+            EMPTY_SCHEMA
+        }
+
         fn make_approval(&self, _input: &Self::Input) -> Approval {
             Approval::new("", "")
         }
 
+        #[allow(clippy::manual_async_fn)]
         fn execute(
             &self,
             _input: Self::Input,
         ) -> impl Future<Output = ToolResult> + Send + 'static {
-            ready(Ok("success".to_owned()))
+            async {
+                sleep(Duration::from_millis(1)).await;
+                Ok("done".into())
+            }
         }
     }
 
@@ -121,19 +324,198 @@ mod tests {
 
         assert_eq!(spawned_ids.len(), 1);
         assert_eq!(spawned_ids[0], "tool:1");
+    }
+
+    #[tokio::test]
+    async fn test_unknown_tool() {
+        let mut manager = Manager::default();
+        manager.add_tool(TestTool);
 
-        // Test with non-existent tool.
         let requests = vec![ToolCallRequest {
             id: "tool:1".to_owned(),
             name: "read_tool".to_owned(),
             arguments: json!({}),
         }];
 
+        let mut futures = vec![];
+        manager.handle_requests(requests.clone(), |id, future| {
+            futures.push((id, future));
+        });
+        assert_eq!(futures.len(), 1);
+        let (id, future) = futures.pop().unwrap();
+        assert_eq!(id, "tool:1");
+        let err = future.await.unwrap_err();
+        assert_eq!(err.reason(), "unknown tool: read_tool");
+
+        manager.set_unknown_tool_policy(UnknownToolPolicy::Ignore);
         let mut spawned_ids: Vec<String> = vec![];
         manager.handle_requests(requests, |id, _future| {
             spawned_ids.push(id);
         });
-
         assert!(spawned_ids.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_execution_start() {
+        let mut manager = Manager::default();
+        manager.add_tool(TestTool);
+        let approvals = Arc::new(Mutex::new(vec![]));
+        manager.on_request({
+            let approvals = Arc::clone(&approvals);
+            move |approval| approvals.lock().unwrap().push(approval)
+        });
+        let events = Arc::new(Mutex::new(vec![]));
+        manager.on_execution_start({
+            let events = Arc::clone(&events);
+            move |id, name| {
+                events.lock().unwrap().push(format!("start {id} {name}"));
+            }
+        });
+
+        let requests = vec![ToolCallRequest {
+            id: "tool:1".to_owned(),
+            name: "test_tool".to_owned(),
+            arguments: json!({}),
+        }];
+        let mut futures = vec![];
+        manager.handle_requests(requests, |_, future| {
+            futures.push(future);
+        });
+        let future = futures.pop().unwrap();
+
+        // The approval is pending, so the tool hasn't started yet.
+        assert!(events.lock().unwrap().is_empty());
+        events.lock().unwrap().push("approved".to_owned());
+        approvals.lock().unwrap().pop().unwrap().approve();
+        let result = future.await.unwrap();
+        events.lock().unwrap().push(result.to_text().into_owned());
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            ["approved", "start tool:1 test_tool", "success"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_approval_decisions() {
+        let mut manager = Manager::default();
+        manager.add_tool(TestTool);
+        let counter = AtomicUsize::new(0);
+        manager.on_request(move |approval| {
+            if counter.fetch_add(1, Ordering::Relaxed) == 0 {
+                approval.approve();
+            } else {
+                approval.reject(Some("not allowed".to_owned()));
+            }
+        });
+        let decisions = Arc::new(Mutex::new(vec![]));
+        manager.on_decision({
+            let decisions = Arc::clone(&decisions);
+            move |decision| decisions.lock().unwrap().push(decision.clone())
+        });
+
+        let requests = (0..2)
+            .map(|i| ToolCallRequest {
+                id: format!("tool:{i}"),
+                name: "test_tool".to_owned(),
+                arguments: json!({}),
+            })
+            .collect();
+        let mut futures = vec![];
+        manager.handle_requests(requests, |_, future| {
+            futures.push(future);
+        });
+        assert!(futures.remove(0).await.is_ok());
+        assert!(futures.remove(0).await.is_err());
+
+        let decisions = decisions.lock().unwrap();
+        assert_eq!(
+            *decisions,
+            [
+                ApprovalDecision {
+                    tool_name: "test_tool".to_owned(),
+                    what: "test".to_owned(),
+                    approved: true,
+                    automatic: false,
+                    reason: None,
+                },
+                ApprovalDecision {
+                    tool_name: "test_tool".to_owned(),
+                    what: "test".to_owned(),
+                    approved: false,
+                    automatic: false,
+                    reason: Some("not allowed".to_owned()),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_approval_batch() {
+        let mut manager = Manager::default();
+        manager.add_tool(TestTool);
+        manager.add_tool(SlowTool);
+        manager.on_request(|_| panic!("approvals should be batched"));
+        let batches = Arc::new(Mutex::new(vec![]));
+        manager.on_batch({
+            let batches = Arc::clone(&batches);
+            move |approvals: Vec<Approval>| {
+                let names = approvals
+                    .iter()
+                    .map(|approval| approval.tool_name().to_owned())
+                    .collect::<Vec<_>>();
+                batches.lock().unwrap().push(names);
+                for approval in approvals {
+                    approval.approve();
+                }
+            }
+        });
+
+        let requests = ["test_tool", "slow_tool"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| ToolCallRequest {
+                id: format!("tool:{i}"),
+                name: name.to_owned(),
+                arguments: json!({}),
+            })
+            .collect();
+        let mut futures = vec![];
+        manager.handle_requests(requests, |_, future| {
+            futures.push(future);
+        });
+        for future in futures {
+            future.await.unwrap();
+        }
+
+        let batches = batches.lock().unwrap();
+        assert_eq!(*batches, [["test_tool", "slow_tool"]]);
+    }
+
+    #[tokio::test]
+    async fn test_metrics() {
+        let mut manager = Manager::default();
+        manager.add_tool(SlowTool);
+
+        for i in 0..3 {
+            let requests = vec![ToolCallRequest {
+                id: format!("tool:{i}"),
+                name: "slow_tool".to_owned(),
+                arguments: json!({}),
+            }];
+            let mut futures = vec![];
+            manager.handle_requests(requests, |_, future| {
+                futures.push(future);
+            });
+            for future in futures {
+                future.await.unwrap();
+            }
+        }
+
+        let stats = manager.metrics().get("slow_tool").unwrap();
+        assert_eq!(stats.invocations, 3);
+        assert_eq!(stats.errors, 0);
+        assert!(stats.total_duration >= Duration::from_millis(3));
+        assert_eq!(manager.metrics().get("test_tool"), None);
+    }
 }
@@ -0,0 +1,96 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Accumulated statistics of a tool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ToolStats {
+    /// How many times the tool has been executed.
+    pub invocations: u64,
+    /// How many executions ended with an error.
+    pub errors: u64,
+    /// Total time spent in executing the tool.
+    pub total_duration: Duration,
+}
+
+impl ToolStats {
+    /// Returns the ratio of failed executions, or `0.0` if the tool has
+    /// never been executed.
+    #[inline]
+    pub fn error_rate(&self) -> f64 {
+        if self.invocations == 0 {
+            return 0.0;
+        }
+        self.errors as f64 / self.invocations as f64
+    }
+
+    /// Returns the average execution time, if the tool has been executed.
+    #[inline]
+    pub fn average_duration(&self) -> Option<Duration> {
+        let invocations = u32::try_from(self.invocations).ok()?;
+        self.total_duration.checked_div(invocations)
+    }
+}
+
+/// Per-tool execution metrics.
+///
+/// This is a cheap handle that can be cloned and shared across threads.
+/// All clones observe the same underlying records. Only executions that
+/// were actually performed are recorded, which means rejected requests
+/// and invalid inputs are not counted.
+#[derive(Clone, Debug, Default)]
+pub struct Metrics {
+    stats: Arc<Mutex<HashMap<String, ToolStats>>>,
+}
+
+impl Metrics {
+    /// Returns the statistics of the specified tool.
+    #[inline]
+    pub fn get(&self, name: &str) -> Option<ToolStats> {
+        self.stats.lock().unwrap().get(name).copied()
+    }
+
+    /// Returns a snapshot of the statistics of all executed tools.
+    #[inline]
+    pub fn snapshot(&self) -> HashMap<String, ToolStats> {
+        self.stats.lock().unwrap().clone()
+    }
+
+    pub(crate) fn record(&self, name: &str, duration: Duration, is_err: bool) {
+        let mut stats = self.stats.lock().unwrap();
+        // Avoid allocating the key for tools that have been recorded.
+        if !stats.contains_key(name) {
+            stats.insert(name.to_owned(), ToolStats::default());
+        }
+        let tool_stats = stats.get_mut(name).unwrap();
+        tool_stats.invocations += 1;
+        tool_stats.total_duration += duration;
+        if is_err {
+            tool_stats.errors += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record() {
+        let metrics = Metrics::default();
+        assert_eq!(metrics.get("shell"), None);
+
+        metrics.record("shell", Duration::from_millis(10), false);
+        metrics
+            .clone()
+            .record("shell", Duration::from_millis(30), true);
+
+        let stats = metrics.get("shell").unwrap();
+        assert_eq!(stats.invocations, 2);
+        assert_eq!(stats.errors, 1);
+        assert_eq!(stats.total_duration, Duration::from_millis(40));
+        assert_eq!(stats.error_rate(), 0.5);
+        assert_eq!(stats.average_duration(), Some(Duration::from_millis(20)));
+        assert_eq!(metrics.snapshot().len(), 1);
+    }
+}
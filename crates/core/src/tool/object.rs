@@ -1,11 +1,30 @@
+use std::cell::RefCell;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Instant;
 
+use serde::Deserialize;
 use serde_json::Value;
 use tokio::sync::oneshot;
 use tracing::Instrument;
 
-use super::{Approval, Error, Tool, ToolResult};
+use super::approval::ApprovalResult;
+use super::{Approval, ApprovalDecision, Error, Metrics, Tool, ToolResult};
+
+pub(crate) type DecisionHandler = Arc<dyn Fn(&ApprovalDecision) + Send + Sync>;
+pub(crate) type ExecutionStartHandler = Arc<dyn Fn(&str, &str) + Send + Sync>;
+
+/// Options and hooks for executing a tool, provided by the tool manager.
+pub(crate) struct ExecutionContext<'a> {
+    pub on_request: &'a Option<Box<dyn Fn(Approval) + Send + Sync>>,
+    /// Collects the approvals instead of sending them to `on_request`, when
+    /// they are delivered as a batch.
+    pub batch: Option<&'a RefCell<Vec<Approval>>>,
+    pub on_decision: &'a Option<DecisionHandler>,
+    pub on_execution_start: &'a Option<ExecutionStartHandler>,
+    pub metrics: &'a Metrics,
+    pub dry_run: bool,
+}
 
 pub(crate) trait ToolObject: Send + Sync + 'static {
     fn name(&self) -> &str;
@@ -16,36 +35,109 @@ pub(crate) trait ToolObject: Send + Sync + 'static {
 
     fn execute(
         self: Arc<Self>,
+        call_id: &str,
         arguments: Value,
-        on_request: &Option<Box<dyn Fn(Approval) + Send + Sync>>,
+        ctx: &ExecutionContext<'_>,
     ) -> Pin<Box<dyn Future<Output = ToolResult> + Send>>;
 }
 
-pub(crate) struct ToolObjectImpl<T: Tool>(pub T);
+pub(crate) struct ToolObjectImpl<T: Tool> {
+    tool: T,
+    /// Replaces the description of the tool when set.
+    description: Option<String>,
+    #[cfg(feature = "schema-validation")]
+    validator: std::sync::OnceLock<Option<jsonschema::Validator>>,
+}
+
+impl<T: Tool> ToolObjectImpl<T> {
+    #[inline]
+    pub fn new(tool: T) -> Self {
+        Self {
+            tool,
+            description: None,
+            #[cfg(feature = "schema-validation")]
+            validator: Default::default(),
+        }
+    }
+
+    #[inline]
+    pub fn with_description(mut self, description: String) -> Self {
+        self.description = Some(description);
+        self
+    }
+
+    /// Validates the arguments against the parameter schema of the tool.
+    ///
+    /// The validator is compiled on first use. If the schema itself is
+    /// invalid, the validation is skipped.
+    #[cfg(feature = "schema-validation")]
+    fn validate(&self, arguments: &Value) -> Result<(), Error> {
+        let validator = self.validator.get_or_init(|| {
+            let schema = self.tool.parameter_schema();
+            match jsonschema::validator_for(schema) {
+                Ok(validator) => Some(validator),
+                Err(err) => {
+                    let name = self.tool.name();
+                    warn!("skipping validation for tool {name}: {err}");
+                    None
+                }
+            }
+        });
+        let Some(validator) = validator else {
+            return Ok(());
+        };
+
+        let problems: Vec<_> = validator
+            .iter_errors(arguments)
+            .map(|err| {
+                let path = err.instance_path().to_string();
+                let path = if path.is_empty() { "/" } else { &path };
+                format!("{path}: {err}")
+            })
+            .collect();
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(Error::invalid_input().with_reason(problems.join("\n")))
+    }
+
+    #[cfg(not(feature = "schema-validation"))]
+    #[inline]
+    fn validate(&self, _arguments: &Value) -> Result<(), Error> {
+        Ok(())
+    }
+}
 
 impl<T: Tool> ToolObject for ToolObjectImpl<T> {
     #[inline]
     fn name(&self) -> &str {
-        self.0.name()
+        self.tool.name()
     }
 
     #[inline]
     fn description(&self) -> &str {
-        self.0.description()
+        match &self.description {
+            Some(description) => description,
+            None => self.tool.description(),
+        }
     }
 
     #[inline]
     fn parameter_schema(&self) -> &Value {
-        self.0.parameter_schema()
+        self.tool.parameter_schema()
     }
 
     #[inline]
     fn execute(
         self: Arc<Self>,
+        call_id: &str,
         arguments: Value,
-        on_request: &Option<Box<dyn Fn(Approval) + Send + Sync>>,
+        ctx: &ExecutionContext<'_>,
     ) -> Pin<Box<dyn Future<Output = ToolResult> + Send>> {
-        let input: T::Input = match serde_json::from_value(arguments) {
+        if let Err(err) = self.validate(&arguments) {
+            return Box::pin(std::future::ready(ToolResult::Err(err)));
+        }
+        let input = match T::Input::deserialize(&arguments) {
             Ok(input) => input,
             Err(err) => {
                 let reason = format!("{err}");
@@ -56,24 +148,49 @@ impl<T: Tool> ToolObject for ToolObjectImpl<T> {
         };
 
         let (approval_res_tx, approval_res_rx) = oneshot::channel();
-        let mut approval = self.0.make_approval(&input);
+        let mut approval = self.tool.make_approval(&input);
+        approval.tool_name = self.tool.name().to_owned();
+        approval.tool_call_id = call_id.to_owned();
+        let what = approval.what().to_owned();
+        let automatic = !self.tool.requires_approval(&input)
+            || (ctx.on_request.is_none() && ctx.batch.is_none());
         approval.on_result = Some(Box::new(move |result| {
             approval_res_tx.send(result).ok();
         }));
 
-        if let Some(on_request) = on_request {
-            on_request(approval);
-        } else {
-            // No request handler provided, assuming yolo mode.
+        if automatic {
+            // The tool doesn't need the approval, or no request handler is
+            // provided, assuming yolo mode.
             approval.approve();
+        } else if let Some(batch) = ctx.batch {
+            batch.borrow_mut().push(approval);
+        } else if let Some(on_request) = ctx.on_request {
+            on_request(approval);
         }
 
+        let metrics = ctx.metrics.clone();
+        let on_decision = ctx.on_decision.clone();
+        let on_execution_start = ctx.on_execution_start.clone();
+        let call_id = call_id.to_owned();
+        let dry_run = ctx.dry_run;
         Box::pin(
             async move {
-                let Ok(approval_res) = approval_res_rx.await else {
-                    return ToolResult::Err(Error::user_rejected());
-                };
+                // A dropped approval is treated as a rejection.
+                let approval_res =
+                    approval_res_rx.await.unwrap_or(ApprovalResult {
+                        approved: false,
+                        why: None,
+                    });
                 trace!("tool call approval result: {approval_res:?}");
+                if let Some(on_decision) = on_decision {
+                    on_decision(&ApprovalDecision {
+                        tool_name: self.tool.name().to_owned(),
+                        what,
+                        approved: approval_res.approved,
+                        automatic,
+                        reason: approval_res.why.clone(),
+                    });
+                }
                 if !approval_res.approved {
                     let mut err = Error::user_rejected();
                     if let Some(reason) = approval_res.why {
@@ -81,9 +198,121 @@ impl<T: Tool> ToolObject for ToolObjectImpl<T> {
                     }
                     return ToolResult::Err(err);
                 }
-                self.0.execute(input).await
+                if dry_run {
+                    let name = self.tool.name();
+                    return Ok(format!(
+                        "[dry-run] would run {name} with {arguments}"
+                    )
+                    .into());
+                }
+
+                if let Some(on_execution_start) = on_execution_start {
+                    on_execution_start(&call_id, self.tool.name());
+                }
+                let start = Instant::now();
+                let mut cancel_guard = CancelGuard {
+                    tool: &self.tool,
+                    armed: true,
+                };
+                let result = self.tool.execute(input).await;
+                cancel_guard.armed = false;
+                metrics.record(
+                    self.tool.name(),
+                    start.elapsed(),
+                    result.is_err(),
+                );
+                result
             }
             .instrument(debug_span!("tool execute")),
         )
     }
 }
+
+/// Calls [`Tool::on_cancel`] if dropped while still armed, i.e. when the
+/// execution is cancelled.
+struct CancelGuard<'a, T: Tool> {
+    tool: &'a T,
+    armed: bool,
+}
+
+impl<T: Tool> Drop for CancelGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.armed {
+            debug!("tool execution is cancelled: {}", self.tool.name());
+            self.tool.on_cancel();
+        }
+    }
+}
+
+#[cfg(all(test, feature = "schema-validation"))]
+mod tests {
+    use std::future::ready;
+    use std::sync::LazyLock;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::tool::ErrorKind;
+
+    static SCHEMA: LazyLock<Value> = LazyLock::new(|| {
+        json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string" },
+                "mode": { "enum": ["read", "write"] },
+            },
+            "required": ["path", "mode"],
+        })
+    });
+
+    struct OpenFileTool;
+
+    impl Tool for OpenFileTool {
+        type Input = Value;
+
+        fn name(&self) -> &str {
+            "open_file"
+        }
+
+        fn description(&self) -> &str {
+            "Opens a file"
+        }
+
+        fn parameter_schema(&self) -> &Value {
+            &SCHEMA
+        }
+
+        fn make_approval(&self, _input: &Self::Input) -> Approval {
+            Approval::new("", "")
+        }
+
+        fn execute(
+            &self,
+            _input: Self::Input,
+        ) -> impl Future<Output = ToolResult> + Send + 'static {
+            ready(Ok("opened".into()))
+        }
+    }
+
+    #[test]
+    fn test_missing_required_field() {
+        let tool = ToolObjectImpl::new(OpenFileTool);
+        let err = tool.validate(&json!({ "path": "a.txt" })).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(err.reason().starts_with("/: "));
+        assert!(err.reason().contains("\"mode\""));
+    }
+
+    #[test]
+    fn test_out_of_enum_value() {
+        let tool = ToolObjectImpl::new(OpenFileTool);
+        let err = tool
+            .validate(&json!({ "path": "a.txt", "mode": "delete" }))
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+        assert!(err.reason().starts_with("/mode: "));
+
+        let args = json!({ "path": "a.txt", "mode": "read" });
+        assert!(tool.validate(&args).is_ok());
+    }
+}
@@ -0,0 +1,161 @@
+use super::Error;
+
+const CURSOR_PREFIX: &str = "p1.";
+
+/// A page of items taken from a longer sequence, with a cursor to continue
+/// from when there are more.
+///
+/// Tools that produce long outputs can accept an optional cursor in their
+/// input, and resume from it with [`Paginated::resume`]. The cursor is
+/// opaque to the model, it's only meant to be passed back verbatim.
+///
+/// # Examples
+///
+/// ```
+/// use little_agent_core::tool::Paginated;
+///
+/// let page = Paginated::resume(1..=5, None, 3).unwrap();
+/// assert_eq!(page.items(), [1, 2, 3]);
+///
+/// let cursor = page.next_cursor().unwrap();
+/// let page = Paginated::resume(1..=5, Some(cursor), 3).unwrap();
+/// assert_eq!(page.items(), [4, 5]);
+/// assert!(page.next_cursor().is_none());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Paginated<T> {
+    items: Vec<T>,
+    offset: usize,
+    next_cursor: Option<String>,
+}
+
+impl<T> Paginated<T> {
+    /// Takes at most `page_size` items, skipping the first `offset` ones.
+    pub fn new<I>(iter: I, offset: usize, page_size: usize) -> Self
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut iter = iter.into_iter().skip(offset);
+        let items: Vec<_> = iter.by_ref().take(page_size).collect();
+        // Only peek one more item, the sequence can be expensive to walk.
+        let next_cursor = iter.next().map(|_| {
+            let next_offset = offset + items.len();
+            format!("{CURSOR_PREFIX}{next_offset:x}")
+        });
+        Self {
+            items,
+            offset,
+            next_cursor,
+        }
+    }
+
+    /// Takes the page after `cursor`, or the first page if it's `None`.
+    ///
+    /// Returns an invalid input error if the cursor is malformed.
+    pub fn resume<I>(
+        iter: I,
+        cursor: Option<&str>,
+        page_size: usize,
+    ) -> Result<Self, Error>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let offset = match cursor {
+            Some(cursor) => parse_cursor(cursor)?,
+            None => 0,
+        };
+        Ok(Self::new(iter, offset, page_size))
+    }
+
+    /// Returns the items in this page.
+    #[inline]
+    pub fn items(&self) -> &[T] {
+        &self.items
+    }
+
+    /// Consumes the page and returns its items.
+    #[inline]
+    pub fn into_items(self) -> Vec<T> {
+        self.items
+    }
+
+    /// Returns the number of items skipped before this page.
+    #[inline]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the cursor of the next page, or `None` if this is the last
+    /// one.
+    #[inline]
+    pub fn next_cursor(&self) -> Option<&str> {
+        self.next_cursor.as_deref()
+    }
+
+    /// Returns whether there are more items after this page.
+    ///
+    /// The output made from a truncated page should be marked with
+    /// [`ToolOutput::truncated`].
+    ///
+    /// [`ToolOutput::truncated`]: crate::tool::ToolOutput::truncated
+    #[inline]
+    pub fn is_truncated(&self) -> bool {
+        self.next_cursor.is_some()
+    }
+
+    /// Returns a line telling the model how to continue, or `None` if this
+    /// is the last page.
+    pub fn continuation_hint(&self) -> Option<String> {
+        let cursor = self.next_cursor()?;
+        Some(format!("[pass cursor \"{cursor}\" to see the rest]"))
+    }
+}
+
+fn parse_cursor(cursor: &str) -> Result<usize, Error> {
+    cursor
+        .strip_prefix(CURSOR_PREFIX)
+        .and_then(|offset| usize::from_str_radix(offset, 16).ok())
+        .ok_or_else(|| {
+            Error::invalid_input()
+                .with_reason(format!("invalid cursor: {cursor}"))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::ErrorKind;
+
+    #[test]
+    fn test_two_call_pagination() {
+        let lines = || (1..=7).map(|i| format!("line {i}"));
+
+        let page = Paginated::resume(lines(), None, 4).unwrap();
+        assert_eq!(page.items(), ["line 1", "line 2", "line 3", "line 4"]);
+        assert_eq!(page.offset(), 0);
+        assert!(page.is_truncated());
+        let hint = page.continuation_hint().unwrap();
+        let cursor = page.next_cursor().unwrap();
+        assert!(hint.contains(cursor));
+
+        let page = Paginated::resume(lines(), Some(cursor), 4).unwrap();
+        assert_eq!(page.items(), ["line 5", "line 6", "line 7"]);
+        assert_eq!(page.offset(), 4);
+        assert!(page.next_cursor().is_none());
+        assert!(!page.is_truncated());
+        assert!(page.continuation_hint().is_none());
+    }
+
+    #[test]
+    fn test_exact_page_has_no_cursor() {
+        let page = Paginated::new(0..4, 0, 4);
+        assert_eq!(page.items(), [0, 1, 2, 3]);
+        assert!(page.next_cursor().is_none());
+    }
+
+    #[test]
+    fn test_invalid_cursor() {
+        let err = Paginated::resume(0..4, Some("bogus"), 2).unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,172 @@
+//! Transcript logging.
+//!
+//! A [`TranscriptWriter`] appends the transcripts of an agent to a writer as
+//! JSON lines, attach it with [`AgentBuilder::with_transcript_writer`] or
+//! [`AgentBuilder::with_transcript_log`].
+//!
+//! [`AgentBuilder::with_transcript_writer`]: crate::AgentBuilder::with_transcript_writer
+//! [`AgentBuilder::with_transcript_log`]: crate::AgentBuilder::with_transcript_log
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use little_agent_model::ModelMessage;
+use serde::Serialize;
+
+use crate::TranscriptSource;
+use crate::conversation::Item as ConversationItem;
+
+/// Appends transcripts to a writer as JSON lines.
+///
+/// Each line is an object with the `source` (`"user"`, `"assistant"` or
+/// `"tool"`), the `transcript`, and the `timestamp` in milliseconds since
+/// the Unix epoch. When attached to an agent, a line is written for each
+/// item added to the conversation, so a streamed response makes a single
+/// line. The writes are buffered, and flushed at the end of each turn.
+///
+/// This is a cheap handle that can be cloned and shared across threads.
+#[derive(Clone)]
+pub struct TranscriptWriter {
+    writer: Arc<Mutex<BufWriter<Box<dyn Write + Send>>>>,
+}
+
+#[derive(Serialize)]
+struct Record<'a> {
+    source: TranscriptSource,
+    transcript: &'a str,
+    timestamp: u64,
+}
+
+impl TranscriptWriter {
+    /// Creates a transcript writer that writes to `writer`.
+    pub fn new<W: Write + Send + 'static>(writer: W) -> Self {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        Self {
+            writer: Arc::new(Mutex::new(BufWriter::new(writer))),
+        }
+    }
+
+    /// Creates a transcript writer that appends to the file at `path`,
+    /// creating it if it doesn't exist.
+    pub fn append_to_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file: File =
+            OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(file))
+    }
+
+    /// Appends a transcript as a JSON line.
+    ///
+    /// Errors are logged and ignored, so logging never breaks the agent.
+    pub fn write(&self, transcript: &str, source: TranscriptSource) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_millis() as u64)
+            .unwrap_or_default();
+        let record = Record {
+            source,
+            transcript,
+            timestamp,
+        };
+
+        let mut writer = self.writer.lock().unwrap();
+        let result = serde_json::to_writer(&mut *writer, &record)
+            .map_err(io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"));
+        if let Err(err) = result {
+            warn!("failed to write the transcript: {err}");
+        }
+    }
+
+    /// Appends the transcript of a conversation item. System messages are
+    /// skipped.
+    pub(crate) fn write_item(&self, item: &ConversationItem) {
+        let source = match item.message() {
+            ModelMessage::User(_) => TranscriptSource::User,
+            ModelMessage::Assistant(_)
+            | ModelMessage::AssistantToolCalls { .. }
+            | ModelMessage::Opaque(_) => TranscriptSource::Assistant,
+            ModelMessage::Tool(_) => TranscriptSource::Tool,
+            ModelMessage::System(_) => return,
+        };
+        self.write(item.transcript(), source);
+    }
+
+    /// Flushes the buffered transcripts.
+    pub fn flush(&self) {
+        if let Err(err) = self.writer.lock().unwrap().flush() {
+            warn!("failed to flush the transcripts: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use little_agent_test_model::{
+        PresetEvent, PresetResponse, TestModelProvider,
+    };
+    use serde_json::Value;
+    use tokio::sync::watch;
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::AgentBuilder;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transcript_lines() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([
+                PresetEvent::MessageDelta("Hi, ".to_owned()),
+                PresetEvent::MessageDelta("how can ".to_owned()),
+                PresetEvent::MessageDelta("I help?".to_owned()),
+            ]),
+        );
+
+        let buffer = SharedBuffer::default();
+        let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+        let agent = AgentBuilder::with_model_provider(model_provider)
+            .with_transcript_writer(TranscriptWriter::new(buffer.clone()))
+            .on_idle(move || {
+                idle_tx.send(true).unwrap();
+            })
+            .build();
+        agent.enqueue_user_input("Hello").unwrap();
+
+        timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone());
+        let records: Vec<Value> = output
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["source"], "user");
+        assert_eq!(records[0]["transcript"], "Hello");
+        assert_eq!(records[1]["source"], "assistant");
+        assert_eq!(records[1]["transcript"], "Hi, how can I help?");
+        assert!(records.iter().all(|record| record["timestamp"].is_u64()));
+    }
+}
@@ -2,7 +2,8 @@
 
 use std::ffi::{CStr, c_char, c_void};
 use std::ops::Deref;
-use std::sync::{Arc, LazyLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 
 use little_agent_core::TranscriptSource;
 use little_agent_core::tool::Approval as ToolApproval;
@@ -11,13 +12,20 @@ use tokio::runtime::{Builder as RuntimeBuilder, Runtime};
 
 use crate::{Session, SessionBuilder};
 
-static TOKIO_RUNTIME: LazyLock<Runtime> = LazyLock::new(|| {
-    RuntimeBuilder::new_multi_thread()
-        .enable_all()
-        .worker_threads(4)
-        .build()
-        .unwrap()
-});
+const DEFAULT_WORKER_THREADS: usize = 4;
+
+static WORKER_THREADS: AtomicUsize = AtomicUsize::new(DEFAULT_WORKER_THREADS);
+static TOKIO_RUNTIME: OnceLock<Runtime> = OnceLock::new();
+
+fn tokio_runtime() -> &'static Runtime {
+    TOKIO_RUNTIME.get_or_init(|| {
+        RuntimeBuilder::new_multi_thread()
+            .enable_all()
+            .worker_threads(WORKER_THREADS.load(Ordering::Relaxed))
+            .build()
+            .unwrap()
+    })
+}
 
 /// Error codes returned by the C APIs.
 #[repr(u32)]
@@ -26,6 +34,30 @@ pub enum ErrorCode {
     Ok = 0,
     /// Invalid parameters or strings.
     Invalid = 1,
+    /// The agent has stopped unexpectedly, the session can't be used
+    /// anymore.
+    AgentDead = 2,
+    /// The runtime is already running, it can't be configured anymore.
+    AlreadyInitialized = 3,
+}
+
+/// Configures the runtime that drives the sessions.
+///
+/// It must be called before the first session is built, otherwise
+/// [`ErrorCode::AlreadyInitialized`] is returned and the call has no effect.
+/// If it's never called, the runtime uses 4 worker threads.
+///
+/// Returns [`ErrorCode::Invalid`] if `worker_threads` is zero.
+#[unsafe(no_mangle)]
+pub extern "C" fn la_runtime_configure(worker_threads: u32) -> ErrorCode {
+    if worker_threads == 0 {
+        return ErrorCode::Invalid;
+    }
+    if TOKIO_RUNTIME.get().is_some() {
+        return ErrorCode::AlreadyInitialized;
+    }
+    WORKER_THREADS.store(worker_threads as usize, Ordering::Relaxed);
+    ErrorCode::Ok
 }
 
 /// A wrapper around `SessionBuilder`. It's needed mainly because most methods
@@ -106,10 +138,13 @@ pub unsafe extern "C" fn la_session_builder_new_openai(
         (api_key, base_url, model)
     };
 
-    let config = OpenAIConfigBuilder::with_api_key(api_key)
+    let Ok(config) = OpenAIConfigBuilder::with_api_key(api_key)
         .with_base_url(base_url)
         .with_model(model)
-        .build();
+        .build()
+    else {
+        return ErrorCode::Invalid;
+    };
     let model_provider = OpenAIProvider::new(config);
     let builder = SessionBuilder::with_model_provider(model_provider);
     let builder_wrapper_ptr = Box::into_raw(Box::new(SessionBuilderWrapper {
@@ -181,6 +216,7 @@ pub unsafe extern "C" fn la_session_builder_set_callbacks(
                 let source = match source {
                     TranscriptSource::User => 0,
                     TranscriptSource::Assistant => 1,
+                    TranscriptSource::Tool => 2,
                 };
                 unsafe {
                     (wrapper.on_transcript.unwrap())(
@@ -240,7 +276,7 @@ pub unsafe extern "C" fn la_session_builder_build(
 ) -> *mut c_void {
     // We must enter the runtime before building the session, since it will
     // spawn the agent actor, which requires a runtime.
-    let runtime = &*TOKIO_RUNTIME;
+    let runtime = tokio_runtime();
     let _enter = runtime.enter();
 
     // SAFETY: Assume the caller has provided the valid pointer.
@@ -255,6 +291,8 @@ pub unsafe extern "C" fn la_session_builder_build(
 
 /// Sends a message to the session.
 ///
+/// Returns [`ErrorCode::AgentDead`] if the agent has stopped unexpectedly.
+///
 /// # Safety
 ///
 /// `session` must be a valid pointer returned from `la_session_builder_build`.
@@ -271,9 +309,73 @@ pub unsafe extern "C" fn la_session_send_message(
 
     // SAFETY: Assume the caller has provided the valid pointer.
     let session = unsafe { &*(session as *mut Session) };
-    session.send_message(message);
+    match session.send_message(message) {
+        Ok(()) => ErrorCode::Ok,
+        Err(_) => ErrorCode::AgentDead,
+    }
+}
 
-    ErrorCode::Ok
+/// Sends multiple messages to the session, in order.
+///
+/// All the strings are validated before sending, if any of them is null or
+/// not valid UTF-8, [`ErrorCode::Invalid`] is returned and none of the
+/// messages is sent. Returns [`ErrorCode::AgentDead`] if the agent has
+/// stopped unexpectedly.
+///
+/// # Safety
+///
+/// `session` must be a valid pointer returned from `la_session_builder_build`.
+/// `messages` must point to `count` string pointers, and each non-null string
+/// must contain a valid nul terminator at the end of the string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn la_session_send_messages(
+    session: *mut c_void,
+    messages: *const *const c_char,
+    count: usize,
+) -> ErrorCode {
+    if count == 0 {
+        return ErrorCode::Ok;
+    }
+    if messages.is_null() {
+        return ErrorCode::Invalid;
+    }
+
+    // SAFETY: Assume the caller has provided `count` valid pointers.
+    let message_ptrs = unsafe { std::slice::from_raw_parts(messages, count) };
+    let mut batch = Vec::with_capacity(count);
+    for &message_ptr in message_ptrs {
+        if message_ptr.is_null() {
+            return ErrorCode::Invalid;
+        }
+        let Ok(message) = unsafe { CStr::from_ptr(message_ptr) }.to_str()
+        else {
+            return ErrorCode::Invalid;
+        };
+        batch.push(message);
+    }
+
+    // SAFETY: Assume the caller has provided the valid pointer.
+    let session = unsafe { &*(session as *mut Session) };
+    match session.send_messages(&batch) {
+        Ok(()) => ErrorCode::Ok,
+        Err(_) => ErrorCode::AgentDead,
+    }
+}
+
+/// Returns 1 if the agent is idle, or 0 if it's processing a message.
+///
+/// A message just sent is only taken into account once its turn starts.
+/// It doesn't block, so it's safe to call from any thread, including the
+/// session callbacks, concurrently with `la_session_send_message`.
+///
+/// # Safety
+///
+/// `session` must be a valid pointer returned from `la_session_builder_build`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn la_session_is_idle(session: *mut c_void) -> u32 {
+    // SAFETY: Assume the caller has provided the valid pointer.
+    let session = unsafe { &*(session as *mut Session) };
+    session.is_idle() as u32
 }
 
 /// Approves a tool call request.
@@ -357,3 +459,130 @@ pub unsafe extern "C" fn la_tool_approval_get_justification(
     unsafe { out_len.write(justification.len()) };
     justification.as_ptr() as _
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use std::time::Duration;
+
+    use little_agent_model::ModelMessage;
+    use little_agent_test_model::{
+        PresetEvent, PresetResponse, TestModelProvider,
+    };
+
+    use super::*;
+
+    fn user_messages(model_provider: &TestModelProvider) -> Vec<String> {
+        let requests = model_provider.requests();
+        let Some(request) = requests.last() else {
+            return vec![];
+        };
+        request
+            .messages
+            .iter()
+            .filter_map(|msg| match msg {
+                ModelMessage::User(text) => Some(text.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_send_messages() {
+        let mut model_provider = TestModelProvider::default();
+        for text in ["Hi", "Hi again"] {
+            model_provider.add_user_input_step();
+            model_provider.add_assistant_response_step(
+                PresetResponse::with_events([PresetEvent::MessageDelta(
+                    text.to_owned(),
+                )]),
+            );
+        }
+        let session =
+            SessionBuilder::with_model_provider(model_provider.clone()).build();
+        let session_ptr = &session as *const Session as *mut c_void;
+
+        let messages = [c"first".as_ptr(), c"second".as_ptr()];
+        let code = unsafe {
+            la_session_send_messages(session_ptr, messages.as_ptr(), 2)
+        };
+        assert!(matches!(code, ErrorCode::Ok));
+
+        for _ in 0..50 {
+            if model_provider.requests().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(user_messages(&model_provider), ["first", "second"]);
+    }
+
+    #[tokio::test]
+    async fn test_send_messages_invalid() {
+        let model_provider = TestModelProvider::default();
+        let session =
+            SessionBuilder::with_model_provider(model_provider.clone()).build();
+        let session_ptr = &session as *const Session as *mut c_void;
+
+        let invalid = [b'h', 0xff, 0];
+        let messages = [c"valid".as_ptr(), invalid.as_ptr() as *const c_char];
+        let code = unsafe {
+            la_session_send_messages(session_ptr, messages.as_ptr(), 2)
+        };
+        assert!(matches!(code, ErrorCode::Invalid));
+        let messages = [c"valid".as_ptr(), ptr::null()];
+        let code = unsafe {
+            la_session_send_messages(session_ptr, messages.as_ptr(), 2)
+        };
+        assert!(matches!(code, ErrorCode::Invalid));
+
+        // Give the agent a chance to run, if anything was sent.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(model_provider.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_is_idle() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Hi".to_owned(),
+            )]),
+        );
+        model_provider.set_delay(Duration::from_millis(100));
+        let session =
+            SessionBuilder::with_model_provider(model_provider).build();
+        let session_ptr = &session as *const Session as *mut c_void;
+        assert_eq!(unsafe { la_session_is_idle(session_ptr) }, 1);
+
+        let code =
+            unsafe { la_session_send_message(session_ptr, c"Hello".as_ptr()) };
+        assert!(matches!(code, ErrorCode::Ok));
+
+        // Busy once the turn starts, and idle again after it ends.
+        for expected in [0, 1] {
+            for _ in 0..100 {
+                if unsafe { la_session_is_idle(session_ptr) } == expected {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+            assert_eq!(unsafe { la_session_is_idle(session_ptr) }, expected);
+        }
+    }
+
+    #[test]
+    fn test_runtime_configure() {
+        assert!(matches!(la_runtime_configure(0), ErrorCode::Invalid));
+        assert!(matches!(la_runtime_configure(2), ErrorCode::Ok));
+
+        let runtime = tokio_runtime();
+        assert_eq!(runtime.metrics().num_workers(), 2);
+        assert!(matches!(
+            la_runtime_configure(8),
+            ErrorCode::AlreadyInitialized
+        ));
+        assert_eq!(tokio_runtime().metrics().num_workers(), 2);
+    }
+}
@@ -3,61 +3,255 @@
 #[macro_use]
 extern crate tracing;
 
+use std::collections::HashMap;
 use std::env;
-use std::io::Write as _;
+use std::io::{self as std_io, Write as _};
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use indicatif::{ProgressBar, ProgressStyle};
-use little_agent::SessionBuilder;
+use little_agent::tools::parse_utc_offset;
+use little_agent::{Session, SessionBuilder};
 use little_agent_core::TranscriptSource;
+use little_agent_core::conversation::Conversation;
+use little_agent_core::prompt::render_template;
 use little_agent_core::tool::Approval as ToolApproval;
+use little_agent_model::ModelFinishReason;
 use little_agent_openai_model::{OpenAIConfigBuilder, OpenAIProvider};
 use owo_colors::OwoColorize;
+use serde::Serialize;
 use tokio::io::{self, AsyncBufReadExt};
 use tokio::select;
-use tokio::sync::mpsc;
+use tokio::signal;
+use tokio::sync::mpsc::{self, UnboundedReceiver};
 use tokio::time::sleep;
 
+#[derive(Serialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
 enum SessionEvent {
     Idle,
-    Transcript(String, TranscriptSource),
+    Transcript(String),
+    ToolOutput(String),
+    #[serde(serialize_with = "serialize_approval")]
     ToolCallRequest(ToolApproval),
 }
 
+fn serialize_approval<S: serde::Serializer>(
+    approval: &ToolApproval,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct ApprovalData<'a> {
+        tool_name: &'a str,
+        what: &'a str,
+        justification: &'a str,
+    }
+
+    ApprovalData {
+        tool_name: approval.tool_name(),
+        what: approval.what(),
+        justification: approval.justification(),
+    }
+    .serialize(serializer)
+}
+
+impl SessionEvent {
+    /// Maps a transcript to the event to render. User inputs are not echoed,
+    /// since they are typed by the user.
+    fn from_transcript(
+        transcript: &str,
+        source: TranscriptSource,
+    ) -> Option<Self> {
+        match source {
+            TranscriptSource::User => None,
+            TranscriptSource::Assistant if transcript.is_empty() => None,
+            TranscriptSource::Assistant => {
+                Some(SessionEvent::Transcript(transcript.to_owned()))
+            }
+            TranscriptSource::Tool => {
+                Some(SessionEvent::ToolOutput(transcript.to_owned()))
+            }
+        }
+    }
+}
+
+const USAGE: &str = "Usage: little-agent [--history <path>] \
+                     [--prompt <prompt>] [--output pretty|json]";
+
+/// How the events are printed in the interactive mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum OutputFormat {
+    /// Rendered for the terminal.
+    #[default]
+    Pretty,
+    /// One JSON object per event, tool call requests are still answered via
+    /// stdin.
+    Json,
+}
+
+/// Command line arguments.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Args {
+    /// The file to load the conversation from and save it to.
+    history: Option<PathBuf>,
+    /// The prompt to run in the one-shot mode.
+    prompt: Option<String>,
+    output: OutputFormat,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Args::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--history" => {
+                    let path = args.next().ok_or("--history needs a path")?;
+                    parsed.history = Some(path.into());
+                }
+                "--prompt" => {
+                    let prompt = args.next().ok_or("--prompt needs a value")?;
+                    parsed.prompt = Some(prompt);
+                }
+                "--output" => {
+                    parsed.output = match args.next().as_deref() {
+                        Some("pretty") => OutputFormat::Pretty,
+                        Some("json") => OutputFormat::Json,
+                        _ => return Err("--output needs pretty or json".into()),
+                    };
+                }
+                _ => return Err(format!("unknown argument: {arg}")),
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Tools that are approved automatically in the one-shot mode, since they
+/// don't modify anything.
+const SAFE_TOOLS: &[&str] = &["clock", "glob", "read_file", "recall"];
+
 const BAR_CHAR: &str = "▎";
+const MAX_TOOL_OUTPUT_LINES: usize = 8;
 
 #[tokio::main(flavor = "current_thread")]
-async fn main() {
+async fn main() -> ExitCode {
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
+    let args = match Args::parse(env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
     let Ok(api_key) = env::var("OPENAI_API_KEY") else {
         eprintln!("OPENAI_API_KEY environment variable is not set");
-        return;
+        return ExitCode::FAILURE;
     };
     let Ok(base_url) = env::var("OPENAI_BASE_URL") else {
         eprintln!("OPENAI_BASE_URL environment variable is not set");
-        return;
+        return ExitCode::FAILURE;
     };
     let Ok(model) = env::var("OPENAI_MODEL") else {
         eprintln!("OPENAI_MODEL environment variable is not set");
-        return;
+        return ExitCode::FAILURE;
     };
 
-    let config = OpenAIConfigBuilder::with_api_key(api_key)
+    let config = match OpenAIConfigBuilder::with_api_key(api_key)
         .with_base_url(base_url)
         .with_model(model)
-        .build();
+        .build()
+    {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Invalid OpenAI configuration: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
     let model_provider = OpenAIProvider::new(config);
 
-    let (event_tx, mut event_rx) = mpsc::unbounded_channel();
+    // The provider must be created first, so the opaque messages in the
+    // history can be restored.
+    let history = match &args.history {
+        Some(path) => match load_history(path) {
+            Ok(history) => history,
+            Err(err) => {
+                eprintln!("Failed to load the history: {err}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Conversation::default(),
+    };
+    let history = Arc::new(Mutex::new(history));
 
-    let session = SessionBuilder::with_model_provider(model_provider)
-        .with_system_prompt(
-            include_str!("./system_prompt.md")
-                .replace("{{HOST_OS}}", host_os()),
-        )
+    let mut session_builder =
+        SessionBuilder::with_model_provider(model_provider)
+            .with_write_file(true)
+            .with_utc_offset(local_utc_offset().unwrap_or(0));
+    if args.history.is_some() {
+        session_builder = session_builder
+            .with_conversation(history.lock().unwrap().clone())
+            .on_conversation_item({
+                let history = Arc::clone(&history);
+                move |item| history.lock().unwrap().extend([item.clone()])
+            })
+            .on_conversation_reset({
+                let history = Arc::clone(&history);
+                move |conversation| {
+                    *history.lock().unwrap() = conversation.clone();
+                }
+            });
+    }
+    let vars = HashMap::from([("HOST_OS".to_owned(), host_os().to_owned())]);
+    let system_prompt =
+        render_template(include_str!("./system_prompt.md"), &vars)
+            .expect("the system prompt should only use the known variables");
+    let session_builder = session_builder.with_system_prompt(system_prompt);
+    let on_turn_done = || {
+        let Some(path) = &args.history else {
+            return;
+        };
+        let history = history.lock().unwrap();
+        if let Err(err) = save_history(path, &history) {
+            eprintln!("Failed to save the history: {err}");
+        }
+    };
+
+    if let Some(prompt) = &args.prompt {
+        let result = run_headless(session_builder, prompt).await;
+        on_turn_done();
+        return match result {
+            Ok(output) => {
+                println!("{output}");
+                ExitCode::SUCCESS
+            }
+            Err(err) => {
+                eprintln!("{err}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    run_interactive(session_builder, args.output, on_turn_done).await;
+    ExitCode::SUCCESS
+}
+
+/// The receivers of the session events and the Ctrl-C presses.
+type EventReceivers = (UnboundedReceiver<SessionEvent>, UnboundedReceiver<()>);
+
+/// Runs the interactive loop until the input ends. `on_turn_done` is called
+/// after each turn.
+async fn run_interactive(
+    session_builder: SessionBuilder,
+    output_format: OutputFormat,
+    on_turn_done: impl Fn(),
+) {
+    let (event_tx, event_rx) = mpsc::unbounded_channel();
+    let session = session_builder
         .on_idle({
             let event_tx = event_tx.clone();
             move || {
@@ -67,12 +261,11 @@ async fn main() {
         .on_transcript({
             let event_tx = event_tx.clone();
             move |transcript, source| {
-                event_tx
-                    .send(SessionEvent::Transcript(
-                        transcript.to_owned(),
-                        source,
-                    ))
-                    .ok();
+                if let Some(event) =
+                    SessionEvent::from_transcript(transcript, source)
+                {
+                    event_tx.send(event).ok();
+                }
             }
         })
         .on_tool_call_request({
@@ -83,18 +276,91 @@ async fn main() {
         })
         .build();
 
+    // Once the handler is installed, Ctrl-C no longer kills the process, so
+    // keep listening for the whole session.
+    let (ctrl_c_tx, ctrl_c_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while signal::ctrl_c().await.is_ok() {
+            if ctrl_c_tx.send(()).is_err() {
+                break;
+            }
+        }
+    });
+
+    let event_rxs = (event_rx, ctrl_c_rx);
+    match output_format {
+        OutputFormat::Pretty => {
+            run_pretty_loop(&session, event_rxs, on_turn_done).await;
+        }
+        OutputFormat::Json => {
+            run_json_loop(&session, event_rxs, on_turn_done).await;
+        }
+    }
+
+    // Abort the running tools, so they are notified before the session is
+    // dropped. Nothing is running if the agent has stopped.
+    session.interrupt().ok();
+    drop(session);
+}
+
+/// What to do when Ctrl-C is pressed during a turn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CtrlCAction {
+    /// Interrupt the current turn.
+    Interrupt,
+    /// Exit the program.
+    Exit,
+}
+
+/// Tracks Ctrl-C presses during a turn: the first one interrupts the turn,
+/// and the second one exits.
+#[derive(Debug, Default)]
+struct CtrlCHandler {
+    interrupted: bool,
+}
+
+impl CtrlCHandler {
+    /// Resets the state for a new turn.
+    fn start_turn(&mut self) {
+        self.interrupted = false;
+    }
+
+    fn on_ctrl_c(&mut self) -> CtrlCAction {
+        if self.interrupted {
+            return CtrlCAction::Exit;
+        }
+        self.interrupted = true;
+        CtrlCAction::Interrupt
+    }
+}
+
+async fn run_pretty_loop(
+    session: &Session,
+    (mut event_rx, mut ctrl_c_rx): EventReceivers,
+    on_turn_done: impl Fn(),
+) {
     let progress_style = ProgressStyle::with_template("{spinner} {wide_msg}")
         .unwrap()
         .tick_chars("⠋⠙⠹⠸⠼⠴⠦⠧⠇⠏");
+    let mut ctrl_c = CtrlCHandler::default();
 
     'outer: loop {
         print!("> ");
         std::io::stdout().flush().unwrap();
 
-        let Some(line) = read_line().await else {
+        // Ctrl-C exits directly when waiting for the input.
+        let line = select! {
+            line = read_line() => line,
+            _ = ctrl_c_rx.recv() => None,
+        };
+        let Some(line) = line else {
             break;
         };
-        session.send_message(line.trim());
+        if let Err(err) = session.send_message(line.trim()) {
+            eprintln!("{err}");
+            break;
+        }
+        ctrl_c.start_turn();
 
         let mut is_streaming_transcript = false;
         let mut progress_bar = None;
@@ -120,6 +386,17 @@ async fn main() {
                     };
                     event
                 },
+                _ = ctrl_c_rx.recv() => {
+                    match ctrl_c.on_ctrl_c() {
+                        CtrlCAction::Interrupt => {
+                            if session.interrupt().is_err() {
+                                break 'outer;
+                            }
+                        }
+                        CtrlCAction::Exit => break 'outer,
+                    }
+                    continue;
+                }
                 _ = sleep => {
                     continue;
                 }
@@ -143,22 +420,31 @@ async fn main() {
                     print!("Proceed? [Y/n]: ");
                     std::io::stdout().flush().unwrap();
 
-                    let Some(line) = read_line().await else {
+                    let line = select! {
+                        line = read_line() => line,
+                        _ = ctrl_c_rx.recv() => {
+                            println!();
+                            // The dropped approval is treated as a rejection.
+                            match ctrl_c.on_ctrl_c() {
+                                CtrlCAction::Interrupt => {
+                                    if session.interrupt().is_err() {
+                                        break 'outer;
+                                    }
+                                }
+                                CtrlCAction::Exit => break 'outer,
+                            }
+                            continue;
+                        }
+                    };
+                    let Some(line) = line else {
                         break 'outer;
                     };
-                    let line = line.trim();
-                    if line.is_empty() || line.eq_ignore_ascii_case("y") {
-                        approval.approve();
-                    } else {
-                        approval.reject(None);
-                    }
+                    answer_approval(approval, &line);
 
                     is_streaming_transcript = false;
                     println!();
                 }
-                SessionEvent::Transcript(transcript, source)
-                    if source.is_assistant() && !transcript.is_empty() =>
-                {
+                SessionEvent::Transcript(transcript) => {
                     let transcript = transcript.bright_white();
                     if is_streaming_transcript {
                         print!("{transcript}");
@@ -168,8 +454,73 @@ async fn main() {
                     }
                     std::io::stdout().flush().unwrap();
                 }
+                SessionEvent::ToolOutput(output) => {
+                    if is_streaming_transcript {
+                        println!();
+                    }
+                    print_tool_output(&output);
+                    is_streaming_transcript = false;
+                }
                 SessionEvent::Idle => {
                     println!();
+                    on_turn_done();
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn run_json_loop(
+    session: &Session,
+    (mut event_rx, mut ctrl_c_rx): EventReceivers,
+    on_turn_done: impl Fn(),
+) {
+    let mut ctrl_c = CtrlCHandler::default();
+    loop {
+        let line = select! {
+            line = read_line() => line,
+            _ = ctrl_c_rx.recv() => None,
+        };
+        let Some(line) = line else {
+            return;
+        };
+        if let Err(err) = session.send_message(line.trim()) {
+            eprintln!("{err}");
+            return;
+        }
+        ctrl_c.start_turn();
+
+        loop {
+            let event = select! {
+                event = event_rx.recv() => event,
+                _ = ctrl_c_rx.recv() => {
+                    match ctrl_c.on_ctrl_c() {
+                        CtrlCAction::Interrupt => {
+                            if session.interrupt().is_err() {
+                                return;
+                            }
+                        }
+                        CtrlCAction::Exit => return,
+                    }
+                    continue;
+                }
+            };
+            let Some(event) = event else {
+                return;
+            };
+            let json = serde_json::to_string(&event)
+                .expect("events should be serializable");
+            println!("{json}");
+            match event {
+                SessionEvent::ToolCallRequest(approval) => {
+                    let Some(line) = read_line().await else {
+                        return;
+                    };
+                    answer_approval(approval, &line);
+                }
+                SessionEvent::Idle => {
+                    on_turn_done();
                     break;
                 }
                 _ => {}
@@ -178,6 +529,120 @@ async fn main() {
     }
 }
 
+/// Approves the request if the answer is empty or "y".
+fn answer_approval(approval: ToolApproval, answer: &str) {
+    let answer = answer.trim();
+    if answer.is_empty() || answer.eq_ignore_ascii_case("y") {
+        approval.approve();
+    } else {
+        approval.reject(None);
+    }
+}
+
+/// Runs a single turn without interaction, and returns the assistant output.
+///
+/// Only the [`SAFE_TOOLS`] are approved, the other tool calls are rejected.
+async fn run_headless(
+    session_builder: SessionBuilder,
+    prompt: &str,
+) -> Result<String, String> {
+    let output = Arc::new(Mutex::new(String::new()));
+    let last_error = Arc::new(Mutex::new(None));
+    let finish_reason = Arc::new(Mutex::new(None));
+    let (idle_tx, mut idle_rx) = mpsc::unbounded_channel();
+
+    let session = session_builder
+        .on_transcript({
+            let output = Arc::clone(&output);
+            move |transcript, source| {
+                let mut output = output.lock().unwrap();
+                match source {
+                    TranscriptSource::Assistant => output.push_str(transcript),
+                    // Separate the messages before and after the tool calls.
+                    TranscriptSource::Tool if !output.ends_with('\n') => {
+                        output.push('\n');
+                    }
+                    _ => {}
+                }
+            }
+        })
+        .on_error({
+            let last_error = Arc::clone(&last_error);
+            move |err| {
+                *last_error.lock().unwrap() = Some(err.to_string());
+            }
+        })
+        .on_tool_call_request(|approval| {
+            if SAFE_TOOLS.contains(&approval.tool_name()) {
+                approval.approve();
+            } else {
+                let reason = "not allowed in the one-shot mode".to_owned();
+                approval.reject(Some(reason));
+            }
+        })
+        .on_turn_end({
+            let finish_reason = Arc::clone(&finish_reason);
+            move |summary| {
+                *finish_reason.lock().unwrap() = summary.finish_reason.clone();
+            }
+        })
+        .on_idle(move || {
+            idle_tx.send(()).ok();
+        })
+        .build();
+    session
+        .send_message(prompt)
+        .map_err(|err| err.to_string())?;
+    idle_rx.recv().await;
+
+    let finish_reason = finish_reason.lock().unwrap().take();
+    match finish_reason {
+        Some(
+            ModelFinishReason::Stop
+            | ModelFinishReason::Length
+            | ModelFinishReason::Other(_),
+        ) => Ok(output.lock().unwrap().trim_end().to_owned()),
+        _ => Err(last_error
+            .lock()
+            .unwrap()
+            .take()
+            .unwrap_or_else(|| "the turn was aborted".to_owned())),
+    }
+}
+
+/// Prints the tool output with a dimmed bar, long outputs are truncated.
+fn print_tool_output(output: &str) {
+    let bar = BAR_CHAR.bright_black();
+    let lines: Vec<_> = output.lines().collect();
+    for line in lines.iter().take(MAX_TOOL_OUTPUT_LINES) {
+        println!("{bar}{}", line.bright_black());
+    }
+    if lines.len() > MAX_TOOL_OUTPUT_LINES {
+        let more = lines.len() - MAX_TOOL_OUTPUT_LINES;
+        println!("{bar}{}", format!("... ({more} more lines)").bright_black());
+    }
+    println!();
+}
+
+/// Loads the conversation from the history file, or returns an empty one if
+/// the file doesn't exist.
+fn load_history(path: &Path) -> std_io::Result<Conversation> {
+    let json = match std::fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(err) if err.kind() == std_io::ErrorKind::NotFound => {
+            return Ok(Conversation::default());
+        }
+        Err(err) => return Err(err),
+    };
+    Conversation::from_json(&json).map_err(std_io::Error::other)
+}
+
+/// Saves the conversation to the history file.
+fn save_history(path: &Path, history: &Conversation) -> std_io::Result<()> {
+    let json = history.to_json().map_err(std_io::Error::other)?;
+    std::fs::write(path, json)
+}
+
 async fn read_line() -> Option<String> {
     let mut stdin = io::BufReader::new(io::stdin());
     let mut line = String::new();
@@ -206,3 +671,194 @@ fn host_os() -> &'static str {
         _ => "some other OS",
     }
 }
+
+/// Returns the UTC offset of the local time zone in minutes, or `None` if
+/// it can't be told.
+///
+/// There is no time zone database in the standard library, so it asks the
+/// `date` command, which is only available on Unix-like systems.
+fn local_utc_offset() -> Option<i32> {
+    let output = Command::new("date").arg("+%z").output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    parse_utc_offset(std::str::from_utf8(&output.stdout).ok()?.trim())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use little_agent_model::{ModelMessage, ToolCallRequest};
+    use little_agent_test_model::{
+        PresetEvent, PresetResponse, TestModelProvider,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_headless() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([
+                PresetEvent::MessageDelta("Let me check.".to_owned()),
+                PresetEvent::ToolCall(ToolCallRequest {
+                    id: "tool:1".to_owned(),
+                    name: "recall".to_owned(),
+                    arguments: json!({}),
+                }),
+                PresetEvent::ToolCall(ToolCallRequest {
+                    id: "tool:2".to_owned(),
+                    name: "shell".to_owned(),
+                    arguments: json!({ "cmdline": "echo hi" }),
+                }),
+            ]),
+        );
+        model_provider.add_user_input_step();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Done.".to_owned(),
+            )]),
+        );
+
+        let session_builder =
+            SessionBuilder::with_model_provider(model_provider.clone());
+        let output = run_headless(session_builder, "Hello").await.unwrap();
+        assert_eq!(output, "Let me check.\nDone.");
+
+        // The safe tool is approved, and the shell is rejected.
+        let requests = model_provider.requests();
+        let results: Vec<_> = requests[1]
+            .messages
+            .iter()
+            .filter_map(|msg| match msg {
+                ModelMessage::Tool(result) => {
+                    Some((result.id.as_str(), result.content.to_text()))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(results.len(), 2);
+        for (id, content) in results {
+            let rejected = content.contains("one-shot mode");
+            assert_eq!(rejected, id == "tool:2");
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parse =
+            |args: &[&str]| Args::parse(args.iter().map(|arg| arg.to_string()));
+        assert_eq!(parse(&[]), Ok(Args::default()));
+        assert_eq!(
+            parse(&["--history", "a.json"]).unwrap().history,
+            Some(PathBuf::from("a.json"))
+        );
+        assert_eq!(
+            parse(&["--prompt", "hi"]).unwrap().prompt.as_deref(),
+            Some("hi")
+        );
+        assert_eq!(
+            parse(&["--output", "json"]).unwrap().output,
+            OutputFormat::Json
+        );
+        assert!(parse(&["--output", "xml"]).is_err());
+        assert!(parse(&["--history"]).is_err());
+        assert!(parse(&["--unknown"]).is_err());
+    }
+
+    #[test]
+    fn test_event_json() {
+        let to_json =
+            |event: &SessionEvent| serde_json::to_value(event).unwrap();
+        assert_eq!(to_json(&SessionEvent::Idle), json!({ "type": "idle" }));
+        assert_eq!(
+            to_json(&SessionEvent::Transcript("Hi".to_owned())),
+            json!({ "type": "transcript", "data": "Hi" })
+        );
+        assert_eq!(
+            to_json(&SessionEvent::ToolOutput("a.rs".to_owned())),
+            json!({ "type": "tool_output", "data": "a.rs" })
+        );
+        let approval =
+            ToolApproval::new("ls", "Agent wants to run the command");
+        assert_eq!(
+            to_json(&SessionEvent::ToolCallRequest(approval)),
+            json!({
+                "type": "tool_call_request",
+                "data": {
+                    "tool_name": "",
+                    "what": "ls",
+                    "justification": "Agent wants to run the command",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_history_round_trip() {
+        let path = env::temp_dir()
+            .join(format!("little-agent-history-{}.json", process::id()));
+        let _ = fs::remove_file(&path);
+
+        // A missing file starts an empty conversation.
+        let history = load_history(&path).unwrap();
+        assert!(history.is_empty());
+
+        let history = Conversation::from_json(
+            r#"[{
+                "message": { "User": "Hi" },
+                "transcript": "Hi",
+                "created_at": { "secs_since_epoch": 0, "nanos_since_epoch": 0 }
+            }]"#,
+        )
+        .unwrap();
+        save_history(&path, &history).unwrap();
+        let restored = load_history(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let transcripts: Vec<_> =
+            restored.iter().map(|i| i.transcript()).collect();
+        assert_eq!(transcripts, ["Hi"]);
+    }
+
+    #[test]
+    fn test_event_from_transcript() {
+        let event = SessionEvent::from_transcript("Hi", TranscriptSource::User);
+        assert!(event.is_none());
+
+        let event =
+            SessionEvent::from_transcript("", TranscriptSource::Assistant);
+        assert!(event.is_none());
+
+        let event =
+            SessionEvent::from_transcript("Hello", TranscriptSource::Assistant);
+        assert!(
+            matches!(event, Some(SessionEvent::Transcript(t)) if t == "Hello")
+        );
+
+        let event = SessionEvent::from_transcript(
+            "Ran a tool, result:\nok",
+            TranscriptSource::Tool,
+        );
+        assert!(matches!(
+            event,
+            Some(SessionEvent::ToolOutput(t)) if t == "Ran a tool, result:\nok"
+        ));
+    }
+
+    #[test]
+    fn test_ctrl_c_dispatch() {
+        let mut ctrl_c = CtrlCHandler::default();
+        ctrl_c.start_turn();
+        assert_eq!(ctrl_c.on_ctrl_c(), CtrlCAction::Interrupt);
+        assert_eq!(ctrl_c.on_ctrl_c(), CtrlCAction::Exit);
+
+        // A new turn can be interrupted again.
+        ctrl_c.start_turn();
+        assert_eq!(ctrl_c.on_ctrl_c(), CtrlCAction::Interrupt);
+    }
+}
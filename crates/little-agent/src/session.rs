@@ -1,14 +1,37 @@
-use little_agent_core::tool::Approval as ToolApproval;
-use little_agent_core::{Agent, AgentBuilder, TranscriptSource};
-use little_agent_model::{ModelProvider, ModelProviderError};
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use little_agent_core::conversation::{Conversation, Item as ConversationItem};
+use little_agent_core::pricing::PricingTable;
+use little_agent_core::tool::{
+    Approval as ToolApproval, ApprovalDecision, Metrics as ToolMetrics,
+    ProvisionalApproval,
+};
+use little_agent_core::{
+    Agent, AgentBuilder, AgentDeadError, CheckpointError, CheckpointId,
+    RequestMiddleware, Tool, TranscriptSource, TurnSummary, UsageReport,
+};
+use little_agent_model::{ModelProvider, ModelProviderError, ModelTool};
 
 use crate::tools::*;
 
 /// A session builder.
 ///
 /// See [`Session`].
+#[allow(clippy::type_complexity)]
 pub struct SessionBuilder {
     agent_builder: AgentBuilder,
+    tool_context: ToolContext,
+    write_file: bool,
+    utc_offset: i32,
+    conversation_log: ConversationLog,
+    on_conversation_item: Option<Box<dyn Fn(&ConversationItem) + Send + Sync>>,
+    on_conversation_reset: Option<Box<dyn Fn(&Conversation) + Send + Sync>>,
+    on_idle: Option<Box<dyn Fn() + Send + Sync>>,
+    on_turn_start: Option<Box<dyn Fn() + Send + Sync>>,
 }
 
 impl SessionBuilder {
@@ -17,7 +40,17 @@ impl SessionBuilder {
         provider: M,
     ) -> Self {
         let agent_builder = AgentBuilder::with_model_provider(provider);
-        Self { agent_builder }
+        Self {
+            agent_builder,
+            tool_context: ToolContext::new(),
+            write_file: false,
+            utc_offset: 0,
+            conversation_log: ConversationLog::new(),
+            on_conversation_item: None,
+            on_conversation_reset: None,
+            on_idle: None,
+            on_turn_start: None,
+        }
     }
 
     /// Sets the system prompt for the agent.
@@ -27,13 +60,114 @@ impl SessionBuilder {
         self
     }
 
+    /// Resumes a previous conversation, e.g. one restored by
+    /// [`Conversation::from_json`].
+    ///
+    /// See [`AgentBuilder::with_conversation`] for details.
+    pub fn with_conversation(mut self, conversation: Conversation) -> Self {
+        for item in &conversation {
+            self.conversation_log.record(item);
+        }
+        self.agent_builder = self.agent_builder.with_conversation(conversation);
+        self
+    }
+
+    /// Limits the number of turns the agent can complete.
+    ///
+    /// See [`AgentBuilder::with_max_turns`] for details.
+    #[inline]
+    pub fn with_max_turns(mut self, max_turns: usize) -> Self {
+        self.agent_builder = self.agent_builder.with_max_turns(max_turns);
+        self
+    }
+
+    /// Drops identical user inputs sent within `window` while a turn is
+    /// active.
+    ///
+    /// See [`AgentBuilder::with_input_dedup_window`] for details.
+    #[inline]
+    pub fn with_input_dedup_window(mut self, window: Duration) -> Self {
+        self.agent_builder = self.agent_builder.with_input_dedup_window(window);
+        self
+    }
+
+    /// Enables or disables the dry-run mode.
+    ///
+    /// See [`AgentBuilder::with_dry_run`] for details.
+    #[inline]
+    pub fn with_dry_run(mut self, dry_run: bool) -> Self {
+        self.agent_builder = self.agent_builder.with_dry_run(dry_run);
+        self
+    }
+
+    /// Sets a filter to select the tools exposed to the model.
+    ///
+    /// See [`AgentBuilder::with_tool_filter`] for details.
+    #[inline]
+    pub fn with_tool_filter(
+        mut self,
+        filter: impl Fn(&ModelTool) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_builder = self.agent_builder.with_tool_filter(filter);
+        self
+    }
+
+    /// Sets a function that compacts the conversation when it outgrows the
+    /// context window of the model.
+    ///
+    /// The compacted conversation is also reported to
+    /// [`SessionBuilder::on_conversation_reset`]. See
+    /// [`AgentBuilder::with_compactor`] for details.
+    #[inline]
+    pub fn with_compactor(
+        mut self,
+        compactor: impl Fn(&mut Conversation) + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_builder = self.agent_builder.with_compactor(compactor);
+        self
+    }
+
+    /// Sets the prices of the models for estimating the cost.
+    ///
+    /// See [`AgentBuilder::with_pricing`] for details.
+    #[inline]
+    pub fn with_pricing(mut self, pricing: PricingTable) -> Self {
+        self.agent_builder = self.agent_builder.with_pricing(pricing);
+        self
+    }
+
+    /// Adds a middleware for the model requests.
+    ///
+    /// See [`AgentBuilder::with_request_middleware`] for details.
+    #[inline]
+    pub fn with_request_middleware<M: RequestMiddleware>(
+        mut self,
+        middleware: M,
+    ) -> Self {
+        self.agent_builder =
+            self.agent_builder.with_request_middleware(middleware);
+        self
+    }
+
+    /// Logs the transcripts to the file at `path` as JSON lines.
+    ///
+    /// See [`AgentBuilder::with_transcript_log`] for details.
+    #[inline]
+    pub fn with_transcript_log<P: AsRef<Path>>(
+        mut self,
+        path: P,
+    ) -> io::Result<Self> {
+        self.agent_builder = self.agent_builder.with_transcript_log(path)?;
+        Ok(self)
+    }
+
     /// Attaches a callback to be invoked when the agent is idle.
     #[inline]
     pub fn on_idle(
         mut self,
         on_idle: impl Fn() + Send + Sync + 'static,
     ) -> Self {
-        self.agent_builder = self.agent_builder.on_idle(on_idle);
+        self.on_idle = Some(Box::new(on_idle));
         self
     }
 
@@ -57,6 +191,101 @@ impl SessionBuilder {
         self
     }
 
+    /// Attaches a callback to be invoked when the turn limit is reached,
+    /// and again for every message refused after that.
+    ///
+    /// See [`AgentBuilder::on_limit_reached`] for details.
+    #[inline]
+    pub fn on_limit_reached(
+        mut self,
+        on_limit_reached: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_builder =
+            self.agent_builder.on_limit_reached(on_limit_reached);
+        self
+    }
+
+    /// Attaches a callback to be invoked when a turn starts.
+    #[inline]
+    pub fn on_turn_start(
+        mut self,
+        on_turn_start: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        self.on_turn_start = Some(Box::new(on_turn_start));
+        self
+    }
+
+    /// Attaches a callback to be invoked when a turn ends.
+    #[inline]
+    pub fn on_turn_end(
+        mut self,
+        on_turn_end: impl Fn(&TurnSummary) + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_builder = self.agent_builder.on_turn_end(on_turn_end);
+        self
+    }
+
+    /// Attaches a callback to be invoked when a model request is sent,
+    /// before any response is received.
+    #[inline]
+    pub fn on_waiting(
+        mut self,
+        on_waiting: impl Fn() + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_builder = self.agent_builder.on_waiting(on_waiting);
+        self
+    }
+
+    /// Attaches a callback to be invoked when a model response reports its
+    /// token usage.
+    #[inline]
+    pub fn on_usage(
+        mut self,
+        on_usage: impl Fn(&UsageReport) + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_builder = self.agent_builder.on_usage(on_usage);
+        self
+    }
+
+    /// Attaches a callback to be invoked when the session is paused or
+    /// resumed.
+    #[inline]
+    pub fn on_pause_changed(
+        mut self,
+        on_pause_changed: impl Fn(bool) + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_builder =
+            self.agent_builder.on_pause_changed(on_pause_changed);
+        self
+    }
+
+    /// Attaches a callback to be invoked when an item is added to the
+    /// conversation.
+    ///
+    /// Collect the items to save the conversation, see
+    /// [`Conversation::to_json`].
+    #[inline]
+    pub fn on_conversation_item(
+        mut self,
+        on_conversation_item: impl Fn(&ConversationItem) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_conversation_item = Some(Box::new(on_conversation_item));
+        self
+    }
+
+    /// Attaches a callback to be invoked when the conversation is replaced
+    /// as a whole, e.g. by [`Session::rollback`] or the compactor.
+    ///
+    /// See [`AgentBuilder::on_conversation_reset`] for details.
+    #[inline]
+    pub fn on_conversation_reset(
+        mut self,
+        on_conversation_reset: impl Fn(&Conversation) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_conversation_reset = Some(Box::new(on_conversation_reset));
+        self
+    }
+
     /// Attaches a callback to be invoked when a tool call request is received.
     #[inline]
     pub fn on_tool_call_request(
@@ -69,16 +298,150 @@ impl SessionBuilder {
         self
     }
 
+    /// Attaches a callback to be invoked once with all the tool call
+    /// requests of a model response, instead of one by one.
+    #[inline]
+    pub fn on_tool_call_batch(
+        mut self,
+        on_tool_call_batch: impl Fn(Vec<ToolApproval>) + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_builder =
+            self.agent_builder.on_tool_call_batch(on_tool_call_batch);
+        self
+    }
+
+    /// Attaches a callback to be invoked while a tool call is streaming in,
+    /// before its approval request.
+    #[inline]
+    pub fn on_provisional_approval(
+        mut self,
+        on_provisional_approval: impl Fn(&ProvisionalApproval)
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self {
+        self.agent_builder = self
+            .agent_builder
+            .on_provisional_approval(on_provisional_approval);
+        self
+    }
+
+    /// Attaches a callback to be invoked when a tool call request is
+    /// approved or rejected.
+    #[inline]
+    pub fn on_approval_decision(
+        mut self,
+        on_approval_decision: impl Fn(&ApprovalDecision) + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_builder = self
+            .agent_builder
+            .on_approval_decision(on_approval_decision);
+        self
+    }
+
+    /// Attaches a callback to be invoked with the tool call id and the tool
+    /// name, when an approved tool call starts executing.
+    #[inline]
+    pub fn on_tool_execution_start(
+        mut self,
+        on_tool_execution_start: impl Fn(&str, &str) + Send + Sync + 'static,
+    ) -> Self {
+        self.agent_builder = self
+            .agent_builder
+            .on_tool_execution_start(on_tool_execution_start);
+        self
+    }
+
+    /// Returns a handle to the execution metrics of the built-in tools.
+    #[inline]
+    pub fn tool_metrics(&self) -> ToolMetrics {
+        self.agent_builder.tool_metrics()
+    }
+
+    /// Sets the context of the built-in filesystem tools.
+    #[inline]
+    pub fn with_tool_context(mut self, context: ToolContext) -> Self {
+        self.tool_context = context;
+        self
+    }
+
+    /// Enables or disables the built-in [`WriteFileTool`], which lets the
+    /// agent create and overwrite files. Disabled by default.
+    #[inline]
+    pub fn with_write_file(mut self, enabled: bool) -> Self {
+        self.write_file = enabled;
+        self
+    }
+
+    /// Sets the UTC offset in minutes of the built-in [`ClockTool`], e.g.
+    /// `480` for `+08:00`, so the agent sees the local time. Defaults to
+    /// `0`, i.e. UTC.
+    #[inline]
+    pub fn with_utc_offset(mut self, minutes: i32) -> Self {
+        self.utc_offset = minutes;
+        self
+    }
+
     /// Builds a new session.
     pub fn build(self) -> Session {
-        let agent = self
-            .agent_builder
+        let conversation_log = self.conversation_log;
+        let on_conversation_item = self.on_conversation_item;
+        let on_conversation_reset = self.on_conversation_reset;
+        let on_idle = self.on_idle;
+        let on_turn_start = self.on_turn_start;
+        let idle = Arc::new(AtomicBool::new(true));
+        let mut agent_builder = self.agent_builder;
+        if self.write_file {
+            agent_builder = agent_builder.with_tool(
+                WriteFileTool::new().with_context(self.tool_context.clone()),
+            );
+        }
+        let agent = agent_builder
+            .on_idle({
+                let idle = Arc::clone(&idle);
+                move || {
+                    idle.store(true, Ordering::Release);
+                    if let Some(on_idle) = &on_idle {
+                        on_idle();
+                    }
+                }
+            })
+            .on_turn_start({
+                let idle = Arc::clone(&idle);
+                move || {
+                    idle.store(false, Ordering::Release);
+                    if let Some(on_turn_start) = &on_turn_start {
+                        on_turn_start();
+                    }
+                }
+            })
+            .on_conversation_item({
+                let conversation_log = conversation_log.clone();
+                move |item| {
+                    conversation_log.record(item);
+                    if let Some(on_conversation_item) = &on_conversation_item {
+                        on_conversation_item(item);
+                    }
+                }
+            })
+            .on_conversation_reset({
+                let conversation_log = conversation_log.clone();
+                move |conversation| {
+                    conversation_log.reset(conversation);
+                    if let Some(on_conversation_reset) = &on_conversation_reset
+                    {
+                        on_conversation_reset(conversation);
+                    }
+                }
+            })
+            .with_tool(RecallTool::new(conversation_log))
             .with_tool(ShellTool::new())
-            .with_tool(GlobTool::new())
-            .with_tool(ReadFileTool::new())
+            .with_tool(ClockTool::new().with_utc_offset(self.utc_offset))
+            .with_tool(GlobTool::new().with_context(self.tool_context.clone()))
+            .with_tool(ReadFileTool::new().with_context(self.tool_context))
             .build();
 
-        Session { agent }
+        Session { agent, idle }
     }
 }
 
@@ -88,12 +451,341 @@ impl SessionBuilder {
 /// is basically a wrapper around [`Agent`].
 pub struct Session {
     agent: Agent,
+    idle: Arc<AtomicBool>,
 }
 
 impl Session {
     /// Sends a message to the session.
+    ///
+    /// Returns an error if the agent has stopped unexpectedly.
+    #[inline]
+    pub fn send_message(&self, message: &str) -> Result<(), AgentDeadError> {
+        self.agent.enqueue_user_input(message)
+    }
+
+    /// Interrupts the current turn.
+    ///
+    /// See [`Agent::interrupt`] for details.
+    #[inline]
+    pub fn interrupt(&self) -> Result<(), AgentDeadError> {
+        self.agent.interrupt()
+    }
+
+    /// Pauses the session, the current turn is allowed to finish.
+    ///
+    /// See [`Agent::pause`] for details.
     #[inline]
-    pub fn send_message(&self, message: &str) {
-        self.agent.enqueue_user_input(message);
+    pub fn pause(&self) -> Result<(), AgentDeadError> {
+        self.agent.pause()
+    }
+
+    /// Resumes the session.
+    ///
+    /// Returns an error if the agent has stopped unexpectedly.
+    #[inline]
+    pub fn resume(&self) -> Result<(), AgentDeadError> {
+        self.agent.resume()
+    }
+
+    /// Checks whether the model provider is usable.
+    ///
+    /// See [`Agent::health_check`] for details.
+    #[inline]
+    pub async fn health_check(
+        &self,
+    ) -> Result<Result<(), Box<dyn ModelProviderError>>, AgentDeadError> {
+        self.agent.health_check().await
+    }
+
+    /// Sends multiple messages to the session at once.
+    ///
+    /// The messages are processed in order, and no other message can be
+    /// interleaved with them. Returns an error if the agent has stopped
+    /// unexpectedly.
+    pub fn send_messages(
+        &self,
+        messages: &[&str],
+    ) -> Result<(), AgentDeadError> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        self.agent.enqueue_user_inputs(messages.iter().copied())
+    }
+
+    /// Returns whether the agent is idle, i.e. it's not working on a turn.
+    ///
+    /// It's tracked from [`SessionBuilder::on_turn_start`] and
+    /// [`SessionBuilder::on_idle`], so it doesn't wait for the agent. A
+    /// message that was just sent is not taken into account until its turn
+    /// starts, and a refused message (see
+    /// [`SessionBuilder::on_limit_reached`]) never makes it busy.
+    #[inline]
+    pub fn is_idle(&self) -> bool {
+        self.idle.load(Ordering::Acquire)
+    }
+
+    /// Takes a snapshot of the conversation.
+    ///
+    /// See [`Agent::checkpoint`] for details.
+    #[inline]
+    pub async fn checkpoint(&self) -> Result<CheckpointId, CheckpointError> {
+        self.agent.checkpoint().await
+    }
+
+    /// Restores the conversation to a checkpoint.
+    ///
+    /// See [`Agent::rollback`] for details.
+    #[inline]
+    pub async fn rollback(
+        &self,
+        checkpoint: CheckpointId,
+    ) -> Result<(), CheckpointError> {
+        self.agent.rollback(checkpoint).await
+    }
+
+    /// Registers an extra tool for the session.
+    ///
+    /// See [`Agent::register_tool`] for details.
+    #[inline]
+    pub fn register_tool<T: Tool>(
+        &self,
+        tool: T,
+    ) -> Result<(), AgentDeadError> {
+        self.agent.register_tool(tool)
+    }
+
+    /// Unregisters a tool by its name.
+    ///
+    /// See [`Agent::unregister_tool`] for details.
+    #[inline]
+    pub fn unregister_tool(&self, name: &str) -> Result<(), AgentDeadError> {
+        self.agent.unregister_tool(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use std::{env, fs, process};
+
+    use little_agent_model::{ModelMessage, ToolCallRequest};
+    use little_agent_test_model::{
+        PresetEvent, PresetResponse, TestModelProvider,
+    };
+    use serde_json::json;
+    use tokio::sync::watch;
+    use tokio::time::timeout;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dry_run() {
+        let path = env::temp_dir()
+            .join(format!("little-agent-dry-run-{}", process::id()));
+        let cmdline = format!("touch {}", path.display());
+
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::ToolCall(
+                ToolCallRequest {
+                    id: "tool:1".to_owned(),
+                    name: "shell".to_owned(),
+                    arguments: json!({ "cmdline": cmdline }),
+                },
+            )]),
+        );
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Done.".to_owned(),
+            )]),
+        );
+
+        let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+        let session =
+            SessionBuilder::with_model_provider(model_provider.clone())
+                .with_dry_run(true)
+                .on_idle(move || {
+                    idle_tx.send(true).unwrap();
+                })
+                .build();
+        session.send_message("Create the file").unwrap();
+
+        timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+            .await
+            .unwrap()
+            .unwrap();
+
+        assert!(!path.exists());
+        let requests = model_provider.requests();
+        let tool_result = requests
+            .last()
+            .unwrap()
+            .messages
+            .iter()
+            .find_map(|msg| match msg {
+                ModelMessage::Tool(result) => Some(result),
+                _ => None,
+            })
+            .unwrap();
+        assert_eq!(tool_result.id, "tool:1");
+        assert!(tool_result.content.to_text().starts_with("[dry-run]"));
+        fs::remove_file(&path).ok();
+    }
+
+    #[tokio::test]
+    async fn test_rollback_resets_recall() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Hi".to_owned(),
+            )]),
+        );
+        // Both the discarded turn and the one after the rollback recall
+        // the conversation.
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::ToolCall(
+                ToolCallRequest {
+                    id: "tool:1".to_owned(),
+                    name: "recall".to_owned(),
+                    arguments: json!({}),
+                },
+            )]),
+        );
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Done.".to_owned(),
+            )]),
+        );
+
+        let resets = Arc::new(Mutex::new(vec![]));
+        let (idle_tx, mut idle_rx) = watch::channel(0);
+        let session =
+            SessionBuilder::with_model_provider(model_provider.clone())
+                .on_conversation_reset({
+                    let resets = Arc::clone(&resets);
+                    move |conversation| {
+                        resets.lock().unwrap().push(conversation.len());
+                    }
+                })
+                .on_idle(move || {
+                    idle_tx.send_modify(|count| *count += 1);
+                })
+                .build();
+        let wait_for_idle = async |rx: &mut watch::Receiver<usize>, count| {
+            timeout(Duration::from_millis(500), rx.wait_for(|v| *v == count))
+                .await
+                .unwrap()
+                .unwrap();
+        };
+
+        session.send_message("Hello").unwrap();
+        wait_for_idle(&mut idle_rx, 1).await;
+        let checkpoint = session.checkpoint().await.unwrap();
+        session.send_message("Discard me").unwrap();
+        wait_for_idle(&mut idle_rx, 2).await;
+        session.rollback(checkpoint).await.unwrap();
+        assert_eq!(*resets.lock().unwrap(), [2]);
+
+        session.send_message("What did I say?").unwrap();
+        wait_for_idle(&mut idle_rx, 3).await;
+        let requests = model_provider.requests();
+        let recalled = requests
+            .last()
+            .unwrap()
+            .messages
+            .iter()
+            .find_map(|msg| match msg {
+                ModelMessage::Tool(result) => Some(result.content.to_text()),
+                _ => None,
+            })
+            .unwrap()
+            .into_owned();
+        assert!(recalled.contains("user: Hello"));
+        assert!(!recalled.contains("Discard me"));
+    }
+
+    #[tokio::test]
+    async fn test_idle_after_turn_limit() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Hi".to_owned(),
+            )]),
+        );
+
+        let (limit_tx, mut limit_rx) = watch::channel(0);
+        let session = SessionBuilder::with_model_provider(model_provider)
+            .with_max_turns(1)
+            .on_limit_reached(move || {
+                limit_tx.send_modify(|count| *count += 1);
+            })
+            .build();
+        assert!(session.is_idle());
+
+        session.send_message("Hello").unwrap();
+        timeout(Duration::from_millis(500), limit_rx.wait_for(|v| *v == 1))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(session.is_idle());
+
+        session.send_message("Are you there?").unwrap();
+        timeout(Duration::from_millis(500), limit_rx.wait_for(|v| *v == 2))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(session.is_idle());
+    }
+
+    #[tokio::test]
+    async fn test_compaction_resets_conversation() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Hi".to_owned(),
+            )]),
+        );
+        model_provider.set_context_limit(2);
+
+        let resets = Arc::new(Mutex::new(vec![]));
+        let (idle_tx, mut idle_rx) = watch::channel(0);
+        let session =
+            SessionBuilder::with_model_provider(model_provider.clone())
+                .with_compactor(|conversation| {
+                    // Keep the latest user input only.
+                    let len = conversation.len();
+                    let mut idx = 0;
+                    conversation.retain(|_| {
+                        idx += 1;
+                        idx == len
+                    });
+                })
+                .on_conversation_reset({
+                    let resets = Arc::clone(&resets);
+                    move |conversation| {
+                        resets.lock().unwrap().push(conversation.len());
+                    }
+                })
+                .on_idle(move || {
+                    idle_tx.send_modify(|count| *count += 1);
+                })
+                .build();
+
+        for (idx, message) in ["Hello", "Hello again"].into_iter().enumerate() {
+            session.send_message(message).unwrap();
+            timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v > idx))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+        assert_eq!(*resets.lock().unwrap(), [1]);
     }
 }
@@ -0,0 +1,311 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use little_agent_core::tool::{
+    Approval as ToolApproval, Error as ToolError, Tool, ToolResult,
+};
+use schemars::{JsonSchema, schema_for};
+use serde::Deserialize;
+use serde_json::Value;
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// The format of the time returned by [`ClockTool`].
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, JsonSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ClockFormat {
+    /// An RFC 3339 timestamp, e.g. `2024-05-01T08:30:00+08:00`.
+    #[default]
+    Rfc3339,
+    /// Seconds since the Unix epoch.
+    Unix,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct ClockToolParameters {
+    #[schemars(description = "The UTC offset like `+08:00`, or `Z` for \
+                              UTC. Time zone names like `Asia/Shanghai` \
+                              are not supported. Omit it to use the \
+                              default.")]
+    utc_offset: Option<String>,
+    #[schemars(description = "The format, either `rfc3339` (default) or \
+                              `unix`.")]
+    format: Option<ClockFormat>,
+}
+
+/// A tool for getting the current date and time.
+///
+/// The time is in UTC by default. Since the local time zone can't be told
+/// without a time zone database, set it with [`ClockTool::with_utc_offset`]
+/// to return the local time.
+pub struct ClockTool {
+    parameter_schema: Value,
+    utc_offset: i32,
+    format: ClockFormat,
+}
+
+impl ClockTool {
+    /// Creates a clock tool that returns the UTC time as RFC 3339.
+    #[inline]
+    pub fn new() -> Self {
+        ClockTool {
+            parameter_schema: schema_for!(ClockToolParameters).to_value(),
+            utc_offset: 0,
+            format: ClockFormat::default(),
+        }
+    }
+
+    /// Sets the default UTC offset in minutes, e.g. `480` for `+08:00`.
+    #[inline]
+    pub fn with_utc_offset(mut self, minutes: i32) -> Self {
+        self.utc_offset = minutes;
+        self
+    }
+
+    /// Sets the default format.
+    #[inline]
+    pub fn with_format(mut self, format: ClockFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    fn now(&self, input: ClockToolParameters) -> ToolResult {
+        let utc_offset = match input.utc_offset.as_deref() {
+            Some(offset) => parse_utc_offset(offset).ok_or_else(|| {
+                ToolError::invalid_input()
+                    .with_reason(format!("invalid UTC offset: {offset}"))
+            })?,
+            None => self.utc_offset,
+        };
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|err| {
+                ToolError::execution_error().with_reason(err.to_string())
+            })?
+            .as_secs() as i64;
+        Ok(match input.format.unwrap_or(self.format) {
+            ClockFormat::Rfc3339 => format_rfc3339(secs, utc_offset).into(),
+            ClockFormat::Unix => secs.to_string().into(),
+        })
+    }
+}
+
+impl Default for ClockTool {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tool for ClockTool {
+    type Input = ClockToolParameters;
+
+    fn name(&self) -> &str {
+        "clock"
+    }
+
+    fn description(&self) -> &str {
+        r#"
+Gets the current date and time.
+Use it whenever the answer depends on today's date or the current time."#
+    }
+
+    fn parameter_schema(&self) -> &Value {
+        &self.parameter_schema
+    }
+
+    fn make_approval(&self, _input: &Self::Input) -> ToolApproval {
+        ToolApproval::new("current time", "Agent wants to check the time")
+    }
+
+    fn requires_approval(&self, _input: &Self::Input) -> bool {
+        false
+    }
+
+    fn execute(
+        &self,
+        input: Self::Input,
+    ) -> impl Future<Output = ToolResult> + Send + 'static {
+        std::future::ready(self.now(input))
+    }
+}
+
+/// Parses a UTC offset like `+08:00`, `+0800` or `Z` into minutes.
+///
+/// Returns `None` if the offset is malformed. Time zone names like
+/// `Asia/Shanghai` are not supported.
+pub fn parse_utc_offset(offset: &str) -> Option<i32> {
+    if offset.eq_ignore_ascii_case("z") || offset.eq_ignore_ascii_case("utc") {
+        return Some(0);
+    }
+    let (sign, rest) = match offset.split_at_checked(1)? {
+        ("+", rest) => (1, rest),
+        ("-", rest) => (-1, rest),
+        _ => return None,
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some(parts) => parts,
+        None => rest.split_at_checked(2)?,
+    };
+    let is_two_digits = |part: &str| {
+        part.len() == 2 && part.bytes().all(|b| b.is_ascii_digit())
+    };
+    if !is_two_digits(hours) || !is_two_digits(minutes) {
+        return None;
+    }
+    let hours: i32 = hours.parse().ok()?;
+    let minutes: i32 = minutes.parse().ok()?;
+    if hours > 23 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 60 + minutes))
+}
+
+/// Formats the Unix time as RFC 3339 in the UTC offset (in minutes).
+fn format_rfc3339(secs: i64, utc_offset: i32) -> String {
+    let local_secs = secs + i64::from(utc_offset) * 60;
+    let (year, month, day) =
+        civil_from_days(local_secs.div_euclid(SECS_PER_DAY));
+    let secs_of_day = local_secs.rem_euclid(SECS_PER_DAY);
+    let (hour, minute, second) =
+        (secs_of_day / 3600, secs_of_day / 60 % 60, secs_of_day % 60);
+    let offset = if utc_offset == 0 {
+        "Z".to_owned()
+    } else {
+        let sign = if utc_offset < 0 { '-' } else { '+' };
+        let minutes = utc_offset.abs();
+        format!("{sign}{:02}:{:02}", minutes / 60, minutes % 60)
+    };
+    format!(
+        "{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}\
+         {offset}"
+    )
+}
+
+/// Converts the days since the Unix epoch into a proleptic Gregorian date.
+///
+/// See <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use little_agent_core::tool::ErrorKind as ToolErrorKind;
+
+    use super::*;
+
+    fn input(
+        utc_offset: Option<&str>,
+        format: Option<ClockFormat>,
+    ) -> ClockToolParameters {
+        ClockToolParameters {
+            utc_offset: utc_offset.map(str::to_owned),
+            format,
+        }
+    }
+
+    /// Parses an RFC 3339 timestamp made by [`format_rfc3339`] back into
+    /// the Unix time.
+    fn parse_rfc3339(timestamp: &str) -> i64 {
+        let (date, time) = timestamp.split_once('T').unwrap();
+        let date: Vec<i64> =
+            date.split('-').map(|part| part.parse().unwrap()).collect();
+        let (time, utc_offset) = match time.strip_suffix('Z') {
+            Some(time) => (time, 0),
+            None => {
+                let (time, offset) = time.split_at(time.len() - 6);
+                (time, parse_utc_offset(offset).unwrap())
+            }
+        };
+        let time: Vec<i64> =
+            time.split(':').map(|part| part.parse().unwrap()).collect();
+
+        // The inverse of `civil_from_days`.
+        let (month, day) = (date[1], date[2]);
+        let year = date[0] - i64::from(month <= 2);
+        let era = year.div_euclid(400);
+        let year_of_era = year.rem_euclid(400);
+        let mp = if month > 2 { month - 3 } else { month + 9 };
+        let day_of_year = (153 * mp + 2) / 5 + day - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4
+            - year_of_era / 100
+            + day_of_year;
+        let days = era * 146_097 + day_of_era - 719_468;
+        days * SECS_PER_DAY + time[0] * 3600 + time[1] * 60 + time[2]
+            - i64::from(utc_offset) * 60
+    }
+
+    #[test]
+    fn test_parse_utc_offset() {
+        assert_eq!(parse_utc_offset("+08:00"), Some(480));
+        assert_eq!(parse_utc_offset("+0800"), Some(480));
+        assert_eq!(parse_utc_offset("-01:30"), Some(-90));
+        assert_eq!(parse_utc_offset("-0130"), Some(-90));
+        assert_eq!(parse_utc_offset("Z"), Some(0));
+        assert_eq!(parse_utc_offset("0800"), None);
+        assert_eq!(parse_utc_offset("+8:00"), None);
+        assert_eq!(parse_utc_offset("+2500"), None);
+        assert_eq!(parse_utc_offset("Asia/Shanghai"), None);
+    }
+
+    #[test]
+    fn test_format_rfc3339() {
+        assert_eq!(format_rfc3339(0, 0), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            format_rfc3339(1_700_000_000, 480),
+            "2023-11-15T06:13:20+08:00"
+        );
+        assert_eq!(
+            format_rfc3339(1_709_164_800, -90),
+            "2024-02-28T22:30:00-01:30"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_now() {
+        let tool = ClockTool::new().with_utc_offset(480);
+        let before = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        let result = tool.execute(input(None, None)).await.unwrap();
+        let timestamp = result.to_text();
+        assert!(timestamp.ends_with("+08:00"));
+        assert!((parse_rfc3339(&timestamp) - before).abs() <= 2);
+
+        let result = tool.execute(input(Some("Z"), None)).await.unwrap();
+        assert!((parse_rfc3339(&result.to_text()) - before).abs() <= 2);
+
+        let result = tool
+            .execute(input(None, Some(ClockFormat::Unix)))
+            .await
+            .unwrap();
+        let secs: i64 = result.to_text().parse().unwrap();
+        assert!((secs - before).abs() <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_utc_offset() {
+        let tool = ClockTool::new();
+        let err = tool
+            .execute(input(Some("Asia/Shanghai"), None))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ToolErrorKind::InvalidInput);
+    }
+}
@@ -0,0 +1,215 @@
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+use little_agent_core::tool::Error as ToolError;
+
+/// The shared context of the filesystem tools.
+///
+/// By default, tools only accept absolute paths. With a root directory set,
+/// relative paths are resolved against it, and paths that traverse outside
+/// of it (like `../secret`) are rejected.
+///
+/// Additionally, the access can be jailed to some allowed roots, see
+/// [`ToolContext::with_allowed_roots`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ToolContext {
+    root: Option<PathBuf>,
+    allowed_roots: Vec<PathBuf>,
+}
+
+impl ToolContext {
+    /// Creates a context that only accepts absolute paths.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a context that resolves relative paths against `root`.
+    ///
+    /// The `root` should be an absolute path.
+    #[inline]
+    pub fn with_root<P: Into<PathBuf>>(root: P) -> Self {
+        Self {
+            root: Some(root.into()),
+            allowed_roots: Vec::new(),
+        }
+    }
+
+    /// Restricts the tools to access only the paths inside `roots`.
+    ///
+    /// Paths are canonicalized before checking, so symlinks pointing
+    /// outside of the allowed roots are rejected as well. An empty list
+    /// means no restrictions, which is the default.
+    #[inline]
+    pub fn with_allowed_roots(mut self, roots: Vec<PathBuf>) -> Self {
+        self.allowed_roots = roots;
+        self
+    }
+
+    /// Returns `true` if the context has a root directory.
+    #[inline]
+    pub(crate) fn has_root(&self) -> bool {
+        self.root.is_some()
+    }
+
+    /// Resolves the path provided by the model, and checks whether it's
+    /// allowed to access.
+    pub(crate) fn resolve(&self, path: &str) -> Result<PathBuf, ToolError> {
+        let resolved = self.resolve_lexically(Path::new(path))?;
+        self.check_allowed(&resolved)?;
+        Ok(resolved)
+    }
+
+    fn check_allowed(&self, path: &Path) -> Result<(), ToolError> {
+        if self.allowed_roots.is_empty() {
+            return Ok(());
+        }
+        let denied = || {
+            ToolError::permission_denied().with_reason(format!(
+                "`{}` is outside of the allowed directories",
+                path.display()
+            ))
+        };
+
+        let canonical = canonicalize_lenient(path).map_err(|_| denied())?;
+        let is_allowed = self.allowed_roots.iter().any(|root| {
+            root.canonicalize()
+                .is_ok_and(|root| canonical.starts_with(root))
+        });
+        if !is_allowed {
+            return Err(denied());
+        }
+        Ok(())
+    }
+
+    fn resolve_lexically(&self, path: &Path) -> Result<PathBuf, ToolError> {
+        if path.is_absolute() {
+            return Ok(path.to_owned());
+        }
+        let Some(root) = &self.root else {
+            return Err(ToolError::execution_error()
+                .with_reason("`path` must be absolute"));
+        };
+
+        let mut resolved = root.clone();
+        let mut depth = 0usize;
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => {
+                    resolved.push(name);
+                    depth += 1;
+                }
+                Component::ParentDir if depth > 0 => {
+                    resolved.pop();
+                    depth -= 1;
+                }
+                Component::ParentDir => {
+                    return Err(ToolError::execution_error()
+                        .with_reason("`path` must not escape the root"));
+                }
+                _ => {}
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+/// Canonicalizes a path that may not exist yet, by canonicalizing the
+/// nearest existing ancestor and appending the rest.
+fn canonicalize_lenient(path: &Path) -> io::Result<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        match existing.canonicalize() {
+            Ok(mut canonical) => {
+                canonical.extend(missing.iter().rev());
+                return Ok(canonical);
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                let (Some(name), Some(parent)) =
+                    (existing.file_name(), existing.parent())
+                else {
+                    return Err(err);
+                };
+                missing.push(name);
+                existing = parent;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_absolute_only() {
+        let ctx = ToolContext::new();
+        assert_eq!(ctx.resolve("/etc/hosts").unwrap(), Path::new("/etc/hosts"));
+        assert!(ctx.resolve("src/lib.rs").is_err());
+    }
+
+    #[test]
+    fn test_resolve_against_root() {
+        let ctx = ToolContext::with_root("/project");
+        assert_eq!(
+            ctx.resolve("src/lib.rs").unwrap(),
+            Path::new("/project/src/lib.rs")
+        );
+        assert_eq!(
+            ctx.resolve("./src/../README.md").unwrap(),
+            Path::new("/project/README.md")
+        );
+        assert_eq!(ctx.resolve(".").unwrap(), Path::new("/project"));
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("little-agent-{name}-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_allowed_roots() {
+        let jail = temp_dir("jail");
+        let outside = temp_dir("outside");
+        std::fs::write(jail.join("inside.txt"), "").unwrap();
+        std::fs::write(outside.join("secret.txt"), "").unwrap();
+
+        let ctx = ToolContext::with_root(&jail)
+            .with_allowed_roots(vec![jail.clone()]);
+        assert!(ctx.resolve("inside.txt").is_ok());
+        // Files to be created are allowed, too.
+        assert!(ctx.resolve("new/file.txt").is_ok());
+
+        let secret = outside.join("secret.txt");
+        let err = ctx.resolve(&secret.to_string_lossy()).unwrap_err();
+        assert_eq!(
+            err.kind(),
+            little_agent_core::tool::ErrorKind::PermissionDenied
+        );
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&outside, jail.join("link")).unwrap();
+            let err = ctx.resolve("link/secret.txt").unwrap_err();
+            assert_eq!(
+                err.kind(),
+                little_agent_core::tool::ErrorKind::PermissionDenied
+            );
+        }
+
+        std::fs::remove_dir_all(&jail).ok();
+        std::fs::remove_dir_all(&outside).ok();
+    }
+
+    #[test]
+    fn test_reject_escape() {
+        let ctx = ToolContext::with_root("/project");
+        assert!(ctx.resolve("../secret").is_err());
+        assert!(ctx.resolve("src/../../secret").is_err());
+    }
+}
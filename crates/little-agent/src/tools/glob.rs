@@ -1,24 +1,41 @@
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+
+use glob::{MatchOptions, Pattern};
+use ignore::WalkBuilder;
 
 use little_agent_core::tool::{
-    Approval as ToolApproval, Error as ToolError, Tool, ToolResult,
+    Approval as ToolApproval, Error as ToolError, Paginated, Tool, ToolOutput,
+    ToolResult,
 };
 use schemars::{JsonSchema, schema_for};
 use serde::Deserialize;
 use serde_json::Value;
 use tokio::task::spawn_blocking;
 
+use super::ToolContext;
+
 #[derive(Deserialize, JsonSchema)]
 pub struct GlobToolParameters {
     #[schemars(description = "The glob pattern, must be relative to `path`.")]
     pattern: String,
-    #[schemars(description = "Absolute path to search in.")]
+    #[schemars(
+        description = "Absolute path to search in, or relative to the root."
+    )]
     path: String,
+    #[schemars(description = "Whether to skip the files ignored by \
+                              `.gitignore`, default to true.")]
+    respect_gitignore: Option<bool>,
+    #[schemars(description = "The cursor returned by the previous call, to \
+                              continue listing from.")]
+    cursor: Option<String>,
 }
 
+const MAX_RESULTS: usize = 50;
+
 /// A tool for finding files using glob patterns.
 pub struct GlobTool {
     parameter_schema: Value,
+    context: ToolContext,
 }
 
 impl GlobTool {
@@ -27,8 +44,16 @@ impl GlobTool {
     pub fn new() -> Self {
         GlobTool {
             parameter_schema: schema_for!(GlobToolParameters).to_value(),
+            context: ToolContext::new(),
         }
     }
+
+    /// Sets the context for resolving paths.
+    #[inline]
+    pub fn with_context(mut self, context: ToolContext) -> Self {
+        self.context = context;
+        self
+    }
 }
 
 impl Default for GlobTool {
@@ -48,7 +73,8 @@ impl Tool for GlobTool {
     fn description(&self) -> &str {
         r#"
 Find files and directories using glob patterns.
-This tool supports standard glob syntax like *, ?, and ** for recursive searches."#
+This tool supports standard glob syntax like *, ?, and ** for recursive searches.
+Returns up to 50 paths, pass the returned cursor to see more."#
     }
 
     fn parameter_schema(&self) -> &Value {
@@ -64,48 +90,108 @@ This tool supports standard glob syntax like *, ?, and ** for recursive searches
         &self,
         input: GlobToolParameters,
     ) -> impl Future<Output = ToolResult> + Send + 'static {
+        let context = self.context.clone();
         async move {
             if Path::new(&input.pattern).is_absolute() {
                 return Err(ToolError::execution_error()
                     .with_reason("`pattern` must be relative to `path`"));
             }
-            if !Path::new(&input.path).is_absolute() {
+            let escapes = Path::new(&input.pattern)
+                .components()
+                .any(|c| c == Component::ParentDir);
+            if context.has_root() && escapes {
                 return Err(ToolError::execution_error()
-                    .with_reason("`path` must be absolute"));
-            }
-
-            let mut pattern = input.path;
-            if pattern.bytes().last() != Some(b'/') {
-                pattern.push('/');
+                    .with_reason("`pattern` must not escape the root"));
             }
-            pattern.push_str(&input.pattern);
-            let pattern = match glob::glob(&pattern) {
-                Ok(pattern) => pattern,
-                Err(err) => {
-                    return Err(ToolError::execution_error()
-                        .with_reason(err.to_string()));
-                }
-            };
+            let path = context.resolve(&input.path)?;
+            let respect_gitignore = input.respect_gitignore.unwrap_or(true);
 
             spawn_blocking(move || {
+                let cursor = input.cursor.as_deref();
+                let page = if respect_gitignore {
+                    walk(&path, &input.pattern, cursor)?
+                } else {
+                    plain_glob(&path, &input.pattern, cursor)?
+                };
                 let mut result = String::new();
-                // FIXME: Ok, the limit here may look arbitrary. And we need a
-                // mechanism to handle continuation.
-                for item in pattern.take(50).flatten() {
+                for item in page.items() {
                     result.push_str(&item.to_string_lossy());
                     result.push('\n');
                 }
-                result
+                let Some(hint) = page.continuation_hint() else {
+                    return Ok(result.into());
+                };
+                result.push_str(&hint);
+                result.push('\n');
+                Ok(ToolOutput::from(result).truncated())
             })
             .await
             .map_err(|_| {
                 ToolError::execution_error()
                     .with_reason("Failed to execute glob")
-            })
+            })?
         }
     }
 }
 
+fn plain_glob(
+    path: &Path,
+    pattern: &str,
+    cursor: Option<&str>,
+) -> Result<Paginated<PathBuf>, ToolError> {
+    let mut full_pattern = path.to_string_lossy().into_owned();
+    if full_pattern.bytes().last() != Some(b'/') {
+        full_pattern.push('/');
+    }
+    full_pattern.push_str(pattern);
+    let paths = glob::glob(&full_pattern).map_err(|err| {
+        ToolError::execution_error().with_reason(err.to_string())
+    })?;
+    Paginated::resume(paths.flatten(), cursor, MAX_RESULTS)
+}
+
+/// Walks the tree under `path` like [`plain_glob`], but skips the files
+/// ignored by the ignore files (like `.gitignore`) and the `.git`
+/// directory.
+fn walk(
+    path: &Path,
+    pattern: &str,
+    cursor: Option<&str>,
+) -> Result<Paginated<PathBuf>, ToolError> {
+    let pattern = Pattern::new(pattern).map_err(|err| {
+        ToolError::execution_error().with_reason(err.to_string())
+    })?;
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+
+    let mut builder = WalkBuilder::new(path);
+    builder
+        .hidden(false)
+        // Honor `.gitignore` even outside of a git repository.
+        .require_git(false)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .sort_by_file_name(|a, b| a.cmp(b));
+    // Without `**`, there is no need to walk deeper than the pattern.
+    if !pattern.as_str().contains("**") {
+        let depth = Path::new(pattern.as_str()).components().count();
+        builder.max_depth(Some(depth));
+    }
+
+    let paths = builder
+        .build()
+        .flatten()
+        .filter(|entry| entry.depth() > 0)
+        .filter(|entry| {
+            let relative =
+                entry.path().strip_prefix(path).unwrap_or(entry.path());
+            pattern.matches_path_with(relative, options)
+        })
+        .map(|entry| entry.into_path());
+    Paginated::resume(paths, cursor, MAX_RESULTS)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -118,6 +204,8 @@ mod tests {
             .execute(GlobToolParameters {
                 pattern: "*.rs".to_owned(),
                 path: "some/relative/path".to_owned(),
+                respect_gitignore: None,
+                cursor: None,
             })
             .await;
         assert!(result.is_err());
@@ -126,6 +214,8 @@ mod tests {
             .execute(GlobToolParameters {
                 pattern: "/*.*".to_owned(),
                 path: "/some/relative/path".to_owned(),
+                respect_gitignore: None,
+                cursor: None,
             })
             .await;
         assert!(result.is_err());
@@ -134,8 +224,52 @@ mod tests {
             .execute(GlobToolParameters {
                 pattern: "*".to_owned(),
                 path: "/".to_owned(),
+                respect_gitignore: None,
+                cursor: None,
             })
             .await;
-        assert!(!result.unwrap().is_empty());
+        assert!(!result.unwrap().to_text().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_respect_gitignore() {
+        let dir = std::env::temp_dir()
+            .join(format!("little-agent-glob-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("target/debug")).unwrap();
+        std::fs::write(dir.join(".gitignore"), "target/\n*.log\n").unwrap();
+        std::fs::write(dir.join("src/main.rs"), "").unwrap();
+        std::fs::write(dir.join("target/debug/build.rs"), "").unwrap();
+        std::fs::write(dir.join("debug.log"), "").unwrap();
+
+        let tool = GlobTool::new();
+        let glob = |respect_gitignore| {
+            tool.execute(GlobToolParameters {
+                pattern: "**/*.rs".to_owned(),
+                path: dir.to_string_lossy().into_owned(),
+                respect_gitignore,
+                cursor: None,
+            })
+        };
+        let relative_paths = |text: &str| -> Vec<String> {
+            text.lines()
+                .map(|line| {
+                    let path = Path::new(line).strip_prefix(&dir).unwrap();
+                    path.to_string_lossy().into_owned()
+                })
+                .collect()
+        };
+
+        let result = glob(None).await.unwrap();
+        assert_eq!(relative_paths(&result.to_text()), ["src/main.rs"]);
+
+        let result = glob(Some(false)).await.unwrap();
+        assert_eq!(
+            relative_paths(&result.to_text()),
+            ["src/main.rs", "target/debug/build.rs"]
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
     }
 }
@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use little_agent_core::tool::{
+    Approval as ToolApproval, Error as ToolError, Tool, ToolResult,
+};
+use schemars::{JsonSchema, schema_for};
+use serde::Deserialize;
+use serde_json::Value;
+
+/// A shared key/value store, which is read and written by [`MemoryTool`].
+///
+/// The entries live as long as the store (and its clones), so they are kept
+/// across turns. Open it with [`Memory::open`] to also persist them to a
+/// file.
+#[derive(Clone, Default)]
+pub struct Memory {
+    entries: Arc<Mutex<BTreeMap<String, String>>>,
+    path: Option<Arc<Path>>,
+}
+
+impl Memory {
+    /// Creates an empty in-process store.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a store that is persisted to the file at `path` as a JSON
+    /// object, loading the existing entries if the file exists.
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<Self> {
+        let path = path.into();
+        let entries = match fs::read_to_string(&path) {
+            Ok(json) => serde_json::from_str(&json)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                BTreeMap::new()
+            }
+            Err(err) => return Err(err),
+        };
+        Ok(Self {
+            entries: Arc::new(Mutex::new(entries)),
+            path: Some(path.into()),
+        })
+    }
+
+    /// Returns the value of a key.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    /// Sets the value of a key, and writes the entries to the file if the
+    /// store is persisted. The store is left unchanged if the write fails.
+    pub fn set<K: Into<String>, V: Into<String>>(
+        &self,
+        key: K,
+        value: V,
+    ) -> io::Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let Some(path) = &self.path else {
+            entries.insert(key.into(), value.into());
+            return Ok(());
+        };
+        let mut updated = entries.clone();
+        updated.insert(key.into(), value.into());
+        // Written under the lock, so the concurrent writes don't interleave.
+        let json = serde_json::to_string_pretty(&updated)?;
+        fs::write(path, json)?;
+        *entries = updated;
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryOp {
+    /// Stores a value.
+    Set,
+    /// Reads a value.
+    Get,
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct MemoryToolParameters {
+    #[schemars(description = "The operation, either `set` or `get`.")]
+    op: MemoryOp,
+    #[schemars(description = "The key of the entry.")]
+    key: String,
+    #[schemars(description = "The value to store, required by `set`.")]
+    value: Option<String>,
+}
+
+/// A tool for storing and reading key/value notes that persist across
+/// turns.
+pub struct MemoryTool {
+    parameter_schema: Value,
+    memory: Memory,
+}
+
+impl MemoryTool {
+    /// Creates a memory tool backed by the store.
+    #[inline]
+    pub fn new(memory: Memory) -> Self {
+        MemoryTool {
+            parameter_schema: schema_for!(MemoryToolParameters).to_value(),
+            memory,
+        }
+    }
+
+    fn set(&self, key: String, value: Option<String>) -> ToolResult {
+        let Some(value) = value else {
+            return Err(ToolError::invalid_input()
+                .with_reason("`value` is required by `set`"));
+        };
+        self.memory.set(key, value).map_err(|err| {
+            ToolError::execution_error().with_reason(err.to_string())
+        })?;
+        Ok("Saved".into())
+    }
+}
+
+impl Tool for MemoryTool {
+    type Input = MemoryToolParameters;
+
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn description(&self) -> &str {
+        r#"
+Stores or reads a note by its key, the notes persist across turns.
+Use `set` with a `value` to store a note, and `get` to read it back."#
+    }
+
+    fn parameter_schema(&self) -> &Value {
+        &self.parameter_schema
+    }
+
+    fn make_approval(&self, input: &Self::Input) -> ToolApproval {
+        match (&input.op, &input.value) {
+            (MemoryOp::Set, Some(value)) => ToolApproval::new(
+                format!("{} = {value}", input.key),
+                "Agent wants to remember this",
+            ),
+            (MemoryOp::Set, None) => {
+                ToolApproval::new(&input.key, "Agent wants to remember this")
+            }
+            (MemoryOp::Get, _) => {
+                ToolApproval::new(&input.key, "Agent wants to read a note")
+            }
+        }
+    }
+
+    fn execute(
+        &self,
+        input: Self::Input,
+    ) -> impl Future<Output = ToolResult> + Send + 'static {
+        let result = match input.op {
+            MemoryOp::Set => self.set(input.key, input.value),
+            MemoryOp::Get => Ok(match self.memory.get(&input.key) {
+                Some(value) => value.into(),
+                None => format!("No note for key \"{}\"", input.key).into(),
+            }),
+        };
+        std::future::ready(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use little_agent_core::tool::ErrorKind as ToolErrorKind;
+
+    use super::*;
+
+    fn input(
+        op: MemoryOp,
+        key: &str,
+        value: Option<&str>,
+    ) -> MemoryToolParameters {
+        MemoryToolParameters {
+            op,
+            key: key.to_owned(),
+            value: value.map(str::to_owned),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_set_get() {
+        let memory = Memory::new();
+        let tool = MemoryTool::new(memory.clone());
+
+        let result = tool
+            .execute(input(MemoryOp::Set, "lang", Some("Rust")))
+            .await
+            .unwrap();
+        assert_eq!(result.to_text(), "Saved");
+        let result = tool
+            .execute(input(MemoryOp::Get, "lang", None))
+            .await
+            .unwrap();
+        assert_eq!(result.to_text(), "Rust");
+        // The store is shared with the tool.
+        assert_eq!(memory.get("lang").as_deref(), Some("Rust"));
+
+        let err = tool
+            .execute(input(MemoryOp::Set, "lang", None))
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), ToolErrorKind::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn test_missing_key() {
+        let tool = MemoryTool::new(Memory::new());
+        let result = tool
+            .execute(input(MemoryOp::Get, "lang", None))
+            .await
+            .unwrap();
+        assert_eq!(result.to_text(), "No note for key \"lang\"");
+    }
+
+    #[test]
+    fn test_persistence() {
+        let path = env::temp_dir()
+            .join(format!("little-agent-memory-{}.json", process::id()));
+        fs::remove_file(&path).ok();
+
+        let memory = Memory::open(&path).unwrap();
+        assert_eq!(memory.get("lang"), None);
+        memory.set("lang", "Rust").unwrap();
+
+        let reopened = Memory::open(&path).unwrap();
+        assert_eq!(reopened.get("lang").as_deref(), Some("Rust"));
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_failed_write() {
+        let dir = env::temp_dir()
+            .join(format!("little-agent-memory-dir-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+
+        // The path is a directory, so writing to it fails.
+        let memory = Memory {
+            entries: Default::default(),
+            path: Some(dir.clone().into()),
+        };
+        assert!(memory.set("lang", "Rust").is_err());
+        assert_eq!(memory.get("lang"), None);
+        fs::remove_dir(&dir).ok();
+    }
+
+    #[test]
+    fn test_set_approval_without_value() {
+        let tool = MemoryTool::new(Memory::new());
+        let approval = tool.make_approval(&input(MemoryOp::Set, "lang", None));
+        assert_eq!(approval.what(), "lang");
+        assert_eq!(approval.justification(), "Agent wants to remember this");
+    }
+}
@@ -1,9 +1,19 @@
 //! A set of built-in tools that models can use.
 
+mod clock;
+mod context;
 mod glob;
+mod memory;
 mod read_file;
+mod recall;
 mod shell;
+mod write_file;
 
+pub use clock::{ClockFormat, ClockTool, parse_utc_offset};
+pub use context::ToolContext;
 pub use glob::GlobTool;
+pub use memory::{Memory, MemoryTool};
 pub use read_file::ReadFileTool;
-pub use shell::ShellTool;
+pub use recall::{ConversationLog, RecallTool};
+pub use shell::{OutputEncoding, ShellTool};
+pub use write_file::WriteFileTool;
@@ -1,23 +1,43 @@
 use std::fs::File;
 use std::io::{BufRead, BufReader, Read};
-use std::path::Path;
+use std::iter;
+use std::path::PathBuf;
 
 use little_agent_core::tool::{
-    Approval as ToolApproval, Error as ToolError, Tool, ToolResult,
+    Approval as ToolApproval, Error as ToolError, Paginated, Tool, ToolOutput,
+    ToolResult,
 };
 use schemars::{JsonSchema, schema_for};
 use serde::Deserialize;
 use serde_json::Value;
-use tokio::task::spawn_blocking;
+use tokio::task::JoinSet;
+
+use super::ToolContext;
 
 const MAX_LINES: usize = 50;
+/// Maximum number of files read at the same time, to avoid exhausting the
+/// file descriptors.
+const MAX_CONCURRENT_READS: usize = 8;
 
 #[derive(Deserialize, JsonSchema)]
 pub struct ReadFileItem {
-    #[schemars(description = "Absolute path to the file.")]
+    #[schemars(
+        description = "Absolute path to the file, or relative to the root."
+    )]
     path: String,
     #[schemars(description = "1-based start line to read from, default to 1.")]
     start_line: Option<usize>,
+    #[schemars(description = "The cursor returned by the previous read of \
+                              this file, to continue from. Overrides \
+                              `start_line`.")]
+    cursor: Option<String>,
+}
+
+/// A file to read, resolved from [`ReadFileItem`].
+struct FileSection {
+    path: PathBuf,
+    start_line: usize,
+    cursor: Option<String>,
 }
 
 #[derive(Deserialize, JsonSchema)]
@@ -29,6 +49,7 @@ pub struct ReadFileParameters {
 /// A tool for reading file content with line numbers.
 pub struct ReadFileTool {
     parameter_schema: Value,
+    context: ToolContext,
 }
 
 impl ReadFileTool {
@@ -37,8 +58,16 @@ impl ReadFileTool {
     pub fn new() -> Self {
         ReadFileTool {
             parameter_schema: schema_for!(ReadFileParameters).to_value(),
+            context: ToolContext::new(),
         }
     }
+
+    /// Sets the context for resolving paths.
+    #[inline]
+    pub fn with_context(mut self, context: ToolContext) -> Self {
+        self.context = context;
+        self
+    }
 }
 
 impl Default for ReadFileTool {
@@ -58,7 +87,8 @@ impl Tool for ReadFileTool {
     fn description(&self) -> &str {
         r#"
 Reads files from absolute paths and returns their contents prefixed with line numbers.
-Each file includes a path and a 1-based start line, and returns up to 50 lines."#
+Each file includes a path and a 1-based start line, and returns up to 50 lines.
+If a file has more lines, pass the returned cursor to continue reading it."#
     }
 
     fn parameter_schema(&self) -> &Value {
@@ -71,6 +101,10 @@ Each file includes a path and a 1-based start line, and returns up to 50 lines."
             if !summary.is_empty() {
                 summary.push('\n');
             }
+            if item.cursor.is_some() {
+                summary.push_str(&format!("{} (continued)", item.path));
+                continue;
+            }
             let start_line = item.start_line.unwrap_or(1);
             let end_line = start_line + MAX_LINES - 1;
             summary.push_str(&format!(
@@ -86,46 +120,86 @@ Each file includes a path and a 1-based start line, and returns up to 50 lines."
         &self,
         input: ReadFileParameters,
     ) -> impl Future<Output = ToolResult> + Send + 'static {
+        let context = self.context.clone();
         async move {
-            let mut result = String::new();
+            let mut files = Vec::with_capacity(input.files.len());
             for file in input.files {
-                if !Path::new(&file.path).is_absolute() {
-                    return Err(ToolError::execution_error()
-                        .with_reason("`path` must be absolute"));
-                }
+                let path = context.resolve(&file.path)?;
                 let start_line = file.start_line.unwrap_or(1);
                 if start_line == 0 {
                     return Err(ToolError::execution_error()
                         .with_reason("`start_line` must be 1-based"));
                 }
-
-                let section = spawn_blocking(move || {
-                    read_file_section(&file.path, start_line)
-                })
-                .await
-                .map_err(|_| {
-                    ToolError::execution_error()
-                        .with_reason("Failed to read file")
-                })??;
-
-                if !result.is_empty() {
-                    result.push('\n');
-                }
-                result.push_str(&section);
+                files.push(FileSection {
+                    path,
+                    start_line,
+                    cursor: file.cursor,
+                });
             }
-            Ok(result)
+
+            let sections = read_file_sections(files).await?;
+            let truncated = sections.iter().any(|(_, truncated)| *truncated);
+            let texts: Vec<_> =
+                sections.into_iter().map(|(text, _)| text).collect();
+            let output = ToolOutput::from(texts.join("\n"));
+            Ok(if truncated {
+                output.truncated()
+            } else {
+                output
+            })
         }
     }
 }
 
+/// Reads the files in parallel, and returns the sections in the same order,
+/// each with whether it's truncated.
+///
+/// If any of the reads fails, the error of the first failed file is
+/// returned.
+async fn read_file_sections(
+    files: Vec<FileSection>,
+) -> Result<Vec<(String, bool)>, ToolError> {
+    let mut sections: Vec<_> =
+        iter::repeat_with(|| None).take(files.len()).collect();
+    let mut join_set = JoinSet::new();
+    for (index, file) in files.into_iter().enumerate() {
+        if join_set.len() >= MAX_CONCURRENT_READS {
+            let Some(joined) = join_set.join_next().await else {
+                unreachable!("the join set should not be empty");
+            };
+            let (index, section) = joined.map_err(|_| read_failed())?;
+            sections[index] = Some(section);
+        }
+        join_set.spawn_blocking(move || (index, read_file_section(&file)));
+    }
+    while let Some(joined) = join_set.join_next().await {
+        let (index, section) = joined.map_err(|_| read_failed())?;
+        sections[index] = Some(section);
+    }
+
+    sections
+        .into_iter()
+        .map(|section| section.unwrap_or_else(|| Err(read_failed())))
+        .collect()
+}
+
+#[inline]
+fn read_failed() -> ToolError {
+    ToolError::execution_error().with_reason("Failed to read file")
+}
+
 fn read_file_section(
-    path: &str,
-    start_line: usize,
-) -> Result<String, ToolError> {
-    let file = File::open(path).map_err(|err| {
+    section: &FileSection,
+) -> Result<(String, bool), ToolError> {
+    let file = File::open(&section.path).map_err(|err| {
         ToolError::execution_error().with_reason(err.to_string())
     })?;
-    format_reader_section(path, file, start_line)
+    format_reader_section(
+        &section.path.to_string_lossy(),
+        file,
+        section.start_line,
+        section.cursor.as_deref(),
+    )
 }
 
 // TODO: AI wrote this function, but I think it's too inefficient. Need to
@@ -134,22 +208,22 @@ fn format_reader_section<R: Read>(
     path: &str,
     reader: R,
     start_line: usize,
-) -> Result<String, ToolError> {
-    let reader = BufReader::new(reader);
-    let mut lines = Vec::new();
-    for (index, line) in reader.lines().enumerate() {
-        let line_no = index + 1;
-        if line_no < start_line {
-            continue;
-        }
-        let line = line.map_err(|err| {
+    cursor: Option<&str>,
+) -> Result<(String, bool), ToolError> {
+    let lines = BufReader::new(reader).lines();
+    let page = match cursor {
+        Some(cursor) => Paginated::resume(lines, Some(cursor), MAX_LINES)?,
+        None => Paginated::new(lines, start_line - 1, MAX_LINES),
+    };
+    let start_line = page.offset() + 1;
+    let hint = page.continuation_hint();
+    let lines = page
+        .into_items()
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| {
             ToolError::execution_error().with_reason(err.to_string())
         })?;
-        lines.push(line);
-        if lines.len() >= MAX_LINES {
-            break;
-        }
-    }
 
     let mut result = String::new();
     result.push_str(&format!("==> {path} <==\n"));
@@ -162,8 +236,13 @@ fn format_reader_section<R: Read>(
             result.push_str(&format!("{line_no:>width$}: {line}\n"));
         }
     }
+    let truncated = hint.is_some();
+    if let Some(hint) = hint {
+        result.push_str(&hint);
+        result.push('\n');
+    }
 
-    Ok(result)
+    Ok((result, truncated))
 }
 
 #[cfg(test)]
@@ -171,12 +250,50 @@ mod tests {
     use super::*;
     use std::io::Cursor;
 
+    #[tokio::test]
+    async fn test_read_files_in_order() {
+        let dir = std::env::temp_dir()
+            .join(format!("little-agent-read-file-{}", std::process::id()));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // More files than the concurrency limit.
+        let count = MAX_CONCURRENT_READS * 2 + 1;
+        let mut files = vec![];
+        for i in 0..count {
+            let path = dir.join(format!("{i}.txt"));
+            std::fs::write(&path, format!("file {i}\n")).unwrap();
+            files.push(ReadFileItem {
+                path: path.to_string_lossy().into_owned(),
+                start_line: None,
+                cursor: None,
+            });
+        }
+
+        let result = ReadFileTool::new()
+            .execute(ReadFileParameters { files })
+            .await
+            .unwrap();
+        let contents: Vec<_> = result
+            .to_text()
+            .lines()
+            .filter_map(|line| line.strip_prefix("1: "))
+            .map(str::to_owned)
+            .collect();
+        let expected: Vec<_> =
+            (0..count).map(|i| format!("file {i}")).collect();
+        assert_eq!(contents, expected);
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
     #[test]
     fn test_read_file_section_formats_lines() {
         let input = b"first\nsecond\nthird\n";
 
-        let output =
-            format_reader_section("/fake/path", Cursor::new(input), 2).unwrap();
+        let (output, _) =
+            format_reader_section("/fake/path", Cursor::new(input), 2, None)
+                .unwrap();
         let mut output_lines = output.lines();
 
         assert_eq!(output_lines.next().unwrap(), "==> /fake/path <==");
@@ -191,9 +308,26 @@ mod tests {
             input.extend_from_slice(b"line\n");
         }
 
-        let output =
-            format_reader_section("/fake/path", Cursor::new(input), 1).unwrap();
+        let (output, truncated) =
+            format_reader_section("/fake/path", Cursor::new(&input), 1, None)
+                .unwrap();
+        assert!(truncated);
+        let lines = output.lines().collect::<Vec<_>>();
+        // The header, the lines, and the continuation hint.
+        assert_eq!(lines.len(), MAX_LINES + 2);
+
+        let hint = lines.last().unwrap();
+        let cursor = hint.split('"').nth(1).unwrap();
+        let (output, truncated) = format_reader_section(
+            "/fake/path",
+            Cursor::new(&input),
+            1,
+            Some(cursor),
+        )
+        .unwrap();
+        assert!(!truncated);
         let lines = output.lines().collect::<Vec<_>>();
-        assert_eq!(lines.len(), MAX_LINES + 1);
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[1], format!("{}: line", MAX_LINES + 1));
     }
 }
@@ -0,0 +1,257 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use little_agent_core::conversation::{Conversation, Item as ConversationItem};
+use little_agent_core::tool::{
+    Approval as ToolApproval, Tool, ToolOutput, ToolResult,
+};
+use little_agent_model::ModelMessage;
+use schemars::{JsonSchema, schema_for};
+use serde::Deserialize;
+use serde_json::Value;
+
+const MAX_ENTRIES: usize = 50;
+const DEFAULT_MESSAGES: usize = 10;
+const MAX_OUTPUT_LEN: usize = 4000;
+
+/// A shared log of the recent conversation text, which is read by
+/// [`RecallTool`].
+///
+/// Feed it with [`AgentBuilder::on_conversation_item`]. Only the text of the
+/// user inputs and model responses is kept, and the older entries are
+/// dropped once the log is full.
+///
+/// [`AgentBuilder::on_conversation_item`]: little_agent_core::AgentBuilder::on_conversation_item
+#[derive(Clone, Default)]
+pub struct ConversationLog {
+    entries: Arc<Mutex<VecDeque<(&'static str, String)>>>,
+}
+
+impl ConversationLog {
+    /// Creates an empty log.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records an item of the conversation.
+    pub fn record(&self, item: &ConversationItem) {
+        let (role, text) = match item.message() {
+            ModelMessage::User(text) => ("user", text.as_str()),
+            ModelMessage::Assistant(text)
+            | ModelMessage::AssistantToolCalls { content: text, .. } => {
+                ("assistant", text.as_str())
+            }
+            // Opaque messages only come from the model.
+            ModelMessage::Opaque(_) => ("assistant", item.transcript()),
+            ModelMessage::System(_) | ModelMessage::Tool(_) => return,
+        };
+        let text = text.trim();
+        if text.is_empty() {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() == MAX_ENTRIES {
+            entries.pop_front();
+        }
+        entries.push_back((role, text.to_owned()));
+    }
+
+    /// Replaces the log with the items of the conversation, e.g. after it's
+    /// rolled back.
+    pub fn reset(&self, conversation: &Conversation) {
+        self.entries.lock().unwrap().clear();
+        for item in conversation {
+            self.record(item);
+        }
+    }
+}
+
+#[derive(Deserialize, JsonSchema)]
+pub struct RecallToolParameters {
+    #[schemars(description = "Number of recent messages to recall, \
+                              default to 10.")]
+    messages: Option<usize>,
+}
+
+/// A tool for recalling the recent messages of the conversation.
+///
+/// The result is capped at 4000 bytes, keeping the most recent messages.
+/// Since it only reads the conversation, no approval is needed.
+pub struct RecallTool {
+    parameter_schema: Value,
+    log: ConversationLog,
+}
+
+impl RecallTool {
+    /// Creates a recall tool that reads from the log.
+    #[inline]
+    pub fn new(log: ConversationLog) -> Self {
+        RecallTool {
+            parameter_schema: schema_for!(RecallToolParameters).to_value(),
+            log,
+        }
+    }
+}
+
+impl Tool for RecallTool {
+    type Input = RecallToolParameters;
+
+    fn name(&self) -> &str {
+        "recall"
+    }
+
+    fn description(&self) -> &str {
+        r#"
+Recalls the recent user and assistant messages of this conversation, from the oldest to the newest.
+Tool calls and their results are not included."#
+    }
+
+    fn parameter_schema(&self) -> &Value {
+        &self.parameter_schema
+    }
+
+    fn make_approval(&self, input: &Self::Input) -> ToolApproval {
+        let messages = input.messages.unwrap_or(DEFAULT_MESSAGES);
+        ToolApproval::new(
+            format!("last {messages} messages"),
+            "Agent wants to recall the conversation",
+        )
+    }
+
+    fn requires_approval(&self, _input: &Self::Input) -> bool {
+        false
+    }
+
+    fn execute(
+        &self,
+        input: Self::Input,
+    ) -> impl Future<Output = ToolResult> + Send + 'static {
+        let messages = input.messages.unwrap_or(DEFAULT_MESSAGES);
+        let entries = self.log.entries.lock().unwrap();
+
+        // Walk backwards, so the most recent messages are kept when the
+        // output is too long.
+        let mut lines = vec![];
+        let mut len = 0;
+        let mut truncated = false;
+        for (role, text) in entries.iter().rev().take(messages) {
+            let mut line = format!("{role}: {text}");
+            len += line.len() + 1;
+            if len > MAX_OUTPUT_LEN {
+                // Keep the beginning of the newest message, if it's too
+                // long on its own.
+                if lines.is_empty() {
+                    truncate_at_char_boundary(&mut line, MAX_OUTPUT_LEN);
+                    lines.push(line);
+                }
+                truncated = true;
+                break;
+            }
+            lines.push(line);
+        }
+        lines.reverse();
+
+        let output = if lines.is_empty() {
+            ToolOutput::from("(no messages)")
+        } else {
+            ToolOutput::from(lines.join("\n"))
+        };
+        let output = if truncated {
+            output.truncated()
+        } else {
+            output
+        };
+        std::future::ready(Ok(output))
+    }
+}
+
+/// Truncates the string to at most `len` bytes, without splitting a char.
+fn truncate_at_char_boundary(s: &mut String, len: usize) {
+    let mut len = len.min(s.len());
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    s.truncate(len);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use little_agent_core::AgentBuilder;
+    use little_agent_test_model::{
+        PresetEvent, PresetResponse, TestModelProvider,
+    };
+    use tokio::sync::watch;
+    use tokio::time::timeout;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_recall() {
+        let mut model_provider = TestModelProvider::default();
+        model_provider.add_user_input_step();
+        model_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Hi, how can I help?".to_owned(),
+            )]),
+        );
+
+        let log = ConversationLog::new();
+        let (idle_tx, mut idle_rx) = watch::channel::<bool>(false);
+        let agent = AgentBuilder::with_model_provider(model_provider)
+            .on_conversation_item({
+                let log = log.clone();
+                move |item| log.record(item)
+            })
+            .on_idle(move || {
+                idle_tx.send(true).unwrap();
+            })
+            .build();
+        agent.enqueue_user_input("Hello").unwrap();
+
+        timeout(Duration::from_millis(500), idle_rx.wait_for(|v| *v))
+            .await
+            .unwrap()
+            .unwrap();
+
+        let tool = RecallTool::new(log);
+        let result = tool
+            .execute(RecallToolParameters { messages: None })
+            .await
+            .unwrap();
+        assert_eq!(
+            result.to_text(),
+            "user: Hello\nassistant: Hi, how can I help?"
+        );
+
+        let result = tool
+            .execute(RecallToolParameters { messages: Some(1) })
+            .await
+            .unwrap();
+        assert_eq!(result.to_text(), "assistant: Hi, how can I help?");
+    }
+
+    #[tokio::test]
+    async fn test_long_message_truncated() {
+        let log = ConversationLog::new();
+        log.entries.lock().unwrap().extend([
+            ("user", "Hi".to_owned()),
+            ("assistant", "é".repeat(3000)),
+        ]);
+
+        let tool = RecallTool::new(log);
+        let input = RecallToolParameters { messages: None };
+        assert!(!tool.requires_approval(&input));
+        let result = tool.execute(input).await.unwrap();
+        assert!(result.is_truncated());
+        let ToolOutput::Truncated(output) = result else {
+            unreachable!();
+        };
+        let text = output.to_text();
+        assert!(text.starts_with("assistant: éé"));
+        assert!(text.len() <= MAX_OUTPUT_LEN);
+    }
+}
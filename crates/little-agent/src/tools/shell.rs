@@ -1,6 +1,9 @@
 use std::env;
 use std::io;
 
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+
 use little_agent_core::tool::{
     Approval as ToolApproval, Error as ToolError, Tool, ToolResult,
 };
@@ -15,9 +18,22 @@ pub struct ShellToolParameters {
     cmdline: String,
 }
 
+/// How the shell tool handles command output that is not valid UTF-8.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OutputEncoding {
+    /// Replaces the invalid bytes with `U+FFFD`.
+    #[default]
+    Lossy,
+    /// Returns the output as a base64-encoded payload.
+    Base64,
+    /// Fails the tool call with an error.
+    Strict,
+}
+
 /// A tool for running shell commands.
 pub struct ShellTool {
     parameter_schema: Value,
+    output_encoding: OutputEncoding,
 }
 
 impl ShellTool {
@@ -26,8 +42,17 @@ impl ShellTool {
     pub fn new() -> Self {
         ShellTool {
             parameter_schema: schema_for!(ShellToolParameters).to_value(),
+            output_encoding: OutputEncoding::default(),
         }
     }
+
+    /// Sets how the output that is not valid UTF-8 is handled. Defaults to
+    /// [`OutputEncoding::Lossy`].
+    #[inline]
+    pub fn with_output_encoding(mut self, encoding: OutputEncoding) -> Self {
+        self.output_encoding = encoding;
+        self
+    }
 }
 
 impl Default for ShellTool {
@@ -63,10 +88,14 @@ The command line should be single line if possible. Strings collected from stdou
         &self,
         input: ShellToolParameters,
     ) -> impl Future<Output = ToolResult> + Send + 'static {
+        let encoding = self.output_encoding;
         async move {
-            run_command_line(&input.cmdline).await.map_err(|err| {
-                ToolError::execution_error().with_reason(format!("{err}"))
-            })
+            run_command_line(&input.cmdline, encoding)
+                .await
+                .map(Into::into)
+                .map_err(|err| {
+                    ToolError::execution_error().with_reason(format!("{err}"))
+                })
         }
     }
 }
@@ -80,7 +109,10 @@ fn create_command_with_inferred_shell() -> Command {
 }
 
 #[inline]
-async fn run_command_line(cmdline: &str) -> Result<String, io::Error> {
+async fn run_command_line(
+    cmdline: &str,
+    encoding: OutputEncoding,
+) -> Result<String, io::Error> {
     let output = create_command_with_inferred_shell()
         .arg("-cl")
         .arg(cmdline)
@@ -89,23 +121,66 @@ async fn run_command_line(cmdline: &str) -> Result<String, io::Error> {
 
     let mut result = String::new();
     if !output.stdout.is_empty() {
-        result.push_str("==> STDOUT <==\n");
-        result.push_str(&String::from_utf8_lossy(&output.stdout));
+        append_stream(&mut result, "STDOUT", &output.stdout, encoding)?;
     }
     if !output.stderr.is_empty() {
-        result.push_str("\n==> STDERR <==\n");
-        result.push_str(&String::from_utf8_lossy(&output.stderr));
+        result.push('\n');
+        append_stream(&mut result, "STDERR", &output.stderr, encoding)?;
     }
     Ok(result)
 }
 
+fn append_stream(
+    result: &mut String,
+    name: &str,
+    bytes: &[u8],
+    encoding: OutputEncoding,
+) -> Result<(), io::Error> {
+    let text = match (std::str::from_utf8(bytes), encoding) {
+        (Ok(text), _) => text,
+        (Err(_), OutputEncoding::Lossy) => &String::from_utf8_lossy(bytes),
+        (Err(_), OutputEncoding::Base64) => {
+            result.push_str(&format!("==> {name} (base64) <==\n"));
+            result.push_str(&BASE64.encode(bytes));
+            return Ok(());
+        }
+        (Err(err), OutputEncoding::Strict) => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{name} is not valid UTF-8: {err}"),
+            ));
+        }
+    };
+    result.push_str(&format!("==> {name} <==\n"));
+    result.push_str(text);
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[tokio::test]
     async fn test_run_command_line() {
-        let result = run_command_line("echo 'Hello, World!'").await;
+        let result =
+            run_command_line("echo 'Hello, World!'", OutputEncoding::Lossy)
+                .await;
         assert_eq!(result.unwrap(), "==> STDOUT <==\nHello, World!\n");
     }
+
+    #[tokio::test]
+    async fn test_invalid_utf8_output() {
+        let cmdline = r"printf 'ok\377'";
+
+        let result = run_command_line(cmdline, OutputEncoding::Lossy).await;
+        assert_eq!(result.unwrap(), "==> STDOUT <==\nok\u{FFFD}");
+
+        let result = run_command_line(cmdline, OutputEncoding::Base64).await;
+        assert_eq!(result.unwrap(), "==> STDOUT (base64) <==\nb2v/");
+
+        let result = run_command_line(cmdline, OutputEncoding::Strict).await;
+        let err = result.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("STDOUT is not valid UTF-8"));
+    }
 }
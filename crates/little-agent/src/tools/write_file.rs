@@ -0,0 +1,264 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::Path;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use little_agent_core::tool::{
+    Approval as ToolApproval, Error as ToolError, Tool, ToolResult,
+};
+use schemars::{JsonSchema, schema_for};
+use serde::Deserialize;
+use serde_json::Value;
+use similar::TextDiff;
+use tokio::task::spawn_blocking;
+
+use super::ToolContext;
+
+const MAX_DIFF_LINES: usize = 80;
+/// The largest file to read for the diff in the approval.
+const MAX_DIFF_BYTES: u64 = 64 * 1024;
+
+#[derive(Deserialize, JsonSchema)]
+pub struct WriteFileParameters {
+    #[schemars(
+        description = "Absolute path to the file, or relative to the root."
+    )]
+    path: String,
+    #[schemars(description = "The full content to write.")]
+    content: String,
+}
+
+/// A tool for creating or overwriting files.
+///
+/// The approval shows a diff between the current and the proposed content.
+/// Since the approval is made on the agent's thread, the diff is skipped
+/// for files larger than 64 KiB to keep the read short.
+pub struct WriteFileTool {
+    parameter_schema: Value,
+    context: ToolContext,
+}
+
+impl WriteFileTool {
+    /// Creates a new write file tool.
+    #[inline]
+    pub fn new() -> Self {
+        WriteFileTool {
+            parameter_schema: schema_for!(WriteFileParameters).to_value(),
+            context: ToolContext::new(),
+        }
+    }
+
+    /// Sets the context for resolving paths.
+    #[inline]
+    pub fn with_context(mut self, context: ToolContext) -> Self {
+        self.context = context;
+        self
+    }
+}
+
+impl Default for WriteFileTool {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Tool for WriteFileTool {
+    type Input = WriteFileParameters;
+
+    fn name(&self) -> &str {
+        "write_file"
+    }
+
+    fn description(&self) -> &str {
+        r#"
+Writes the content to a file at an absolute path, creating it if it doesn't exist.
+The existing content will be replaced entirely."#
+    }
+
+    fn parameter_schema(&self) -> &Value {
+        &self.parameter_schema
+    }
+
+    fn make_approval(&self, input: &WriteFileParameters) -> ToolApproval {
+        let Ok(path) = self.context.resolve(&input.path) else {
+            // The execution will fail anyway.
+            return ToolApproval::new(
+                &input.path,
+                "Agent wants to write this file",
+            );
+        };
+        let what = match read_for_diff(&path) {
+            Ok(Some(current)) => format!(
+                "{}\n{}",
+                input.path,
+                make_diff(&input.path, &current, &input.content)
+            ),
+            Ok(None) => format!("{} (too large to diff)", input.path),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                format!("{} (new file)", input.path)
+            }
+            Err(_) => input.path.clone(),
+        };
+        ToolApproval::new(what, "Agent wants to write this file")
+    }
+
+    #[allow(clippy::manual_async_fn)]
+    fn execute(
+        &self,
+        input: WriteFileParameters,
+    ) -> impl Future<Output = ToolResult> + Send + 'static {
+        let context = self.context.clone();
+        async move {
+            let path = context.resolve(&input.path)?;
+            let len = input.content.len();
+            spawn_blocking(move || write_atomically(&path, &input.content))
+                .await
+                .map_err(|_| {
+                    ToolError::execution_error()
+                        .with_reason("Failed to write file")
+                })?
+                .map_err(|err| {
+                    ToolError::execution_error().with_reason(err.to_string())
+                })?;
+            Ok(format!("Wrote {len} bytes to {}", input.path).into())
+        }
+    }
+}
+
+/// Reads the current content for the diff, or returns `None` if the file
+/// is larger than [`MAX_DIFF_BYTES`].
+fn read_for_diff(path: &Path) -> io::Result<Option<String>> {
+    let file = File::open(path)?;
+    if file.metadata()?.len() > MAX_DIFF_BYTES {
+        return Ok(None);
+    }
+    let mut content = String::new();
+    // The file may grow after the check, the limit still holds.
+    file.take(MAX_DIFF_BYTES).read_to_string(&mut content)?;
+    Ok(Some(content))
+}
+
+/// Writes to a temporary file first and then renames it, so the target
+/// is never left half-written.
+///
+/// The temporary file is unique to each write, and gets the permissions
+/// of the file being replaced.
+fn write_atomically(path: &Path, content: &str) -> io::Result<()> {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+    tmp_name.push(format!(".{}.{id}.tmp", process::id()));
+    let tmp_path = path.with_file_name(tmp_name);
+    fs::write(&tmp_path, content)?;
+
+    let result = match fs::metadata(path) {
+        Ok(metadata) => fs::set_permissions(&tmp_path, metadata.permissions()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    };
+    result
+        .and_then(|_| fs::rename(&tmp_path, path))
+        .inspect_err(|_| {
+            fs::remove_file(&tmp_path).ok();
+        })
+}
+
+/// Makes a unified diff, truncated to [`MAX_DIFF_LINES`] lines.
+fn make_diff(path: &str, current: &str, proposed: &str) -> String {
+    let diff = TextDiff::from_lines(current, proposed)
+        .unified_diff()
+        .context_radius(3)
+        .header(path, path)
+        .to_string();
+    if diff.is_empty() {
+        return "(no changes)".to_owned();
+    }
+
+    let lines: Vec<_> = diff.lines().collect();
+    if lines.len() <= MAX_DIFF_LINES {
+        return diff;
+    }
+    let mut truncated = lines[..MAX_DIFF_LINES].join("\n");
+    let remaining = lines.len() - MAX_DIFF_LINES;
+    truncated.push_str(&format!("\n... ({remaining} more lines)"));
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn test_approval_shows_diff() {
+        let path = env::temp_dir()
+            .join(format!("little-agent-write-{}.txt", std::process::id()));
+        fs::write(&path, "first\nsecond\nthird\n").unwrap();
+
+        let tool = WriteFileTool::new();
+        let input = WriteFileParameters {
+            path: path.to_string_lossy().into_owned(),
+            content: "first\n2nd\nthird\n".to_owned(),
+        };
+        let approval = tool.make_approval(&input);
+        assert!(approval.what().contains("-second\n"));
+        assert!(approval.what().contains("+2nd\n"));
+        assert!(!approval.what().contains("-first"));
+
+        fs::remove_file(&path).ok();
+        let approval = tool.make_approval(&input);
+        assert!(approval.what().ends_with("(new file)"));
+    }
+
+    #[test]
+    fn test_write_atomically() {
+        let dir = env::temp_dir()
+            .join(format!("little-agent-write-atomic-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("script.sh");
+
+        write_atomically(&path, "echo 1\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "echo 1\n");
+
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+        write_atomically(&path, "echo 2\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "echo 2\n");
+        assert!(fs::metadata(&path).unwrap().permissions().readonly());
+
+        // No temporary files are left behind.
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_large_file_not_diffed() {
+        let path = env::temp_dir()
+            .join(format!("little-agent-write-large-{}.txt", process::id()));
+        fs::write(&path, "x".repeat(MAX_DIFF_BYTES as usize + 1)).unwrap();
+
+        let tool = WriteFileTool::new();
+        let input = WriteFileParameters {
+            path: path.to_string_lossy().into_owned(),
+            content: "small".to_owned(),
+        };
+        let approval = tool.make_approval(&input);
+        assert!(approval.what().ends_with("(too large to diff)"));
+
+        fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_diff_truncated() {
+        let proposed: String =
+            (0..MAX_DIFF_LINES * 2).map(|i| format!("{i}\n")).collect();
+        let diff = make_diff("/fake/path", "", &proposed);
+        assert_eq!(diff.lines().count(), MAX_DIFF_LINES + 1);
+        assert!(diff.ends_with("more lines)"));
+    }
+}
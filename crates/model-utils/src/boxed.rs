@@ -0,0 +1,255 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Debug, Display};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use little_agent_model::{
+    ErrorKind, MessageJoinPolicy, ModelProvider, ModelProviderError,
+    ModelRequest, ModelResponse, ModelResponseEvent, OpaqueMessage, Usage,
+};
+
+type BoxedHealthCheckFuture =
+    Pin<Box<dyn Future<Output = Result<(), BoxedModelProviderError>> + Send>>;
+type BoxedSendRequestFuture = Pin<
+    Box<
+        dyn Future<Output = Result<BoxedModelResponse, BoxedModelProviderError>>
+            + Send,
+    >,
+>;
+
+/// A type-erased model provider.
+///
+/// Since [`ModelProvider`] has associated types, providers of different
+/// types can't be stored in one collection. This type erases them behind
+/// [`BoxedModelResponse`] and [`BoxedModelProviderError`], so it can be
+/// used with wrappers like [`RoundRobinProvider`].
+///
+/// Use [`ModelProviderExt::boxed`] to create it.
+///
+/// [`RoundRobinProvider`]: crate::RoundRobinProvider
+pub struct BoxedModelProvider(Box<dyn DynModelProvider>);
+
+trait DynModelProvider: Send + Sync {
+    fn dyn_send_request(&self, req: &ModelRequest) -> BoxedSendRequestFuture;
+
+    fn dyn_health_check(&self) -> BoxedHealthCheckFuture;
+
+    fn dyn_message_join_policy(&self) -> MessageJoinPolicy;
+}
+
+impl<P: ModelProvider> DynModelProvider for P {
+    fn dyn_send_request(&self, req: &ModelRequest) -> BoxedSendRequestFuture {
+        let fut = self.send_request(req);
+        Box::pin(async move {
+            match fut.await {
+                Ok(resp) => Ok(BoxedModelResponse(Box::pin(resp))),
+                Err(err) => Err(BoxedModelProviderError(Box::new(err))),
+            }
+        })
+    }
+
+    fn dyn_health_check(&self) -> BoxedHealthCheckFuture {
+        let fut = self.health_check();
+        Box::pin(async move {
+            fut.await
+                .map_err(|err| BoxedModelProviderError(Box::new(err)))
+        })
+    }
+
+    #[inline]
+    fn dyn_message_join_policy(&self) -> MessageJoinPolicy {
+        self.message_join_policy()
+    }
+}
+
+impl ModelProvider for BoxedModelProvider {
+    type Error = BoxedModelProviderError;
+    type Response = BoxedModelResponse;
+
+    #[inline]
+    fn send_request(
+        &self,
+        req: &ModelRequest,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
+    {
+        self.0.dyn_send_request(req)
+    }
+
+    #[inline]
+    fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        self.0.dyn_health_check()
+    }
+
+    #[inline]
+    fn message_join_policy(&self) -> MessageJoinPolicy {
+        self.0.dyn_message_join_policy()
+    }
+}
+
+/// Extension methods for [`ModelProvider`].
+pub trait ModelProviderExt: ModelProvider + Sized + 'static {
+    /// Erases the type of this provider.
+    #[inline]
+    fn boxed(self) -> BoxedModelProvider {
+        BoxedModelProvider(Box::new(self))
+    }
+}
+
+impl<P: ModelProvider + 'static> ModelProviderExt for P {}
+
+/// A type-erased model response, see [`BoxedModelProvider`].
+pub struct BoxedModelResponse(Pin<Box<dyn DynModelResponse>>);
+
+trait DynModelResponse: Send {
+    fn dyn_poll_next_event(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<ModelResponseEvent>, BoxedModelProviderError>>;
+
+    fn dyn_make_opaque_message(&self) -> Option<OpaqueMessage>;
+
+    fn dyn_usage(&self) -> Option<Usage>;
+}
+
+impl<R: ModelResponse> DynModelResponse for R {
+    #[inline]
+    fn dyn_poll_next_event(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<ModelResponseEvent>, BoxedModelProviderError>> {
+        self.poll_next_event(cx)
+            .map_err(|err| BoxedModelProviderError(Box::new(err)))
+    }
+
+    #[inline]
+    fn dyn_make_opaque_message(&self) -> Option<OpaqueMessage> {
+        self.make_opaque_message()
+    }
+
+    #[inline]
+    fn dyn_usage(&self) -> Option<Usage> {
+        self.usage()
+    }
+}
+
+impl ModelResponse for BoxedModelResponse {
+    type Error = BoxedModelProviderError;
+
+    #[inline]
+    fn poll_next_event(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<ModelResponseEvent>, Self::Error>> {
+        self.get_mut().0.as_mut().dyn_poll_next_event(cx)
+    }
+
+    #[inline]
+    fn make_opaque_message(&self) -> Option<OpaqueMessage> {
+        self.0.dyn_make_opaque_message()
+    }
+
+    #[inline]
+    fn usage(&self) -> Option<Usage> {
+        self.0.dyn_usage()
+    }
+}
+
+/// A type-erased model provider error, see [`BoxedModelProvider`].
+pub struct BoxedModelProviderError(Box<dyn ModelProviderError>);
+
+impl BoxedModelProviderError {
+    /// Returns the inner error.
+    #[inline]
+    pub fn into_inner(self) -> Box<dyn ModelProviderError> {
+        self.0
+    }
+}
+
+impl Debug for BoxedModelProviderError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl Display for BoxedModelProviderError {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl StdError for BoxedModelProviderError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.0.source()
+    }
+}
+
+impl ModelProviderError for BoxedModelProviderError {
+    #[inline]
+    fn kind(&self) -> ErrorKind {
+        self.0.kind()
+    }
+
+    #[inline]
+    fn retry_after(&self) -> Option<Duration> {
+        self.0.retry_after()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use little_agent_model::ModelMessage;
+    use little_agent_openai_model::{OpenAIConfigBuilder, OpenAIProvider};
+    use little_agent_test_model::{
+        PresetEvent, PresetResponse, TestModelProvider,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_heterogeneous_providers() {
+        let mut test_provider = TestModelProvider::default();
+        test_provider.add_user_input_step();
+        test_provider.add_assistant_response_step(
+            PresetResponse::with_events([PresetEvent::MessageDelta(
+                "Hi".to_owned(),
+            )])
+            .with_failures(1),
+        );
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_message_join_policy(MessageJoinPolicy::SpaceJoin)
+            .build()
+            .unwrap();
+        let openai_provider = OpenAIProvider::new(config);
+
+        let providers: Vec<BoxedModelProvider> =
+            vec![test_provider.boxed(), openai_provider.boxed()];
+        assert_eq!(providers.len(), 2);
+        // Each boxed provider forwards to its own implementation.
+        assert_eq!(
+            providers[0].message_join_policy(),
+            MessageJoinPolicy::Concatenate
+        );
+        assert_eq!(
+            providers[1].message_join_policy(),
+            MessageJoinPolicy::SpaceJoin
+        );
+
+        let req = ModelRequest {
+            messages: vec![ModelMessage::User("Hi".to_owned())],
+            ..Default::default()
+        };
+        let resp = providers[0].send_request(&req).await.unwrap();
+        let err = resp.collect_text().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::RateLimitExceeded);
+
+        let resp = providers[0].send_request(&req).await.unwrap();
+        assert!(resp.make_opaque_message().is_some());
+        assert_eq!(resp.collect_text().await.unwrap(), "Hi");
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::{HashMap, VecDeque};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, ready};
+
+use little_agent_model::{
+    MessageJoinPolicy, ModelProvider, ModelRequest, ModelResponse,
+    ModelResponseEvent, OpaqueMessage, Usage,
+};
+
+/// A provider that caches the responses of the inner provider.
+///
+/// Responses are keyed by the whole [`ModelRequest`], so only identical
+/// requests share the same response. A response is cached only after it
+/// completes successfully, and cache hits replay the recorded events
+/// without calling the inner provider.
+///
+/// The cache lives in memory and is shared by all requests sent through
+/// this provider. It's unbounded by default, use
+/// [`CachingProvider::with_max_entries`] to limit its size.
+pub struct CachingProvider<P> {
+    inner: P,
+    cache: Arc<Cache>,
+}
+
+impl<P> CachingProvider<P> {
+    /// Creates a caching provider that wraps `inner`.
+    #[inline]
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            cache: Default::default(),
+        }
+    }
+
+    /// Limits the number of cached responses. When the limit is exceeded,
+    /// the oldest entry is evicted.
+    #[inline]
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        Arc::get_mut(&mut self.cache)
+            .expect("provider should not be in use")
+            .max_entries = Some(max_entries);
+        self
+    }
+}
+
+impl<P: ModelProvider> ModelProvider for CachingProvider<P> {
+    type Error = P::Error;
+    type Response = CachingResponse<P::Response>;
+
+    fn send_request(
+        &self,
+        req: &ModelRequest,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
+    {
+        let cached = self.cache.get(req);
+        let fut = match cached {
+            Some(_) => None,
+            None => Some(self.inner.send_request(req)),
+        };
+        let cache = Arc::clone(&self.cache);
+        let req = req.clone();
+        async move {
+            let Some(fut) = fut else {
+                trace!("serving the request from cache");
+                return Ok(CachingResponse(ResponseState::Cached {
+                    cached: cached.expect("should be a cache hit"),
+                    event_idx: 0,
+                }));
+            };
+            let inner = fut.await?;
+            Ok(CachingResponse(ResponseState::Live {
+                inner: Box::pin(inner),
+                recorded: Default::default(),
+                cache,
+                req: Some(req),
+            }))
+        }
+    }
+
+    #[inline]
+    fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        self.inner.health_check()
+    }
+
+    #[inline]
+    fn message_join_policy(&self) -> MessageJoinPolicy {
+        self.inner.message_join_policy()
+    }
+}
+
+#[derive(Default)]
+struct Cache {
+    entries: Mutex<CacheEntries>,
+    max_entries: Option<usize>,
+}
+
+#[derive(Default)]
+struct CacheEntries {
+    map: HashMap<ModelRequest, Arc<CachedResponse>>,
+    order: VecDeque<ModelRequest>,
+}
+
+impl Cache {
+    #[inline]
+    fn get(&self, req: &ModelRequest) -> Option<Arc<CachedResponse>> {
+        self.entries.lock().unwrap().map.get(req).cloned()
+    }
+
+    fn insert(&self, req: ModelRequest, resp: CachedResponse) {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.map.insert(req.clone(), Arc::new(resp)).is_none() {
+            entries.order.push_back(req);
+        }
+        let Some(max_entries) = self.max_entries else {
+            return;
+        };
+        while entries.order.len() > max_entries {
+            let Some(oldest) = entries.order.pop_front() else {
+                break;
+            };
+            entries.map.remove(&oldest);
+        }
+    }
+}
+
+struct CachedResponse {
+    events: Vec<ModelResponseEvent>,
+    opaque_msg: Option<OpaqueMessage>,
+}
+
+/// The response of [`CachingProvider`].
+pub struct CachingResponse<R>(ResponseState<R>);
+
+enum ResponseState<R> {
+    /// A response from the inner provider, which is recorded while being
+    /// consumed.
+    Live {
+        inner: Pin<Box<R>>,
+        recorded: Vec<ModelResponseEvent>,
+        cache: Arc<Cache>,
+        // Taken when the response is stored into the cache.
+        req: Option<ModelRequest>,
+    },
+    /// A response replayed from the cache.
+    Cached {
+        cached: Arc<CachedResponse>,
+        event_idx: usize,
+    },
+}
+
+impl<R: ModelResponse> ModelResponse for CachingResponse<R> {
+    type Error = R::Error;
+
+    fn poll_next_event(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<ModelResponseEvent>, Self::Error>> {
+        match &mut self.get_mut().0 {
+            ResponseState::Live {
+                inner,
+                recorded,
+                cache,
+                req,
+            } => {
+                let event = ready!(inner.as_mut().poll_next_event(cx))?;
+                match &event {
+                    Some(event) => recorded.push(event.clone()),
+                    None => {
+                        if let Some(req) = req.take() {
+                            let resp = CachedResponse {
+                                events: std::mem::take(recorded),
+                                opaque_msg: inner.make_opaque_message(),
+                            };
+                            cache.insert(req, resp);
+                        }
+                    }
+                }
+                Poll::Ready(Ok(event))
+            }
+            ResponseState::Cached { cached, event_idx } => {
+                let event = cached.events.get(*event_idx).cloned();
+                *event_idx += 1;
+                Poll::Ready(Ok(event))
+            }
+        }
+    }
+
+    fn make_opaque_message(&self) -> Option<OpaqueMessage> {
+        match &self.0 {
+            ResponseState::Live { inner, .. } => inner.make_opaque_message(),
+            ResponseState::Cached { cached, .. } => cached.opaque_msg.clone(),
+        }
+    }
+
+    fn usage(&self) -> Option<Usage> {
+        match &self.0 {
+            ResponseState::Live { inner, .. } => inner.usage(),
+            // Cached responses cost nothing.
+            ResponseState::Cached { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{make_provider, make_request};
+
+    #[tokio::test]
+    async fn test_cache_hit() {
+        let inner = make_provider(&["Hello, ", "world!"], None);
+        let provider = CachingProvider::new(inner.clone());
+
+        let resp = provider.send_request(&make_request("Hi")).await.unwrap();
+        assert_eq!(resp.collect_text().await.unwrap(), "Hello, world!");
+
+        let resp = provider.send_request(&make_request("Hi")).await.unwrap();
+        assert!(resp.make_opaque_message().is_some());
+        assert_eq!(resp.collect_text().await.unwrap(), "Hello, world!");
+        assert_eq!(inner.requests().len(), 1);
+
+        let resp = provider.send_request(&make_request("Bye")).await.unwrap();
+        assert_eq!(resp.collect_text().await.unwrap(), "Hello, world!");
+        assert_eq!(inner.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_max_entries() {
+        let inner = make_provider(&["Hello, ", "world!"], None);
+        let provider = CachingProvider::new(inner.clone()).with_max_entries(1);
+
+        for text in ["A", "B", "A"] {
+            let resp =
+                provider.send_request(&make_request(text)).await.unwrap();
+            resp.collect_text().await.unwrap();
+        }
+        // "A" is evicted by "B", so it's requested again.
+        assert_eq!(inner.requests().len(), 3);
+    }
+}
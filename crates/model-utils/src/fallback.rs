@@ -0,0 +1,265 @@
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use little_agent_model::{
+    ErrorKind, MessageJoinPolicy, ModelProvider, ModelProviderError,
+    ModelRequest, ModelResponse, ModelResponseEvent, OpaqueMessage, Usage,
+};
+
+/// A provider that tries a primary provider first, and falls back to a
+/// secondary provider if the primary one fails.
+///
+/// The fallback only happens when the primary provider fails with a
+/// retryable error (see [`ErrorKind::is_retryable`]) before producing any
+/// response events. Once an event is produced, the response is committed
+/// to the primary provider, and later errors are returned as-is.
+///
+/// To find out whether the primary provider fails early, the first event
+/// of its response is pulled eagerly when sending the request.
+///
+/// Fallback providers can be nested to chain more than two providers.
+pub struct FallbackProvider<P1, P2> {
+    primary: P1,
+    secondary: Arc<P2>,
+}
+
+impl<P1, P2> FallbackProvider<P1, P2> {
+    /// Creates a provider with the primary and secondary providers.
+    #[inline]
+    pub fn new(primary: P1, secondary: P2) -> Self {
+        Self {
+            primary,
+            secondary: Arc::new(secondary),
+        }
+    }
+}
+
+impl<P1, P2> ModelProvider for FallbackProvider<P1, P2>
+where
+    P1: ModelProvider,
+    P2: ModelProvider + 'static,
+{
+    type Error = FallbackError<P1::Error, P2::Error>;
+    type Response = FallbackResponse<P1::Response, P2::Response>;
+
+    fn send_request(
+        &self,
+        req: &ModelRequest,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
+    {
+        let primary_fut = self.primary.send_request(req);
+        let secondary = Arc::clone(&self.secondary);
+        let req = req.clone();
+        async move {
+            let primary_err = match try_primary(primary_fut).await {
+                Ok(resp) => return Ok(resp),
+                Err(err) if err.kind().is_retryable() => err,
+                Err(err) => return Err(FallbackError::Primary(err)),
+            };
+            debug!("primary provider failed, falling back: {primary_err}");
+
+            let resp = secondary
+                .send_request(&req)
+                .await
+                .map_err(FallbackError::Secondary)?;
+            Ok(FallbackResponse::Secondary(Box::pin(resp)))
+        }
+    }
+
+    /// Succeeds if either provider is healthy. Like requests, the secondary
+    /// provider is only checked if the primary one fails with a retryable
+    /// error.
+    fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        let primary_fut = self.primary.health_check();
+        let secondary = Arc::clone(&self.secondary);
+        async move {
+            match primary_fut.await {
+                Ok(()) => return Ok(()),
+                Err(err) if err.kind().is_retryable() => {
+                    debug!("primary provider is unhealthy: {err}");
+                }
+                Err(err) => return Err(FallbackError::Primary(err)),
+            }
+            secondary
+                .health_check()
+                .await
+                .map_err(FallbackError::Secondary)
+        }
+    }
+
+    /// Returns the policy of the primary provider.
+    #[inline]
+    fn message_join_policy(&self) -> MessageJoinPolicy {
+        self.primary.message_join_policy()
+    }
+}
+
+async fn try_primary<R: ModelResponse, R2>(
+    fut: impl Future<Output = Result<R, R::Error>>,
+) -> Result<FallbackResponse<R, R2>, R::Error> {
+    let mut resp = Box::pin(fut.await?);
+    let first_event = poll_fn(|cx| resp.as_mut().poll_next_event(cx)).await?;
+    Ok(FallbackResponse::Primary {
+        resp,
+        first_event: Some(first_event),
+    })
+}
+
+/// The response of [`FallbackProvider`].
+pub enum FallbackResponse<R1, R2> {
+    /// The response from the primary provider.
+    Primary {
+        /// The inner response.
+        resp: Pin<Box<R1>>,
+        /// The event pulled eagerly, which is not yet consumed.
+        first_event: Option<Option<ModelResponseEvent>>,
+    },
+    /// The response from the secondary provider.
+    Secondary(Pin<Box<R2>>),
+}
+
+impl<R1, R2> ModelResponse for FallbackResponse<R1, R2>
+where
+    R1: ModelResponse,
+    R2: ModelResponse,
+{
+    type Error = FallbackError<R1::Error, R2::Error>;
+
+    fn poll_next_event(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<ModelResponseEvent>, Self::Error>> {
+        match self.get_mut() {
+            FallbackResponse::Primary { resp, first_event } => {
+                if let Some(event) = first_event.take() {
+                    return Poll::Ready(Ok(event));
+                }
+                resp.as_mut()
+                    .poll_next_event(cx)
+                    .map_err(FallbackError::Primary)
+            }
+            FallbackResponse::Secondary(resp) => resp
+                .as_mut()
+                .poll_next_event(cx)
+                .map_err(FallbackError::Secondary),
+        }
+    }
+
+    fn make_opaque_message(&self) -> Option<OpaqueMessage> {
+        match self {
+            FallbackResponse::Primary { resp, .. } => {
+                resp.make_opaque_message()
+            }
+            FallbackResponse::Secondary(resp) => resp.make_opaque_message(),
+        }
+    }
+
+    fn usage(&self) -> Option<Usage> {
+        match self {
+            FallbackResponse::Primary { resp, .. } => resp.usage(),
+            FallbackResponse::Secondary(resp) => resp.usage(),
+        }
+    }
+}
+
+/// The error type of [`FallbackProvider`].
+#[derive(Debug)]
+pub enum FallbackError<E1, E2> {
+    /// The error from the primary provider.
+    Primary(E1),
+    /// The error from the secondary provider.
+    Secondary(E2),
+}
+
+impl<E1: Display, E2: Display> Display for FallbackError<E1, E2> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FallbackError::Primary(err) => write!(f, "primary: {err}"),
+            FallbackError::Secondary(err) => write!(f, "secondary: {err}"),
+        }
+    }
+}
+
+impl<E1, E2> StdError for FallbackError<E1, E2>
+where
+    E1: StdError + 'static,
+    E2: StdError + 'static,
+{
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            FallbackError::Primary(err) => Some(err),
+            FallbackError::Secondary(err) => Some(err),
+        }
+    }
+}
+
+impl<E1, E2> ModelProviderError for FallbackError<E1, E2>
+where
+    E1: ModelProviderError,
+    E2: ModelProviderError,
+{
+    #[inline]
+    fn kind(&self) -> ErrorKind {
+        match self {
+            FallbackError::Primary(err) => err.kind(),
+            FallbackError::Secondary(err) => err.kind(),
+        }
+    }
+
+    #[inline]
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            FallbackError::Primary(err) => err.retry_after(),
+            FallbackError::Secondary(err) => err.retry_after(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{make_provider, make_request};
+
+    #[tokio::test]
+    async fn test_fallback() {
+        let primary = make_provider(&["primary"], Some(0));
+        let secondary = make_provider(&["secondary"], None);
+        let provider =
+            FallbackProvider::new(primary.clone(), secondary.clone());
+
+        let resp = provider.send_request(&make_request("Hi")).await.unwrap();
+        assert_eq!(resp.collect_text().await.unwrap(), "secondary");
+        assert_eq!(primary.requests().len(), 1);
+        assert_eq!(secondary.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_no_fallback() {
+        let primary = make_provider(&["primary"], None);
+        let secondary = make_provider(&["secondary"], None);
+        let provider =
+            FallbackProvider::new(primary.clone(), secondary.clone());
+
+        let resp = provider.send_request(&make_request("Hi")).await.unwrap();
+        assert_eq!(resp.collect_text().await.unwrap(), "primary");
+        assert_eq!(secondary.requests().len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_all_failed() {
+        let primary = make_provider(&["primary"], Some(0));
+        let secondary = make_provider(&["secondary"], Some(0));
+        let provider = FallbackProvider::new(primary, secondary);
+
+        let resp = provider.send_request(&make_request("Hi")).await.unwrap();
+        let err = resp.collect_text().await.unwrap_err();
+        assert!(matches!(err, FallbackError::Secondary(_)));
+    }
+}
@@ -0,0 +1,26 @@
+//! Utilities built on top of the model abstraction layer.
+//!
+//! This crate provides wrappers that add extra behaviors to any
+//! [`ModelProvider`], like falling back between multiple providers, or
+//! spreading load across them.
+//!
+//! [`ModelProvider`]: little_agent_model::ModelProvider
+
+#![deny(missing_docs)]
+
+#[macro_use]
+extern crate tracing;
+
+mod boxed;
+mod caching;
+mod fallback;
+mod rate_limited;
+mod round_robin;
+#[cfg(test)]
+mod test_util;
+
+pub use boxed::*;
+pub use caching::*;
+pub use fallback::*;
+pub use rate_limited::*;
+pub use round_robin::*;
@@ -0,0 +1,267 @@
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use little_agent_model::{
+    MessageJoinPolicy, ModelProvider, ModelRequest, ModelResponse,
+    ModelResponseEvent, OpaqueMessage, Usage,
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{Instant, sleep};
+
+/// A provider that limits the rate of requests sent to the inner provider.
+///
+/// Two kinds of limits are supported, and both are disabled by default:
+///
+/// - Requests per minute, enforced by a token bucket that allows bursts up
+///   to the limit.
+/// - Maximum concurrency, which limits the number of responses that are
+///   alive at the same time.
+///
+/// Requests wait for a permit before being sent. Waiting is cancel safe:
+/// a dropped request doesn't consume any permit.
+pub struct RateLimitedProvider<P> {
+    inner: Arc<P>,
+    bucket: Option<Arc<Mutex<TokenBucket>>>,
+    semaphore: Option<Arc<Semaphore>>,
+}
+
+impl<P> RateLimitedProvider<P> {
+    /// Creates a provider that wraps `inner`, with no limits.
+    #[inline]
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner: Arc::new(inner),
+            bucket: None,
+            semaphore: None,
+        }
+    }
+
+    /// Limits the number of requests that can be sent per minute.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `requests_per_minute` is zero.
+    #[inline]
+    pub fn with_requests_per_minute(
+        mut self,
+        requests_per_minute: u32,
+    ) -> Self {
+        assert!(requests_per_minute > 0, "limit must be greater than zero");
+        let bucket = TokenBucket::new(requests_per_minute);
+        self.bucket = Some(Arc::new(Mutex::new(bucket)));
+        self
+    }
+
+    /// Limits the number of requests that can be in flight at the same time.
+    ///
+    /// A request is in flight until its response is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_concurrency` is zero.
+    #[inline]
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        assert!(max_concurrency > 0, "limit must be greater than zero");
+        self.semaphore = Some(Arc::new(Semaphore::new(max_concurrency)));
+        self
+    }
+}
+
+impl<P: ModelProvider + 'static> ModelProvider for RateLimitedProvider<P> {
+    type Error = P::Error;
+    type Response = RateLimitedResponse<P::Response>;
+
+    fn send_request(
+        &self,
+        req: &ModelRequest,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
+    {
+        // The inner request is only created after the permits are acquired,
+        // since some providers start the work right away.
+        let inner = Arc::clone(&self.inner);
+        let req = req.clone();
+        let bucket = self.bucket.clone();
+        let semaphore = self.semaphore.clone();
+        async move {
+            let permit = match semaphore {
+                Some(semaphore) => Some(
+                    semaphore
+                        .acquire_owned()
+                        .await
+                        .expect("semaphore should never be closed"),
+                ),
+                None => None,
+            };
+            if let Some(bucket) = bucket {
+                acquire_token(&bucket).await;
+            }
+            let inner = inner.send_request(&req).await?;
+            Ok(RateLimitedResponse {
+                inner: Box::pin(inner),
+                _permit: permit,
+            })
+        }
+    }
+
+    #[inline]
+    fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        self.inner.health_check()
+    }
+
+    #[inline]
+    fn message_join_policy(&self) -> MessageJoinPolicy {
+        self.inner.message_join_policy()
+    }
+}
+
+async fn acquire_token(bucket: &Mutex<TokenBucket>) {
+    loop {
+        let wait = bucket.lock().unwrap().try_acquire();
+        let Some(wait) = wait else {
+            return;
+        };
+        trace!("rate limited, waiting for {wait:?}");
+        sleep(wait).await;
+    }
+}
+
+struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_interval: Duration,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(requests_per_minute: u32) -> Self {
+        let capacity = requests_per_minute as f64;
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_interval: Duration::from_secs(60) / requests_per_minute,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token if available, otherwise returns the time to wait
+    /// until the next token is available.
+    fn try_acquire(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill);
+        let refilled =
+            elapsed.as_secs_f64() / self.refill_interval.as_secs_f64();
+        self.tokens = (self.tokens + refilled).min(self.capacity);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return None;
+        }
+        Some(self.refill_interval.mul_f64(1.0 - self.tokens))
+    }
+}
+
+/// The response of [`RateLimitedProvider`].
+pub struct RateLimitedResponse<R> {
+    inner: Pin<Box<R>>,
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl<R: ModelResponse> ModelResponse for RateLimitedResponse<R> {
+    type Error = R::Error;
+
+    #[inline]
+    fn poll_next_event(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<ModelResponseEvent>, Self::Error>> {
+        self.get_mut().inner.as_mut().poll_next_event(cx)
+    }
+
+    #[inline]
+    fn make_opaque_message(&self) -> Option<OpaqueMessage> {
+        self.inner.make_opaque_message()
+    }
+
+    #[inline]
+    fn usage(&self) -> Option<Usage> {
+        self.inner.usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::test_util::{make_provider, make_request};
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_per_minute() {
+        let provider = RateLimitedProvider::new(make_provider(&["Hi"], None))
+            .with_requests_per_minute(2);
+
+        let start = Instant::now();
+        provider.send_request(&make_request("Hi")).await.unwrap();
+        provider.send_request(&make_request("Hi")).await.unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // A cancelled request doesn't consume the token.
+        let result = timeout(
+            Duration::from_secs(10),
+            provider.send_request(&make_request("Hi")),
+        )
+        .await;
+        assert!(result.is_err());
+
+        provider.send_request(&make_request("Hi")).await.unwrap();
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(30));
+        assert!(elapsed < Duration::from_secs(31));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_max_concurrency() {
+        let provider = RateLimitedProvider::new(make_provider(&["Hi"], None))
+            .with_max_concurrency(1);
+
+        let resp = provider.send_request(&make_request("Hi")).await.unwrap();
+        let result = timeout(
+            Duration::from_secs(1),
+            provider.send_request(&make_request("Hi")),
+        )
+        .await;
+        assert!(result.is_err());
+
+        drop(resp);
+        let resp = timeout(
+            Duration::from_secs(1),
+            provider.send_request(&make_request("Hi")),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(resp.collect_text().await.unwrap(), "Hi");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_inner_request_waits_for_permit() {
+        let inner = make_provider(&["Hi"], None);
+        let provider =
+            RateLimitedProvider::new(inner.clone()).with_max_concurrency(1);
+
+        let _resp = provider.send_request(&make_request("Hi")).await.unwrap();
+        let result = timeout(
+            Duration::from_secs(1),
+            provider.send_request(&make_request("Hi")),
+        )
+        .await;
+        assert!(result.is_err());
+        // The waiting request never reached the inner provider.
+        assert_eq!(inner.requests().len(), 1);
+    }
+}
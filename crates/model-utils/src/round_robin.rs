@@ -0,0 +1,228 @@
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use little_agent_model::{
+    MessageJoinPolicy, ModelProvider, ModelRequest, ModelResponse,
+    ModelResponseEvent, OpaqueMessage, Usage,
+};
+
+const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// A provider that spreads requests across several inner providers.
+///
+/// Each request is handled by the next inner provider in turn. Providers
+/// that failed recently (within the cooldown period) are skipped, unless
+/// all of them failed recently.
+///
+/// Per the [`ModelProvider`] contract, this provider is stateless except
+/// for the rotation counter and the failure records, which only affect the
+/// choice of the inner provider.
+///
+/// All inner providers must have the same type. To mix different kinds of
+/// providers, erase their types with [`BoxedModelProvider`] first.
+///
+/// [`BoxedModelProvider`]: crate::BoxedModelProvider
+pub struct RoundRobinProvider<P> {
+    providers: Vec<P>,
+    state: Arc<State>,
+}
+
+struct State {
+    slots: Mutex<Slots>,
+    cooldown: Duration,
+}
+
+struct Slots {
+    next_idx: usize,
+    failed_at: Vec<Option<Instant>>,
+}
+
+impl State {
+    fn pick(&self) -> usize {
+        let mut slots = self.slots.lock().unwrap();
+        let count = slots.failed_at.len();
+        let start = slots.next_idx % count;
+        let idx = (0..count)
+            .map(|offset| (start + offset) % count)
+            .find(|&idx| {
+                slots.failed_at[idx]
+                    .is_none_or(|instant| instant.elapsed() >= self.cooldown)
+            })
+            .unwrap_or(start);
+        slots.next_idx = idx + 1;
+        idx
+    }
+
+    #[inline]
+    fn mark_failed(&self, idx: usize) {
+        self.slots.lock().unwrap().failed_at[idx] = Some(Instant::now());
+    }
+}
+
+impl<P> RoundRobinProvider<P> {
+    /// Creates a provider that rotates between the given providers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `providers` is empty.
+    pub fn new(providers: Vec<P>) -> Self {
+        assert!(!providers.is_empty(), "at least one provider is required");
+        let state = State {
+            slots: Mutex::new(Slots {
+                next_idx: 0,
+                failed_at: vec![None; providers.len()],
+            }),
+            cooldown: DEFAULT_COOLDOWN,
+        };
+        Self {
+            providers,
+            state: Arc::new(state),
+        }
+    }
+
+    /// Sets how long a failed provider is skipped. Defaults to 30 seconds.
+    #[inline]
+    pub fn with_cooldown(mut self, cooldown: Duration) -> Self {
+        Arc::get_mut(&mut self.state)
+            .expect("provider should not be in use")
+            .cooldown = cooldown;
+        self
+    }
+}
+
+impl<P: ModelProvider> ModelProvider for RoundRobinProvider<P> {
+    type Error = P::Error;
+    type Response = RoundRobinResponse<P::Response>;
+
+    fn send_request(
+        &self,
+        req: &ModelRequest,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
+    {
+        let idx = self.state.pick();
+        trace!("sending request with provider #{idx}");
+        let fut = self.providers[idx].send_request(req);
+        let state = Arc::clone(&self.state);
+        async move {
+            match fut.await {
+                Ok(resp) => Ok(RoundRobinResponse {
+                    inner: Box::pin(resp),
+                    state,
+                    idx,
+                }),
+                Err(err) => {
+                    state.mark_failed(idx);
+                    Err(err)
+                }
+            }
+        }
+    }
+
+    /// Checks all the inner providers, and fails with the first error.
+    /// Failed providers are skipped for the cooldown period.
+    fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        let futs: Vec<_> =
+            self.providers.iter().map(|p| p.health_check()).collect();
+        let state = Arc::clone(&self.state);
+        async move {
+            for (idx, fut) in futs.into_iter().enumerate() {
+                if let Err(err) = fut.await {
+                    state.mark_failed(idx);
+                    return Err(err);
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Returns the policy of the first provider, the providers are
+    /// expected to be of the same kind.
+    #[inline]
+    fn message_join_policy(&self) -> MessageJoinPolicy {
+        self.providers
+            .first()
+            .map(|p| p.message_join_policy())
+            .unwrap_or_default()
+    }
+}
+
+/// The response of [`RoundRobinProvider`].
+pub struct RoundRobinResponse<R> {
+    inner: Pin<Box<R>>,
+    state: Arc<State>,
+    idx: usize,
+}
+
+impl<R: ModelResponse> ModelResponse for RoundRobinResponse<R> {
+    type Error = R::Error;
+
+    fn poll_next_event(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<ModelResponseEvent>, Self::Error>> {
+        let this = self.get_mut();
+        let poll = this.inner.as_mut().poll_next_event(cx);
+        if let Poll::Ready(Err(_)) = &poll {
+            this.state.mark_failed(this.idx);
+        }
+        poll
+    }
+
+    #[inline]
+    fn make_opaque_message(&self) -> Option<OpaqueMessage> {
+        self.inner.make_opaque_message()
+    }
+
+    #[inline]
+    fn usage(&self) -> Option<Usage> {
+        self.inner.usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{make_provider, make_request};
+
+    #[tokio::test]
+    async fn test_distribution() {
+        let providers: Vec<_> =
+            (0..3).map(|_| make_provider(&["Hi"], None)).collect();
+        let provider = RoundRobinProvider::new(providers.clone());
+
+        for _ in 0..6 {
+            let resp =
+                provider.send_request(&make_request("Hi")).await.unwrap();
+            assert_eq!(resp.collect_text().await.unwrap(), "Hi");
+        }
+        for inner in &providers {
+            assert_eq!(inner.requests().len(), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_skip_failed() {
+        let providers = vec![
+            make_provider(&["Hi"], Some(0)),
+            make_provider(&["Hi"], None),
+            make_provider(&["Hi"], None),
+        ];
+        let provider = RoundRobinProvider::new(providers.clone());
+
+        let resp = provider.send_request(&make_request("Hi")).await.unwrap();
+        assert!(resp.collect_text().await.is_err());
+
+        for _ in 0..4 {
+            let resp =
+                provider.send_request(&make_request("Hi")).await.unwrap();
+            assert_eq!(resp.collect_text().await.unwrap(), "Hi");
+        }
+        assert_eq!(providers[0].requests().len(), 1);
+        assert_eq!(providers[1].requests().len(), 2);
+        assert_eq!(providers[2].requests().len(), 2);
+    }
+}
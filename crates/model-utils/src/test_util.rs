@@ -0,0 +1,32 @@
+//! Fixtures shared by the tests.
+
+use little_agent_model::{ModelMessage, ModelRequest};
+use little_agent_test_model::{PresetEvent, PresetResponse, TestModelProvider};
+
+/// Makes a provider that responds with the message deltas, after failing
+/// `failures` times (see [`PresetResponse::with_failures`]).
+pub(crate) fn make_provider(
+    deltas: &[&str],
+    failures: Option<u64>,
+) -> TestModelProvider {
+    let events = deltas
+        .iter()
+        .map(|delta| PresetEvent::MessageDelta((*delta).to_owned()))
+        .collect::<Vec<_>>();
+    let mut response = PresetResponse::with_events(events);
+    if let Some(failures) = failures {
+        response = response.with_failures(failures);
+    }
+    let mut provider = TestModelProvider::default();
+    provider.add_user_input_step();
+    provider.add_assistant_response_step(response);
+    provider
+}
+
+/// Makes a request with a single user message.
+pub(crate) fn make_request(text: &str) -> ModelRequest {
+    ModelRequest {
+        messages: vec![ModelMessage::User(text.to_owned())],
+        ..Default::default()
+    }
+}
@@ -1,10 +1,54 @@
+use serde::{Deserialize, Serialize};
+
 /// The kind of error that occurred.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ErrorKind {
     /// The content is moderated.
     Moderated,
     /// The model provider is rate limited.
     RateLimitExceeded,
+    /// The request timed out.
+    Timeout,
+    /// The request is rejected by the model provider as malformed.
+    InvalidRequest,
+    /// The credentials are missing, invalid or lack permissions.
+    Authentication,
+    /// The request doesn't fit in the context window of the model.
+    ContextLengthExceeded,
     /// Any other errors.
     Other,
 }
+
+impl ErrorKind {
+    /// Returns `true` if the failed request may succeed when retried.
+    ///
+    /// Errors caused by the request itself (like moderated content or bad
+    /// credentials) will fail the same way again, so they are not
+    /// retryable. Unknown errors are assumed to be transient.
+    #[inline]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::RateLimitExceeded | Self::Timeout | Self::Other => true,
+            Self::Moderated
+            | Self::InvalidRequest
+            | Self::Authentication
+            | Self::ContextLengthExceeded => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_retryable() {
+        assert!(ErrorKind::RateLimitExceeded.is_retryable());
+        assert!(ErrorKind::Timeout.is_retryable());
+        assert!(ErrorKind::Other.is_retryable());
+        assert!(!ErrorKind::Moderated.is_retryable());
+        assert!(!ErrorKind::InvalidRequest.is_retryable());
+        assert!(!ErrorKind::Authentication.is_retryable());
+        assert!(!ErrorKind::ContextLengthExceeded.is_retryable());
+    }
+}
@@ -18,6 +18,8 @@ mod opaque;
 mod provider;
 mod request;
 mod response;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 pub use error::*;
 pub use opaque::*;
@@ -1,7 +1,12 @@
-use std::any::Any;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
 use std::fmt::{self, Debug, Formatter};
 use std::hash::{Hash, Hasher};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use serde::de::{self, DeserializeOwned};
+use serde::{Deserialize, Deserializer, Serialize, Serializer, ser};
+use serde_json::Value;
 
 /// An opaque message from the model that doesn't need to be processed
 /// by the agent.
@@ -12,7 +17,15 @@ use std::sync::Arc;
 /// For example, some models rely on complete tool call message to work
 /// correctly, the model implementor can use this type to store that
 /// structure and later serialize to the request payload.
-pub struct OpaqueMessage(Arc<dyn OpaqueMessageObject>);
+///
+/// The message can also carry its transcript, which providers fall back to
+/// when the payload is not theirs, e.g. when the history is replayed to a
+/// different provider. The transcript is attached when the request is
+/// built, so it's not serialized with the message.
+pub struct OpaqueMessage {
+    object: Arc<dyn OpaqueMessageObject>,
+    transcript: Option<Arc<str>>,
+}
 
 impl OpaqueMessage {
     /// Creates a new `OpaqueMessage`.
@@ -26,34 +39,167 @@ impl OpaqueMessage {
         value: T,
     ) -> Self {
         let id = id.into();
-        Self(Arc::new(OpaqueMessageInner { id, value }))
+        Self {
+            object: Arc::new(OpaqueMessageInner { id, value }),
+            transcript: None,
+        }
+    }
+
+    /// Attaches the transcript of this message.
+    #[inline]
+    pub fn with_transcript<S: Into<Arc<str>>>(self, transcript: S) -> Self {
+        Self {
+            transcript: Some(transcript.into()),
+            ..self
+        }
+    }
+
+    /// Returns the id of this message.
+    #[inline]
+    pub fn id(&self) -> &str {
+        self.object.id()
+    }
+
+    /// Returns the transcript of this message, if attached.
+    #[inline]
+    pub fn transcript(&self) -> Option<&str> {
+        self.transcript.as_deref()
     }
 
     /// Converts the `OpaqueMessage` into its raw type.
     #[inline]
     pub fn to_raw<T: 'static>(&self) -> Option<&T> {
-        self.0.as_any().downcast_ref()
+        self.object.as_any().downcast_ref()
+    }
+
+    /// Registers a raw payload type, making the messages that wrap it
+    /// serializable.
+    ///
+    /// The `tag` identifies the payload type in the serialized form, so it
+    /// should be unique and stable across versions. Model implementors
+    /// usually call this when the provider is created. Registering the
+    /// same type again replaces the previous registration.
+    pub fn register<T>(tag: &'static str)
+    where
+        T: Serialize + DeserializeOwned + Send + Sync + 'static,
+    {
+        let entry = RegistryEntry {
+            tag,
+            serialize: |value| {
+                let value = value
+                    .downcast_ref::<T>()
+                    .expect("payload type should match the registration");
+                serde_json::to_value(value)
+            },
+            deserialize: |id, payload| {
+                let value: T = serde_json::from_value(payload)?;
+                Ok(OpaqueMessage::new(id, value))
+            },
+        };
+        let mut registry = registry().write().unwrap();
+        registry.by_type.insert(TypeId::of::<T>(), entry);
+        registry.by_tag.insert(tag, entry);
+    }
+
+    /// Converts the `OpaqueMessage` into a serializable form.
+    ///
+    /// Returns an error if the payload type is not registered. See
+    /// [`OpaqueMessage::register`].
+    pub fn to_serializable(
+        &self,
+    ) -> Result<SerializableOpaqueMessage, serde_json::Error> {
+        let value = self.object.as_any();
+        let entry = registry()
+            .read()
+            .unwrap()
+            .by_type
+            .get(&value.type_id())
+            .copied()
+            .ok_or_else(|| {
+                ser::Error::custom("unregistered opaque message type")
+            })?;
+        Ok(SerializableOpaqueMessage {
+            tag: entry.tag.to_owned(),
+            id: self.object.id().to_owned(),
+            payload: (entry.serialize)(value)?,
+        })
+    }
+
+    /// Restores an `OpaqueMessage` from its serializable form.
+    ///
+    /// Returns an error if the tag is not registered, or the payload
+    /// doesn't match the registered type.
+    pub fn from_serializable(
+        message: SerializableOpaqueMessage,
+    ) -> Result<Self, serde_json::Error> {
+        let entry = registry()
+            .read()
+            .unwrap()
+            .by_tag
+            .get(message.tag.as_str())
+            .copied()
+            .ok_or_else(|| {
+                de::Error::custom(format!(
+                    "unregistered opaque message tag: {}",
+                    message.tag
+                ))
+            })?;
+        (entry.deserialize)(message.id, message.payload)
+    }
+}
+
+/// The serializable form of an [`OpaqueMessage`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SerializableOpaqueMessage {
+    /// The tag of the registered payload type.
+    pub tag: String,
+    /// The message id.
+    pub id: String,
+    /// The serialized payload.
+    pub payload: Value,
+}
+
+impl Serialize for OpaqueMessage {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        self.to_serializable()
+            .map_err(ser::Error::custom)?
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for OpaqueMessage {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        let message = SerializableOpaqueMessage::deserialize(deserializer)?;
+        Self::from_serializable(message).map_err(de::Error::custom)
     }
 }
 
 impl Clone for OpaqueMessage {
     #[inline]
     fn clone(&self) -> Self {
-        Self(Arc::clone(&self.0))
+        Self {
+            object: Arc::clone(&self.object),
+            transcript: self.transcript.clone(),
+        }
     }
 }
 
 impl Debug for OpaqueMessage {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("OpaqueMessage")
-            .field("id", &self.0.id())
+            .field("id", &self.object.id())
             .finish()
     }
 }
 
 impl PartialEq for OpaqueMessage {
     fn eq(&self, other: &Self) -> bool {
-        self.0.id() == other.0.id()
+        self.object.id() == other.object.id()
     }
 }
 
@@ -61,7 +207,7 @@ impl Eq for OpaqueMessage {}
 
 impl Hash for OpaqueMessage {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        self.0.id().hash(state);
+        self.object.id().hash(state);
     }
 }
 
@@ -85,13 +231,31 @@ impl<T: Send + Sync + 'static> OpaqueMessageObject for OpaqueMessageInner<T> {
     }
 }
 
+#[derive(Clone, Copy)]
+struct RegistryEntry {
+    tag: &'static str,
+    serialize: fn(&dyn Any) -> Result<Value, serde_json::Error>,
+    deserialize: fn(String, Value) -> Result<OpaqueMessage, serde_json::Error>,
+}
+
+#[derive(Default)]
+struct Registry {
+    by_type: HashMap<TypeId, RegistryEntry>,
+    by_tag: HashMap<&'static str, RegistryEntry>,
+}
+
+fn registry() -> &'static RwLock<Registry> {
+    static REGISTRY: OnceLock<RwLock<Registry>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
 
     use super::*;
 
-    #[derive(Clone)]
+    #[derive(Clone, Serialize, Deserialize)]
     struct RawMessage(String);
 
     #[test]
@@ -119,4 +283,38 @@ mod tests {
         set.insert(opaque_1);
         assert_eq!(set.len(), 2);
     }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        OpaqueMessage::register::<RawMessage>("test.raw");
+
+        let opaque = OpaqueMessage::new("msg:0", RawMessage("Hi".to_owned()));
+        let json = serde_json::to_value(&opaque).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "tag": "test.raw",
+                "id": "msg:0",
+                "payload": "Hi",
+            })
+        );
+
+        let restored: OpaqueMessage = serde_json::from_value(json).unwrap();
+        assert_eq!(restored, opaque);
+        assert_eq!(restored.to_raw::<RawMessage>().unwrap().0, "Hi");
+        assert_eq!(restored.transcript(), None);
+
+        // The transcript is kept by the conversation, not the message.
+        let opaque = opaque.with_transcript("Hi");
+        let json = serde_json::to_value(&opaque).unwrap();
+        assert!(json.get("transcript").is_none());
+    }
+
+    #[test]
+    fn test_serialize_unregistered() {
+        struct Unregistered;
+
+        let opaque = OpaqueMessage::new("msg:0", Unregistered);
+        assert!(opaque.to_serializable().is_err());
+    }
 }
@@ -1,13 +1,36 @@
 use std::error::Error;
+use std::time::Duration;
 
 use crate::error::ErrorKind;
 use crate::request::ModelRequest;
 use crate::response::ModelResponse;
 
+/// How the message deltas of a response are joined into its transcript.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MessageJoinPolicy {
+    /// Concatenates the deltas as is.
+    ///
+    /// Use it for the providers that stream tokens, whose deltas already
+    /// carry the whitespaces.
+    #[default]
+    Concatenate,
+    /// Joins the deltas with a space, unless there is already a whitespace
+    /// at the boundary.
+    ///
+    /// Use it for the providers that send whole sentences as deltas.
+    SpaceJoin,
+}
+
 /// The error type for a model provider.
 pub trait ModelProviderError: Error + Send + Sync + 'static {
     /// Returns the kind of this error.
     fn kind(&self) -> ErrorKind;
+
+    /// Returns how long the caller should wait before retrying, if the
+    /// provider suggests one (e.g. via the `Retry-After` header).
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
 }
 
 /// A type that represents a model provider, which is an entry for getting
@@ -28,4 +51,21 @@ pub trait ModelProvider: Send + Sync {
         &self,
         req: &ModelRequest,
     ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static;
+
+    /// Checks whether the provider is usable, e.g. the credentials are valid
+    /// and the endpoint is reachable.
+    ///
+    /// The default implementation always succeeds.
+    fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        std::future::ready(Ok(()))
+    }
+
+    /// Returns how the message deltas of the responses should be joined.
+    ///
+    /// The default implementation returns [`MessageJoinPolicy::Concatenate`].
+    fn message_join_policy(&self) -> MessageJoinPolicy {
+        MessageJoinPolicy::Concatenate
+    }
 }
@@ -1,18 +1,34 @@
-use serde_json::Value;
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
 
-use crate::OpaqueMessage;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{OpaqueMessage, ToolCallRequest};
 
 /// A request to be sent to the model provider.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
 pub struct ModelRequest {
     /// The input messages.
     pub messages: Vec<ModelMessage>,
     /// Tools that are available to the model.
     pub tools: Vec<ModelTool>,
+    /// The model to use for this request. If not set, the provider will use
+    /// its default model.
+    pub model: Option<String>,
+    /// Provider-specific parameters (e.g. `top_k`), which are merged into
+    /// the request body by the providers that support them.
+    ///
+    /// Providers never overwrite the parameters they already set with
+    /// these entries.
+    pub extra: Map<String, Value>,
 }
 
 /// A complete message.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+///
+/// Opaque messages can only be serialized if their payload types are
+/// registered, see [`OpaqueMessage::register`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ModelMessage {
     /// The system instructions.
     System(String),
@@ -20,6 +36,16 @@ pub enum ModelMessage {
     User(String),
     /// An assistant text.
     Assistant(String),
+    /// An assistant text with the tool calls it requested.
+    ///
+    /// This is used when the response can't be kept as an opaque message,
+    /// so that the following tool results still have matching calls.
+    AssistantToolCalls {
+        /// The assistant text, which may be empty.
+        content: String,
+        /// The requested tool calls.
+        tool_calls: Vec<ToolCallRequest>,
+    },
     /// A tool call result.
     Tool(ToolCallResult),
     /// An opaque message (usually the history message from the model)
@@ -27,12 +53,100 @@ pub enum ModelMessage {
 }
 
 /// The result of calling a tool.
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct ToolCallResult {
     /// The unique identifier for the tool call request.
     pub id: String,
     /// The result of the tool call.
-    pub content: String,
+    pub content: ToolOutput,
+}
+
+/// The output produced by a tool.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ToolOutput {
+    /// A plain text output.
+    Text(String),
+    /// A structured JSON output.
+    Json(Value),
+    /// An image, e.g. a screenshot.
+    ///
+    /// It's replaced by a text placeholder for the models that can't see
+    /// images, see [`ToolOutput::to_text`].
+    Image {
+        /// The media type, e.g. `image/png`.
+        media_type: String,
+        /// The base64-encoded image data.
+        data: String,
+    },
+    /// An output that was cut short, e.g. because it was too long.
+    ///
+    /// The model is told about it by an annotation, see
+    /// [`ToolOutput::to_text`].
+    Truncated(Box<ToolOutput>),
+}
+
+impl ToolOutput {
+    /// Returns the textual representation of this output.
+    ///
+    /// JSON outputs are serialized in their compact form, which is what
+    /// most text-only model providers expect. Images are replaced by a
+    /// placeholder with their media type. Truncated outputs are prefixed
+    /// with a `[truncated]` line.
+    #[inline]
+    pub fn to_text(&self) -> Cow<'_, str> {
+        match self {
+            ToolOutput::Text(text) => Cow::Borrowed(text),
+            ToolOutput::Json(value) => Cow::Owned(value.to_string()),
+            ToolOutput::Image { media_type, .. } => {
+                Cow::Owned(format!("[{media_type} image]"))
+            }
+            ToolOutput::Truncated(output) => {
+                Cow::Owned(format!("[truncated]\n{}", output.to_text()))
+            }
+        }
+    }
+
+    /// Marks this output as truncated.
+    #[inline]
+    pub fn truncated(self) -> Self {
+        match self {
+            ToolOutput::Truncated(_) => self,
+            output => ToolOutput::Truncated(Box::new(output)),
+        }
+    }
+
+    /// Returns whether this output is truncated.
+    #[inline]
+    pub fn is_truncated(&self) -> bool {
+        matches!(self, ToolOutput::Truncated(_))
+    }
+}
+
+impl Display for ToolOutput {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_text())
+    }
+}
+
+impl From<String> for ToolOutput {
+    #[inline]
+    fn from(text: String) -> Self {
+        ToolOutput::Text(text)
+    }
+}
+
+impl From<&str> for ToolOutput {
+    #[inline]
+    fn from(text: &str) -> Self {
+        ToolOutput::Text(text.to_owned())
+    }
+}
+
+impl From<Value> for ToolOutput {
+    #[inline]
+    fn from(value: Value) -> Self {
+        ToolOutput::Json(value)
+    }
 }
 
 /// Describes a tool that can be used by the model.
@@ -48,3 +162,30 @@ pub struct ModelTool {
     /// defined by a [JSON schema](https://json-schema.org/).
     pub parameters: Value,
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_tool_output_to_text() {
+        let output = ToolOutput::from("Hello");
+        assert_eq!(output.to_text(), "Hello");
+
+        let output = ToolOutput::from(json!({ "count": 1 }));
+        assert_eq!(output.to_text(), r#"{"count":1}"#);
+        assert_eq!(format!("{output}"), r#"{"count":1}"#);
+
+        let output = ToolOutput::Image {
+            media_type: "image/png".to_owned(),
+            data: "iVBORw0KGgo=".to_owned(),
+        };
+        assert_eq!(output.to_text(), "[image/png image]");
+
+        let output = ToolOutput::from("line 1").truncated().truncated();
+        assert!(output.is_truncated());
+        assert_eq!(output.to_text(), "[truncated]\nline 1");
+    }
+}
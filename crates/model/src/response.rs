@@ -1,5 +1,8 @@
-use std::pin::Pin;
-use std::task::{self, Poll};
+use std::future::poll_fn;
+use std::pin::{Pin, pin};
+use std::task::{self, Poll, ready};
+
+use futures_core::Stream;
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -47,15 +50,135 @@ pub trait ModelResponse: Sized + Send + 'static {
     fn make_opaque_message(&self) -> Option<OpaqueMessage> {
         None
     }
+
+    /// Returns the token usage of this response, if reported by the
+    /// provider.
+    ///
+    /// Like [`ModelResponse::make_opaque_message`], this should be called
+    /// after polling all events from this response.
+    fn usage(&self) -> Option<Usage> {
+        None
+    }
+
+    /// Converts the response into a [`Stream`] of events.
+    ///
+    /// The stream ends after the response completes or an error occurs.
+    #[inline]
+    fn into_event_stream(self) -> EventStream<Self> {
+        EventStream {
+            inner: Box::pin(self),
+            done: false,
+        }
+    }
+
+    /// Drives the response to completion and collects the message text.
+    ///
+    /// Events other than message deltas (like tool calls) are ignored.
+    fn collect_text(
+        self,
+    ) -> impl Future<Output = Result<String, Self::Error>> + Send {
+        async move {
+            let mut this = pin!(self);
+            let mut text = String::new();
+            while let Some(event) =
+                poll_fn(|cx| this.as_mut().poll_next_event(cx)).await?
+            {
+                if let ModelResponseEvent::MessageDelta(delta) = event {
+                    text.push_str(&delta);
+                }
+            }
+            Ok(text)
+        }
+    }
+}
+
+/// A [`Stream`] of the events in a response.
+///
+/// This is created by [`ModelResponse::into_event_stream`].
+pub struct EventStream<R> {
+    inner: Pin<Box<R>>,
+    done: bool,
+}
+
+impl<R> EventStream<R> {
+    /// Returns a reference to the underlying response, e.g. for making the
+    /// opaque message after the stream ends.
+    #[inline]
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+}
+
+impl<R: ModelResponse> Stream for EventStream<R> {
+    type Item = Result<ModelResponseEvent, R::Error>;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        let result = ready!(this.inner.as_mut().poll_next_event(cx));
+        match result {
+            Ok(Some(event)) => Poll::Ready(Some(Ok(event))),
+            Ok(None) => {
+                this.done = true;
+                Poll::Ready(None)
+            }
+            Err(err) => {
+                this.done = true;
+                Poll::Ready(Some(Err(err)))
+            }
+        }
+    }
 }
 
 /// The reason why a model response has finished.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ModelFinishReason {
     /// The model needs to call a tool.
     ToolCalls,
     /// The model has finished generating text.
     Stop,
+    /// The response was truncated because it reached the token limit.
+    Length,
+    /// The model has stopped for a reason unknown to the provider, which is
+    /// kept verbatim. It's handled like [`ModelFinishReason::Stop`].
+    Other(String),
+}
+
+/// The token usage of a model response.
+#[derive(
+    Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+pub struct Usage {
+    /// The model that served the request, if reported.
+    pub model: Option<String>,
+    /// Number of tokens in the prompt.
+    pub prompt_tokens: u64,
+    /// Number of tokens generated by the model.
+    pub completion_tokens: u64,
+}
+
+impl Usage {
+    /// Returns the total number of tokens.
+    #[inline]
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+}
+
+/// A snapshot of a tool call that is still streaming in.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ToolCallProgress {
+    /// The identifier of the tool call, if already known.
+    pub id: Option<String>,
+    /// The name of the tool to call.
+    pub name: String,
+    /// The arguments received so far, which are usually incomplete JSON.
+    pub partial_arguments: String,
 }
 
 /// Describes a tool call request from the model.
@@ -78,4 +201,11 @@ pub enum ModelResponseEvent {
     MessageDelta(String),
     /// Received a tool call request.
     ToolCall(ToolCallRequest),
+    /// A tool call is still streaming in. Only emitted by providers that
+    /// support it, and always followed by the complete
+    /// [`ModelResponseEvent::ToolCall`] later.
+    ToolCallProgress(ToolCallProgress),
+    /// The stream is still alive, but has no content to deliver yet (e.g.
+    /// the model is reasoning). Only emitted by providers that support it.
+    Heartbeat,
 }
@@ -0,0 +1,95 @@
+//! Utilities for testing the implementations of [`ModelResponse`].
+
+use std::collections::HashSet;
+use std::future::poll_fn;
+use std::pin::pin;
+
+use crate::{ModelFinishReason, ModelResponse, ModelResponseEvent};
+
+/// Drives the response to completion, and asserts that it obeys the
+/// contract of [`ModelResponse`]:
+///
+/// - Message deltas come before the tool calls, and both of them come
+///   before the [`Completed`] event.
+/// - There is at most one [`Completed`] event, and it's the last event.
+/// - A response finished with [`ModelFinishReason::ToolCalls`] has at least
+///   one tool call, and the ids of the tool calls are unique.
+/// - The progress of a tool call comes before the tool call itself.
+/// - Polling after completion always returns `None`.
+/// - [`ModelResponse::make_opaque_message`] returns the same message when
+///   called multiple times.
+///
+/// Returns the events of the response.
+///
+/// # Panics
+///
+/// Panics if the response fails or violates the contract.
+///
+/// [`Completed`]: ModelResponseEvent::Completed
+pub async fn assert_response_contract<R: ModelResponse>(
+    resp: R,
+) -> Vec<ModelResponseEvent> {
+    let mut resp = pin!(resp);
+    let mut events = vec![];
+    let mut tool_call_ids = HashSet::new();
+    let mut finish_reason = None;
+
+    while let Some(event) = poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+        .await
+        .unwrap_or_else(|err| panic!("response failed: {err}"))
+    {
+        assert!(
+            finish_reason.is_none(),
+            "got {event:?} after the response has completed"
+        );
+        match &event {
+            ModelResponseEvent::MessageDelta(_) => {
+                assert!(
+                    tool_call_ids.is_empty(),
+                    "got a message delta after the tool calls"
+                );
+            }
+            ModelResponseEvent::ToolCall(req) => {
+                assert!(
+                    tool_call_ids.insert(req.id.clone()),
+                    "duplicate tool call id: {}",
+                    req.id
+                );
+            }
+            ModelResponseEvent::ToolCallProgress(progress) => {
+                let id = progress.id.as_deref().unwrap_or_default();
+                assert!(
+                    !tool_call_ids.contains(id),
+                    "got the progress of a finished tool call: {id}"
+                );
+            }
+            ModelResponseEvent::Completed(reason) => {
+                finish_reason = Some(reason.clone());
+            }
+            ModelResponseEvent::Heartbeat => {}
+        }
+        events.push(event);
+    }
+
+    if finish_reason == Some(ModelFinishReason::ToolCalls) {
+        assert!(
+            !tool_call_ids.is_empty(),
+            "finished with tool calls, but got none"
+        );
+    }
+
+    for _ in 0..3 {
+        let event = poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+            .await
+            .unwrap_or_else(|err| panic!("response failed: {err}"));
+        assert!(event.is_none(), "got {event:?} after completion");
+    }
+
+    assert_eq!(
+        resp.make_opaque_message(),
+        resp.make_opaque_message(),
+        "opaque message is not stable"
+    );
+
+    events
+}
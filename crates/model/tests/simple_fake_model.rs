@@ -117,6 +117,8 @@ mod tests {
         let req = ModelRequest {
             messages: vec![ModelMessage::User("Good morning".to_string())],
             tools: vec![],
+            model: None,
+            extra: Default::default(),
         };
         let mut resp = provider.send_request(&req).await.unwrap();
 
@@ -148,6 +150,8 @@ mod tests {
         let req = ModelRequest {
             messages: vec![],
             tools: vec![],
+            model: None,
+            extra: Default::default(),
         };
         let result = provider.send_request(&req).await;
         let err = result.unwrap_err();
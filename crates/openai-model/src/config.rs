@@ -1,4 +1,14 @@
-use std::fmt::Debug;
+use std::collections::BTreeMap;
+use std::error::Error as StdError;
+use std::fmt::{self, Debug, Display};
+use std::time::Duration;
+
+use little_agent_model::MessageJoinPolicy;
+use reqwest::Url;
+
+const DEFAULT_COMPLETIONS_PATH: &str = "/chat/completions";
+const DEFAULT_STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(120);
+pub(crate) const DEFAULT_MAX_EVENTS: usize = 100_000;
 
 /// Builder for [`OpenAIConfig`].
 #[derive(Clone, PartialEq, Eq, Hash)]
@@ -6,6 +16,28 @@ pub struct OpenAIConfigBuilder {
     api_key: String,
     model: Option<String>,
     base_url: Option<String>,
+    completions_path: Option<String>,
+    organization: Option<String>,
+    project: Option<String>,
+    stream_idle_timeout: Option<Duration>,
+    delta_coalescing: Option<DeltaCoalescing>,
+    heartbeats: bool,
+    tool_call_progress: bool,
+    usage_reporting: bool,
+    n: Option<u32>,
+    logit_bias: BTreeMap<u32, i32>,
+    assistant_name: Option<String>,
+    max_response_bytes: Option<usize>,
+    max_events: usize,
+    vision: bool,
+    message_join_policy: MessageJoinPolicy,
+}
+
+/// Options for merging consecutive content deltas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct DeltaCoalescing {
+    pub window: Duration,
+    pub max_len: usize,
 }
 
 impl OpenAIConfigBuilder {
@@ -16,6 +48,21 @@ impl OpenAIConfigBuilder {
             api_key: api_key.into(),
             model: None,
             base_url: None,
+            completions_path: None,
+            organization: None,
+            project: None,
+            stream_idle_timeout: Some(DEFAULT_STREAM_IDLE_TIMEOUT),
+            delta_coalescing: None,
+            heartbeats: false,
+            tool_call_progress: false,
+            usage_reporting: true,
+            n: None,
+            logit_bias: BTreeMap::new(),
+            assistant_name: None,
+            max_response_bytes: None,
+            max_events: DEFAULT_MAX_EVENTS,
+            vision: false,
+            message_join_policy: MessageJoinPolicy::default(),
         }
     }
 
@@ -33,25 +80,245 @@ impl OpenAIConfigBuilder {
         self
     }
 
-    /// Builds the configuration.
+    /// Sets a custom path of the chat completions endpoint, relative to the
+    /// base URL. Defaults to `/chat/completions`.
+    #[inline]
+    pub fn with_completions_path<S: Into<String>>(mut self, path: S) -> Self {
+        self.completions_path = Some(path.into());
+        self
+    }
+
+    /// Sets the organization that the requests are made under.
+    #[inline]
+    pub fn with_organization<S: Into<String>>(
+        mut self,
+        organization: S,
+    ) -> Self {
+        self.organization = Some(organization.into());
+        self
+    }
+
+    /// Sets the project that the requests are made under.
+    #[inline]
+    pub fn with_project<S: Into<String>>(mut self, project: S) -> Self {
+        self.project = Some(project.into());
+        self
+    }
+
+    /// Sets the maximum duration to wait for new data while streaming the
+    /// response. Pass `None` to wait indefinitely.
+    ///
+    /// A stalled stream fails with a retryable timeout error. Defaults to
+    /// 120 seconds.
+    #[inline]
+    pub fn with_stream_idle_timeout(
+        mut self,
+        stream_idle_timeout: Option<Duration>,
+    ) -> Self {
+        self.stream_idle_timeout = stream_idle_timeout;
+        self
+    }
+
+    /// Merges consecutive content deltas that arrive within `window` into a
+    /// single event, to reduce the churn for fast streams. A merged delta is
+    /// emitted early once it reaches `max_len` bytes.
+    ///
+    /// By default, each delta is emitted immediately.
+    #[inline]
+    pub fn with_delta_coalescing(
+        mut self,
+        window: Duration,
+        max_len: usize,
+    ) -> Self {
+        self.delta_coalescing = Some(DeltaCoalescing { window, max_len });
+        self
+    }
+
+    /// Emits [`ModelResponseEvent::Heartbeat`] events when the server sends
+    /// keep-alive comments, so that callers know the stream is still alive
+    /// during a long reasoning. Defaults to `false`.
+    ///
+    /// [`ModelResponseEvent::Heartbeat`]: little_agent_model::ModelResponseEvent::Heartbeat
+    #[inline]
+    pub fn with_heartbeats(mut self, heartbeats: bool) -> Self {
+        self.heartbeats = heartbeats;
+        self
+    }
+
+    /// Emits [`ModelResponseEvent::ToolCallProgress`] events while the
+    /// arguments of a tool call are streaming in. Defaults to `false`.
+    ///
+    /// [`ModelResponseEvent::ToolCallProgress`]: little_agent_model::ModelResponseEvent::ToolCallProgress
+    #[inline]
+    pub fn with_tool_call_progress(mut self, tool_call_progress: bool) -> Self {
+        self.tool_call_progress = tool_call_progress;
+        self
+    }
+
+    /// Asks the server to report the token usage at the end of the stream,
+    /// via `stream_options`. Defaults to `true`.
+    ///
+    /// Disable it for the gateways that reject the unknown `stream_options`.
+    #[inline]
+    pub fn with_usage_reporting(mut self, usage_reporting: bool) -> Self {
+        self.usage_reporting = usage_reporting;
+        self
+    }
+
+    /// Sets the number of candidate completions to generate for each
+    /// request.
+    ///
+    /// Only the first candidate is streamed as events, the others can be
+    /// read from [`OpenAIResponse::candidates`] after the stream ends.
+    ///
+    /// [`OpenAIResponse::candidates`]: crate::OpenAIResponse::candidates
+    #[inline]
+    pub fn with_n(mut self, n: u32) -> Self {
+        self.n = Some(n);
+        self
+    }
+
+    /// Adds biases to the likelihood of the tokens, keyed by the token ids.
+    ///
+    /// A bias must be in the range of `-100..=100`, where `-100` bans the
+    /// token and `100` forces it.
+    #[inline]
+    pub fn with_logit_bias<I>(mut self, logit_bias: I) -> Self
+    where
+        I: IntoIterator<Item = (u32, i32)>,
+    {
+        self.logit_bias.extend(logit_bias);
+        self
+    }
+
+    /// Sets the name of the assistant, which is sent along with the assistant
+    /// messages in the history, to tell the participants apart in the
+    /// multi-agent setups.
+    #[inline]
+    pub fn with_assistant_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.assistant_name = Some(name.into());
+        self
+    }
+
+    /// Caps the content of a response at `max_bytes` bytes, to protect the
+    /// memory from a runaway stream.
+    ///
+    /// Once the cap is reached, the rest of the stream is dropped and the
+    /// response completes with [`ModelFinishReason::Length`]. Unlimited by
+    /// default.
+    ///
+    /// [`ModelFinishReason::Length`]: little_agent_model::ModelFinishReason::Length
+    #[inline]
+    pub fn with_max_response_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_response_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Caps the number of server-sent events in a response, to protect the
+    /// CPU and memory from a pathological stream.
+    ///
+    /// Unlike [`OpenAIConfigBuilder::with_max_response_bytes`], exceeding
+    /// the cap fails the response. Defaults to 100,000, which is far more
+    /// than a normal response needs. The error is not retryable, since the
+    /// same request is likely to produce the same stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_events` is zero, which would fail every response.
     #[inline]
-    pub fn build(self) -> OpenAIConfig {
-        OpenAIConfig {
+    pub fn with_max_events(mut self, max_events: usize) -> Self {
+        assert!(max_events > 0, "max_events must be greater than zero");
+        self.max_events = max_events;
+        self
+    }
+
+    /// Sends the images in the tool results to the model, for the models
+    /// that support vision. Defaults to `false`, where the images are
+    /// replaced by text placeholders.
+    #[inline]
+    pub fn with_vision(mut self, vision: bool) -> Self {
+        self.vision = vision;
+        self
+    }
+
+    /// Sets how the message deltas are joined into the transcript.
+    ///
+    /// Defaults to [`MessageJoinPolicy::Concatenate`], which suits the
+    /// endpoints streaming tokens. Use [`MessageJoinPolicy::SpaceJoin`] for
+    /// the ones sending whole sentences as deltas.
+    #[inline]
+    pub fn with_message_join_policy(
+        mut self,
+        policy: MessageJoinPolicy,
+    ) -> Self {
+        self.message_join_policy = policy;
+        self
+    }
+
+    /// Builds the configuration.
+    ///
+    /// The base URL is normalized by stripping the trailing slashes. Returns
+    /// an error if it's not an absolute HTTP(S) URL, or a logit bias is out
+    /// of range.
+    pub fn build(self) -> Result<OpenAIConfig, ConfigError> {
+        let base_url = match self.base_url {
+            Some(base_url) => normalize_base_url(base_url)?,
+            None => "https://api.openai.com/v1".to_string(),
+        };
+        let invalid_bias = self
+            .logit_bias
+            .iter()
+            .find(|(_, bias)| !(-100..=100).contains(*bias));
+        if let Some((&token, &bias)) = invalid_bias {
+            return Err(ConfigError::InvalidLogitBias { token, bias });
+        }
+        Ok(OpenAIConfig {
             api_key: self.api_key,
             model: self.model.unwrap_or_else(|| "gpt-5.2".to_string()),
-            base_url: self
-                .base_url
-                .unwrap_or_else(|| "https://api.openai.com/v1".to_string()),
-        }
+            base_url,
+            completions_path: self
+                .completions_path
+                .map(normalize_path)
+                .unwrap_or_else(|| DEFAULT_COMPLETIONS_PATH.to_string()),
+            organization: self.organization,
+            project: self.project,
+            stream_idle_timeout: self.stream_idle_timeout,
+            delta_coalescing: self.delta_coalescing,
+            heartbeats: self.heartbeats,
+            tool_call_progress: self.tool_call_progress,
+            usage_reporting: self.usage_reporting,
+            n: self.n,
+            logit_bias: self.logit_bias,
+            assistant_name: self.assistant_name,
+            max_response_bytes: self.max_response_bytes,
+            max_events: self.max_events,
+            vision: self.vision,
+            message_join_policy: self.message_join_policy,
+        })
     }
 }
 
 impl Debug for OpenAIConfigBuilder {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("OpenAIConfigBuilder")
             .field("api_key", &"<deducted>")
             .field("model", &self.model)
             .field("base_url", &self.base_url)
+            .field("completions_path", &self.completions_path)
+            .field("organization", &self.organization)
+            .field("project", &self.project)
+            .field("stream_idle_timeout", &self.stream_idle_timeout)
+            .field("delta_coalescing", &self.delta_coalescing)
+            .field("heartbeats", &self.heartbeats)
+            .field("tool_call_progress", &self.tool_call_progress)
+            .field("usage_reporting", &self.usage_reporting)
+            .field("n", &self.n)
+            .field("logit_bias", &self.logit_bias)
+            .field("assistant_name", &self.assistant_name)
+            .field("max_response_bytes", &self.max_response_bytes)
+            .field("max_events", &self.max_events)
+            .field("vision", &self.vision)
+            .field("message_join_policy", &self.message_join_policy)
             .finish()
     }
 }
@@ -62,14 +329,180 @@ pub struct OpenAIConfig {
     pub(crate) api_key: String,
     pub(crate) model: String,
     pub(crate) base_url: String,
+    pub(crate) completions_path: String,
+    pub(crate) organization: Option<String>,
+    pub(crate) project: Option<String>,
+    pub(crate) stream_idle_timeout: Option<Duration>,
+    pub(crate) delta_coalescing: Option<DeltaCoalescing>,
+    pub(crate) heartbeats: bool,
+    pub(crate) tool_call_progress: bool,
+    pub(crate) usage_reporting: bool,
+    pub(crate) n: Option<u32>,
+    pub(crate) logit_bias: BTreeMap<u32, i32>,
+    pub(crate) assistant_name: Option<String>,
+    pub(crate) max_response_bytes: Option<usize>,
+    pub(crate) max_events: usize,
+    pub(crate) vision: bool,
+    pub(crate) message_join_policy: MessageJoinPolicy,
+}
+
+impl OpenAIConfig {
+    /// Returns the full URL of the chat completions endpoint.
+    #[inline]
+    pub(crate) fn completions_url(&self) -> String {
+        format!("{}{}", self.base_url, self.completions_path)
+    }
+
+    /// Returns the full URL of the models endpoint.
+    #[inline]
+    pub(crate) fn models_url(&self) -> String {
+        format!("{}/models", self.base_url)
+    }
 }
 
 impl Debug for OpenAIConfig {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("OpenAIConfig")
             .field("api_key", &"<deducted>")
             .field("model", &self.model)
             .field("base_url", &self.base_url)
+            .field("completions_path", &self.completions_path)
+            .field("organization", &self.organization)
+            .field("project", &self.project)
+            .field("stream_idle_timeout", &self.stream_idle_timeout)
+            .field("delta_coalescing", &self.delta_coalescing)
+            .field("heartbeats", &self.heartbeats)
+            .field("tool_call_progress", &self.tool_call_progress)
+            .field("usage_reporting", &self.usage_reporting)
+            .field("n", &self.n)
+            .field("logit_bias", &self.logit_bias)
+            .field("assistant_name", &self.assistant_name)
+            .field("max_response_bytes", &self.max_response_bytes)
+            .field("max_events", &self.max_events)
+            .field("vision", &self.vision)
+            .field("message_join_policy", &self.message_join_policy)
             .finish()
     }
 }
+
+/// Error type for building an [`OpenAIConfig`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The base URL is not an absolute HTTP(S) URL.
+    InvalidBaseUrl(String),
+    /// The logit bias of a token is out of the range of `-100..=100`.
+    InvalidLogitBias {
+        /// The token id.
+        token: u32,
+        /// The invalid bias.
+        bias: i32,
+    },
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::InvalidBaseUrl(url) => {
+                write!(f, "invalid base URL: {url:?}")
+            }
+            ConfigError::InvalidLogitBias { token, bias } => {
+                write!(f, "invalid logit bias for token {token}: {bias}")
+            }
+        }
+    }
+}
+
+impl StdError for ConfigError {}
+
+fn normalize_base_url(base_url: String) -> Result<String, ConfigError> {
+    let is_valid = Url::parse(&base_url)
+        .map(|url| matches!(url.scheme(), "http" | "https"))
+        .unwrap_or(false);
+    if !is_valid {
+        return Err(ConfigError::InvalidBaseUrl(base_url));
+    }
+    Ok(base_url.trim_end_matches('/').to_owned())
+}
+
+fn normalize_path(path: String) -> String {
+    if path.starts_with('/') {
+        path
+    } else {
+        format!("/{path}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_base_url() {
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_base_url("https://example.com/v1/")
+            .build()
+            .unwrap();
+        assert_eq!(config.base_url, "https://example.com/v1");
+    }
+
+    #[test]
+    fn test_invalid_base_url() {
+        let err = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_base_url("not a url")
+            .build()
+            .unwrap_err();
+        assert_eq!(err, ConfigError::InvalidBaseUrl("not a url".to_owned()));
+
+        let err = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_base_url("ftp://example.com")
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, ConfigError::InvalidBaseUrl(_)));
+    }
+
+    #[test]
+    fn test_logit_bias_range() {
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_logit_bias([(1, -100), (2, 100)])
+            .build()
+            .unwrap();
+        assert_eq!(config.logit_bias.len(), 2);
+
+        let err = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_logit_bias([(1, 50), (2, 101)])
+            .build()
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ConfigError::InvalidLogitBias {
+                token: 2,
+                bias: 101
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "max_events must be greater than zero")]
+    fn test_zero_max_events() {
+        let _ = OpenAIConfigBuilder::with_api_key("xxx").with_max_events(0);
+    }
+
+    #[test]
+    fn test_completions_url() {
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_base_url("https://example.com/")
+            .build()
+            .unwrap();
+        assert_eq!(
+            config.completions_url(),
+            "https://example.com/chat/completions"
+        );
+
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_base_url("https://example.com/")
+            .with_completions_path("v1/chat")
+            .build()
+            .unwrap();
+        assert_eq!(config.completions_url(), "https://example.com/v1/chat");
+    }
+}
@@ -1,17 +1,97 @@
 #[cfg(test)]
+use std::time::Duration;
+
 use std::collections::VecDeque;
+use std::error::Error as StdError;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
 
 use bytes::Bytes;
 use reqwest::Response;
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct Error;
+/// An error reading the chunks.
+///
+/// The cause is classified, so that a transient failure (like a timeout or
+/// a dropped connection) can be told apart from the others.
+#[derive(Debug)]
+pub struct Error {
+    timeout: bool,
+    connect: bool,
+    source: Option<reqwest::Error>,
+}
+
+impl Error {
+    #[inline]
+    fn from_reqwest(err: reqwest::Error) -> Self {
+        Self {
+            timeout: err.is_timeout(),
+            connect: err.is_connect(),
+            source: Some(err),
+        }
+    }
+
+    /// Creates an error without the underlying cause, for injecting faults.
+    #[cfg(test)]
+    pub fn injected(timeout: bool, connect: bool) -> Self {
+        Self {
+            timeout,
+            connect,
+            source: None,
+        }
+    }
+
+    /// Returns true if the read timed out.
+    #[inline]
+    pub fn is_timeout(&self) -> bool {
+        self.timeout
+    }
+
+    /// Returns true if the connection failed.
+    #[inline]
+    pub fn is_connect(&self) -> bool {
+        self.connect
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.source {
+            Some(source) => write!(f, "failed to read the response: {source}"),
+            None if self.timeout => {
+                f.write_str("reading the response timed out")
+            }
+            None if self.connect => f.write_str("connection failed"),
+            None => f.write_str("failed to read the response"),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source.as_ref().map(|err| err as _)
+    }
+}
+
+// The underlying errors are not comparable, only the classification is.
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        self.timeout == other.timeout && self.connect == other.connect
+    }
+}
+
+impl Eq for Error {}
 
 /// An adapter for streaming byte chunks.
 pub enum Chunks {
     Response(Response),
-    #[cfg(test)]
+    Recording(Response, File),
     VecDeque(VecDeque<Bytes>),
+    #[cfg(test)]
+    Delayed(VecDeque<(Duration, Bytes)>),
+    #[cfg(test)]
+    Faulty(VecDeque<Result<Bytes, Error>>),
 }
 
 impl Chunks {
@@ -19,25 +99,72 @@ impl Chunks {
         Chunks::Response(response)
     }
 
-    #[cfg(test)]
+    /// Creates chunks that also write the raw bytes into the file at
+    /// `path`, as they are read.
+    ///
+    /// The bytes are not recorded if the file can't be created.
+    pub fn recording(response: Response, path: &Path) -> Self {
+        match File::create(path) {
+            Ok(file) => Chunks::Recording(response, file),
+            Err(err) => {
+                warn!("failed to create {}: {err}", path.display());
+                Chunks::Response(response)
+            }
+        }
+    }
+
     pub fn from_vec_deque(vec: VecDeque<Bytes>) -> Self {
         Chunks::VecDeque(vec)
     }
 
+    /// Creates chunks that are yielded after the paired delays.
+    #[cfg(test)]
+    pub fn from_delayed(vec: VecDeque<(Duration, Bytes)>) -> Self {
+        Chunks::Delayed(vec)
+    }
+
+    /// Creates chunks that yield the given results in order, for injecting
+    /// faults.
+    #[cfg(test)]
+    pub fn from_results(vec: VecDeque<Result<Bytes, Error>>) -> Self {
+        Chunks::Faulty(vec)
+    }
+
+    /// Returns the next chunk, or `None` if the stream is exhausted.
+    ///
+    /// This method is cancel safe, no data is lost if the future is dropped
+    /// before completion.
     #[inline]
     pub async fn next_chunk(&mut self) -> Result<Option<Bytes>, Error> {
         match self {
             Chunks::Response(response) => {
-                let Ok(chunk) = response.chunk().await else {
-                    return Err(Error);
+                response.chunk().await.map_err(Error::from_reqwest)
+            }
+            Chunks::Recording(response, file) => {
+                let chunk =
+                    response.chunk().await.map_err(Error::from_reqwest)?;
+                let Some(chunk) = chunk else {
+                    return Ok(None);
                 };
-                Ok(chunk)
+                if let Err(err) = file.write_all(&chunk) {
+                    warn!("failed to record the response: {err}");
+                }
+                Ok(Some(chunk))
             }
-            #[cfg(test)]
             Chunks::VecDeque(vec) => {
                 let chunk = vec.pop_front();
                 Ok(chunk)
             }
+            #[cfg(test)]
+            Chunks::Delayed(vec) => {
+                let Some(&(delay, _)) = vec.front() else {
+                    return Ok(None);
+                };
+                tokio::time::sleep(delay).await;
+                Ok(vec.pop_front().map(|(_, chunk)| chunk))
+            }
+            #[cfg(test)]
+            Chunks::Faulty(vec) => vec.pop_front().transpose(),
         }
     }
 }
@@ -2,4 +2,4 @@ mod chunks;
 mod sse;
 
 pub use chunks::{Chunks, Error as ChunksError};
-pub use sse::Sse;
+pub use sse::{Error as SseError, Event as SseEvent, Sse};
@@ -1,15 +1,32 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use tokio::time::timeout;
+
 use super::{Chunks, ChunksError};
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Error {
     ChunksError(ChunksError),
     InvalidPayload,
+    /// No data arrived within the idle timeout.
+    StreamStalled(Duration),
+}
+
+/// An event read from the stream.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The data of an event.
+    Data(String),
+    /// A comment without data, which servers often send as a keep-alive.
+    Comment(String),
 }
 
 /// A type for reading server-sent events from a chunk stream.
 pub struct Sse {
     buf: String,
     chunks: Chunks,
+    idle_timeout: Option<Duration>,
 }
 
 impl Sse {
@@ -18,16 +35,26 @@ impl Sse {
         Self {
             buf: String::new(),
             chunks,
+            idle_timeout: None,
         }
     }
 
-    pub async fn next_event(&mut self) -> Result<Option<String>, Error> {
+    /// Sets the maximum duration to wait for the next chunk.
+    #[inline]
+    pub fn with_idle_timeout(mut self, idle_timeout: Option<Duration>) -> Self {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Reads the next event.
+    ///
+    /// This method is cancel safe, the partially received data is kept in
+    /// the buffer.
+    pub async fn next_event(&mut self) -> Result<Option<Event>, Error> {
         loop {
             // Read more data from the stream first.
             let mut has_more_data = false;
-            if let Some(bytes) =
-                self.chunks.next_chunk().await.map_err(Error::ChunksError)?
-            {
+            if let Some(bytes) = self.next_chunk().await? {
                 let Ok(s) = str::from_utf8(&bytes) else {
                     return Err(Error::InvalidPayload);
                 };
@@ -48,44 +75,72 @@ impl Sse {
         }
     }
 
-    fn try_parse_event(&mut self) -> Result<Option<String>, Error> {
+    async fn next_chunk(&mut self) -> Result<Option<Bytes>, Error> {
+        let next_chunk = self.chunks.next_chunk();
+        let result = match self.idle_timeout {
+            Some(idle_timeout) => timeout(idle_timeout, next_chunk)
+                .await
+                .map_err(|_| Error::StreamStalled(idle_timeout))?,
+            None => next_chunk.await,
+        };
+        result.map_err(Error::ChunksError)
+    }
+
+    fn try_parse_event(&mut self) -> Result<Option<Event>, Error> {
         if self.buf.is_empty() {
             return Ok(None);
         }
 
-        // For `end-of-line`, we only handle line feed. And for event, we
-        // only handle field.
+        // For `end-of-line`, we only handle line feed. And for field, we only
+        // handle `data`.
         //
         // event         = *( comment / field ) end-of-line
+        // comment       = colon *any-char end-of-line
         // field         = 1*name-char [ colon [ space ] *any-char ] end-of-line
         // end-of-line   = ( cr lf / cr / lf )
         let Some(eol_idx) = self.buf.find("\n\n") else {
             return Ok(None);
         };
 
-        // Parse the field line.
-        let field = &self.buf[..eol_idx];
-        let Some(delim_idx) = field.find(": ") else {
-            return Err(Error::InvalidPayload);
-        };
-        let header = &field[..delim_idx];
-        if header != "data" {
-            // Other events are not supported.
-            return Err(Error::InvalidPayload);
+        let mut data: Option<String> = None;
+        let mut comment = None;
+        for line in self.buf[..eol_idx].split('\n') {
+            if let Some(text) = line.strip_prefix(':') {
+                comment.get_or_insert_with(|| text.trim_start().to_owned());
+                continue;
+            }
+            // Parse the field line.
+            let Some(delim_idx) = line.find(": ") else {
+                return Err(Error::InvalidPayload);
+            };
+            let header = &line[..delim_idx];
+            if header != "data" {
+                // Other events are not supported.
+                return Err(Error::InvalidPayload);
+            }
+            let value = &line[delim_idx + 2..];
+            match &mut data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => data = Some(value.to_owned()),
+            }
         }
-        let data = field[delim_idx + 2..].to_owned();
 
         // Consume the bytes from the buffer.
         self.buf.drain(..eol_idx + 2);
 
-        Ok(Some(data))
+        match (data, comment) {
+            (Some(data), _) => Ok(Some(Event::Data(data))),
+            (None, Some(comment)) => Ok(Some(Event::Comment(comment))),
+            (None, None) => Err(Error::InvalidPayload),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use bytes::Bytes;
-
     use super::*;
 
     #[tokio::test]
@@ -98,8 +153,35 @@ mod tests {
             .into(),
         );
         let mut sse = Sse::new(chunks);
-        assert_eq!(sse.next_event().await.unwrap().unwrap(), "hello");
-        assert_eq!(sse.next_event().await.unwrap().unwrap(), "bye");
+        assert_eq!(
+            sse.next_event().await.unwrap().unwrap(),
+            Event::Data("hello".to_owned())
+        );
+        assert_eq!(
+            sse.next_event().await.unwrap().unwrap(),
+            Event::Data("bye".to_owned())
+        );
+        assert_eq!(sse.next_event().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_comments() {
+        let chunks = Chunks::from_vec_deque(
+            vec![
+                Bytes::from_static(b": ping\n\n"),
+                Bytes::from_static(b": ignored\ndata: hello\n\n"),
+            ]
+            .into(),
+        );
+        let mut sse = Sse::new(chunks);
+        assert_eq!(
+            sse.next_event().await.unwrap().unwrap(),
+            Event::Comment("ping".to_owned())
+        );
+        assert_eq!(
+            sse.next_event().await.unwrap().unwrap(),
+            Event::Data("hello".to_owned())
+        );
         assert_eq!(sse.next_event().await.unwrap(), None);
     }
 
@@ -114,7 +196,10 @@ mod tests {
             .into(),
         );
         let mut sse = Sse::new(chunks);
-        assert_eq!(sse.next_event().await.unwrap().unwrap(), "hello");
+        assert_eq!(
+            sse.next_event().await.unwrap().unwrap(),
+            Event::Data("hello".to_owned())
+        );
         assert_eq!(sse.next_event().await.unwrap(), None);
     }
 
@@ -142,4 +227,46 @@ mod tests {
         let mut sse = Sse::new(chunks);
         assert_eq!(sse.next_event().await.unwrap(), None);
     }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout() {
+        let chunks = Chunks::from_delayed(
+            vec![
+                (
+                    Duration::from_secs(1),
+                    Bytes::from_static(b"data: hello\n\n"),
+                ),
+                (
+                    Duration::from_secs(10),
+                    Bytes::from_static(b"data: bye\n\n"),
+                ),
+            ]
+            .into(),
+        );
+        let mut sse =
+            Sse::new(chunks).with_idle_timeout(Some(Duration::from_secs(5)));
+        assert_eq!(
+            sse.next_event().await.unwrap().unwrap(),
+            Event::Data("hello".to_owned())
+        );
+        assert_eq!(
+            sse.next_event().await.unwrap_err(),
+            Error::StreamStalled(Duration::from_secs(5))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_idle_timeout_end_of_stream() {
+        let chunks = Chunks::from_delayed(
+            vec![(Duration::from_secs(1), Bytes::from_static(b"data: hi\n\n"))]
+                .into(),
+        );
+        let mut sse =
+            Sse::new(chunks).with_idle_timeout(Some(Duration::from_secs(5)));
+        assert_eq!(
+            sse.next_event().await.unwrap().unwrap(),
+            Event::Data("hi".to_owned())
+        );
+        assert_eq!(sse.next_event().await.unwrap(), None);
+    }
 }
@@ -6,27 +6,41 @@ extern crate tracing;
 mod config;
 mod io;
 mod proto;
+mod recording;
 mod response;
+#[cfg(test)]
+mod test_util;
 
 use std::error::Error as StdError;
 use std::fmt::{self, Display};
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use little_agent_model::{
-    ErrorKind, ModelProvider, ModelProviderError, ModelRequest,
+    ErrorKind, MessageJoinPolicy, ModelProvider, ModelProviderError,
+    ModelRequest,
 };
 use mime::Mime;
-use reqwest::{Client, Response, header};
+use reqwest::{Client, RequestBuilder, Response, StatusCode, header};
+use serde_json::Value;
 
-pub use config::{OpenAIConfig, OpenAIConfigBuilder};
-use io::{Chunks, Sse};
-use response::OpenAIResponse;
+pub use config::{ConfigError, OpenAIConfig, OpenAIConfigBuilder};
+use io::{Chunks, ChunksError, Sse};
+pub use proto::ModelInfo;
+pub use recording::{RecordingProvider, ReplayProvider};
+pub use response::{Candidate, OpenAIResponse};
 
 /// Error type for [`OpenAIProvider`].
 #[derive(Debug)]
 pub struct Error {
     message: String,
     kind: ErrorKind,
+    retry_after: Option<Duration>,
+    connect: bool,
+    code: Option<String>,
+    param: Option<String>,
+    source: Option<Box<dyn StdError + Send + Sync>>,
 }
 
 impl Error {
@@ -34,14 +48,90 @@ impl Error {
         Self {
             message: message.into(),
             kind,
+            retry_after: None,
+            connect: false,
+            code: None,
+            param: None,
+            source: None,
         }
     }
 
+    fn from_reqwest(err: reqwest::Error) -> Self {
+        let mut this = Self::new(format!("{err}"), error_kind_of(&err));
+        this.connect = err.is_connect();
+        this.source = Some(Box::new(err));
+        this
+    }
+
+    fn from_chunks(err: ChunksError) -> Self {
+        let kind = if err.is_timeout() {
+            ErrorKind::Timeout
+        } else {
+            ErrorKind::Other
+        };
+        let mut this = Self::new(format!("{err}"), kind);
+        this.connect = err.is_connect();
+        this.source = Some(Box::new(err));
+        this
+    }
+
+    #[inline]
+    fn with_retry_after(mut self, retry_after: Option<Duration>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Captures the details from the body of a failed response, the body
+    /// is ignored if it's not an error envelope.
+    fn with_error_body(mut self, body: &str) -> Self {
+        let Ok(envelope) = serde_json::from_str::<proto::ErrorEnvelope>(body)
+        else {
+            return self;
+        };
+        self.code = envelope.error.code.map(|code| match code {
+            Value::String(code) => code,
+            code => code.to_string(),
+        });
+        self.param = envelope.error.param;
+        if self.code() == Some("context_length_exceeded") {
+            self.kind = ErrorKind::ContextLengthExceeded;
+        }
+        self
+    }
+
     /// Returns the error message.
     #[inline]
     pub fn message(&self) -> &str {
         &self.message
     }
+
+    /// Returns true if the request or the stream timed out.
+    #[inline]
+    pub fn is_timeout(&self) -> bool {
+        self.kind == ErrorKind::Timeout
+    }
+
+    /// Returns true if the connection to the server failed.
+    ///
+    /// This is usually transient, e.g. the network is temporarily down.
+    #[inline]
+    pub fn is_connect(&self) -> bool {
+        self.connect
+    }
+
+    /// Returns the error code sent by the server, e.g.
+    /// `context_length_exceeded`.
+    #[inline]
+    pub fn code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
+
+    /// Returns the request parameter that caused the error, as told by the
+    /// server.
+    #[inline]
+    pub fn param(&self) -> Option<&str> {
+        self.param.as_deref()
+    }
 }
 
 impl Display for Error {
@@ -50,13 +140,22 @@ impl Display for Error {
     }
 }
 
-impl StdError for Error {}
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source.as_deref().map(|err| err as _)
+    }
+}
 
 impl ModelProviderError for Error {
     #[inline]
     fn kind(&self) -> ErrorKind {
         self.kind
     }
+
+    #[inline]
+    fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
 }
 
 /// OpenAI-compatible model provider.
@@ -70,43 +169,36 @@ impl OpenAIProvider {
     /// Creates a new `OpenAIProvider` with the given configuration.
     #[inline]
     pub fn new(config: OpenAIConfig) -> Self {
+        proto::register_opaque_types();
         Self {
             client: Client::new(),
             config: Arc::new(config),
         }
     }
-}
-
-impl ModelProvider for OpenAIProvider {
-    type Error = Error;
-    type Response = OpenAIResponse;
 
-    fn send_request(
-        &self,
-        req: &ModelRequest,
-    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
-    {
+    fn build_request(&self, req: &ModelRequest) -> RequestBuilder {
         let openai_req = proto::create_request(req, &self.config);
-        let resp_fut = self
+        let builder = self
             .client
-            .post(format!("{}{}", self.config.base_url, "/chat/completions"))
-            .header(
-                header::AUTHORIZATION,
-                format!("Bearer {}", self.config.api_key),
-            )
+            .post(self.config.completions_url())
             .header(header::CONTENT_TYPE, "application/json")
-            .header(header::ACCEPT, "text/event-stream")
-            .json(&openai_req)
-            .send();
+            .header(header::ACCEPT, "text/event-stream");
+        self.authorize(builder).json(&openai_req)
+    }
 
+    /// Sends the request, and writes the raw bytes of a successful
+    /// response into `record_to` if given.
+    fn send(
+        &self,
+        req: &ModelRequest,
+        record_to: Option<PathBuf>,
+    ) -> impl Future<Output = Result<OpenAIResponse, Error>> + Send + 'static
+    {
+        let resp_fut = self.build_request(req).send();
+        let config = Arc::clone(&self.config);
         async move {
-            let resp = match resp_fut.await.and_then(Response::error_for_status)
-            {
-                Ok(resp) => resp,
-                Err(err) => {
-                    return Err(Error::new(format!("{err}"), ErrorKind::Other));
-                }
-            };
+            let resp = resp_fut.await.map_err(Error::from_reqwest)?;
+            let resp = check_status(resp).await?;
 
             let content_type = resp
                 .headers()
@@ -124,9 +216,347 @@ impl ModelProvider for OpenAIProvider {
             }
 
             // Here we got a successful response.
-            let chunks = Chunks::from_response(resp);
-            let sse = Sse::new(chunks);
-            Ok(OpenAIResponse::from_sse(sse))
+            let chunks = match record_to {
+                Some(path) => Chunks::recording(resp, &path),
+                None => Chunks::from_response(resp),
+            };
+            Ok(make_response(&config, chunks))
         }
     }
+
+    /// Lists the models available to the API key.
+    pub async fn list_models(&self) -> Result<Vec<ModelInfo>, Error> {
+        let resp = self.get_models().await?;
+        let list: proto::ModelList =
+            resp.json().await.map_err(Error::from_reqwest)?;
+        Ok(list.data)
+    }
+
+    fn get_models(
+        &self,
+    ) -> impl Future<Output = Result<Response, Error>> + Send + 'static {
+        let resp_fut = self
+            .authorize(self.client.get(self.config.models_url()))
+            .send();
+        async move {
+            let resp = resp_fut.await.map_err(Error::from_reqwest)?;
+            check_status(resp).await
+        }
+    }
+
+    /// Adds the credentials and scope headers to the request.
+    fn authorize(&self, mut builder: RequestBuilder) -> RequestBuilder {
+        builder = builder.header(
+            header::AUTHORIZATION,
+            format!("Bearer {}", self.config.api_key),
+        );
+        if let Some(organization) = &self.config.organization {
+            builder = builder.header("OpenAI-Organization", organization);
+        }
+        if let Some(project) = &self.config.project {
+            builder = builder.header("OpenAI-Project", project);
+        }
+        builder
+    }
+}
+
+impl ModelProvider for OpenAIProvider {
+    type Error = Error;
+    type Response = OpenAIResponse;
+
+    fn send_request(
+        &self,
+        req: &ModelRequest,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
+    {
+        self.send(req, None)
+    }
+
+    fn message_join_policy(&self) -> MessageJoinPolicy {
+        self.config.message_join_policy
+    }
+
+    /// Lists the models to verify the API key and the endpoint.
+    fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        let resp_fut = self.get_models();
+        async move {
+            resp_fut.await?;
+            Ok(())
+        }
+    }
+}
+
+/// Parses the streamed chunks into a response with the configured options.
+fn make_response(config: &OpenAIConfig, chunks: Chunks) -> OpenAIResponse {
+    let sse = Sse::new(chunks).with_idle_timeout(config.stream_idle_timeout);
+    OpenAIResponse::with_options(
+        sse,
+        config.delta_coalescing,
+        config.heartbeats,
+        config.tool_call_progress,
+        config.max_response_bytes,
+        config.max_events,
+    )
+}
+
+/// Turns a response with a failure status into an error.
+async fn check_status(resp: Response) -> Result<Response, Error> {
+    let Err(err) = resp.error_for_status_ref() else {
+        return Ok(resp);
+    };
+    let err = Error::from_reqwest(err)
+        .with_retry_after(retry_after_of(resp.headers()));
+    // The body is only for the details, the error stands without it.
+    let body = resp.text().await.unwrap_or_default();
+    Err(err.with_error_body(&body))
+}
+
+fn error_kind_of(err: &reqwest::Error) -> ErrorKind {
+    if err.is_timeout() {
+        return ErrorKind::Timeout;
+    }
+    let Some(status) = err.status() else {
+        return ErrorKind::Other;
+    };
+    error_kind_of_status(status)
+}
+
+/// Parses the `Retry-After` header. Only the delay-seconds form is
+/// supported.
+fn retry_after_of(headers: &header::HeaderMap) -> Option<Duration> {
+    let value = headers.get(header::RETRY_AFTER)?.to_str().ok()?;
+    let secs = value.trim().parse().ok()?;
+    Some(Duration::from_secs(secs))
+}
+
+fn error_kind_of_status(status: StatusCode) -> ErrorKind {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => {
+            ErrorKind::Authentication
+        }
+        StatusCode::TOO_MANY_REQUESTS => ErrorKind::RateLimitExceeded,
+        StatusCode::REQUEST_TIMEOUT | StatusCode::GATEWAY_TIMEOUT => {
+            ErrorKind::Timeout
+        }
+        StatusCode::BAD_REQUEST
+        | StatusCode::NOT_FOUND
+        | StatusCode::PAYLOAD_TOO_LARGE
+        | StatusCode::UNPROCESSABLE_ENTITY => ErrorKind::InvalidRequest,
+        _ => ErrorKind::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{TestServer, http_response};
+
+    /// Serves a single request with the given status line and body.
+    async fn serve_once(status: &str, body: &str) -> TestServer {
+        TestServer::serve(vec![http_response(status, "", body)]).await
+    }
+
+    fn provider_with_base_url(base_url: &str) -> OpenAIProvider {
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_base_url(base_url)
+            .build()
+            .unwrap();
+        OpenAIProvider::new(config)
+    }
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let server = serve_once("200 OK", "{}").await;
+        let provider = provider_with_base_url(server.base_url());
+        assert!(provider.health_check().await.is_ok());
+        let requests = server.requests();
+        assert!(requests[0].starts_with("GET /v1/models "));
+        assert!(requests[0].contains("authorization: Bearer xxx"));
+
+        let server = serve_once("401 Unauthorized", "{}").await;
+        let provider = provider_with_base_url(server.base_url());
+        let err = provider.health_check().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::Authentication);
+    }
+
+    #[tokio::test]
+    async fn test_error_code() {
+        let body = r#"{
+            "error": {
+                "message": "This model's maximum context length is 128000",
+                "type": "invalid_request_error",
+                "param": "messages",
+                "code": "context_length_exceeded"
+            }
+        }"#;
+        let server = serve_once("400 Bad Request", body).await;
+        let provider = provider_with_base_url(server.base_url());
+        let err = provider.health_check().await.unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ContextLengthExceeded);
+        assert_eq!(err.code(), Some("context_length_exceeded"));
+        assert_eq!(err.param(), Some("messages"));
+
+        let server = serve_once("502 Bad Gateway", "<html></html>").await;
+        let provider = provider_with_base_url(server.base_url());
+        let err = provider.health_check().await.unwrap_err();
+        assert_eq!(err.code(), None);
+        assert_eq!(err.param(), None);
+    }
+
+    #[tokio::test]
+    async fn test_list_models() {
+        let body = r#"{
+            "object": "list",
+            "data": [
+                {
+                    "id": "gpt-5.2",
+                    "object": "model",
+                    "created": 1686935002,
+                    "owned_by": "openai"
+                },
+                {
+                    "id": "ft:gpt-5.2:acme",
+                    "object": "model",
+                    "created": 1700000000,
+                    "owned_by": "acme"
+                },
+                {
+                    "id": "local-model",
+                    "object": "model"
+                }
+            ]
+        }"#;
+        let server = serve_once("200 OK", body).await;
+        let provider = provider_with_base_url(server.base_url());
+        let models = provider.list_models().await.unwrap();
+        assert_eq!(
+            models,
+            [
+                ModelInfo {
+                    id: "gpt-5.2".to_owned(),
+                    owned_by: Some("openai".to_owned()),
+                    created: Some(1686935002),
+                },
+                ModelInfo {
+                    id: "ft:gpt-5.2:acme".to_owned(),
+                    owned_by: Some("acme".to_owned()),
+                    created: Some(1700000000),
+                },
+                ModelInfo {
+                    id: "local-model".to_owned(),
+                    owned_by: None,
+                    created: None,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_header() {
+        let server = TestServer::serve(vec![http_response(
+            "429 Too Many Requests",
+            "retry-after: 5\r\n",
+            r#"{"error":{"message":"Rate limit reached"}}"#,
+        )])
+        .await;
+        let provider = provider_with_base_url(server.base_url());
+        let req = ModelRequest {
+            messages: vec![],
+            tools: vec![],
+            model: None,
+            extra: Default::default(),
+        };
+        let err = provider.send_request(&req).await.err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::RateLimitExceeded);
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn test_scope_headers() {
+        let req = ModelRequest {
+            messages: vec![],
+            tools: vec![],
+            model: None,
+            extra: Default::default(),
+        };
+
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_organization("org-123")
+            .with_project("proj-456")
+            .build()
+            .unwrap();
+        let request = OpenAIProvider::new(config)
+            .build_request(&req)
+            .build()
+            .unwrap();
+        let headers = request.headers();
+        assert_eq!(headers["OpenAI-Organization"], "org-123");
+        assert_eq!(headers["OpenAI-Project"], "proj-456");
+
+        let config = OpenAIConfigBuilder::with_api_key("xxx").build().unwrap();
+        let request = OpenAIProvider::new(config)
+            .build_request(&req)
+            .build()
+            .unwrap();
+        let headers = request.headers();
+        assert!(!headers.contains_key("OpenAI-Organization"));
+        assert!(!headers.contains_key("OpenAI-Project"));
+    }
+
+    #[test]
+    fn test_message_join_policy() {
+        let config = OpenAIConfigBuilder::with_api_key("xxx").build().unwrap();
+        let provider = OpenAIProvider::new(config);
+        assert_eq!(
+            provider.message_join_policy(),
+            MessageJoinPolicy::Concatenate
+        );
+
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_message_join_policy(MessageJoinPolicy::SpaceJoin)
+            .build()
+            .unwrap();
+        let provider = OpenAIProvider::new(config);
+        assert_eq!(
+            provider.message_join_policy(),
+            MessageJoinPolicy::SpaceJoin
+        );
+    }
+
+    #[test]
+    fn test_retry_after_of() {
+        let mut headers = header::HeaderMap::new();
+        assert_eq!(retry_after_of(&headers), None);
+
+        headers.insert(header::RETRY_AFTER, "120".parse().unwrap());
+        assert_eq!(retry_after_of(&headers), Some(Duration::from_secs(120)));
+
+        headers.insert(
+            header::RETRY_AFTER,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(retry_after_of(&headers), None);
+    }
+
+    #[test]
+    fn test_error_kind_of_status() {
+        assert_eq!(
+            error_kind_of_status(StatusCode::UNAUTHORIZED),
+            ErrorKind::Authentication
+        );
+        assert_eq!(
+            error_kind_of_status(StatusCode::TOO_MANY_REQUESTS),
+            ErrorKind::RateLimitExceeded
+        );
+        assert_eq!(
+            error_kind_of_status(StatusCode::BAD_REQUEST),
+            ErrorKind::InvalidRequest
+        );
+        assert_eq!(
+            error_kind_of_status(StatusCode::INTERNAL_SERVER_ERROR),
+            ErrorKind::Other
+        );
+    }
 }
@@ -1,6 +1,11 @@
-use little_agent_model::{ModelMessage, ModelRequest, ModelTool};
+use std::collections::BTreeMap;
+
+use little_agent_model::{
+    ModelMessage, ModelRequest, ModelTool, OpaqueMessage, ToolCallRequest,
+    ToolOutput,
+};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{Map, Value};
 
 use crate::OpenAIConfig;
 
@@ -25,13 +30,28 @@ pub struct ToolCall {
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
 pub struct ChatCompletionChunk {
     pub id: String,
+    #[serde(default)]
+    pub model: Option<String>,
     pub choices: Vec<Choice>,
+    #[serde(default)]
+    pub usage: Option<ChunkUsage>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub struct ChunkUsage {
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
 pub struct Choice {
+    #[serde(default)]
+    pub index: u32,
     pub delta: Delta,
     pub finish_reason: Option<String>,
+    // Some servers (like Moonshot) report the usage in the choice.
+    #[serde(default)]
+    pub usage: Option<ChunkUsage>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
@@ -41,6 +61,39 @@ pub struct Delta {
     pub reasoning_content: Option<String>,
 }
 
+/// Information of a model, as listed by the models endpoint.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub struct ModelInfo {
+    /// The model identifier, which can be used in the requests.
+    pub id: String,
+    /// The organization that owns the model, if told by the server.
+    #[serde(default)]
+    pub owned_by: Option<String>,
+    /// The Unix timestamp (in seconds) when the model was created, if told
+    /// by the server.
+    #[serde(default)]
+    pub created: Option<u64>,
+}
+
+/// The body of a failed response, e.g.
+/// `{"error": {"message": "...", "code": "...", "param": "..."}}`.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ErrorEnvelope {
+    pub error: ErrorBody,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct ErrorBody {
+    /// Usually a string, but some compatible servers send a number.
+    pub code: Option<Value>,
+    pub param: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub struct ModelList {
+    pub data: Vec<ModelInfo>,
+}
+
 // ------------------------
 // Types sent to the server
 // ------------------------
@@ -58,7 +111,7 @@ struct Tool {
     function: FunctionTool,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(tag = "role", rename_all = "lowercase")]
 pub enum Message {
     System {
@@ -73,13 +126,36 @@ pub enum Message {
         tool_calls: Option<Vec<ToolCall>>,
         #[serde(skip_serializing_if = "Option::is_none")]
         reasoning_content: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
     },
     Tool {
         tool_call_id: String,
-        content: String,
+        content: ToolContent,
     },
 }
 
+/// The content of a tool message, which is either a plain text or a list of
+/// content parts.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ToolContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ImageUrl {
+    url: String,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
 pub struct ChatCompletionRequest {
     model: String,
@@ -88,9 +164,27 @@ pub struct ChatCompletionRequest {
     tools: Vec<Tool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     stream_options: Option<StreamOptions>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    n: Option<u32>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    logit_bias: BTreeMap<u32, i32>,
     stream: bool,
+    #[serde(flatten)]
+    extra: Map<String, Value>,
 }
 
+/// Fields of [`ChatCompletionRequest`] that can't be overwritten by the extra
+/// parameters.
+const KNOWN_FIELDS: &[&str] = &[
+    "model",
+    "messages",
+    "tools",
+    "stream_options",
+    "n",
+    "logit_bias",
+    "stream",
+];
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize)]
 struct StreamOptions {
     include_usage: bool,
@@ -100,24 +194,52 @@ struct StreamOptions {
 // Conversions
 // -----------
 
+/// Registers the opaque message types produced by this crate, so that they
+/// can be persisted along with the conversation.
+#[inline]
+pub fn register_opaque_types() {
+    OpaqueMessage::register::<Message>("openai.message");
+}
+
 #[inline]
 pub fn create_request(
     req: &ModelRequest,
     config: &OpenAIConfig,
 ) -> ChatCompletionRequest {
+    let mut messages: Vec<_> = req
+        .messages
+        .iter()
+        .map(|msg| create_message(msg, config.vision))
+        .collect();
+    if let Some(assistant_name) = &config.assistant_name {
+        for msg in &mut messages {
+            if let Message::Assistant { name, .. } = msg {
+                *name = Some(assistant_name.clone());
+            }
+        }
+    }
+
     ChatCompletionRequest {
-        model: config.model.clone(),
-        messages: req.messages.iter().map(create_message).collect(),
+        model: req.model.clone().unwrap_or_else(|| config.model.clone()),
+        messages,
         tools: req.tools.iter().map(create_tool).collect(),
-        stream_options: Some(StreamOptions {
+        stream_options: config.usage_reporting.then_some(StreamOptions {
             include_usage: true,
         }),
+        n: config.n,
+        logit_bias: config.logit_bias.clone(),
         stream: true,
+        extra: req
+            .extra
+            .iter()
+            .filter(|(key, _)| !KNOWN_FIELDS.contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect(),
     }
 }
 
 #[inline]
-fn create_message(msg: &ModelMessage) -> Message {
+fn create_message(msg: &ModelMessage, vision: bool) -> Message {
     match msg {
         ModelMessage::System(content) => Message::System {
             content: content.clone(),
@@ -129,18 +251,39 @@ fn create_message(msg: &ModelMessage) -> Message {
             content: Some(content.clone()),
             tool_calls: None,
             reasoning_content: None,
+            name: None,
+        },
+        ModelMessage::AssistantToolCalls {
+            content,
+            tool_calls,
+        } => Message::Assistant {
+            content: (!content.is_empty()).then(|| content.clone()),
+            tool_calls: Some(
+                tool_calls
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, call)| create_tool_call(idx, call))
+                    .collect(),
+            ),
+            reasoning_content: None,
+            name: None,
         },
         ModelMessage::Tool(result) => Message::Tool {
             tool_call_id: result.id.clone(),
-            content: result.content.clone(),
+            content: create_tool_content(&result.content, vision),
         },
         ModelMessage::Opaque(opaque_message) => {
             // Opaque messages from this provide always have `Message` type.
             let Some(msg) = opaque_message.to_raw::<Message>() else {
+                // From another provider, keep the text at least.
                 return Message::Assistant {
-                    content: None,
+                    content: opaque_message
+                        .transcript()
+                        .filter(|transcript| !transcript.is_empty())
+                        .map(str::to_owned),
                     tool_calls: None,
                     reasoning_content: None,
+                    name: None,
                 };
             };
             msg.clone()
@@ -148,6 +291,49 @@ fn create_message(msg: &ModelMessage) -> Message {
     }
 }
 
+#[inline]
+fn create_tool_content(output: &ToolOutput, vision: bool) -> ToolContent {
+    match output {
+        ToolOutput::Image { media_type, data } if vision => {
+            ToolContent::Parts(vec![create_image_part(media_type, data)])
+        }
+        ToolOutput::Truncated(inner) if vision => {
+            let ToolOutput::Image { media_type, data } = inner.as_ref() else {
+                return ToolContent::Text(output.to_text().into_owned());
+            };
+            ToolContent::Parts(vec![
+                ContentPart::Text {
+                    text: "[truncated]".to_owned(),
+                },
+                create_image_part(media_type, data),
+            ])
+        }
+        _ => ToolContent::Text(output.to_text().into_owned()),
+    }
+}
+
+#[inline]
+fn create_image_part(media_type: &str, data: &str) -> ContentPart {
+    ContentPart::ImageUrl {
+        image_url: ImageUrl {
+            url: format!("data:{media_type};base64,{data}"),
+        },
+    }
+}
+
+#[inline]
+fn create_tool_call(idx: usize, call: &ToolCallRequest) -> ToolCall {
+    ToolCall {
+        index: Some(idx as u32),
+        id: Some(call.id.clone()),
+        r#type: Some("function".to_owned()),
+        function: Some(FunctionToolCall {
+            name: Some(call.name.clone()),
+            arguments: Some(call.arguments.to_string()),
+        }),
+    }
+}
+
 #[inline]
 fn create_tool(tool: &ModelTool) -> Tool {
     Tool {
@@ -164,6 +350,8 @@ fn create_tool(tool: &ModelTool) -> Tool {
 mod tests {
     use serde_json::json;
 
+    use little_agent_model::{ToolCallResult, ToolOutput};
+
     use super::*;
     use crate::OpenAIConfigBuilder;
 
@@ -182,10 +370,13 @@ mod tests {
                     "description": "The command line."
                 }),
             }],
+            model: None,
+            extra: Default::default(),
         };
         let config = OpenAIConfigBuilder::with_api_key("xxx")
             .with_model("custom")
-            .build();
+            .build()
+            .unwrap();
         let expected = ChatCompletionRequest {
             model: "custom".to_owned(),
             messages: vec![
@@ -210,8 +401,283 @@ mod tests {
             stream_options: Some(StreamOptions {
                 include_usage: true,
             }),
+            n: None,
+            logit_bias: Default::default(),
             stream: true,
+            extra: Default::default(),
         };
         assert_eq!(create_request(&request, &config), expected);
     }
+
+    #[test]
+    fn test_model_override() {
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_model("default")
+            .build()
+            .unwrap();
+
+        let request = ModelRequest {
+            model: Some("cheap".to_owned()),
+            ..Default::default()
+        };
+        let json =
+            serde_json::to_value(create_request(&request, &config)).unwrap();
+        assert_eq!(json["model"], "cheap");
+
+        let request = ModelRequest::default();
+        let json =
+            serde_json::to_value(create_request(&request, &config)).unwrap();
+        assert_eq!(json["model"], "default");
+    }
+
+    #[test]
+    fn test_extra_parameters() {
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_model("default")
+            .build()
+            .unwrap();
+        let mut request = ModelRequest::default();
+        request.extra.insert("top_k".to_owned(), json!(40));
+        request.extra.insert("model".to_owned(), json!("other"));
+
+        let json =
+            serde_json::to_value(create_request(&request, &config)).unwrap();
+        assert_eq!(json["top_k"], 40);
+        // Known fields are not overwritten.
+        assert_eq!(json["model"], "default");
+    }
+
+    #[test]
+    fn test_logit_bias() {
+        let request = ModelRequest::default();
+
+        let config = OpenAIConfigBuilder::with_api_key("xxx").build().unwrap();
+        let json =
+            serde_json::to_value(create_request(&request, &config)).unwrap();
+        assert!(json.get("logit_bias").is_none());
+
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_logit_bias([(50256, -100), (1234, 5)])
+            .build()
+            .unwrap();
+        let json =
+            serde_json::to_value(create_request(&request, &config)).unwrap();
+        assert_eq!(json["logit_bias"], json!({ "1234": 5, "50256": -100 }));
+    }
+
+    #[test]
+    fn test_assistant_name() {
+        let request = ModelRequest {
+            messages: vec![
+                ModelMessage::User("Hi".to_owned()),
+                ModelMessage::Assistant("Hello!".to_owned()),
+            ],
+            ..Default::default()
+        };
+
+        let config = OpenAIConfigBuilder::with_api_key("xxx").build().unwrap();
+        let json =
+            serde_json::to_value(create_request(&request, &config)).unwrap();
+        assert!(json["messages"][1].get("name").is_none());
+
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_assistant_name("planner")
+            .build()
+            .unwrap();
+        let json =
+            serde_json::to_value(create_request(&request, &config)).unwrap();
+        assert!(json["messages"][0].get("name").is_none());
+        assert_eq!(json["messages"][1]["name"], "planner");
+    }
+
+    #[test]
+    fn test_usage_reporting() {
+        let request = ModelRequest::default();
+
+        let config = OpenAIConfigBuilder::with_api_key("xxx").build().unwrap();
+        let json =
+            serde_json::to_value(create_request(&request, &config)).unwrap();
+        assert_eq!(json["stream_options"]["include_usage"], true);
+
+        let config = OpenAIConfigBuilder::with_api_key("xxx")
+            .with_usage_reporting(false)
+            .build()
+            .unwrap();
+        let json =
+            serde_json::to_value(create_request(&request, &config)).unwrap();
+        assert!(json.get("stream_options").is_none());
+    }
+
+    #[test]
+    fn test_create_json_tool_message() {
+        let msg = ModelMessage::Tool(ToolCallResult {
+            id: "call_1".to_owned(),
+            content: ToolOutput::Json(json!({ "files": ["a.rs", "b.rs"] })),
+        });
+        let expected = Message::Tool {
+            tool_call_id: "call_1".to_owned(),
+            content: ToolContent::Text(
+                r#"{"files":["a.rs","b.rs"]}"#.to_owned(),
+            ),
+        };
+        assert_eq!(create_message(&msg, false), expected);
+    }
+
+    #[test]
+    fn test_image_tool_message() {
+        let msg = ModelMessage::Tool(ToolCallResult {
+            id: "call_1".to_owned(),
+            content: ToolOutput::Image {
+                media_type: "image/png".to_owned(),
+                data: "iVBORw0KGgo=".to_owned(),
+            },
+        });
+
+        let json = serde_json::to_value(create_message(&msg, true)).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "role": "tool",
+                "tool_call_id": "call_1",
+                "content": [{
+                    "type": "image_url",
+                    "image_url": {
+                        "url": "data:image/png;base64,iVBORw0KGgo=",
+                    },
+                }],
+            })
+        );
+
+        let json = serde_json::to_value(create_message(&msg, false)).unwrap();
+        assert_eq!(json["content"], "[image/png image]");
+    }
+
+    #[test]
+    fn test_truncated_image_tool_message() {
+        let msg = ModelMessage::Tool(ToolCallResult {
+            id: "call_1".to_owned(),
+            content: ToolOutput::Image {
+                media_type: "image/png".to_owned(),
+                data: "iVBORw0KGgo=".to_owned(),
+            }
+            .truncated(),
+        });
+
+        let json = serde_json::to_value(create_message(&msg, true)).unwrap();
+        assert_eq!(
+            json["content"],
+            json!([
+                { "type": "text", "text": "[truncated]" },
+                {
+                    "type": "image_url",
+                    "image_url": {
+                        "url": "data:image/png;base64,iVBORw0KGgo=",
+                    },
+                },
+            ])
+        );
+
+        let json = serde_json::to_value(create_message(&msg, false)).unwrap();
+        assert_eq!(json["content"], "[truncated]\n[image/png image]");
+    }
+
+    #[test]
+    fn test_replay_reconstructed_tool_calls() {
+        let config = OpenAIConfigBuilder::with_api_key("xxx").build().unwrap();
+        let request = ModelRequest {
+            messages: vec![
+                ModelMessage::User("List the files".to_owned()),
+                ModelMessage::AssistantToolCalls {
+                    content: String::new(),
+                    tool_calls: vec![ToolCallRequest {
+                        id: "call_1".to_owned(),
+                        name: "shell".to_owned(),
+                        arguments: json!({ "cmdline": "ls" }),
+                    }],
+                },
+                ModelMessage::Tool(ToolCallResult {
+                    id: "call_1".to_owned(),
+                    content: ToolOutput::Text("a.rs".to_owned()),
+                }),
+            ],
+            ..Default::default()
+        };
+        let json =
+            serde_json::to_value(create_request(&request, &config)).unwrap();
+        assert_eq!(
+            json["messages"][1],
+            json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "index": 0,
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {
+                        "name": "shell",
+                        "arguments": r#"{"cmdline":"ls"}"#,
+                    },
+                }],
+            })
+        );
+        assert_eq!(json["messages"][2]["tool_call_id"], "call_1");
+    }
+
+    #[test]
+    fn test_opaque_message_round_trip() {
+        let config = OpenAIConfigBuilder::with_api_key("xxx").build().unwrap();
+        let _provider = crate::OpenAIProvider::new(config);
+
+        let message = Message::Assistant {
+            content: Some("Let me check.".to_owned()),
+            tool_calls: Some(vec![ToolCall {
+                index: Some(0),
+                id: Some("call_0".to_owned()),
+                r#type: Some("function".to_owned()),
+                function: Some(FunctionToolCall {
+                    name: Some("shell".to_owned()),
+                    arguments: Some(r#"{"cmdline":"ls"}"#.to_owned()),
+                }),
+            }]),
+            reasoning_content: None,
+            name: None,
+        };
+        let opaque = OpaqueMessage::new("chatcmpl-0", message.clone());
+
+        let json = serde_json::to_string(&opaque).unwrap();
+        let restored: OpaqueMessage = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, opaque);
+        assert_eq!(restored.to_raw::<Message>(), Some(&message));
+    }
+
+    #[test]
+    fn test_foreign_opaque_message() {
+        struct ForeignMessage;
+
+        let config = OpenAIConfigBuilder::with_api_key("xxx").build().unwrap();
+        let request = ModelRequest {
+            messages: vec![
+                ModelMessage::User("Hi".to_owned()),
+                ModelMessage::Opaque(
+                    OpaqueMessage::new("msg_0", ForeignMessage)
+                        .with_transcript("Hello, how can I help?"),
+                ),
+                ModelMessage::Opaque(OpaqueMessage::new(
+                    "msg_1",
+                    ForeignMessage,
+                )),
+            ],
+            ..Default::default()
+        };
+        let json =
+            serde_json::to_value(create_request(&request, &config)).unwrap();
+        assert_eq!(
+            json["messages"][1],
+            json!({
+                "role": "assistant",
+                "content": "Hello, how can I help?",
+            })
+        );
+        assert_eq!(json["messages"][2]["content"], Value::Null);
+    }
 }
@@ -0,0 +1,348 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use bytes::Bytes;
+use little_agent_model::{
+    ErrorKind, MessageJoinPolicy, ModelProvider, ModelRequest,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::io::Chunks;
+use crate::{Error, OpenAIConfig, OpenAIProvider, OpenAIResponse, proto};
+
+#[inline]
+fn request_path(dir: &Path, idx: usize) -> PathBuf {
+    dir.join(format!("{idx:04}.request.json"))
+}
+
+#[inline]
+fn response_path(dir: &Path, idx: usize) -> PathBuf {
+    dir.join(format!("{idx:04}.response.sse"))
+}
+
+#[inline]
+fn error_path(dir: &Path, idx: usize) -> PathBuf {
+    dir.join(format!("{idx:04}.error.json"))
+}
+
+/// Returns the body sent to the server for the request.
+fn request_body(req: &ModelRequest, config: &OpenAIConfig) -> Value {
+    serde_json::to_value(proto::create_request(req, config))
+        .expect("requests should be serializable")
+}
+
+/// A failed request, recorded with the details that drive the retries.
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedError {
+    message: String,
+    kind: ErrorKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retry_after_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    param: Option<String>,
+    #[serde(default)]
+    connect: bool,
+}
+
+impl RecordedError {
+    fn new(err: &Error) -> Self {
+        Self {
+            message: err.message.clone(),
+            kind: err.kind,
+            retry_after_ms: err.retry_after.map(|d| d.as_millis() as u64),
+            code: err.code.clone(),
+            param: err.param.clone(),
+            connect: err.connect,
+        }
+    }
+
+    fn into_error(self) -> Error {
+        let mut err = Error::new(self.message, self.kind)
+            .with_retry_after(self.retry_after_ms.map(Duration::from_millis));
+        err.code = self.code;
+        err.param = self.param;
+        err.connect = self.connect;
+        err
+    }
+}
+
+/// A provider that records the raw interactions with the server into a
+/// directory, which can be replayed later by [`ReplayProvider`].
+///
+/// For the `n`-th request, the following files are written:
+///
+/// - `{n:04}.request.json`: The request body sent to the server.
+/// - `{n:04}.response.sse`: The raw bytes of the streamed response, written
+///   as they arrive.
+/// - `{n:04}.error.json`: The error, if the request failed before the
+///   response started streaming.
+///
+/// Files are written synchronously, so this provider is meant for building
+/// test fixtures rather than production use.
+pub struct RecordingProvider {
+    inner: OpenAIProvider,
+    dir: Arc<PathBuf>,
+    next_idx: AtomicUsize,
+}
+
+impl RecordingProvider {
+    /// Creates a provider that records into `dir`, which is created if it
+    /// doesn't exist.
+    pub fn new(
+        inner: OpenAIProvider,
+        dir: impl Into<PathBuf>,
+    ) -> io::Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            inner,
+            dir: Arc::new(dir),
+            next_idx: AtomicUsize::new(0),
+        })
+    }
+}
+
+impl ModelProvider for RecordingProvider {
+    type Error = Error;
+    type Response = OpenAIResponse;
+
+    fn send_request(
+        &self,
+        req: &ModelRequest,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
+    {
+        let idx = self.next_idx.fetch_add(1, Ordering::Relaxed);
+        let dir = Arc::clone(&self.dir);
+        let body = request_body(req, &self.inner.config);
+        let body = serde_json::to_string_pretty(&body)
+            .expect("requests should be serializable");
+        if let Err(err) = fs::write(request_path(&dir, idx), body) {
+            warn!("failed to record request #{idx}: {err}");
+        }
+
+        let fut = self.inner.send(req, Some(response_path(&dir, idx)));
+        async move {
+            let result = fut.await;
+            if let Err(err) = &result {
+                let json =
+                    serde_json::to_string_pretty(&RecordedError::new(err))
+                        .expect("recorded errors should be serializable");
+                if let Err(err) = fs::write(error_path(&dir, idx), json) {
+                    warn!("failed to record error #{idx}: {err}");
+                }
+            }
+            result
+        }
+    }
+
+    #[inline]
+    fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        self.inner.health_check()
+    }
+
+    #[inline]
+    fn message_join_policy(&self) -> MessageJoinPolicy {
+        self.inner.message_join_policy()
+    }
+}
+
+/// A provider that replays the interactions recorded by
+/// [`RecordingProvider`].
+///
+/// Recordings are served in order, and the recorded bytes are parsed the
+/// same way as a live response. The `n`-th request must produce the same
+/// body as the `n`-th recorded one, otherwise it fails with
+/// [`ErrorKind::InvalidRequest`].
+pub struct ReplayProvider {
+    config: OpenAIConfig,
+    dir: PathBuf,
+    next_idx: AtomicUsize,
+}
+
+impl ReplayProvider {
+    /// Creates a provider that replays the recordings in `dir`, with the
+    /// configuration used for recording.
+    #[inline]
+    pub fn new(config: OpenAIConfig, dir: impl Into<PathBuf>) -> Self {
+        proto::register_opaque_types();
+        Self {
+            config,
+            dir: dir.into(),
+            next_idx: AtomicUsize::new(0),
+        }
+    }
+
+    fn replay(&self, req: &ModelRequest) -> Result<OpenAIResponse, Error> {
+        let idx = self.next_idx.fetch_add(1, Ordering::Relaxed);
+        let invalid =
+            |message: String| Error::new(message, ErrorKind::InvalidRequest);
+
+        let recorded = fs::read_to_string(request_path(&self.dir, idx))
+            .map_err(|err| invalid(format!("no recording #{idx}: {err}")))?;
+        let recorded: Value = serde_json::from_str(&recorded)
+            .map_err(|err| invalid(format!("bad recording #{idx}: {err}")))?;
+        if recorded != request_body(req, &self.config) {
+            return Err(invalid(format!(
+                "request #{idx} doesn't match the recording"
+            )));
+        }
+
+        if let Ok(json) = fs::read_to_string(error_path(&self.dir, idx)) {
+            let recorded: RecordedError = serde_json::from_str(&json)
+                .map_err(|err| invalid(format!("bad error #{idx}: {err}")))?;
+            return Err(recorded.into_error());
+        }
+
+        let bytes = fs::read(response_path(&self.dir, idx))
+            .map_err(|err| invalid(format!("no response #{idx}: {err}")))?;
+        let chunks =
+            Chunks::from_vec_deque(VecDeque::from([Bytes::from(bytes)]));
+        Ok(crate::make_response(&self.config, chunks))
+    }
+}
+
+impl ModelProvider for ReplayProvider {
+    type Error = Error;
+    type Response = OpenAIResponse;
+
+    fn send_request(
+        &self,
+        req: &ModelRequest,
+    ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
+    {
+        std::future::ready(self.replay(req))
+    }
+
+    #[inline]
+    fn message_join_policy(&self) -> MessageJoinPolicy {
+        self.config.message_join_policy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+    use crate::OpenAIConfigBuilder;
+    use crate::test_util::{TestServer, http_response};
+    use little_agent_model::{
+        ModelMessage, ModelProviderError, ModelResponse, ModelResponseEvent,
+    };
+
+    const SSE_BODY: &str = "\
+data: {\"id\":\"c1\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hello\"},\"finish_reason\":null}]}\n\n\
+data: {\"id\":\"c1\",\"choices\":[{\"index\":0,\"delta\":{\"content\":\", world!\"},\"finish_reason\":null}]}\n\n\
+data: {\"id\":\"c1\",\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}\n\n\
+data: [DONE]\n\n";
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir()
+            .join(format!("little-agent-{name}-{}", process::id()));
+        fs::remove_dir_all(&dir).ok();
+        dir
+    }
+
+    fn config(base_url: &str) -> OpenAIConfig {
+        OpenAIConfigBuilder::with_api_key("xxx")
+            .with_base_url(base_url)
+            .with_model("gpt-test")
+            .build()
+            .unwrap()
+    }
+
+    fn request(text: &str) -> ModelRequest {
+        ModelRequest {
+            messages: vec![ModelMessage::User(text.to_owned())],
+            ..Default::default()
+        }
+    }
+
+    async fn collect_events(resp: OpenAIResponse) -> Vec<ModelResponseEvent> {
+        let mut resp = std::pin::pin!(resp);
+        let mut events = Vec::new();
+        while let Some(event) =
+            std::future::poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+                .await
+                .unwrap()
+        {
+            events.push(event);
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let server = TestServer::serve(vec![
+            http_response(
+                "429 Too Many Requests",
+                "retry-after: 7\r\n",
+                r#"{"error":{"message":"slow down","code":"rate_limit"}}"#,
+            ),
+            http_response("200 OK", "", SSE_BODY),
+        ])
+        .await;
+        let base_url = server.base_url();
+
+        let dir = temp_dir("record");
+        let recorder =
+            RecordingProvider::new(OpenAIProvider::new(config(base_url)), &dir)
+                .unwrap();
+        let err = recorder.send_request(&request("Hi")).await.err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::RateLimitExceeded);
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(7)));
+        let resp = recorder.send_request(&request("Hi")).await.unwrap();
+        let recorded = collect_events(resp).await;
+        assert!(!recorded.is_empty());
+        assert_eq!(
+            fs::read(response_path(&dir, 1)).unwrap(),
+            SSE_BODY.as_bytes()
+        );
+
+        // The server is gone, the responses come from the recording.
+        let replayer = ReplayProvider::new(config(base_url), &dir);
+        let err = replayer.send_request(&request("Hi")).await.err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::RateLimitExceeded);
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(7)));
+        assert_eq!(err.code(), Some("rate_limit"));
+        let resp = replayer.send_request(&request("Hi")).await.unwrap();
+        assert_eq!(collect_events(resp).await, recorded);
+
+        // Out of recordings.
+        let err = replayer.send_request(&request("Hi")).await.err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidRequest);
+
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn test_replay_mismatched_request() {
+        let server =
+            TestServer::serve(vec![http_response("200 OK", "", SSE_BODY)])
+                .await;
+        let base_url = server.base_url();
+        let dir = temp_dir("mismatch");
+        let recorder =
+            RecordingProvider::new(OpenAIProvider::new(config(base_url)), &dir)
+                .unwrap();
+        let resp = recorder.send_request(&request("Hi")).await.unwrap();
+        collect_events(resp).await;
+
+        let replayer = ReplayProvider::new(config(base_url), &dir);
+        let err = replayer.send_request(&request("Bye")).await.err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidRequest);
+        assert!(err.message().contains("doesn't match"));
+
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -1,20 +1,46 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::pin::Pin;
 use std::task::{Context, Poll, ready};
 
 use little_agent_model::{
     ErrorKind, ModelFinishReason, ModelResponse, ModelResponseEvent,
-    OpaqueMessage, ToolCallRequest,
+    OpaqueMessage, ToolCallProgress, ToolCallRequest, Usage,
 };
 use pin_project_lite::pin_project;
 use serde_json::Value;
+use tokio::time::{Instant, timeout_at};
 
 use crate::Error;
-use crate::io::Sse;
-use crate::proto::{ChatCompletionChunk, Message, ToolCall};
+use crate::config::{DEFAULT_MAX_EVENTS, DeltaCoalescing};
+use crate::io::{Sse, SseError, SseEvent};
+use crate::proto::{ChatCompletionChunk, Choice, Message, ToolCall};
+
+/// A candidate completion of a response, see [`OpenAIConfigBuilder::with_n`].
+///
+/// Only the text content is assembled for a candidate.
+///
+/// [`OpenAIConfigBuilder::with_n`]: crate::OpenAIConfigBuilder::with_n
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Candidate {
+    /// The index of the candidate, the first one is streamed as events.
+    pub index: u32,
+    /// The text content of the candidate.
+    pub content: String,
+    /// The finish reason, or `None` if the candidate didn't finish.
+    pub finish_reason: Option<ModelFinishReason>,
+}
 
 struct PartialState {
     sse: Sse,
+    coalescing: Option<DeltaCoalescing>,
+    heartbeats: bool,
+    tool_call_progress: bool,
+    max_content_len: Option<usize>,
+    max_events: usize,
+    event_count: usize,
+    // Set when the content exceeds `max_content_len`, the rest of the stream
+    // is not consumed then.
+    truncated: bool,
     id: Option<String>,
     content: String,
     reasoning_content: Option<String>,
@@ -25,9 +51,30 @@ struct PartialState {
     pending_tool_call_idx: VecDeque<usize>,
     // This field will be cleared after the response returns the complete event.
     pending_finish_reason: Option<ModelFinishReason>,
+    finish_reason: Option<ModelFinishReason>,
+    // The other candidates (when `n` is greater than 1), keyed by index.
+    other_candidates: BTreeMap<u32, Candidate>,
+    model: Option<String>,
+    usage: Option<Usage>,
 }
 
 impl PartialState {
+    #[inline]
+    fn candidates(&mut self) -> Vec<Candidate> {
+        let first = Candidate {
+            index: 0,
+            content: self.content.clone(),
+            finish_reason: self.finish_reason.clone(),
+        };
+        let others = std::mem::take(&mut self.other_candidates);
+        [first].into_iter().chain(others.into_values()).collect()
+    }
+
+    /// Makes the full message for replaying.
+    ///
+    /// Reasoning models may finish with only the reasoning content. In that
+    /// case, the content stays empty (so does the transcript), and the
+    /// reasoning is still carried by the message.
     #[inline]
     fn finish(self) -> Option<(String, Message)> {
         Some((
@@ -40,6 +87,7 @@ impl PartialState {
                     Some(self.tool_calls)
                 },
                 reasoning_content: self.reasoning_content,
+                name: None,
             },
         ))
     }
@@ -49,30 +97,81 @@ type PinnedFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
 type NextEvent = Result<(Option<ModelResponseEvent>, PartialState), Error>;
 
 pin_project! {
+    /// The streaming response of [`OpenAIProvider`].
+    ///
+    /// [`OpenAIProvider`]: crate::OpenAIProvider
     pub struct OpenAIResponse {
         next_event_fut: Option<PinnedFuture<NextEvent>>,
         full_msg: Option<(String, Message)>,
+        candidates: Vec<Candidate>,
+        truncated: bool,
+        usage: Option<Usage>,
     }
 }
 
 impl OpenAIResponse {
     #[inline]
     pub fn from_sse(sse: Sse) -> Self {
+        Self::with_options(sse, None, false, false, None, DEFAULT_MAX_EVENTS)
+    }
+
+    pub(crate) fn with_options(
+        sse: Sse,
+        coalescing: Option<DeltaCoalescing>,
+        heartbeats: bool,
+        tool_call_progress: bool,
+        max_content_len: Option<usize>,
+        max_events: usize,
+    ) -> Self {
         let partial_state = PartialState {
             sse,
+            coalescing,
+            heartbeats,
+            tool_call_progress,
+            max_content_len,
+            max_events,
+            event_count: 0,
+            truncated: false,
             id: None,
             content: Default::default(),
             reasoning_content: Default::default(),
             tool_calls: Default::default(),
             pending_tool_call_idx: Default::default(),
             pending_finish_reason: Default::default(),
+            finish_reason: None,
+            other_candidates: Default::default(),
+            model: None,
+            usage: None,
         };
         let next_event_fut = async move { next_event(partial_state).await };
         Self {
             next_event_fut: Some(Box::pin(next_event_fut)),
             full_msg: None,
+            candidates: vec![],
+            truncated: false,
+            usage: None,
         }
     }
+
+    /// Returns the candidate completions ordered by index, the first one is
+    /// the same as the streamed message.
+    ///
+    /// It's empty until the stream ends.
+    #[inline]
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    /// Returns true if the content was cut off at the limit set by
+    /// [`OpenAIConfigBuilder::with_max_response_bytes`].
+    ///
+    /// It's `false` until the stream ends.
+    ///
+    /// [`OpenAIConfigBuilder::with_max_response_bytes`]: crate::OpenAIConfigBuilder::with_max_response_bytes
+    #[inline]
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
 }
 
 impl ModelResponse for OpenAIResponse {
@@ -90,8 +189,11 @@ impl ModelResponse for OpenAIResponse {
         let (event, partial_state) =
             match ready!(next_event_fut.as_mut().poll(cx)) {
                 Ok((Some(event), partial_state)) => (event, partial_state),
-                Ok((None, partial_state)) => {
+                Ok((None, mut partial_state)) => {
                     *this.next_event_fut = None;
+                    *this.candidates = partial_state.candidates();
+                    *this.truncated = partial_state.truncated;
+                    *this.usage = partial_state.usage.take();
                     *this.full_msg = partial_state.finish();
                     return Poll::Ready(Ok(None));
                 }
@@ -114,22 +216,81 @@ impl ModelResponse for OpenAIResponse {
             .as_ref()
             .map(|(id, msg)| OpaqueMessage::new(id, msg.clone()))
     }
+
+    #[inline]
+    fn usage(&self) -> Option<Usage> {
+        self.usage.clone()
+    }
 }
 
 async fn next_event(
     mut partial_state: PartialState,
 ) -> Result<(Option<ModelResponseEvent>, PartialState), Error> {
     let sse = &mut partial_state.sse;
-    let mut message_delta = None;
+    let mut message_delta: Option<String> = None;
+    // When coalescing, the buffered delta is emitted once the deadline is
+    // reached, even if no more events arrive.
+    let mut deadline = None;
 
     loop {
-        let sse_event = match sse.next_event().await {
-            Ok(Some(event)) => event,
+        if partial_state.truncated {
+            break;
+        }
+        let next_sse_event = match deadline {
+            Some(deadline) => {
+                match timeout_at(deadline, sse.next_event()).await {
+                    Ok(result) => result,
+                    Err(_) => break,
+                }
+            }
+            None => sse.next_event().await,
+        };
+        let sse_event = match next_sse_event {
+            Ok(Some(_))
+                if partial_state.event_count >= partial_state.max_events =>
+            {
+                return Err(Error::new(
+                    format!(
+                        "too many events in the response, the limit is {}",
+                        partial_state.max_events
+                    ),
+                    // The same stream would hit the limit again, so it
+                    // must not be retried.
+                    ErrorKind::InvalidRequest,
+                ));
+            }
+            Ok(Some(event)) => {
+                partial_state.event_count += 1;
+                event
+            }
             Ok(None) => break,
+            Err(SseError::StreamStalled(timeout)) => {
+                return Err(Error::new(
+                    format!("stream stalled, no data received in {timeout:?}"),
+                    ErrorKind::Timeout,
+                ));
+            }
+            Err(SseError::ChunksError(err)) => {
+                return Err(Error::from_chunks(err));
+            }
             Err(err) => {
                 return Err(Error::new(format!("{err:?}"), ErrorKind::Other));
             }
         };
+        let sse_event = match sse_event {
+            SseEvent::Data(data) => data,
+            SseEvent::Comment(comment) => {
+                trace!("got sse comment: {comment}");
+                // A buffered delta is more useful than a heartbeat.
+                if partial_state.heartbeats && message_delta.is_none() {
+                    return Ok((
+                        Some(ModelResponseEvent::Heartbeat),
+                        partial_state,
+                    ));
+                }
+                continue;
+            }
+        };
         trace!("got sse event: {sse_event}");
         if sse_event == "[DONE]" {
             break;
@@ -141,24 +302,71 @@ async fn next_event(
         {
             return Err(Error::new("chunk id mismatch", ErrorKind::Other));
         };
+        if let Some(model) = chunk.model.take() {
+            partial_state.model = Some(model);
+        }
+        // The usage is cumulative, so the last report wins.
+        let usage = chunk.usage.take().or_else(|| {
+            chunk
+                .choices
+                .iter_mut()
+                .find_map(|choice| choice.usage.take())
+        });
+        if let Some(usage) = usage {
+            partial_state.usage = Some(Usage {
+                model: partial_state.model.clone(),
+                prompt_tokens: usage.prompt_tokens,
+                completion_tokens: usage.completion_tokens,
+            });
+        }
 
-        let Some(choice) = chunk.choices.pop() else {
-            break;
+        // Only the first candidate is streamed, the others are recorded.
+        let mut first_choice = None;
+        for choice in chunk.choices.drain(..) {
+            if choice.index == 0 {
+                first_choice = Some(choice);
+            } else {
+                record_candidate(&mut partial_state.other_candidates, choice);
+            }
+        }
+        let Some(choice) = first_choice else {
+            // Some chunks (like the usage report) carry no choices, more data
+            // may still follow.
+            continue;
         };
 
         if let Some(finish_reason) = choice.finish_reason {
-            let finish_reason = if finish_reason == "tool_calls" {
-                ModelFinishReason::ToolCalls
-            } else {
-                ModelFinishReason::Stop
-            };
+            let finish_reason = finish_reason_of(&finish_reason)?;
+            partial_state.finish_reason = Some(finish_reason.clone());
             partial_state.pending_finish_reason = Some(finish_reason);
             break;
         }
 
-        if let Some(content) = choice.delta.content {
-            partial_state.content.push_str(&content);
-            message_delta = Some(content.to_owned());
+        // Some servers send empty content alongside the reasoning content,
+        // which is not worth an event.
+        if let Some(mut content) =
+            choice.delta.content.filter(|c| !c.is_empty())
+        {
+            if let Some(max_len) = partial_state.max_content_len {
+                let remaining =
+                    max_len.saturating_sub(partial_state.content.len());
+                if content.len() > remaining {
+                    truncate_at_char_boundary(&mut content, remaining);
+                    partial_state.truncated = true;
+                    partial_state.finish_reason =
+                        Some(ModelFinishReason::Length);
+                    partial_state.pending_finish_reason =
+                        Some(ModelFinishReason::Length);
+                }
+            }
+            if !content.is_empty() {
+                partial_state.content.push_str(&content);
+                message_delta.get_or_insert_default().push_str(&content);
+            }
+            if partial_state.truncated {
+                warn!("response content exceeds the limit, truncating");
+                break;
+            }
         }
         if let Some(reasoning_content) = &choice.delta.reasoning_content {
             partial_state
@@ -166,19 +374,24 @@ async fn next_event(
                 .get_or_insert_default()
                 .push_str(reasoning_content);
         }
+        // The index of the last tool call that got more data in this chunk.
+        let mut progressed = None;
         if let Some(tool_calls) = choice.delta.tool_calls {
             for tool_call in tool_calls {
-                let Some(partial_tool_call) = partial_state
+                let Some((idx, partial_tool_call)) = partial_state
                     .tool_calls
                     .iter_mut()
-                    .find(|t| t.index == tool_call.index)
+                    .enumerate()
+                    .find(|(_, t)| t.index == tool_call.index)
                 else {
+                    progressed = Some(partial_state.tool_calls.len());
                     partial_state
                         .pending_tool_call_idx
                         .push_back(partial_state.tool_calls.len());
                     partial_state.tool_calls.push(tool_call);
                     continue;
                 };
+                progressed = Some(idx);
                 // Patch the partial tool call.
                 if let Some(id) = tool_call.id {
                     partial_tool_call.id.get_or_insert_default().push_str(&id);
@@ -211,9 +424,29 @@ async fn next_event(
             }
         }
 
-        if message_delta.is_some() {
+        let Some(message_delta) = &message_delta else {
+            // The complete tool calls are only emitted after the stream ends,
+            // so the progress always comes first.
+            let progress = progressed
+                .filter(|_| partial_state.tool_call_progress)
+                .and_then(|idx| {
+                    tool_call_progress_of(&partial_state.tool_calls[idx])
+                });
+            if let Some(progress) = progress {
+                return Ok((
+                    Some(ModelResponseEvent::ToolCallProgress(progress)),
+                    partial_state,
+                ));
+            }
+            continue;
+        };
+        let Some(coalescing) = partial_state.coalescing else {
+            break;
+        };
+        if message_delta.len() >= coalescing.max_len {
             break;
         }
+        deadline.get_or_insert_with(|| Instant::now() + coalescing.window);
     }
 
     // The order of events are important. Always emit message delta first, then
@@ -227,13 +460,25 @@ async fn next_event(
     }
 
     if let Some(idx) = partial_state.pending_tool_call_idx.pop_front() {
-        let tool_call = &partial_state.tool_calls[idx];
+        let tool_call = &mut partial_state.tool_calls[idx];
+        // Some gateways omit the ids. Fill in a synthetic one, so the results
+        // can still be matched with the calls in the replayed message.
+        if tool_call.id.as_deref().is_none_or(str::is_empty) {
+            let index = tool_call.index.unwrap_or(idx as u32);
+            tool_call.id = Some(format!("call_{index}"));
+        }
         let id = tool_call.id.clone().unwrap_or_default();
-        let name = tool_call
+        let Some(name) = tool_call
             .function
             .as_ref()
             .and_then(|f| f.name.clone())
-            .unwrap_or_default();
+            .filter(|name| !name.is_empty())
+        else {
+            return Err(Error::new(
+                format!("tool call without name: {tool_call:?}"),
+                ErrorKind::Other,
+            ));
+        };
         let arguments = tool_call
             .function
             .as_ref()
@@ -260,17 +505,80 @@ async fn next_event(
     Ok((None, partial_state))
 }
 
+fn record_candidate(candidates: &mut BTreeMap<u32, Candidate>, choice: Choice) {
+    let candidate =
+        candidates.entry(choice.index).or_insert_with(|| Candidate {
+            index: choice.index,
+            ..Default::default()
+        });
+    if let Some(content) = choice.delta.content {
+        candidate.content.push_str(&content);
+    }
+    if let Some(finish_reason) = choice.finish_reason {
+        // A filtered candidate is dropped.
+        match finish_reason_of(&finish_reason) {
+            Ok(finish_reason) => {
+                candidate.finish_reason = Some(finish_reason);
+            }
+            Err(_) => {
+                candidates.remove(&choice.index);
+            }
+        }
+    }
+}
+
+/// Truncates the string to at most `len` bytes, without splitting a char.
+fn truncate_at_char_boundary(s: &mut String, len: usize) {
+    let mut len = len.min(s.len());
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    s.truncate(len);
+}
+
+fn tool_call_progress_of(tool_call: &ToolCall) -> Option<ToolCallProgress> {
+    let function = tool_call.function.as_ref()?;
+    // The progress is useless to the UI until the name is known.
+    let name = function.name.clone().filter(|name| !name.is_empty())?;
+    Some(ToolCallProgress {
+        id: tool_call.id.clone().filter(|id| !id.is_empty()),
+        name,
+        partial_arguments: function.arguments.clone().unwrap_or_default(),
+    })
+}
+
+fn finish_reason_of(finish_reason: &str) -> Result<ModelFinishReason, Error> {
+    match finish_reason {
+        "tool_calls" => Ok(ModelFinishReason::ToolCalls),
+        "length" => Ok(ModelFinishReason::Length),
+        "content_filter" => Err(Error::new(
+            "response is blocked by the content filter",
+            ErrorKind::Moderated,
+        )),
+        "stop" => Ok(ModelFinishReason::Stop),
+        _ => {
+            warn!("unknown finish reason: {finish_reason}");
+            Ok(ModelFinishReason::Other(finish_reason.to_owned()))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use std::error::Error as _;
     use std::future::poll_fn;
     use std::pin::pin;
+    use std::time::Duration;
 
     use bytes::Bytes;
+    use little_agent_model::{ModelMessage, ModelProviderError, ModelRequest};
 
     use super::*;
-    use crate::Chunks;
+    use crate::proto::create_request;
+    use crate::{Chunks, ChunksError, OpenAIConfigBuilder};
 
     #[tokio::test]
+    #[allow(clippy::while_let_loop)]
     async fn test_simple_events() {
         let chunks = Chunks::from_vec_deque(
             vec![Bytes::from_static(include_bytes!(
@@ -300,4 +608,512 @@ mod tests {
         let full_msg: &Message = full_msg.to_raw().unwrap();
         assert!(matches!(full_msg, Message::Assistant { .. }));
     }
+
+    #[tokio::test]
+    async fn test_tool_call_progress() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        let mut resp = pin!(OpenAIResponse::with_options(
+            sse,
+            None,
+            false,
+            true,
+            None,
+            DEFAULT_MAX_EVENTS
+        ));
+        let mut progress = vec![];
+        let mut tool_calls = vec![];
+        while let Some(event) = poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+            .await
+            .unwrap()
+        {
+            match event {
+                ModelResponseEvent::ToolCallProgress(p) => {
+                    assert!(tool_calls.is_empty());
+                    progress.push(p);
+                }
+                ModelResponseEvent::ToolCall(call) => tool_calls.push(call),
+                _ => {}
+            }
+        }
+
+        assert_eq!(tool_calls.len(), 2);
+        for call in &tool_calls {
+            let last = progress
+                .iter()
+                .rfind(|p| p.id.as_deref() == Some(call.id.as_str()))
+                .unwrap();
+            assert_eq!(last.name, call.name);
+            let arguments: Value =
+                serde_json::from_str(&last.partial_arguments).unwrap();
+            assert_eq!(arguments, call.arguments);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_length_finish_reason() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response_length.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        let mut resp = pin!(OpenAIResponse::from_sse(sse));
+        let mut finish_reason = None;
+        while let Some(event) = poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+            .await
+            .unwrap()
+        {
+            if let ModelResponseEvent::Completed(reason) = event {
+                finish_reason = Some(reason);
+            }
+        }
+        assert_eq!(finish_reason, Some(ModelFinishReason::Length));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_finish_reason() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response_unknown_finish_reason.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        let mut resp = pin!(OpenAIResponse::from_sse(sse));
+        let mut finish_reason = None;
+        while let Some(event) = poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+            .await
+            .unwrap()
+        {
+            if let ModelResponseEvent::Completed(reason) = event {
+                finish_reason = Some(reason);
+            }
+        }
+        assert_eq!(
+            finish_reason,
+            Some(ModelFinishReason::Other("end_turn".to_owned()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_events() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response_long.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        let mut resp = pin!(OpenAIResponse::with_options(
+            sse, None, false, false, None, 3
+        ));
+        let mut deltas = 0;
+        let err = loop {
+            match poll_fn(|cx| resp.as_mut().poll_next_event(cx)).await {
+                Ok(Some(ModelResponseEvent::MessageDelta(_))) => deltas += 1,
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("the response should fail"),
+                Err(err) => break err,
+            }
+        };
+        assert!(deltas <= 3);
+        assert!(err.to_string().contains("too many events"));
+        assert_eq!(err.kind(), ErrorKind::InvalidRequest);
+    }
+
+    #[tokio::test]
+    async fn test_content_filter() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response_content_filter.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        let mut resp = pin!(OpenAIResponse::from_sse(sse));
+        let err = loop {
+            match poll_fn(|cx| resp.as_mut().poll_next_event(cx)).await {
+                Ok(Some(_)) => continue,
+                Ok(None) => panic!("response should fail"),
+                Err(err) => break err,
+            }
+        };
+        assert_eq!(err.kind(), ErrorKind::Moderated);
+    }
+
+    #[tokio::test]
+    async fn test_collect_text() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response_length.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        let text = OpenAIResponse::from_sse(sse).collect_text().await.unwrap();
+        assert_eq!(text, "Once upon a time, there was a");
+    }
+
+    #[tokio::test]
+    async fn test_empty_choices() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response_empty_choices.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        let mut resp = pin!(OpenAIResponse::from_sse(sse));
+        let mut text = String::new();
+        let mut finish_reason = None;
+        while let Some(event) = poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+            .await
+            .unwrap()
+        {
+            match event {
+                ModelResponseEvent::MessageDelta(delta) => {
+                    text.push_str(&delta);
+                }
+                ModelResponseEvent::Completed(reason) => {
+                    finish_reason = Some(reason);
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(text, "Hello, world!");
+        assert_eq!(finish_reason, Some(ModelFinishReason::Stop));
+        assert_eq!(
+            resp.usage(),
+            Some(Usage {
+                model: Some("kimi-k2-turbo-preview".to_owned()),
+                prompt_tokens: 12,
+                completion_tokens: 4,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_response_bytes() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response_long.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        // The cap falls in the middle of "Ç", which is dropped as a whole.
+        let mut resp = pin!(OpenAIResponse::with_options(
+            sse,
+            None,
+            false,
+            false,
+            Some(15),
+            DEFAULT_MAX_EVENTS
+        ));
+        let mut events = vec![];
+        while let Some(event) = poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+            .await
+            .unwrap()
+        {
+            events.push(event);
+        }
+
+        let delta = |s: &str| ModelResponseEvent::MessageDelta(s.to_owned());
+        assert_eq!(
+            events,
+            [
+                delta("Hello, "),
+                delta("world! "),
+                ModelResponseEvent::Completed(ModelFinishReason::Length),
+            ]
+        );
+        assert!(resp.is_truncated());
+        let full_msg = resp.make_opaque_message().unwrap();
+        let Message::Assistant { content, .. } = full_msg.to_raw().unwrap()
+        else {
+            panic!("expected an assistant message");
+        };
+        assert_eq!(content.as_deref(), Some("Hello, world! "));
+    }
+
+    #[tokio::test]
+    async fn test_chunk_read_failure() {
+        async fn read_until_failure(fault: ChunksError) -> Error {
+            let chunks = Chunks::from_results(
+                vec![Ok(Bytes::from_static(b": ping\n\n")), Err(fault)].into(),
+            );
+            let resp = OpenAIResponse::from_sse(Sse::new(chunks));
+            resp.collect_text().await.unwrap_err()
+        }
+
+        let err = read_until_failure(ChunksError::injected(true, false)).await;
+        assert!(err.is_timeout());
+        assert!(!err.is_connect());
+        assert_eq!(err.kind(), ErrorKind::Timeout);
+
+        let err = read_until_failure(ChunksError::injected(false, true)).await;
+        assert!(!err.is_timeout());
+        assert!(err.is_connect());
+        assert!(err.kind().is_retryable());
+        assert!(err.source().unwrap().is::<ChunksError>());
+    }
+
+    #[tokio::test]
+    async fn test_heartbeats() {
+        async fn collect_events(heartbeats: bool) -> Vec<ModelResponseEvent> {
+            let chunks = Chunks::from_vec_deque(
+                vec![Bytes::from_static(include_bytes!(
+                    "../fixtures/test_response_heartbeat.txt"
+                ))]
+                .into(),
+            );
+            let sse = Sse::new(chunks);
+            let mut resp = pin!(OpenAIResponse::with_options(
+                sse,
+                None,
+                heartbeats,
+                false,
+                None,
+                DEFAULT_MAX_EVENTS
+            ));
+            let mut events = vec![];
+            while let Some(event) =
+                poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+                    .await
+                    .unwrap()
+            {
+                events.push(event);
+            }
+            events
+        }
+
+        let delta = |s: &str| ModelResponseEvent::MessageDelta(s.to_owned());
+        let completed = ModelResponseEvent::Completed(ModelFinishReason::Stop);
+        assert_eq!(
+            collect_events(true).await,
+            [
+                ModelResponseEvent::Heartbeat,
+                ModelResponseEvent::Heartbeat,
+                delta("Hello, "),
+                ModelResponseEvent::Heartbeat,
+                delta("world!"),
+                completed.clone(),
+            ]
+        );
+        assert_eq!(
+            collect_events(false).await,
+            [delta("Hello, "), delta("world!"), completed]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reasoning_only() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response_reasoning_only.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        let mut resp = pin!(OpenAIResponse::from_sse(sse));
+        let mut events = vec![];
+        while let Some(event) = poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+            .await
+            .unwrap()
+        {
+            events.push(event);
+        }
+        assert_eq!(
+            events,
+            [ModelResponseEvent::Completed(ModelFinishReason::Stop)]
+        );
+
+        // The reasoning is preserved when the history is replayed.
+        let opaque_msg = resp.make_opaque_message().unwrap();
+        let req = ModelRequest {
+            messages: vec![ModelMessage::Opaque(opaque_msg)],
+            ..Default::default()
+        };
+        let config = OpenAIConfigBuilder::with_api_key("xxx").build().unwrap();
+        let json = serde_json::to_value(create_request(&req, &config)).unwrap();
+        let msg = &json["messages"][0];
+        assert_eq!(msg["content"], "");
+        assert_eq!(
+            msg["reasoning_content"],
+            "The user says hi. I should greet back."
+        );
+    }
+
+    #[tokio::test]
+    async fn test_multiple_candidates() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response_n.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        let mut resp = pin!(OpenAIResponse::from_sse(sse));
+        let mut text = String::new();
+        while let Some(event) = poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+            .await
+            .unwrap()
+        {
+            if let ModelResponseEvent::MessageDelta(delta) = event {
+                text.push_str(&delta);
+            }
+        }
+        assert_eq!(text, "Hello, world!");
+
+        let candidates = resp.candidates();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].content, "Hello, world!");
+        assert_eq!(candidates[1].index, 1);
+        assert_eq!(candidates[1].content, "Hi there!");
+        assert_eq!(
+            candidates[1].finish_reason,
+            Some(ModelFinishReason::Length)
+        );
+    }
+
+    fn delayed_chunk(millis: u64, choice: &str) -> (Duration, Bytes) {
+        let data = format!(r#"data: {{"id":"c1","choices":[{choice}]}}"#);
+        (
+            Duration::from_millis(millis),
+            Bytes::from(format!("{data}\n\n")),
+        )
+    }
+
+    async fn collect_deltas(
+        coalescing: Option<DeltaCoalescing>,
+    ) -> (Vec<String>, Option<ModelFinishReason>) {
+        let content = |millis, content| {
+            let choice = format!(
+                r#"{{"delta":{{"content":"{content}"}},"finish_reason":null}}"#
+            );
+            delayed_chunk(millis, &choice)
+        };
+        let chunks = VecDeque::from([
+            content(5, "Once "),
+            content(5, "upon "),
+            content(5, "a "),
+            content(5, "time"),
+            // The last delta comes much later than the others.
+            content(500, "."),
+            delayed_chunk(5, r#"{"delta":{},"finish_reason":"stop"}"#),
+        ]);
+
+        let sse = Sse::new(Chunks::from_delayed(chunks));
+        let mut resp = pin!(OpenAIResponse::with_options(
+            sse,
+            coalescing,
+            false,
+            false,
+            None,
+            DEFAULT_MAX_EVENTS
+        ));
+        let mut deltas = vec![];
+        let mut finish_reason = None;
+        while let Some(event) = poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+            .await
+            .unwrap()
+        {
+            match event {
+                ModelResponseEvent::MessageDelta(delta) => deltas.push(delta),
+                ModelResponseEvent::Completed(reason) => {
+                    finish_reason = Some(reason);
+                }
+                _ => {}
+            }
+        }
+        (deltas, finish_reason)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_delta_coalescing() {
+        let (deltas, finish_reason) = collect_deltas(None).await;
+        assert_eq!(deltas, ["Once ", "upon ", "a ", "time", "."]);
+        assert_eq!(finish_reason, Some(ModelFinishReason::Stop));
+
+        let coalescing = DeltaCoalescing {
+            window: Duration::from_millis(50),
+            max_len: 1024,
+        };
+        let (deltas, finish_reason) = collect_deltas(Some(coalescing)).await;
+        assert_eq!(deltas, ["Once upon a time", "."]);
+        assert_eq!(finish_reason, Some(ModelFinishReason::Stop));
+
+        let coalescing = DeltaCoalescing {
+            window: Duration::from_millis(50),
+            max_len: 8,
+        };
+        let (deltas, _) = collect_deltas(Some(coalescing)).await;
+        assert_eq!(deltas, ["Once upon ", "a time", "."]);
+    }
+
+    #[tokio::test]
+    async fn test_synthetic_tool_call_ids() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response_idless_tool_calls.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        let mut resp = pin!(OpenAIResponse::from_sse(sse));
+        let mut ids = vec![];
+        while let Some(event) = poll_fn(|cx| resp.as_mut().poll_next_event(cx))
+            .await
+            .unwrap()
+        {
+            if let ModelResponseEvent::ToolCall(req) = event {
+                ids.push(req.id);
+            }
+        }
+        assert_eq!(ids, ["call_0", "call_1"]);
+
+        let full_msg = resp.make_opaque_message().unwrap();
+        let Message::Assistant {
+            tool_calls: Some(tool_calls),
+            ..
+        } = full_msg.to_raw().unwrap()
+        else {
+            panic!("expected an assistant message with tool calls");
+        };
+        let replayed_ids: Vec<_> = tool_calls
+            .iter()
+            .map(|t| t.id.as_deref().unwrap())
+            .collect();
+        assert_eq!(replayed_ids, ids);
+    }
+
+    #[tokio::test]
+    async fn test_nameless_tool_call() {
+        let chunks = Chunks::from_vec_deque(
+            vec![Bytes::from_static(include_bytes!(
+                "../fixtures/test_response_nameless_tool_call.txt"
+            ))]
+            .into(),
+        );
+        let sse = Sse::new(chunks);
+        let mut resp = pin!(OpenAIResponse::from_sse(sse));
+        let err = loop {
+            match poll_fn(|cx| resp.as_mut().poll_next_event(cx)).await {
+                Ok(Some(ModelResponseEvent::ToolCall(req))) => {
+                    panic!("unexpected tool call: {req:?}");
+                }
+                Ok(Some(_)) => continue,
+                Ok(None) => panic!("response should fail"),
+                Err(err) => break err,
+            }
+        };
+        assert!(err.message().contains("call_9f2c"));
+    }
 }
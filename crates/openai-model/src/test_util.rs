@@ -0,0 +1,84 @@
+//! A minimal HTTP server for the tests.
+
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// A server that serves the given responses in order, one per connection.
+pub(crate) struct TestServer {
+    base_url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl TestServer {
+    /// Starts serving the responses, see [`http_response`].
+    pub(crate) async fn serve(responses: Vec<String>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let requests = Arc::new(Mutex::new(vec![]));
+        tokio::spawn({
+            let requests = Arc::clone(&requests);
+            async move {
+                for resp in responses {
+                    let (mut stream, _) = listener.accept().await.unwrap();
+                    let req = read_request(&mut stream).await;
+                    requests.lock().unwrap().push(req);
+                    stream.write_all(resp.as_bytes()).await.unwrap();
+                }
+            }
+        });
+        Self {
+            base_url: format!("http://{addr}/v1"),
+            requests,
+        }
+    }
+
+    /// Returns the base URL of the server.
+    pub(crate) fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Returns the requests received so far.
+    pub(crate) fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Makes a response with the status line like `200 OK`, and the extra
+/// headers each ending with `\r\n`.
+pub(crate) fn http_response(status: &str, headers: &str, body: &str) -> String {
+    let len = body.len();
+    format!(
+        "HTTP/1.1 {status}\r\nconnection: close\r\n{headers}\
+         content-length: {len}\r\n\r\n{body}"
+    )
+}
+
+/// Reads the whole request, so that the connection is closed cleanly.
+async fn read_request(stream: &mut TcpStream) -> String {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let len = stream.read(&mut chunk).await.unwrap();
+        buf.extend_from_slice(&chunk[..len]);
+        let req = String::from_utf8_lossy(&buf);
+        let Some((head, body)) = req.split_once("\r\n\r\n") else {
+            if len == 0 {
+                return req.into_owned();
+            }
+            continue;
+        };
+        let content_len = head
+            .lines()
+            .find_map(|line| {
+                line.to_ascii_lowercase()
+                    .strip_prefix("content-length:")
+                    .map(|len| len.trim().parse::<usize>().unwrap())
+            })
+            .unwrap_or(0);
+        if len == 0 || body.len() >= content_len {
+            return req.into_owned();
+        }
+    }
+}
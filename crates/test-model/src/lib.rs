@@ -7,14 +7,15 @@ use std::error::Error as StdError;
 use std::fmt::{self, Debug, Display, Formatter};
 use std::future::ready;
 use std::pin::Pin;
-use std::sync::Arc;
 use std::sync::atomic::{self, AtomicU64};
+use std::sync::{Arc, Mutex};
 use std::task::{Context, Poll, ready};
 use std::time::Duration;
 
 use little_agent_model::{
-    ErrorKind, ModelFinishReason, ModelProvider, ModelProviderError,
-    ModelRequest, ModelResponse, ModelResponseEvent, OpaqueMessage,
+    ErrorKind, MessageJoinPolicy, ModelFinishReason, ModelProvider,
+    ModelProviderError, ModelRequest, ModelResponse, ModelResponseEvent,
+    OpaqueMessage, Usage,
 };
 use tokio::time::{Sleep, sleep};
 
@@ -25,6 +26,7 @@ pub struct Error {
     #[allow(dead_code)]
     message: &'static str,
     kind: ErrorKind,
+    retry_after: Option<Duration>,
 }
 
 impl Display for Error {
@@ -40,6 +42,11 @@ impl ModelProviderError for Error {
     fn kind(&self) -> ErrorKind {
         self.kind
     }
+
+    #[inline]
+    fn retry_after(&self) -> Option<Duration> {
+        self.retry_after
+    }
 }
 
 pub struct TestModelResponse {
@@ -57,10 +64,22 @@ impl ModelResponse for TestModelResponse {
         cx: &mut Context<'_>,
     ) -> Poll<Result<Option<ModelResponseEvent>, Self::Error>> {
         let step_idx = self.request.messages.len();
+        if self
+            .provider
+            .context_limit
+            .is_some_and(|limit| step_idx > limit)
+        {
+            return Poll::Ready(Err(Error {
+                message: "simulated context length failure",
+                kind: ErrorKind::ContextLengthExceeded,
+                retry_after: None,
+            }));
+        }
         if step_idx >= self.provider.conversation_script.len() {
             return Poll::Ready(Err(Error {
                 message: "no enough steps",
                 kind: ErrorKind::RateLimitExceeded,
+                retry_after: None,
             }));
         }
 
@@ -72,7 +91,8 @@ impl ModelResponse for TestModelResponse {
             ConversationStep::UserInput => {
                 return Poll::Ready(Err(Error {
                     message: "not an assistant response step",
-                    kind: ErrorKind::Moderated,
+                    kind: ErrorKind::InvalidRequest,
+                    retry_after: None,
                 }));
             }
             ConversationStep::AssistantResponse(response) => response,
@@ -81,6 +101,7 @@ impl ModelResponse for TestModelResponse {
             return Poll::Ready(Err(Error {
                 message: "simulated network failure",
                 kind: ErrorKind::RateLimitExceeded,
+                retry_after: response.retry_after,
             }));
         }
 
@@ -91,6 +112,7 @@ impl ModelResponse for TestModelResponse {
                 return Poll::Ready(Err(Error {
                     message: "simulated network failure",
                     kind: ErrorKind::RateLimitExceeded,
+                    retry_after: response.retry_after,
                 }));
             }
         }
@@ -109,6 +131,16 @@ impl ModelResponse for TestModelResponse {
                     PresetEvent::ToolCall(req) => {
                         ModelResponseEvent::ToolCall(req.clone())
                     }
+                    PresetEvent::ToolCallProgress(progress) => {
+                        ModelResponseEvent::ToolCallProgress(progress.clone())
+                    }
+                    PresetEvent::Failure => {
+                        return Poll::Ready(Err(Error {
+                            message: "simulated stream failure",
+                            kind: ErrorKind::Other,
+                            retry_after: None,
+                        }));
+                    }
                 };
                 this.event_idx += 1;
                 return Poll::Ready(Ok(Some(event)));
@@ -140,6 +172,16 @@ impl ModelResponse for TestModelResponse {
         let id = format!("msg:{step_idx}");
         Some(OpaqueMessage::new(id.clone(), id))
     }
+
+    fn usage(&self) -> Option<Usage> {
+        let step_idx = self.request.messages.len();
+        match self.provider.conversation_script.get(step_idx)? {
+            ConversationStep::AssistantResponse(response) => {
+                response.usage.clone()
+            }
+            ConversationStep::UserInput => None,
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -164,6 +206,10 @@ pub struct TestModelProvider {
     conversation_script: Vec<ConversationStep>,
     delay: Option<Duration>,
     failure_counters: HashMap<usize, Arc<AtomicU64>>,
+    requests: Arc<Mutex<Vec<ModelRequest>>>,
+    unhealthy: bool,
+    message_join_policy: MessageJoinPolicy,
+    context_limit: Option<usize>,
 }
 
 impl TestModelProvider {
@@ -182,10 +228,36 @@ impl TestModelProvider {
         self.conversation_script.push(ConversationStep::UserInput);
     }
 
+    /// Makes the requests with more than `max_messages` messages fail with
+    /// a context length error.
+    #[inline]
+    pub fn set_context_limit(&mut self, max_messages: usize) {
+        self.context_limit = Some(max_messages);
+    }
+
+    /// Sets the policy reported by [`ModelProvider::message_join_policy`].
+    #[inline]
+    pub fn set_message_join_policy(&mut self, policy: MessageJoinPolicy) {
+        self.message_join_policy = policy;
+    }
+
+    /// Makes the health check fail with an authentication error.
+    #[inline]
+    pub fn set_unhealthy(&mut self) {
+        self.unhealthy = true;
+    }
+
     #[inline]
     pub fn set_delay(&mut self, duration: Duration) {
         self.delay = Some(duration);
     }
+
+    /// Returns all requests this provider (and its clones) has received,
+    /// in the order they were sent.
+    #[inline]
+    pub fn requests(&self) -> Vec<ModelRequest> {
+        self.requests.lock().unwrap().clone()
+    }
 }
 
 impl ModelProvider for TestModelProvider {
@@ -197,6 +269,7 @@ impl ModelProvider for TestModelProvider {
         req: &ModelRequest,
     ) -> impl Future<Output = Result<Self::Response, Self::Error>> + Send + 'static
     {
+        self.requests.lock().unwrap().push(req.clone());
         let resp = TestModelResponse {
             provider: self.clone(),
             request: req.clone(),
@@ -205,6 +278,24 @@ impl ModelProvider for TestModelProvider {
         };
         ready(Ok(resp))
     }
+
+    fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<(), Self::Error>> + Send + 'static {
+        if !self.unhealthy {
+            return ready(Ok(()));
+        }
+        ready(Err(Error {
+            message: "simulated authentication failure",
+            kind: ErrorKind::Authentication,
+            retry_after: None,
+        }))
+    }
+
+    #[inline]
+    fn message_join_policy(&self) -> MessageJoinPolicy {
+        self.message_join_policy
+    }
 }
 
 #[cfg(test)]
@@ -212,6 +303,8 @@ mod tests {
     use std::future::poll_fn;
     use std::pin::pin;
 
+    use futures_util::StreamExt;
+    use little_agent_model::testing::assert_response_contract;
     use little_agent_model::{
         ModelMessage, ModelRequest, ModelTool, OpaqueMessage, ToolCallRequest,
     };
@@ -235,11 +328,39 @@ mod tests {
                     msg.push_str(&delta);
                 }
                 ModelResponseEvent::ToolCall(req) => tool_call = Some(req),
+                ModelResponseEvent::ToolCallProgress(_)
+                | ModelResponseEvent::Heartbeat => {}
             }
         }
         Ok((msg, tool_call, resp.make_opaque_message().unwrap()))
     }
 
+    #[tokio::test]
+    async fn test_response_contract() {
+        let mut provider = TestModelProvider::default();
+        provider.add_user_input_step();
+        provider.add_assistant_response_step(PresetResponse::with_events([
+            PresetEvent::MessageDelta("Let me check.".to_owned()),
+            PresetEvent::ToolCall(ToolCallRequest {
+                id: "tool:1".to_owned(),
+                name: "read_file".to_owned(),
+                arguments: json!({ "filename": "todo.txt" }),
+            }),
+        ]));
+
+        let req = ModelRequest {
+            messages: vec![ModelMessage::User("Hi".to_owned())],
+            ..Default::default()
+        };
+        let resp = provider.send_request(&req).await.unwrap();
+        let events = assert_response_contract(resp).await;
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events.last(),
+            Some(&ModelResponseEvent::Completed(ModelFinishReason::ToolCalls))
+        );
+    }
+
     #[tokio::test]
     async fn test_send_request() {
         let mut provider = TestModelProvider::default();
@@ -275,6 +396,8 @@ mod tests {
                     }
                 }),
             }],
+            model: None,
+            extra: Default::default(),
         };
         let resp = provider.send_request(&req).await.unwrap();
         let (msg, _, opaque_msg) = collect_response(resp).await.unwrap();
@@ -291,6 +414,32 @@ mod tests {
         assert_eq!(tool_call.arguments, json!({ "filename": "todo.txt" }));
     }
 
+    #[tokio::test]
+    async fn test_event_stream() {
+        let mut provider = TestModelProvider::default();
+        provider.add_user_input_step();
+        provider.add_assistant_response_step(PresetResponse::with_events([
+            PresetEvent::MessageDelta("Hello, ".to_owned()),
+            PresetEvent::MessageDelta("world!".to_owned()),
+        ]));
+
+        let req = ModelRequest {
+            messages: vec![ModelMessage::User("Hi".to_owned())],
+            ..Default::default()
+        };
+        let resp = provider.send_request(&req).await.unwrap();
+        let events: Vec<_> = resp.into_event_stream().collect().await;
+        let events: Vec<_> = events.into_iter().map(Result::unwrap).collect();
+        assert_eq!(
+            events,
+            [
+                ModelResponseEvent::MessageDelta("Hello, ".to_owned()),
+                ModelResponseEvent::MessageDelta("world!".to_owned()),
+                ModelResponseEvent::Completed(ModelFinishReason::Stop),
+            ]
+        );
+    }
+
     #[tokio::test]
     async fn test_simulated_failure() {
         let mut provider = TestModelProvider::default();
@@ -306,6 +455,8 @@ mod tests {
         let req = ModelRequest {
             messages: vec![ModelMessage::User("Hi".to_owned())],
             tools: vec![],
+            model: None,
+            extra: Default::default(),
         };
         let resp = provider.send_request(&req).await.unwrap();
         let err = collect_response(resp).await.unwrap_err();
@@ -314,6 +465,8 @@ mod tests {
         let req = ModelRequest {
             messages: vec![ModelMessage::User("Hi".to_owned())],
             tools: vec![],
+            model: None,
+            extra: Default::default(),
         };
         let resp = provider.send_request(&req).await.unwrap();
         let (msg, ..) = collect_response(resp).await.unwrap();
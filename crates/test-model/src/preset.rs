@@ -1,4 +1,6 @@
-use little_agent_model::ToolCallRequest;
+use std::time::Duration;
+
+use little_agent_model::{ToolCallProgress, ToolCallRequest, Usage};
 use serde::{Deserialize, Serialize};
 
 /// The events in a preset response.
@@ -9,6 +11,11 @@ pub enum PresetEvent {
     MessageDelta(String),
     #[serde(rename = "tool_call")]
     ToolCall(ToolCallRequest),
+    #[serde(rename = "tool_call_progress")]
+    ToolCallProgress(ToolCallProgress),
+    /// Fails the response in the middle of the stream.
+    #[serde(rename = "failure")]
+    Failure,
 }
 
 /// The preset response for an assistant step.
@@ -19,6 +26,12 @@ pub struct PresetResponse {
     /// If set, the request will fail in the first `failure` attempts.
     /// `Some(0)` means the request will fail infinitely.
     pub failures: Option<u64>,
+    /// The suggested retry delay carried by the simulated failures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<Duration>,
+    /// The token usage reported by the response.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
 }
 
 impl PresetResponse {
@@ -28,6 +41,8 @@ impl PresetResponse {
         Self {
             events: events.into(),
             failures: None,
+            retry_after: None,
+            usage: None,
         }
     }
 
@@ -38,6 +53,20 @@ impl PresetResponse {
         self.failures = Some(failures);
         self
     }
+
+    /// Sets the suggested retry delay of the simulated failures.
+    #[inline]
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(retry_after);
+        self
+    }
+
+    /// Sets the token usage reported by the response.
+    #[inline]
+    pub fn with_usage(mut self, usage: Usage) -> Self {
+        self.usage = Some(usage);
+        self
+    }
 }
 
 #[cfg(test)]
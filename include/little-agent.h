@@ -11,15 +11,18 @@ extern "C" {
 /* Error codes returned by the C APIs. */
 typedef enum la_error_code {
   /* No error occurred. */
-  LA_ERROR_OK      = 0,
+  LA_ERROR_OK         = 0,
   /* Invalid parameters or strings. */
-  LA_ERROR_INVALID = 1
+  LA_ERROR_INVALID    = 1,
+  /* The agent has stopped unexpectedly, the session can't be used anymore. */
+  LA_ERROR_AGENT_DEAD = 2
 } la_error_code_t;
 
 /* Transcript sources. */
 typedef enum la_transcript_source {
   LA_TRANSCRIPT_SOURCE_USER      = 0,
-  LA_TRANSCRIPT_SOURCE_ASSISTANT = 1
+  LA_TRANSCRIPT_SOURCE_ASSISTANT = 1,
+  LA_TRANSCRIPT_SOURCE_TOOL      = 2
 } la_transcript_source_t;
 
 /* Opaque representation of a session builder. */
@@ -74,7 +77,8 @@ typedef struct la_session_callbacks {
  * @param[out]     Pointer to the session builder, which will be set if the
  *                 call succeeds.
  * @param api_key  API key for the OpenAI provider.
- * @param base_url Base URL for the OpenAI provider.
+ * @param base_url Base URL for the OpenAI provider, must be an absolute
+ *                 HTTP(S) URL.
  * @param model    Model to use.
  *
  * @return 0 or an error code.
@@ -104,7 +108,11 @@ void la_session_builder_free(la_session_builder_t *builder);
  */
 la_session_t *la_session_builder_build(la_session_builder_t *builder);
 
-/* Sends a message to the session. */
+/*
+ * Sends a message to the session.
+ *
+ * Returns `LA_ERROR_AGENT_DEAD` if the agent has stopped unexpectedly.
+ */
 la_error_code_t la_session_send_message(
     la_session_t *session,
     const char *message);